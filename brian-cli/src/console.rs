@@ -0,0 +1,225 @@
+//! Program I/O on the terminal
+
+use std::{io::{self, IsTerminal, Read, Write}, process::ExitCode, slice, sync::OnceLock};
+use brian::Io;
+use crate::diagnostics;
+
+/// First error writing program output to stdout
+static OUTPUT_ERROR: OnceLock<io::Error> = OnceLock::new();
+
+/// Stdin flushing stdout before every read if it is a terminal, so prompts written without a newline
+/// show up before the program waits for input
+pub struct PromptingStdin {
+  flush: bool,
+}
+impl Read for PromptingStdin {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    if self.flush {
+      Stdout.flush()?;
+    }
+    io::stdin().read(buffer)
+  }
+}
+
+/// Stdout keeping the first write error (e.g. a closed pipe) for the exit status, see [`output_status`]
+pub struct Stdout;
+impl Stdout {
+  fn check<T>(result: io::Result<T>) -> io::Result<T> {
+    match result {
+      Err(error) if error.kind() != io::ErrorKind::Interrupted => {
+        let _ = OUTPUT_ERROR.set(io::Error::new(error.kind(), error.to_string()));
+        Err(error)
+      },
+      result => result,
+    }
+  }
+}
+impl Write for Stdout {
+  fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+    Self::check(io::stdout().write(buffer))
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Self::check(io::stdout().flush())
+  }
+}
+
+/// Program I/O on stdin and stdout, stopping the program once the output fails
+pub struct ConsoleIo {
+  pub input: PromptingStdin,
+  pub output: Stdout,
+}
+impl Io for ConsoleIo {
+  fn input(&mut self) -> Option<u8> {
+    let mut byte = 0;
+    match self.input.read(slice::from_mut(&mut byte)) {
+      Ok(1) => Some(byte),
+      _ => None,
+    }
+  }
+
+  fn output(&mut self, byte: u8) {
+    let _ = self.output.write_all(&[byte]);
+  }
+
+  fn flush(&mut self) {
+    let _ = self.output.flush();
+  }
+
+  fn failed(&mut self) -> bool {
+    OUTPUT_ERROR.get().is_some()
+  }
+}
+
+/// Program I/O on stdin and stdout
+pub fn stdio() -> ConsoleIo {
+  ConsoleIo { input: PromptingStdin { flush: io::stdout().is_terminal() }, output: Stdout }
+}
+
+/// Exit status for a failed write of program output: the status of a process killed by `SIGPIPE` if the output
+/// was closed (e.g. piped into `head`), like tools not handling it, or a reported failure otherwise
+pub fn write_failure(error: &io::Error) -> ExitCode {
+  if error.kind() == io::ErrorKind::BrokenPipe {
+    return ExitCode::from(128 + 13)
+  }
+  diagnostics::error(format_args!("failed to write output: {error}"));
+  ExitCode::FAILURE
+}
+
+/// Exit status of a command after writing program output to [`Stdout`]
+pub fn output_status() -> ExitCode {
+  OUTPUT_ERROR.get().map_or(ExitCode::SUCCESS, write_failure)
+}
+
+#[cfg(unix)]
+pub use raw::RawMode;
+
+//The `termios` layout is only written out for these platforms, `RawMode` is unsupported elsewhere
+#[cfg(any(
+  all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64", target_arch = "riscv64")),
+  all(any(target_os = "macos", target_os = "ios"), target_pointer_width = "64"),
+))]
+mod raw {
+  use std::{io::{self, IsTerminal}, sync::OnceLock};
+
+  #[cfg(target_os = "linux")]
+  #[derive(Clone, Copy)]
+  #[repr(C)]
+  struct Termios {
+    iflag: u32,
+    oflag: u32,
+    cflag: u32,
+    lflag: u32,
+    line: u8,
+    cc: [u8; 32],
+    ispeed: u32,
+    ospeed: u32,
+  }
+  #[cfg(target_os = "linux")]
+  const ICANON: u32 = 0o2;
+  #[cfg(target_os = "linux")]
+  const ECHO: u32 = 0o10;
+  #[cfg(target_os = "linux")]
+  const VTIME: usize = 5;
+  #[cfg(target_os = "linux")]
+  const VMIN: usize = 6;
+
+  #[cfg(not(target_os = "linux"))]
+  #[derive(Clone, Copy)]
+  #[repr(C)]
+  struct Termios {
+    iflag: u64,
+    oflag: u64,
+    cflag: u64,
+    lflag: u64,
+    cc: [u8; 20],
+    ispeed: u64,
+    ospeed: u64,
+  }
+  #[cfg(not(target_os = "linux"))]
+  const ICANON: u64 = 0x100;
+  #[cfg(not(target_os = "linux"))]
+  const ECHO: u64 = 0x8;
+  #[cfg(not(target_os = "linux"))]
+  const VMIN: usize = 16;
+  #[cfg(not(target_os = "linux"))]
+  const VTIME: usize = 17;
+
+  const STDIN: i32 = 0;
+  const TCSANOW: i32 = 0;
+  const SIGINT: i32 = 2;
+
+  extern "C" {
+    fn tcgetattr(fd: i32, termios: *mut Termios) -> i32;
+    fn tcsetattr(fd: i32, action: i32, termios: *const Termios) -> i32;
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    fn _exit(status: i32) -> !;
+  }
+
+  /// Terminal mode from before the switch, for the Ctrl-C handler
+  static SAVED: OnceLock<Termios> = OnceLock::new();
+
+  extern "C" fn interrupted(_: i32) {
+    //tcsetattr and _exit are async-signal-safe, and the mode was saved before the handler got installed
+    if let Some(saved) = SAVED.get() {
+      unsafe { tcsetattr(STDIN, TCSANOW, saved) };
+    }
+    unsafe { _exit(130) }
+  }
+
+  /// Stdin switched to raw mode, which passes every key to the program as soon as it's pressed, without echoing it
+  ///
+  /// The previous mode is restored on drop (also when unwinding from a panic), and before exiting on Ctrl-C
+  pub struct RawMode {
+    saved: Termios,
+  }
+  impl RawMode {
+    /// Switch stdin to raw mode, doing nothing if it isn't a terminal
+    pub fn enable() -> io::Result<Option<Self>> {
+      if !io::stdin().is_terminal() {
+        return Ok(None)
+      }
+      let mut termios = std::mem::MaybeUninit::uninit();
+      //SAFETY: tcgetattr initializes the struct on success
+      let saved = unsafe {
+        if tcgetattr(STDIN, termios.as_mut_ptr()) != 0 {
+          return Err(io::Error::last_os_error())
+        }
+        termios.assume_init()
+      };
+      let mut raw = saved;
+      raw.lflag &= !(ICANON | ECHO);
+      raw.cc[VMIN] = 1;
+      raw.cc[VTIME] = 0;
+      SAVED.get_or_init(|| saved);
+      //SAFETY: the handler only restores the terminal and exits
+      unsafe { signal(SIGINT, interrupted) };
+      if unsafe { tcsetattr(STDIN, TCSANOW, &raw) } != 0 {
+        return Err(io::Error::last_os_error())
+      }
+      Ok(Some(Self { saved }))
+    }
+  }
+  impl Drop for RawMode {
+    fn drop(&mut self) {
+      unsafe { tcsetattr(STDIN, TCSANOW, &self.saved) };
+    }
+  }
+}
+
+#[cfg(all(unix, not(any(
+  all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64", target_arch = "riscv64")),
+  all(any(target_os = "macos", target_os = "ios"), target_pointer_width = "64"),
+))))]
+mod raw {
+  use std::io;
+
+  /// Stand-in for platforms with an unknown `termios` layout
+  pub struct RawMode;
+  impl RawMode {
+    /// Always fails with [`io::ErrorKind::Unsupported`]
+    pub fn enable() -> io::Result<Option<Self>> {
+      Err(io::ErrorKind::Unsupported.into())
+    }
+  }
+}
@@ -0,0 +1,79 @@
+//! Diagnostics printed to stderr, keeping stdout free for program output
+//!
+//! Severities and locations are colored when stderr is a terminal, unless `NO_COLOR` is set
+
+use std::{env, fmt::Display, io::{self, IsTerminal}, sync::OnceLock};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+
+/// Severity of a diagnostic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+  Error,
+  Warning,
+}
+impl Level {
+  fn name(self) -> &'static str {
+    match self {
+      Self::Error => "error",
+      Self::Warning => "warning",
+    }
+  }
+
+  fn color(self) -> &'static str {
+    match self {
+      Self::Error => "\x1b[1;31m",
+      Self::Warning => "\x1b[1;33m",
+    }
+  }
+}
+impl From<brian::lint::Severity> for Level {
+  fn from(severity: brian::lint::Severity) -> Self {
+    match severity {
+      brian::lint::Severity::Error => Self::Error,
+      brian::lint::Severity::Warning => Self::Warning,
+    }
+  }
+}
+
+fn color_enabled() -> bool {
+  static ENABLED: OnceLock<bool> = OnceLock::new();
+  *ENABLED.get_or_init(|| {
+    //Per https://no-color.org, an empty NO_COLOR doesn't count
+    env::var_os("NO_COLOR").is_none_or(|x| x.is_empty()) && io::stderr().is_terminal()
+  })
+}
+
+/// Wrap `text` in the color of `level`, if colors are enabled
+pub fn paint(level: Level, text: impl Display) -> String {
+  match color_enabled() {
+    true => format!("{}{text}{RESET}", level.color()),
+    false => text.to_string(),
+  }
+}
+
+/// Print a diagnostic with a label like `warning[lint-name]`, optionally prefixed by a source location
+pub fn report_labeled(location: Option<&dyn Display>, level: Level, label: &str, message: impl Display) {
+  let label = paint(level, format_args!("{}{label}", level.name()));
+  match location {
+    Some(location) if color_enabled() => eprintln!("{BOLD}{location}:{RESET} {label}: {message}"),
+    Some(location) => eprintln!("{location}: {label}: {message}"),
+    None => eprintln!("{label}: {message}"),
+  }
+}
+
+/// Print a diagnostic, optionally prefixed by a source location
+pub fn report(location: Option<&dyn Display>, level: Level, message: impl Display) {
+  report_labeled(location, level, "", message);
+}
+
+/// Print an error without a location
+pub fn error(message: impl Display) {
+  report(None, Level::Error, message);
+}
+
+/// Print a warning without a location
+pub fn warning(message: impl Display) {
+  report(None, Level::Warning, message);
+}
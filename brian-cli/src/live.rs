@@ -0,0 +1,87 @@
+//! Live state dumps of running programs, requested with SIGUSR1
+
+use std::{
+  ops::ControlFlow,
+  sync::atomic::{AtomicBool, Ordering},
+  time::Instant,
+};
+use brian::{BrainfuckState, Hooks, Opcode, StateView};
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64", target_arch = "riscv64")))]
+const SIGUSR1: Option<i32> = Some(10);
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly"))]
+const SIGUSR1: Option<i32> = Some(30);
+/// Elsewhere the signal number isn't known here, so no dumps can be requested
+#[cfg(not(any(
+  all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64", target_arch = "riscv64")),
+  target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly",
+)))]
+const SIGUSR1: Option<i32> = None;
+
+/// Number of cells shown on each side of the pointer
+const TAPE_WINDOW: isize = 8;
+/// Number of instructions a packed program runs between checks for dump requests
+pub const SLICE: usize = 1 << 20;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+  fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+}
+
+extern "C" fn request_dump(_: i32) {
+  //Only async-signal-safe operations are allowed here, the dump itself happens in the interpreter loop
+  REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// [`Hooks`] printing interpreter stats and a tape window to stderr whenever SIGUSR1 is received
+pub struct LiveDump {
+  start: Instant,
+  instructions: u64,
+}
+impl LiveDump {
+  /// Install the SIGUSR1 handler, if the signal number is known for the platform
+  pub fn install() -> Self {
+    if let Some(signum) = SIGUSR1 {
+      //SAFETY: the handler only touches an atomic
+      unsafe { signal(signum, request_dump) };
+    }
+    Self { start: Instant::now(), instructions: 0 }
+  }
+
+  /// Account for a slice of a packed program run, and print a dump if one was requested meanwhile
+  pub fn slice_done(&mut self, program_counter: usize, state: &BrainfuckState) {
+    self.instructions += SLICE as u64;
+    if REQUESTED.swap(false, Ordering::Relaxed) {
+      self.dump(program_counter, None, StateView { memory: &state.memory, pointer: state.pointer });
+    }
+  }
+
+  #[cold]
+  #[inline(never)]
+  fn dump(&self, program_counter: usize, op: Option<&Opcode>, state: StateView) {
+    let elapsed = self.start.elapsed().as_secs_f64();
+    eprintln!("--- brian: {} instructions in {elapsed:.3} s ({:.1} M/s)", self.instructions, self.instructions as f64 / elapsed / 1e6);
+    let pointer = state.pointer & (state.memory.len() - 1);
+    match op {
+      Some(op) => eprintln!("next instruction: {program_counter} ({op:?}), pointer: {pointer}"),
+      None => eprintln!("next instruction: {program_counter}, pointer: {pointer}"),
+    }
+    let cells: Vec<String> = (-TAPE_WINDOW..=TAPE_WINDOW).map(|offset| match offset {
+      0 => format!("[{}]", state.cell(0)),
+      _ => state.cell(offset).to_string(),
+    }).collect();
+    eprintln!("tape: {}", cells.join(" "));
+  }
+}
+impl Hooks for LiveDump {
+  #[inline(always)]
+  fn before(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    self.instructions += 1;
+    if REQUESTED.load(Ordering::Relaxed) {
+      REQUESTED.store(false, Ordering::Relaxed);
+      self.dump(program_counter, Some(op), state);
+    }
+    ControlFlow::Continue(())
+  }
+}
@@ -1,12 +1,1145 @@
-use brian::Brainfuck;
-use std::time::Instant;
+mod console;
+mod diagnostics;
+#[cfg(unix)]
+mod live;
+mod repl;
 
-fn main() {
+use brian::{
+  breakpoint::{Breakpoint, Breakpoints, Condition},
+  corpus::{self, CorpusOptions, Status},
+  diff::{self, Behavior},
+  doc,
+  io::SinkOutput,
+  lint::{self, Diagnostic, Lint, LintLevel, LintOptions, Severity},
+  loop_limit::LoopLimit,
+  pipeline::{run_pipeline, PipelineOutcome},
+  pointer_history::PointerHistory,
+  recording::{Recording, RecordingIo},
+  run_stats::RunStatsRecorder,
+  sanitizer::{Finding, FindingKind, Sanitizer},
+  stress::{self, Verification},
+  teaching::Explainer,
+  throttle::Throttle,
+  timeline::TimelineRecorder,
+  translate,
+  brainfuck_tokens, BracketPolicy, Brainfuck, BrainfuckState, BoundsPolicy, Compatibility, Compilation, CommentStyle, CompileError, CompileOptions, CompilePipeline, CompileReport, CostModel, EofMode, InterpreterConfig, Io, LoopTree, OverflowPolicy, Profile,
+  Opcode, PackedProgram, Program, ProgramMetrics, RunConfig, RuntimeError, StreamIo, TapeInit, TeeIo, Token, Trap,
+};
+use std::{borrow::Cow, collections::BTreeSet, env, fs, io::{self, Read, Write}, process::{Command, ExitCode, Stdio}, sync::{atomic::{AtomicBool, Ordering}, OnceLock}, time::Instant};
+use diagnostics::{paint, report, report_labeled, Level};
+
+const USAGE: &str = "\
+Usage: brian [OPTIONS] [COMMAND]
+
+Commands:
+  run <FILE>                 Compile and run a program (source code or bytecode)
+                             (--profile-out <PATH> to record a profile, --profile-in <PATH> to optimize using one,
+                             --verify to compare against the unoptimized program and report the first divergence,
+                             --cycles to print the virtual cycle count,
+                             --nesting to print the deepest loop nesting reached and the iterations at each depth,
+                             --pointer-history <N> to print the last N pointer moves and a dump of the visited cells,
+                             --strict to stop on cell overflow or the pointer leaving the tape, printing the crash context,
+                             --sanitize to warn about cell wraparound, pointer wraparound and reads of unwritten cells,
+                             --tee <PATH> to also write program output to a file,
+                             --timeline <PATH> to record loop and I/O events as JSON,
+                             --explain to describe every executed instruction on stderr,
+                             --throttle <N> to execute at most N instructions per second (also after --explain),
+                             --max-loop-iterations <N> to stop and report the first loop iterating more than N times,
+                             --break <CONDITION> to stop once a condition like `cell[42] > 100` or `pointer == 12` holds,
+                             --find <TEXT> to list where TEXT is on the tape after the run,
+                             --count <BYTE> to count the cells holding BYTE after the run,
+                             --tape <PATTERN> to start from a tape other than zeroed (source code only):
+                             fill:<BYTE>, random:<SEED> or image:<PATH> (a file copied to the first cells),
+                             --eof <MODE> to pick what `,` does on EOF: unchanged (default), zero or max,
+                             --bounds <POLICY> to pick what moving off the tape does: wrap (default), trap
+                             or trap-canonical (trap past the first 30000 cells),
+                             --compat <PRESET> to behave like a reference interpreter: brian (default) or canonical
+                             (30000 cells, moving off them is an error, every other character is a comment),
+                             --raw to pass keys to the program as they are pressed, without echo (Unix only),
+                             --record <PATH> to save the bytes read and written to a recording,
+                             --replay <PATH> to feed a recording's input back in and check the output still matches,
+                             --unpacked to run the opcode enum directly instead of the packed form, for debugging;
+                             on Unix, SIGUSR1 prints stats and the tape around the pointer without stopping)
+  compile <FILE> [-o <OUT>]  Compile a program to bytecode, writing to stdout without -o
+                             (--emit <KIND> to write another artifact instead: tokens, ast (unoptimized opcodes),
+                             ir (optimized opcodes), bytecode, c, rust or wasm (a WASI module);
+                             --meta <KEY>=<VALUE>, repeatable, to embed metadata like author or license)
+  dump <FILE>                Print the metadata and opcodes of a program (source code or bytecode)
+  stats <FILE>               Print static metrics of a program
+  discover <FILE> <PROFILE>  Suggest superinstructions based on a profile recorded with --profile-out
+  reduce <FILE> <COMMAND>... Shrink a program while COMMAND succeeds
+                             (the candidate program path is passed to COMMAND as its last argument)
+  test <DIR>                 Run all programs in a directory, comparing against .out files if present
+                             (--json <PATH> to write a JSON report)
+  check <FILE>               Report suspicious constructs in a program (--strict to treat them as errors)
+  dbfi <FILE>                Run a program through the bundled dbfi self-interpreter, passing stdin to it
+  repl [SESSION]             Enter and run code interactively on a persistent tape
+                             (loading SESSION if it exists and saving to it on exit, see :help)
+  pipe <FILE>...             Run programs connected like a shell pipeline, each reading the output of the previous one
+                             (--fuel <N> before the files to stop after N instructions across all programs)
+  doc <FILE>                 Print a markdown report of a program's metrics, loop structure, lint findings and listing
+  diff <FILE> <FILE>         Compare two programs by commands, optimized opcodes and output on stdin
+                             (fails if the outputs differ)
+  bench                      Run the bundled mandelbrot benchmark (default)
+                             (--dispatch to compare dispatch tuning on mandelbrot and dbfi)
+
+Options:
+  --comments <STYLE>         Comment convention of source code: standard (default), semicolon (`;` to the end of the line)
+                             or braces (nestable `{ }` regions), commands inside comments are not executed
+  --brackets <MODE>          Parse mode: strict (default) rejects unmatched brackets, lenient ignores stray `]`
+                             and closes loops still open at the end of the program
+  --flush-char <CHAR>        Compile CHAR (e.g. `;`) to an instruction flushing the output, for interactive prompts
+  -A, -W, -D <LINT>          Allow, warn about or deny a lint (infinite-loop, unread-write, or warnings for all),
+                             checked whenever source code is compiled; denied lints fail compilation
+  --time-passes              Print the time spent in every compilation phase and optimization pass
+
+Diagnostics and stats go to stderr, colored on terminals unless NO_COLOR is set";
+
+/// Maximum number of events recorded by `run --timeline`
+const TIMELINE_MAX_EVENTS: usize = 1_000_000;
+/// Rows of the tape dump printed by `run --pointer-history`
+const POINTER_DUMP_ROWS: usize = 8;
+/// Maximum number of tape addresses listed by `run --find`
+const FIND_MAX_RESULTS: usize = 16;
+/// Maximum number of instructions each program runs for in `diff`
+const DIFF_FUEL: usize = 1 << 30;
+/// Number of straight-line superinstructions picked by `run --profile-in`
+///
+/// Fused handlers dispatch their opcodes one by one, which measured slower than plain dispatch on the bundled
+/// programs, so only loop brackets are fused
+const PROFILE_SUPERINSTRUCTIONS: usize = 0;
+
+/// Options for programs run by the CLI, which always start with a fresh interpreter
+fn compile_options() -> CompileOptions {
+  CompileOptions {
+    assume_zeroed_tape: true,
+    flush: FLUSH_CHAR.get().copied(),
+    brackets: BRACKETS.get().copied().unwrap_or_default(),
+    lints: LINTS.get().cloned(),
+    ..Default::default()
+  }
+}
+
+/// Comment convention selected with `--comments`, applied to all source code read by the CLI
+static COMMENTS: OnceLock<CommentStyle> = OnceLock::new();
+/// Parse mode selected with `--brackets`
+static BRACKETS: OnceLock<BracketPolicy> = OnceLock::new();
+/// Flush extension character selected with `--flush-char`
+static FLUSH_CHAR: OnceLock<char> = OnceLock::new();
+/// Lint levels selected with `-A`, `-W` and `-D`, enforced whenever source code is compiled
+static LINTS: OnceLock<LintOptions> = OnceLock::new();
+/// Whether `--time-passes` was given
+static TIME_PASSES: AtomicBool = AtomicBool::new(false);
+
+/// Blank out comment regions of the selected comment style, keeping offsets intact for diagnostics
+fn strip_comments(code: String) -> String {
+  match COMMENTS.get().copied().unwrap_or_default().strip(&code) {
+    Cow::Borrowed(_) => code,
+    Cow::Owned(code) => code,
+  }
+}
+
+fn read_source(path: &str) -> Result<String, ExitCode> {
+  fs::read_to_string(path).map(strip_comments).map_err(|error| {
+    diagnostics::error(format_args!("failed to read {path}: {error}"));
+    ExitCode::FAILURE
+  })
+}
+
+/// Load a program from either bytecode or source code, detected by magic bytes
+fn load_program(path: &str) -> Result<Program, ExitCode> {
+  let data = fs::read(path).map_err(|error| {
+    diagnostics::error(format_args!("failed to read {path}: {error}"));
+    ExitCode::FAILURE
+  })?;
+  if Program::is_bytecode(&data) {
+    return Program::from_bytecode(&data).map_err(|error| {
+      diagnostics::error(format_args!("failed to load {path}: {error}"));
+      ExitCode::FAILURE
+    })
+  }
+  let code = String::from_utf8(data).map_err(|_| {
+    diagnostics::error(format_args!("{path} is neither bytecode nor valid UTF-8 source code"));
+    ExitCode::FAILURE
+  })?;
+  let code = strip_comments(code);
+  warn_lints(path, &code);
+  compile_source(path, &code, &compile_options())
+}
+
+/// Compile source code, printing the time spent in every phase and pass if `--time-passes` was given
+fn compile_source(path: &str, code: &str, options: &CompileOptions) -> Result<Program, ExitCode> {
+  compile_source_with_report(path, code, options).map(|(program, _)| program)
+}
+
+/// Like [`compile_source`], also returning the report, for locating opcodes in the source code
+fn compile_source_with_report(path: &str, code: &str, options: &CompileOptions) -> Result<(Program, CompileReport), ExitCode> {
+  let (program, compile_report) = Program::compile_with_report(code, options).map_err(|error| compile_error(path, code, error))?;
+  //Passes rejected by the guards of debug builds were skipped, the program is still correct
+  for miscompilation in &compile_report.miscompilations {
+    report(Some(&path), Level::Warning, miscompilation);
+  }
+  if TIME_PASSES.load(Ordering::Relaxed) {
+    eprint!("{}", compile_report.stats);
+  }
+  Ok((program, compile_report))
+}
+
+/// Compile source code without optimizations, for tools following the source command by command
+fn compile_unoptimized(path: &str, code: &str) -> Result<Program, ExitCode> {
+  let options = CompileOptions {
+    flush: FLUSH_CHAR.get().copied(),
+    brackets: BRACKETS.get().copied().unwrap_or_default(),
+    ..Default::default()
+  };
+  let pipeline = CompilePipeline::try_parse(code, &options).map_err(|error| compile_error(path, code, error))?;
+  Ok(pipeline.link().finalize().0)
+}
+
+/// Report an unbalanced bracket, or the issues found by denied lints, at their location in the source code
+fn compile_error(path: &str, code: &str, error: CompileError) -> ExitCode {
+  match error {
+    CompileError::UnbalancedBrackets { offset, bracket } => {
+      let (line, column) = line_column(code, offset);
+      let message = match bracket {
+        Token::LoopStart => "unclosed `[`",
+        _ => "unexpected `]`",
+      };
+      report(Some(&format_args!("{path}:{line}:{column}")), Level::Error, message);
+    },
+    CompileError::DeniedLints(diagnostics) => diagnostics.iter().for_each(|x| report_diagnostic(path, code, x)),
+  }
+  ExitCode::FAILURE
+}
+
+/// Report a lint finding at its location in the source code
+fn report_diagnostic(path: &str, code: &str, diagnostic: &Diagnostic) {
+  let (line, column) = line_column(code, diagnostic.span.start);
+  let label = format!("[{}]", diagnostic.lint.name());
+  report_labeled(Some(&format_args!("{path}:{line}:{column}")), diagnostic.severity.into(), &label, &diagnostic.message);
+}
+
+/// Report lint findings of `code` at the given levels, failing if any lint is denied
+fn report_lints(path: &str, code: &str, options: &LintOptions) -> Result<(), ExitCode> {
+  let diagnostics = lint::check(code, options);
+  diagnostics.iter().for_each(|x| report_diagnostic(path, code, x));
+  match diagnostics.iter().any(|x| x.severity == Severity::Error) {
+    true => Err(ExitCode::FAILURE),
+    false => Ok(()),
+  }
+}
+
+/// Report lint warnings of source code before compiling it, if lint levels were given with `-A`/`-W`/`-D`
+///
+/// Issues found by denied lints fail the compilation, see [`compile_options`]
+fn warn_lints(path: &str, code: &str) {
+  if let Some(options) = LINTS.get() {
+    let diagnostics = lint::check(code, options);
+    diagnostics.iter().filter(|x| x.severity == Severity::Warning).for_each(|x| report_diagnostic(path, code, x));
+  }
+}
+
+/// Convert a byte offset into 1-based line and column numbers
+fn line_column(code: &str, offset: usize) -> (usize, usize) {
+  let before = &code[..offset];
+  let line = before.matches('\n').count() + 1;
+  let column = before.chars().rev().take_while(|&x| x != '\n').count() + 1;
+  (line, column)
+}
+
+/// Input making dbfi interpret `code`
+///
+/// Comments are stripped, as dbfi stops reading the program at the first `!`
+fn dbfi_input(code: &str) -> String {
+  let code: String = brainfuck_tokens(code).map(|(token, _)| token.as_char()).collect();
+  format!("{code}!")
+}
+
+fn bench() -> ExitCode {
   let mut bf = Brainfuck::new();
-  bf.compile(include_str!("../../malderbrot.b.txt"));
-  bf._debug();
+  bf.compile_with_options(brian::programs::MANDELBROT.source, &compile_options()).expect("bundled programs are balanced");
   let start = Instant::now();
-  bf.run();
+  if let Err(code) = run_checked(&mut bf) {
+    return code
+  }
   let elapsed_ms = start.elapsed().as_secs_f64();
-  println!("Took {} seconds", elapsed_ms);
+  //Timing goes to stderr, so it doesn't mix with the rendered mandelbrot
+  eprintln!("Took {} seconds", elapsed_ms);
+  ExitCode::SUCCESS
+}
+
+/// Time runs of the packed form of bundled programs with and without dispatch tuning
+fn bench_dispatch() -> ExitCode {
+  let dbfi_input = dbfi_input(brian::programs::HELLO_WORLD.source);
+  let benchmarks = [
+    (brian::programs::MANDELBROT, "", 1),
+    (brian::programs::DBFI, dbfi_input.as_str(), 100),
+  ];
+  for (bundled, input, repeats) in benchmarks {
+    let Ok(program) = Program::compile_with_options(bundled.source, &compile_options()) else {
+      unreachable!("bundled programs are balanced")
+    };
+    let Some(tuned) = program.pack() else { unreachable!("bundled programs are small") };
+    let mut variants = vec![("untuned", tuned.clone().without_dispatch_tuning()), ("static", tuned)];
+    if !bundled.needs_input {
+      let mut bf = Brainfuck::new();
+      bf.load_program(program.clone());
+      let Ok(profile) = bf.run_profiled(SinkOutput) else { unreachable!("the default bounds policy never traps") };
+      variants.extend(program.pack_with_profile(&profile).map(|x| ("profile", x)));
+    }
+    for (name, packed) in variants {
+      let mut state = Box::new(BrainfuckState::new());
+      let start = Instant::now();
+      for _ in 0..repeats {
+        state.reset();
+        packed.run(&mut state, &mut StreamIo::new(input.as_bytes(), SinkOutput));
+      }
+      let hot = packed.hot_opcode().unwrap_or("-");
+      println!("{:<12} {name:<8} hot opcode {hot:<12} {:.3} s", bundled.name, start.elapsed().as_secs_f64());
+    }
+  }
+  ExitCode::SUCCESS
+}
+
+fn run(path: &str, packed: bool) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  run_program(&program, Box::default(), packed, &mut console::stdio());
+  Ok(())
+}
+
+/// Parse a `--tape` pattern: `zero`, `fill:<BYTE>`, `random:<SEED>` or `image:<PATH>`
+fn parse_tape_init(pattern: &str) -> Result<TapeInit, ExitCode> {
+  let init = match pattern.split_once(':') {
+    None if pattern == "zero" => Some(TapeInit::Zero),
+    Some(("fill", value)) => value.parse().ok().map(TapeInit::Fill),
+    Some(("random", seed)) => seed.parse().ok().map(TapeInit::Random),
+    Some(("image", path)) => Some(TapeInit::Image(fs::read(path).map_err(|error| {
+      diagnostics::error(format_args!("failed to read {path}: {error}"));
+      ExitCode::FAILURE
+    })?)),
+    _ => None,
+  };
+  init.ok_or_else(|| {
+    diagnostics::error(format_args!("invalid tape pattern {pattern}, expected fill:<BYTE>, random:<SEED> or image:<PATH>"));
+    ExitCode::FAILURE
+  })
+}
+
+fn run_tape(path: &str, pattern: &str) -> Result<(), ExitCode> {
+  let init = parse_tape_init(pattern)?;
+  //The tape isn't zeroed, so optimizations relying on that must stay off
+  let options = CompileOptions { assume_zeroed_tape: false, ..compile_options() };
+  let program = compile_source(path, &read_source(path)?, &options)?;
+  let state = Box::new(BrainfuckState::with_tape(&init));
+  run_program(&program, state, true, &mut console::stdio());
+  Ok(())
+}
+
+fn run_bounds(path: &str, policy: &str) -> Result<(), ExitCode> {
+  let bounds: BoundsPolicy = policy.parse().map_err(|error| {
+    diagnostics::error(error);
+    ExitCode::FAILURE
+  })?;
+  let mut bf = Brainfuck::new();
+  bf.load_program(load_program(path)?);
+  bf.set_bounds_policy(bounds);
+  run_checked(&mut bf)
+}
+
+fn run_eof(path: &str, mode: &str) -> Result<(), ExitCode> {
+  let eof: EofMode = mode.parse().map_err(|error| {
+    diagnostics::error(error);
+    ExitCode::FAILURE
+  })?;
+  let mut bf = Brainfuck::new();
+  bf.load_program(load_program(path)?);
+  bf.set_eof_mode(eof);
+  run_checked(&mut bf)
+}
+
+fn run_compat(path: &str, preset: &str) -> Result<(), ExitCode> {
+  let compat: Compatibility = preset.parse().map_err(|error| {
+    diagnostics::error(error);
+    ExitCode::FAILURE
+  })?;
+  //The reference decides what is a comment, so `--comments` doesn't apply
+  let code = fs::read_to_string(path).map_err(|error| {
+    diagnostics::error(format_args!("failed to read {path}: {error}"));
+    ExitCode::FAILURE
+  })?;
+  let (program, compile_report) = compile_source_with_report(path, &code, &compat.compile_options())?;
+  let mut state = Box::new(BrainfuckState::new());
+  let mut io = console::stdio();
+  let Err(trap) = compat.interpreter_config().run(&program, &mut state, &mut io, None) else { return Ok(()) };
+  let _ = io.output.flush();
+  let message = format_args!("{trap}, outside the tape of {compat} brainfuck");
+  match compile_report.source_map.span(trap.instruction) {
+    Some(span) => report(Some(&format_args!("{path}:{}:{}", span.line, span.column)), Level::Error, message),
+    None => report(Some(&path), Level::Error, message),
+  }
+  Err(ExitCode::FAILURE)
+}
+
+/// Run the loaded program on stdin and stdout, reporting runtime errors
+fn run_checked(bf: &mut Brainfuck) -> Result<(), ExitCode> {
+  bf.run().map(drop).map_err(|error| match error {
+    RuntimeError::Io(error) => console::write_failure(&error),
+    error => {
+      diagnostics::error(error);
+      ExitCode::FAILURE
+    },
+  })
+}
+
+fn trap_failure(trap: Trap) -> ExitCode {
+  diagnostics::error(trap);
+  ExitCode::FAILURE
+}
+
+fn run_raw(path: &str) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  #[cfg(unix)]
+  let _raw = match console::RawMode::enable() {
+    Err(error) if error.kind() == io::ErrorKind::Unsupported => {
+      diagnostics::warning("raw terminal mode is not supported on this platform, running with line-buffered input");
+      None
+    },
+    raw => raw.map_err(|error| {
+      diagnostics::error(format_args!("failed to switch the terminal to raw mode: {error}"));
+      ExitCode::FAILURE
+    })?,
+  };
+  #[cfg(not(unix))]
+  diagnostics::warning("raw terminal mode is only supported on Unix, running with line-buffered input");
+  run_program(&program, Box::default(), true, &mut console::stdio());
+  Ok(())
+}
+
+fn run_record(path: &str, recording_path: &str) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  let mut io = RecordingIo::new(console::stdio());
+  run_program(&program, Box::default(), true, &mut io);
+  let (_, recording) = io.finish();
+  fs::write(recording_path, recording.to_bytes()).map_err(|error| {
+    diagnostics::error(format_args!("failed to write {recording_path}: {error}"));
+    ExitCode::FAILURE
+  })
+}
+
+fn run_replay(path: &str, recording_path: &str) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  let data = fs::read(recording_path).map_err(|error| {
+    diagnostics::error(format_args!("failed to read {recording_path}: {error}"));
+    ExitCode::FAILURE
+  })?;
+  let recording = Recording::from_bytes(&data).map_err(|error| {
+    diagnostics::error(format_args!("{recording_path}: {error}"));
+    ExitCode::FAILURE
+  })?;
+  let mut io = recording.replay();
+  run_program(&program, Box::default(), true, &mut io);
+  //The replayed output is shown either way, to help spot where the run went wrong
+  let _ = console::Stdout.write_all(io.output());
+  io.finish().map(|_| ()).map_err(|divergence| {
+    diagnostics::error(divergence);
+    report_config(Compilation::Optimized(compile_options()));
+    ExitCode::FAILURE
+  })
+}
+
+/// Print the configuration of a failed run as a reproduction string, so bug reports capture how it was run
+fn report_config(mut compilation: Compilation) {
+  //Comments are stripped by `read_source` rather than by the compiler
+  if let Compilation::Optimized(options) = &mut compilation {
+    options.comments = COMMENTS.get().copied().unwrap_or_default();
+  }
+  eprintln!("  configuration: {}", RunConfig { compilation, ..Default::default() });
+}
+
+fn run_tee(path: &str, tee_path: &str) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  let write_error = |error: io::Error| {
+    diagnostics::error(format_args!("failed to write {tee_path}: {error}"));
+    ExitCode::FAILURE
+  };
+  let file = fs::File::create(tee_path).map_err(write_error)?;
+  let mut io = TeeIo::new(console::stdio()).with_sink(io::BufWriter::new(file));
+  run_program(&program, Box::default(), true, &mut io);
+  io.flush().map_err(write_error)
+}
+
+/// Run a program, printing a live state dump on SIGUSR1
+fn run_program(program: &Program, mut state: Box<BrainfuckState>, packed: bool, io: &mut impl Io) {
+  //Programs too large for the packed form fall back to the enum interpreter
+  match program.pack().filter(|_| packed) {
+    Some(program) => run_packed(&program, state, io),
+    #[cfg(unix)]
+    None => {
+      let mut live = live::LiveDump::install();
+      brian::run_with_hooks(program, &mut state, io, &mut live);
+    },
+    #[cfg(not(unix))]
+    None => brian::run(program, &mut state, io),
+  }
+}
+
+/// Run the packed form of a program, printing a live state dump on SIGUSR1
+fn run_packed(program: &PackedProgram, mut state: Box<BrainfuckState>, io: &mut impl Io) {
+  #[cfg(unix)]
+  {
+    let mut live = live::LiveDump::install();
+    let mut position = brian::PackedPosition::default();
+    while program.resume(&mut state, io, live::SLICE, &mut position) != brian::SliceEnd::Halted {
+      live.slice_done(position.program_counter(), &state);
+    }
+  }
+  #[cfg(not(unix))]
+  program.run(&mut state, io);
+}
+
+fn run_profile_out(path: &str, profile_path: &str) -> Result<(), ExitCode> {
+  let mut bf = Brainfuck::new();
+  bf.load_program(load_program(path)?);
+  let profile = bf.run_profiled(console::Stdout).map_err(trap_failure)?;
+  fs::write(profile_path, profile.to_string()).map_err(|error| {
+    diagnostics::error(format_args!("failed to write {profile_path}: {error}"));
+    ExitCode::FAILURE
+  })
+}
+
+fn run_profile_in(path: &str, profile_path: &str) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  let Some(profile) = Profile::from_text(&read_source(profile_path)?) else {
+    diagnostics::error(format_args!("{profile_path} is not a valid profile"));
+    return Err(ExitCode::FAILURE)
+  };
+  if profile.counts.len() != program.opcodes().len() {
+    diagnostics::error(format_args!("{profile_path} was not recorded for {path}"));
+    return Err(ExitCode::FAILURE)
+  }
+  match program.recompile_with_profile(&profile, PROFILE_SUPERINSTRUCTIONS) {
+    Some(packed) => run_packed(&packed, Box::default(), &mut console::stdio()),
+    //Programs too large for the packed form fall back to the enum interpreter
+    None => run_program(&program, Box::default(), false, &mut console::stdio()),
+  }
+  Ok(())
+}
+
+fn run_cycles(path: &str) -> Result<(), ExitCode> {
+  let mut bf = Brainfuck::new();
+  bf.load_program(load_program(path)?);
+  let cycles = bf.run_with_cost(console::Stdout, &CostModel::default()).map_err(trap_failure)?;
+  eprintln!("virtual cycles: {cycles}");
+  Ok(())
+}
+
+fn run_explain(path: &str, throttle: Option<&str>) -> Result<(), ExitCode> {
+  let throttle = throttle.map(parse_throttle).transpose()?;
+  let code = read_source(path)?;
+  //Explanations should follow the source, so nothing is optimized away
+  let program = compile_unoptimized(path, &code)?;
+  let mut explainer = Explainer::new(|index, explanation| eprintln!("{index:>6}: {explanation}"));
+  let mut state = Box::new(BrainfuckState::new());
+  let mut io = console::stdio();
+  match throttle {
+    Some(throttle) => brian::run_with_hooks(&program, &mut state, &mut io, &mut (throttle, explainer)),
+    None => brian::run_with_hooks(&program, &mut state, &mut io, &mut explainer),
+  };
+  Ok(())
+}
+
+fn parse_throttle(instructions_per_second: &str) -> Result<Throttle, ExitCode> {
+  match instructions_per_second.parse() {
+    Ok(instructions_per_second) if instructions_per_second > 0 => Ok(Throttle::new(instructions_per_second)),
+    _ => {
+      diagnostics::error(format_args!("invalid instruction rate {instructions_per_second}"));
+      Err(ExitCode::FAILURE)
+    },
+  }
+}
+
+fn run_throttled(path: &str, instructions_per_second: &str) -> Result<(), ExitCode> {
+  let mut throttle = parse_throttle(instructions_per_second)?;
+  let program = load_program(path)?;
+  let mut state = Box::new(BrainfuckState::new());
+  brian::run_with_hooks(&program, &mut state, &mut console::stdio(), &mut throttle);
+  Ok(())
+}
+
+fn run_loop_limit(path: &str, max_iterations: &str) -> Result<(), ExitCode> {
+  let Ok(max_iterations) = max_iterations.parse() else {
+    diagnostics::error(format_args!("invalid iteration limit {max_iterations}"));
+    return Err(ExitCode::FAILURE)
+  };
+  let code = read_source(path)?;
+  //Unoptimized loops map to source loops, optimized ones may be gone entirely
+  let program = compile_unoptimized(path, &code)?;
+  let mut limit = LoopLimit::new(max_iterations);
+  let mut state = Box::new(BrainfuckState::new());
+  brian::run_with_hooks(&program, &mut state, &mut console::stdio(), &mut limit);
+  let Some(start) = limit.exceeded() else { return Ok(()) };
+  let span = LoopTree::from_source(&code)
+    .and_then(|tree| tree.nodes.into_iter().find(|x| x.body.start == start + 1))
+    .and_then(|node| node.span);
+  if let Some(span) = span {
+    let (line, column) = line_column(&code, span.start);
+    report(Some(&format_args!("{path}:{line}:{column}")), Level::Error, format_args!("loop exceeded {max_iterations} iterations"));
+  }
+  report_config(Compilation::Unoptimized);
+  Err(ExitCode::FAILURE)
+}
+
+fn run_break(path: &str, condition: &str) -> Result<(), ExitCode> {
+  let condition: Condition = condition.parse().map_err(|error| {
+    diagnostics::error(error);
+    ExitCode::FAILURE
+  })?;
+  let program = load_program(path)?;
+  let mut breakpoints = Breakpoints::new(vec![Breakpoint { program_counter: None, condition: Some(condition) }]);
+  let mut state = Box::new(BrainfuckState::new());
+  brian::run_with_hooks(&program, &mut state, &mut console::stdio(), &mut breakpoints);
+  if let Some((_, program_counter)) = breakpoints.hit() {
+    let pointer = state.pointer_address();
+    eprintln!("breakpoint: {condition} before opcode {program_counter}, pointer {pointer} (cell {})", state.memory[pointer]);
+  }
+  Ok(())
+}
+
+/// Run a program on a fresh state, returning the final state
+fn run_to_end(path: &str) -> Result<Box<BrainfuckState>, ExitCode> {
+  let program = load_program(path)?;
+  let mut state = Box::new(BrainfuckState::new());
+  brian::run(&program, &mut state, &mut console::stdio());
+  Ok(state)
+}
+
+fn run_find(path: &str, text: &str) -> Result<(), ExitCode> {
+  let state = run_to_end(path)?;
+  let addresses: Vec<String> = state.find_all(text.as_bytes()).take(FIND_MAX_RESULTS + 1).map(|x| x.to_string()).collect();
+  match addresses.len() {
+    0 => eprintln!("find: {text:?} is not on the tape"),
+    count if count > FIND_MAX_RESULTS => {
+      eprintln!("find: {text:?} at {}, ...", addresses[..FIND_MAX_RESULTS].join(", "));
+    },
+    _ => eprintln!("find: {text:?} at {}", addresses.join(", ")),
+  }
+  Ok(())
+}
+
+fn run_count(path: &str, value: &str) -> Result<(), ExitCode> {
+  let Ok(value) = value.parse() else {
+    diagnostics::error(format_args!("invalid cell value {value}"));
+    return Err(ExitCode::FAILURE)
+  };
+  let state = run_to_end(path)?;
+  eprintln!("count: {} cells hold {value}", state.count(value));
+  Ok(())
+}
+
+fn run_strict(path: &str) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  //Traps are only exact without optimizations, which also keeps opcodes mapped to source commands
+  let program = compile_unoptimized(path, &code)?;
+  let config = InterpreterConfig { overflow: OverflowPolicy::Trap, bounds: BoundsPolicy::Trap, ..Default::default() };
+  let mut state = Box::new(BrainfuckState::new());
+  let mut io = console::stdio();
+  let Err(context) = config.run_with_context(&program, &mut state, &mut io, None) else { return Ok(()) };
+  let _ = io.output.flush();
+  match context.source_span(&code) {
+    Some(span) => {
+      let (line, column) = line_column(&code, span.start);
+      report(Some(&format_args!("{path}:{line}:{column}")), Level::Error, context.trap);
+    },
+    None => report(Some(&path), Level::Error, context.trap),
+  }
+  //The first line repeats the trap
+  for line in context.to_string().lines().skip(1) {
+    eprintln!("  {line}");
+  }
+  report_config(Compilation::Unoptimized);
+  Err(ExitCode::FAILURE)
+}
+
+fn run_pointer_history(path: &str, capacity: &str) -> Result<(), ExitCode> {
+  let Ok(capacity) = capacity.parse() else {
+    diagnostics::error(format_args!("invalid history length {capacity}"));
+    return Err(ExitCode::FAILURE)
+  };
+  let program = load_program(path)?;
+  let mut history = PointerHistory::new(capacity);
+  let mut state = Box::new(BrainfuckState::new());
+  brian::run_with_hooks(&program, &mut state, &mut console::stdio(), &mut history);
+  eprintln!("pointer history: last {} of {} moves", history.moves().len(), history.total());
+  for step in history.moves() {
+    eprintln!("  opcode {:>6} -> cell {}", step.program_counter, step.address);
+  }
+  //Sample the visited range evenly, so even scans across the whole tape fit in the dump
+  if let Some((low, high)) = history.bounds() {
+    let stride = (high - low) / (POINTER_DUMP_ROWS * 16) + 1;
+    eprintln!("tape from cell {low}, every {stride} cells:");
+    eprint!("{}", state.dump_strided(low, stride, POINTER_DUMP_ROWS));
+  }
+  Ok(())
+}
+
+fn run_nesting(path: &str) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  //Unoptimized loops map to source loops, optimized ones may be gone entirely
+  let program = compile_unoptimized(path, &code)?;
+  let mut recorder = RunStatsRecorder::new();
+  let mut state = Box::new(BrainfuckState::new());
+  brian::run_with_hooks(&program, &mut state, &mut console::stdio(), &mut recorder);
+  let stats = recorder.finish();
+  eprintln!("max loop nesting: {}", stats.max_nesting_depth);
+  for (depth, iterations) in stats.iterations_per_depth.iter().enumerate() {
+    eprintln!("  depth {:>3}: {iterations} iterations", depth + 1);
+  }
+  Ok(())
+}
+
+fn run_sanitize(path: &str) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  //The optimizer merges and reorders the operations being checked, and unoptimized opcodes map to source commands
+  let program = compile_unoptimized(path, &code)?;
+  let offsets: Vec<usize> = code.char_indices().filter(|(_, x)| "+-<>[].,".contains(*x)).map(|(offset, _)| offset).collect();
+  let mut findings = 0;
+  let mut sanitizer = Sanitizer::new(|finding: Finding| {
+    findings += 1;
+    let (line, column) = line_column(&code, offsets[finding.instruction]);
+    let message = match finding.kind {
+      FindingKind::PointerWraparound => format!("{} (from cell {})", finding.kind, finding.address),
+      _ => format!("{} (cell {})", finding.kind, finding.address),
+    };
+    report(Some(&format_args!("{path}:{line}:{column}")), Level::Warning, message);
+  });
+  let mut state = Box::new(BrainfuckState::new());
+  brian::run_with_hooks(&program, &mut state, &mut console::stdio(), &mut sanitizer);
+  match findings {
+    0 => Ok(()),
+    _ => Err(ExitCode::FAILURE),
+  }
+}
+
+fn run_timeline(path: &str, timeline_path: &str) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  let mut recorder = TimelineRecorder::new(TIMELINE_MAX_EVENTS);
+  let mut state = Box::new(BrainfuckState::new());
+  brian::run_with_hooks(&program, &mut state, &mut console::stdio(), &mut recorder);
+  let timeline = recorder.finish();
+  if timeline.truncated {
+    diagnostics::warning(format_args!("timeline truncated to {TIMELINE_MAX_EVENTS} events"));
+  }
+  fs::write(timeline_path, timeline.to_json()).map_err(|error| {
+    diagnostics::error(format_args!("failed to write {timeline_path}: {error}"));
+    ExitCode::FAILURE
+  })
+}
+
+fn run_verify(path: &str) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  let program = compile_source(path, &code, &compile_options())?;
+  //Record the input once, so that both runs see the same bytes
+  let mut input = Vec::new();
+  if let Err(error) = io::stdin().read_to_end(&mut input) {
+    diagnostics::error(format_args!("failed to read input: {error}"));
+    return Err(ExitCode::FAILURE)
+  }
+  match stress::verify(&code, &input, usize::MAX) {
+    Verification::Equal => {
+      let mut bf = Brainfuck::new();
+      bf.load_program(program);
+      let mut output = Vec::new();
+      bf.run_with_io(&input[..], &mut output, usize::MAX).map_err(trap_failure)?;
+      let _ = console::Stdout.write_all(&output);
+      eprintln!("verify: optimized and reference runs match");
+      Ok(())
+    },
+    Verification::Inconclusive => unreachable!("runs are unlimited"),
+    Verification::Diverged(mismatch) => {
+      match &mismatch.span {
+        Some(span) => {
+          let (line, column) = line_column(&code, span.start);
+          report(Some(&format_args!("{path}:{line}:{column}")), Level::Error, "optimizer divergence");
+        },
+        None => report(Some(&path), Level::Error, "optimizer divergence"),
+      }
+      eprintln!("{mismatch}");
+      Err(ExitCode::FAILURE)
+    },
+  }
+}
+
+fn compile(path: &str, options: &[&str]) -> Result<(), ExitCode> {
+  let (mut output_path, mut emit, mut metadata) = (None, "bytecode", Vec::new());
+  for pair in options.chunks(2) {
+    match *pair {
+      ["-o", path] => output_path = Some(path),
+      ["--emit", kind] => emit = kind,
+      ["--meta", entry] => {
+        let Some((key, value)) = entry.split_once('=') else {
+          diagnostics::error(format_args!("invalid metadata entry {entry}, expected <KEY>=<VALUE>"));
+          return Err(ExitCode::FAILURE)
+        };
+        metadata.push((key, value));
+      },
+      _ => {
+        eprintln!("{USAGE}");
+        return Err(ExitCode::FAILURE)
+      },
+    }
+  }
+  let code = read_source(path)?;
+  warn_lints(path, &code);
+  let options = compile_options();
+  let program = || -> Result<Program, ExitCode> {
+    let mut program = compile_source(path, &code, &options)?;
+    for &(key, value) in &metadata {
+      program.metadata_mut().insert(key.to_string(), value.to_string());
+    }
+    Ok(program)
+  };
+  let listing = |ops: &[Opcode]| -> String {
+    ops.iter().enumerate().map(|(index, op)| format!("{index:>6}: {op:?}\n")).collect()
+  };
+  let artifact = match emit {
+    "tokens" => brainfuck_tokens(&code).map(|(token, span)| {
+      format!("{}:{} {} {token:?}\n", span.line, span.column, token.as_char())
+    }).collect::<String>().into_bytes(),
+    //Unoptimized opcodes map one-to-one to commands, indented by loop nesting
+    "ast" => {
+      let pipeline = CompilePipeline::try_parse(&code, &options).map_err(|error| compile_error(path, &code, error))?.link();
+      let mut depth = 0;
+      pipeline.opcodes().iter().enumerate().map(|(index, op)| {
+        depth -= matches!(op, Opcode::LoopEnd(_)) as usize;
+        let line = format!("{index:>6}: {}{op:?}\n", "  ".repeat(depth));
+        depth += matches!(op, Opcode::LoopStart(_)) as usize;
+        line
+      }).collect::<String>().into_bytes()
+    },
+    "ir" => listing(program()?.opcodes()).into_bytes(),
+    "bytecode" => program()?.to_bytecode(),
+    "c" => translate::to_c(&program()?).into_bytes(),
+    "rust" => translate::to_rust(&program()?).into_bytes(),
+    "wasm" => translate::to_wasm(&program()?),
+    _ => {
+      diagnostics::error(format_args!("unknown artifact {emit}, expected tokens, ast, ir, bytecode, c, rust or wasm"));
+      return Err(ExitCode::FAILURE)
+    },
+  };
+  match output_path {
+    Some(output_path) => fs::write(output_path, artifact).map_err(|error| {
+      diagnostics::error(format_args!("failed to write {output_path}: {error}"));
+      ExitCode::FAILURE
+    }),
+    None => io::stdout().write_all(&artifact).map_err(|error| {
+      diagnostics::error(format_args!("failed to write the output: {error}"));
+      ExitCode::FAILURE
+    }),
+  }
+}
+
+fn dump(path: &str) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  for (key, value) in program.metadata() {
+    println!("{key}: {value}");
+  }
+  if !program.metadata().is_empty() {
+    println!();
+  }
+  for (index, op) in program.opcodes().iter().enumerate() {
+    println!("{index:>6}: {op:?}");
+  }
+  Ok(())
+}
+
+fn stats(path: &str) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  let unoptimized = compile_unoptimized(path, &code)?.metrics();
+  let optimized = compile_source(path, &code, &CompileOptions::default())?.metrics();
+  let row = |name: &str, field: fn(&ProgramMetrics) -> usize| {
+    println!("{name:<24}{:>12}{:>12}", field(&unoptimized), field(&optimized));
+  };
+  println!("{:<24}{:>12}{:>12}", "", "unoptimized", "optimized");
+  row("length", |x| x.length);
+  row("loops", |x| x.loop_count);
+  row("max nesting depth", |x| x.max_nesting_depth);
+  row("memory footprint", |x| x.memory_footprint);
+  println!("{:<24}{:>12}{:>12}", "tape footprint", unoptimized.tape_footprint.to_string(), optimized.tape_footprint.to_string());
+  let kinds = unoptimized.opcode_frequencies.keys().chain(optimized.opcode_frequencies.keys());
+  let kinds: BTreeSet<_> = kinds.collect();
+  for kind in kinds {
+    let count = |x: &ProgramMetrics| x.opcode_frequencies.get(kind).copied().unwrap_or(0);
+    println!("{:<24}{:>12}{:>12}", format!("  {kind}"), count(&unoptimized), count(&optimized));
+  }
+  Ok(())
+}
+
+fn discover(path: &str, profile_path: &str) -> Result<(), ExitCode> {
+  let program = load_program(path)?;
+  let Some(profile) = Profile::from_text(&read_source(profile_path)?) else {
+    diagnostics::error(format_args!("{profile_path} is not a valid profile"));
+    return Err(ExitCode::FAILURE)
+  };
+  let Some(candidates) = profile.superinstruction_candidates(&program, 4) else {
+    diagnostics::error(format_args!("{profile_path} was not recorded for {path}"));
+    return Err(ExitCode::FAILURE)
+  };
+  println!("{:<56}{:>8}{:>16}{:>10}", "pattern", "sites", "saved", "share");
+  for candidate in candidates.iter().take(10) {
+    let pattern = candidate.pattern.join(" ");
+    let share = format!("{:.1}%", candidate.saved_fraction * 100.);
+    println!("{pattern:<56}{:>8}{:>16}{share:>10}", candidate.sites, candidate.saved_dispatches);
+  }
+  Ok(())
+}
+
+fn reduce(path: &str, command: &[&str]) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  let candidate_path = env::temp_dir().join(format!("brian-reduce-{}.b", std::process::id()));
+  let interesting = |code: &str| {
+    fs::write(&candidate_path, code).is_ok() && Command::new(command[0])
+      .args(&command[1..])
+      .arg(&candidate_path)
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .status()
+      .is_ok_and(|status| status.success())
+  };
+  if !interesting(&code) {
+    let _ = fs::remove_file(&candidate_path);
+    diagnostics::error(format_args!("{} does not succeed for the original program", command.join(" ")));
+    return Err(ExitCode::FAILURE)
+  }
+  let reduced = stress::shrink(&code, interesting);
+  let _ = fs::remove_file(&candidate_path);
+  println!("{reduced}");
+  Ok(())
+}
+
+fn test(dir: &str, json_path: Option<&str>) -> Result<(), ExitCode> {
+  let summary = corpus::run_corpus(dir.as_ref(), &CorpusOptions::default()).map_err(|error| {
+    diagnostics::error(format_args!("failed to read {dir}: {error}"));
+    ExitCode::FAILURE
+  })?;
+  for result in &summary.results {
+    match &result.status {
+      Status::Passed => (),
+      Status::Failed => eprintln!("{} {}", paint(Level::Error, "FAIL    "), result.path.display()),
+      Status::TimedOut => eprintln!("{} {}", paint(Level::Warning, "TIMEOUT "), result.path.display()),
+      Status::Error(message) => eprintln!("{} {}: {message}", paint(Level::Error, "ERROR   "), result.path.display()),
+    }
+  }
+  println!(
+    "{} passed, {} failed, {} timed out, {} errors ({:.2}s)",
+    summary.passed, summary.failed, summary.timed_out, summary.errors, summary.duration.as_secs_f64()
+  );
+  if let Some(json_path) = json_path {
+    fs::write(json_path, summary.to_json()).map_err(|error| {
+      diagnostics::error(format_args!("failed to write {json_path}: {error}"));
+      ExitCode::FAILURE
+    })?;
+  }
+  if !summary.success() {
+    return Err(ExitCode::FAILURE)
+  }
+  Ok(())
+}
+
+fn dbfi(path: &str) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  let mut bf = Brainfuck::new();
+  bf.compile_with_options(brian::programs::DBFI.source, &compile_options()).expect("bundled programs are balanced");
+  let input = io::Cursor::new(dbfi_input(&code)).chain(io::stdin());
+  bf.run_with_io(input, console::Stdout, usize::MAX).map_err(trap_failure)?;
+  Ok(())
+}
+
+fn diff(path_a: &str, path_b: &str) -> Result<(), ExitCode> {
+  let (code_a, code_b) = (read_source(path_a)?, read_source(path_b)?);
+  //Record the input once, so that both runs see the same bytes
+  let mut input = Vec::new();
+  if let Err(error) = io::stdin().read_to_end(&mut input) {
+    diagnostics::error(format_args!("failed to read input: {error}"));
+    return Err(ExitCode::FAILURE)
+  }
+  let Some(difference) = diff::diff(&code_a, &code_b, &input, DIFF_FUEL) else {
+    diagnostics::error("brackets are unbalanced, programs can't be compared");
+    return Err(ExitCode::FAILURE)
+  };
+  if let Some(commands) = difference.commands {
+    let location = |path: &str, code: &str, offset: Option<usize>| match offset {
+      Some(offset) => {
+        let (line, column) = line_column(code, offset);
+        format!("{path}:{line}:{column}")
+      },
+      None => format!("{path} (end)"),
+    };
+    println!(
+      "commands: first difference at {} / {}",
+      location(path_a, &code_a, commands.offsets.0), location(path_b, &code_b, commands.offsets.1)
+    );
+  }
+  for line in difference.to_string().lines().skip(difference.commands.is_some() as usize) {
+    println!("{line}");
+  }
+  match difference.behavior {
+    Behavior::Diverged { .. } => Err(ExitCode::FAILURE),
+    _ => Ok(()),
+  }
+}
+
+fn pipe(paths: &[&str], fuel: Option<&str>) -> Result<(), ExitCode> {
+  let fuel = match fuel.map(str::parse) {
+    None => usize::MAX,
+    Some(Ok(fuel)) => fuel,
+    Some(Err(_)) => {
+      diagnostics::error(format_args!("invalid fuel {}", fuel.unwrap()));
+      return Err(ExitCode::FAILURE)
+    },
+  };
+  let programs = paths.iter().map(|path| load_program(path)).collect::<Result<Vec<_>, _>>()?;
+  match run_pipeline(&programs, io::stdin(), console::Stdout, fuel) {
+    PipelineOutcome::Halted => Ok(()),
+    PipelineOutcome::OutOfFuel => {
+      diagnostics::error(format_args!("pipeline ran out of fuel after {fuel} instructions"));
+      Err(ExitCode::FAILURE)
+    },
+  }
+}
+
+fn doc(path: &str) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  print!("{}", doc::document(path, &code));
+  Ok(())
+}
+
+fn check(path: &str, strict: bool) -> Result<(), ExitCode> {
+  let code = read_source(path)?;
+  let mut options = LINTS.get().cloned().unwrap_or_default();
+  options.strict |= strict;
+  report_lints(path, &code, &options)
+}
+
+fn main() -> ExitCode {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+  //Global options, set at most once each (except for lint levels, later ones taking precedence)
+  let mut lints: Option<LintOptions> = None;
+  loop {
+    match args[..] {
+      ["--time-passes", ..] => {
+        TIME_PASSES.store(true, Ordering::Relaxed);
+        args.remove(0);
+        continue
+      },
+      ["--comments", style, ..] => match style.parse() {
+        Ok(style) => {
+          let _ = COMMENTS.set(style);
+        },
+        Err(error) => {
+          diagnostics::error(error);
+          return ExitCode::FAILURE
+        },
+      },
+      ["--brackets", mode, ..] => match mode.parse() {
+        Ok(mode) => {
+          let _ = BRACKETS.set(mode);
+        },
+        Err(error) => {
+          diagnostics::error(error);
+          return ExitCode::FAILURE
+        },
+      },
+      ["--flush-char", character, ..] => match character.parse() {
+        Ok(character) => {
+          let _ = FLUSH_CHAR.set(character);
+        },
+        Err(_) => {
+          diagnostics::error(format_args!("expected a single flush character, got `{character}`"));
+          return ExitCode::FAILURE
+        },
+      },
+      [flag @ ("-A" | "-W" | "-D"), name, ..] => {
+        let level = match flag {
+          "-A" => LintLevel::Allow,
+          "-W" => LintLevel::Warn,
+          _ => LintLevel::Deny,
+        };
+        //`warnings` sets the level of every lint, like in rustc
+        let selected = match name {
+          "warnings" => Lint::ALL.to_vec(),
+          name => match name.parse() {
+            Ok(lint) => vec![lint],
+            Err(error) => {
+              diagnostics::error(error);
+              return ExitCode::FAILURE
+            },
+          },
+        };
+        let lints = lints.get_or_insert_with(LintOptions::default);
+        lints.levels.extend(selected.into_iter().map(|lint| (lint, level)));
+      },
+      _ => break,
+    }
+    args.drain(..2);
+  }
+  if let Some(lints) = lints {
+    let _ = LINTS.set(lints);
+  }
+  let result = match args[..] {
+    [] | ["bench"] => return bench(),
+    ["bench", "--dispatch"] => return bench_dispatch(),
+    ["run", path] => run(path, true),
+    ["run", path, "--unpacked"] => run(path, false),
+    ["run", path, "--tee", tee] => run_tee(path, tee),
+    ["run", path, "--profile-out", profile] => run_profile_out(path, profile),
+    ["run", path, "--profile-in", profile] => run_profile_in(path, profile),
+    ["run", path, "--verify"] => run_verify(path),
+    ["run", path, "--cycles"] => run_cycles(path),
+    ["run", path, "--nesting"] => run_nesting(path),
+    ["run", path, "--pointer-history", capacity] => run_pointer_history(path, capacity),
+    ["run", path, "--strict"] => run_strict(path),
+    ["run", path, "--sanitize"] => run_sanitize(path),
+    ["run", path, "--explain"] => run_explain(path, None),
+    ["run", path, "--explain", "--throttle", rate] => run_explain(path, Some(rate)),
+    ["run", path, "--throttle", rate] => run_throttled(path, rate),
+    ["run", path, "--timeline", timeline] => run_timeline(path, timeline),
+    ["run", path, "--max-loop-iterations", max_iterations] => run_loop_limit(path, max_iterations),
+    ["run", path, "--break", condition] => run_break(path, condition),
+    ["run", path, "--find", text] => run_find(path, text),
+    ["run", path, "--count", value] => run_count(path, value),
+    ["run", path, "--tape", pattern] => run_tape(path, pattern),
+    ["run", path, "--eof", mode] => run_eof(path, mode),
+    ["run", path, "--bounds", policy] => run_bounds(path, policy),
+    ["run", path, "--compat", preset] => run_compat(path, preset),
+    ["run", path, "--raw"] => run_raw(path),
+    ["run", path, "--record", recording] => run_record(path, recording),
+    ["run", path, "--replay", recording] => run_replay(path, recording),
+    ["compile", "-o", output, path] => compile(path, &["-o", output]),
+    ["compile", path, ref options @ ..] => compile(path, options),
+    ["dump", path] => dump(path),
+    ["stats", path] => stats(path),
+    ["discover", path, profile] => discover(path, profile),
+    ["reduce", path, ref command @ ..] if !command.is_empty() => reduce(path, command),
+    ["test", dir] => test(dir, None),
+    ["test", dir, "--json", json] => test(dir, Some(json)),
+    ["check", path] => check(path, false),
+    ["check", path, "--strict"] | ["check", "--strict", path] => check(path, true),
+    ["dbfi", path] => dbfi(path),
+    ["repl"] => repl::repl(None),
+    ["repl", session] => repl::repl(Some(session)),
+    ["pipe", "--fuel", fuel, ref paths @ ..] if !paths.is_empty() => pipe(paths, Some(fuel)),
+    ["pipe", ref paths @ ..] if !paths.is_empty() => pipe(paths, None),
+    ["doc", path] => doc(path),
+    ["diff", path_a, path_b] => diff(path_a, path_b),
+    _ => {
+      eprintln!("{USAGE}");
+      return ExitCode::FAILURE
+    }
+  };
+  match result {
+    //Output closed early stops the program cleanly, but still fails the command
+    Ok(()) => console::output_status(),
+    Err(code) => code,
+  }
 }
@@ -0,0 +1,148 @@
+//! Interactive sessions, entering a program piece by piece on a persistent tape
+//!
+//! Sessions are saved as the entered source history followed by a snapshot of the interpreter state,
+//! so loading one continues exactly where it left off without replaying the history
+
+use std::{fs, io::{self, BufRead, Write}, process::ExitCode};
+use brian::{brainfuck_tokens, BrainfuckState, Program, Token};
+use crate::{console, diagnostics};
+
+/// Magic bytes at the start of every session file
+const MAGIC: &[u8; 4] = b"BFSN";
+/// Number of cells shown on each side of the pointer by `:tape`
+const TAPE_WINDOW: usize = 8;
+
+const HELP: &str = "\
+Enter brainfuck code to run it on the session tape, loops may span several lines
+  :save <PATH>  Save the history and state to a session file
+  :load <PATH>  Replace the session with one loaded from a file
+  :history      Print the code entered so far
+  :tape         Print the cells around the pointer
+  :reset        Clear the history and state
+  :quit         Leave (also on EOF)";
+
+#[derive(Default)]
+struct Session {
+  history: Vec<String>,
+  state: Box<BrainfuckState>,
+}
+impl Session {
+  fn save(&self, path: &str) -> io::Result<()> {
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&(self.history.len() as u32).to_le_bytes());
+    for entry in &self.history {
+      data.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+      data.extend_from_slice(entry.as_bytes());
+    }
+    data.extend_from_slice(&self.state.snapshot());
+    fs::write(path, data)
+  }
+
+  fn load(path: &str) -> Result<Self, String> {
+    let data = fs::read(path).map_err(|error| error.to_string())?;
+    let mut rest = data.strip_prefix(MAGIC).ok_or("not a brian session file")?;
+    let mut session = Self::default();
+    for _ in 0..take_length(&mut rest)? {
+      let length = take_length(&mut rest)?;
+      let (entry, tail) = rest.split_at_checked(length).ok_or(TRUNCATED)?;
+      session.history.push(String::from_utf8(entry.to_vec()).map_err(|_| "history is not valid UTF-8")?);
+      rest = tail;
+    }
+    session.state.restore(rest).map_err(|error| error.to_string())?;
+    Ok(session)
+  }
+
+  /// Run one complete entry on the session tape
+  fn run(&mut self, code: String) {
+    //The tape is dirty, so the default options (not assuming a zeroed tape) are required
+    let Ok(program) = Program::compile(&code) else { unreachable!("only balanced entries are run") };
+    let mut io = console::stdio();
+    brian::run(&program, &mut self.state, &mut io);
+    let _ = io.output.flush();
+    self.history.push(code);
+  }
+
+  fn print_tape(&self) {
+    let pointer = self.state.pointer_address();
+    let start = pointer.saturating_sub(TAPE_WINDOW);
+    let end = (pointer + TAPE_WINDOW + 1).min(self.state.tape().len());
+    let cells: Vec<String> = (start..end).map(|address| match address == pointer {
+      true => format!("[{}]", self.state.tape()[address]),
+      false => self.state.tape()[address].to_string(),
+    }).collect();
+    eprintln!("pointer {pointer}, cells {start}..{end}: {}", cells.join(" "));
+  }
+}
+
+const TRUNCATED: &str = "unexpected end of session file";
+
+/// Read a little-endian `u32` length from the start of `data`, advancing past it
+fn take_length(data: &mut &[u8]) -> Result<usize, &'static str> {
+  let (length, rest) = data.split_first_chunk::<4>().ok_or(TRUNCATED)?;
+  *data = rest;
+  Ok(u32::from_le_bytes(*length) as usize)
+}
+
+/// Loop depth of `code`, or `None` if a `]` has no matching `[`
+fn depth(code: &str) -> Option<usize> {
+  brainfuck_tokens(code).try_fold(0usize, |depth, (token, _)| match token {
+    Token::LoopStart => Some(depth + 1),
+    Token::LoopEnd => depth.checked_sub(1),
+    _ => Some(depth),
+  })
+}
+
+/// Run the interactive loop, loading `session_path` first if it exists and saving to it on exit
+pub fn repl(session_path: Option<&str>) -> Result<(), ExitCode> {
+  let mut session = match session_path.filter(|path| fs::exists(path).unwrap_or(false)) {
+    Some(path) => Session::load(path).map_err(|error| {
+      diagnostics::error(format_args!("failed to load {path}: {error}"));
+      ExitCode::FAILURE
+    })?,
+    None => Session::default(),
+  };
+  eprintln!("{HELP}");
+  let mut pending = String::new();
+  loop {
+    eprint!("{}", if pending.is_empty() { "> " } else { ".. " });
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line).map_or(true, |length| length == 0) {
+      break
+    }
+    match line.trim().split_once(' ').unwrap_or((line.trim(), "")) {
+      (":quit", _) => break,
+      (":help", _) => eprintln!("{HELP}"),
+      (":history", _) => session.history.iter().for_each(|entry| eprint!("{entry}")),
+      (":tape", _) => session.print_tape(),
+      (":reset", _) => session = Session::default(),
+      (":save", path) if !path.is_empty() => {
+        if let Err(error) = session.save(path) {
+          diagnostics::error(format_args!("failed to write {path}: {error}"));
+        }
+      },
+      (":load", path) if !path.is_empty() => match Session::load(path) {
+        Ok(loaded) => session = loaded,
+        Err(error) => diagnostics::error(format_args!("failed to load {path}: {error}")),
+      },
+      (command, _) if command.starts_with(':') => diagnostics::error(format_args!("unknown command {command}, try :help")),
+      _ => {
+        pending.push_str(&line);
+        match depth(&pending) {
+          Some(0) => session.run(std::mem::take(&mut pending)),
+          Some(_) => (),
+          None => {
+            diagnostics::error("unmatched `]`, entry discarded");
+            pending.clear();
+          },
+        }
+      },
+    }
+  }
+  match session_path {
+    Some(path) => session.save(path).map_err(|error| {
+      diagnostics::error(format_args!("failed to write {path}: {error}"));
+      ExitCode::FAILURE
+    }),
+    None => Ok(()),
+  }
+}
@@ -0,0 +1,169 @@
+//! Interpreter micro-benchmarks on synthetic programs, each isolating one part of the interpreter
+//!
+//! Run with `cargo bench --bench dispatch`. Rates are printed as `name rate unit` lines (higher is better):
+//! - `--save <PATH>` additionally writes them to a baseline file
+//! - `--baseline <PATH>` compares them against a baseline file and fails if any rate dropped by more than
+//!   `--threshold <PERCENT>` (10 by default), for use as a CI regression gate
+
+use std::{collections::BTreeMap, env, fs, hint::black_box, process::ExitCode, time::{Duration, Instant}};
+use brian::{BrainfuckState, Io, Program};
+
+/// Best of this many runs is reported, to filter out scheduling noise
+const RUNS: usize = 5;
+/// Instructions executed by each run of the dispatch benchmarks
+const DISPATCH_FUEL: usize = 50_000_000;
+/// Bytes written by each run of the output benchmark
+const OUTPUT_BYTES: usize = 20_000_000;
+/// Scans done by each run of the scan benchmark
+const SCANS: usize = 2_000;
+/// Default allowed drop of a rate against the baseline, in percent
+const DEFAULT_THRESHOLD: f64 = 10.;
+
+/// Name, rate unit and function measuring the rate of a benchmark
+type Benchmark = (&'static str, &'static str, fn() -> f64);
+
+/// [`Io`] counting output bytes without storing them
+struct CountingIo(usize);
+impl Io for CountingIo {
+  fn input(&mut self) -> Option<u8> {
+    None
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    black_box(byte);
+    self.0 += 1;
+  }
+}
+
+/// Fastest of [`RUNS`] runs of `run`, which is given a fresh state every time
+fn best(mut run: impl FnMut(&mut BrainfuckState)) -> Duration {
+  let mut state = Box::new(BrainfuckState::new());
+  (0..RUNS).map(|_| {
+    state.reset();
+    let start = Instant::now();
+    run(&mut state);
+    start.elapsed()
+  }).min().unwrap()
+}
+
+/// Dispatch overhead: an endless loop of unoptimized single-cell instructions, stopped by fuel
+fn dispatch_enum() -> f64 {
+  let program = Program::compile_without_optimizations("+[>+<+-]").unwrap();
+  let time = best(|state| {
+    brian::run_with_fuel(&program, state, &mut CountingIo(0), DISPATCH_FUEL);
+  });
+  DISPATCH_FUEL as f64 / time.as_secs_f64()
+}
+
+/// Dispatch overhead of the packed form, on the same program as [`dispatch_enum`]
+fn dispatch_packed() -> f64 {
+  let program = Program::compile_without_optimizations("+[>+<+-]").unwrap().pack().unwrap();
+  let time = best(|state| {
+    program.resume(state, &mut CountingIo(0), DISPATCH_FUEL, &mut Default::default());
+  });
+  DISPATCH_FUEL as f64 / time.as_secs_f64()
+}
+
+/// ScanZero throughput: scans over a tape with a single zero cell at its end, in cells per second
+fn scan_zero() -> f64 {
+  let program = Program::compile("[>]<[<]>").unwrap();
+  let cells = BrainfuckState::new().tape().len();
+  let time = best(|state| {
+    for _ in 0..SCANS {
+      state.tape_mut().fill(1);
+      state.tape_mut()[cells - 1] = 0;
+      state.pointer = 0;
+      brian::run(&program, state, &mut CountingIo(0));
+    }
+  });
+  //Every scan goes right to the last cell, then left across the whole tape
+  (SCANS * 2 * cells) as f64 / time.as_secs_f64()
+}
+
+/// Output throughput: an endless loop printing a cell, stopped by fuel
+fn output() -> f64 {
+  let program = Program::compile("+[.]").unwrap();
+  let mut written = 0;
+  let time = best(|state| {
+    let mut io = CountingIo(0);
+    //Every iteration is an Output and a LoopEnd
+    brian::run_with_fuel(&program, state, &mut io, OUTPUT_BYTES * 2);
+    written = io.0;
+  });
+  written as f64 / time.as_secs_f64()
+}
+
+/// Parse a file of `name rate` lines
+fn parse_baseline(data: &str) -> Option<BTreeMap<String, f64>> {
+  data.lines().filter(|line| !line.trim().is_empty()).map(|line| {
+    let mut fields = line.split_whitespace();
+    Some((fields.next()?.to_string(), fields.next()?.parse().ok()?))
+  }).collect()
+}
+
+fn main() -> ExitCode {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let args: Vec<&str> = args.iter().map(String::as_str).collect();
+  //Cargo passes `--bench` to every bench target
+  let args: Vec<&str> = args.into_iter().filter(|&x| x != "--bench").collect();
+  let (save, baseline, threshold) = match args[..] {
+    [] => (None, None, DEFAULT_THRESHOLD),
+    ["--save", path] => (Some(path), None, DEFAULT_THRESHOLD),
+    ["--baseline", path] => (None, Some(path), DEFAULT_THRESHOLD),
+    ["--baseline", path, "--threshold", threshold] => match threshold.parse() {
+      Ok(threshold) => (None, Some(path), threshold),
+      Err(_) => {
+        eprintln!("invalid threshold {threshold}");
+        return ExitCode::FAILURE
+      },
+    },
+    _ => {
+      eprintln!("usage: dispatch [--save <PATH> | --baseline <PATH> [--threshold <PERCENT>]]");
+      return ExitCode::FAILURE
+    },
+  };
+  let benchmarks: [Benchmark; 4] = [
+    ("dispatch_enum", "instructions/s", dispatch_enum),
+    ("dispatch_packed", "instructions/s", dispatch_packed),
+    ("scan_zero", "cells/s", scan_zero),
+    ("output", "bytes/s", output),
+  ];
+  let rates: BTreeMap<String, f64> = benchmarks.iter().map(|&(name, unit, benchmark)| {
+    let rate = benchmark();
+    println!("{name:<16} {rate:>16.0} {unit}");
+    (name.to_string(), rate)
+  }).collect();
+  if let Some(path) = save {
+    let data: String = rates.iter().map(|(name, rate)| format!("{name} {rate:.0}\n")).collect();
+    if let Err(error) = fs::write(path, data) {
+      eprintln!("failed to write {path}: {error}");
+      return ExitCode::FAILURE
+    }
+  }
+  let Some(path) = baseline else { return ExitCode::SUCCESS };
+  let baseline = match fs::read_to_string(path).map(|x| parse_baseline(&x)) {
+    Ok(Some(baseline)) => baseline,
+    Ok(None) => {
+      eprintln!("failed to parse {path}");
+      return ExitCode::FAILURE
+    },
+    Err(error) => {
+      eprintln!("failed to read {path}: {error}");
+      return ExitCode::FAILURE
+    },
+  };
+  let mut regressed = false;
+  for (name, rate) in &rates {
+    let Some(&expected) = baseline.get(name) else { continue };
+    let change = (rate / expected - 1.) * 100.;
+    if change < -threshold {
+      eprintln!("{name} regressed by {:.1}% ({rate:.0} against {expected:.0})", -change);
+      regressed = true;
+    }
+  }
+  match regressed {
+    true => ExitCode::FAILURE,
+    false => ExitCode::SUCCESS,
+  }
+}
@@ -0,0 +1,3 @@
+Hello World program from the Wikipedia article on brainf*** (licensed under CC BY SA)
+
+++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.
@@ -0,0 +1,4 @@
+ROT13 filter from the Wikipedia article on brainf*** (licensed under CC BY SA)
+Expects EOF to leave the cell unchanged or to set it to zero
+
+-,+[-[>>++++[>++++++++<-]<+<-[>+>+>-[>>>]<[[>+<-]>>+>]<<<<<-]]>>>[-]+>--[-[<->+++[-]]]<[++++++++++++<[>-[>+>>]>[+[<+>-]>+>>]<<<<<-]>>[<+>-]>[-[-<<[-]>>]<<[<<->>-]>>]<<[<<+>>-]]<[-]<.[-]<-,+]
@@ -1,10 +1,80 @@
-use std::collections::HashMap;
+use std::{collections::BTreeMap, fmt, ops::Range, slice, sync::Arc, time::Duration};
+use crate::lint::{Diagnostic, LintOptions};
 
 pub mod interpreter;
+mod accel;
+mod analysis;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod brackets;
+mod buffered;
+mod bytecode;
+mod canonical;
+mod comments;
+mod config;
+mod cost;
+mod crash;
+mod dataflow;
+mod dump;
+mod equivalence;
+mod footprint;
+mod future;
+mod guard;
+mod hooks;
+mod init;
+mod loops;
+mod marshal;
+mod metrics;
+mod optimizer;
+mod outcome;
+mod packed;
+mod parallel;
+mod patch;
+mod phases;
+mod preview;
+mod profile;
+mod snapshot;
+mod source_map;
+mod superinstruction;
+mod verify;
 
-const MEMORY_MASK: usize = 0xffff;
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncRead, AsyncRun, AsyncWrite};
+pub use buffered::FlushPolicy;
+pub use bytecode::BytecodeError;
+pub use brackets::{BracketPolicy, ParseBracketPolicyError};
+pub use comments::{CommentStyle, ParseCommentStyleError};
+pub use config::{
+  BoundsPolicy, Compatibility, Compilation, Config, DefaultConfig, EofMode, InterpreterConfig, OverflowPolicy,
+  ParseBoundsPolicyError, ParseCompatibilityError, ParseEofModeError, RunConfig, Trap, TrapKind,
+};
+pub use cost::CostModel;
+pub use crash::CrashContext;
+pub use footprint::TapeFootprint;
+pub use future::RunFuture;
+#[cfg(all(feature = "async", feature = "std"))]
+pub use future::BlockingRun;
+pub use guard::{Miscompilation, MiscompilationKind};
+pub use hooks::{Hooks, NoHooks, StateView};
+pub use init::TapeInit;
+pub use interpreter::{run, run_static, run_with_fuel, run_with_hooks, CaptureIo, Io, IterInput, NewlineIo, Newlines, RandomInput, SliceEnd, StreamIo, TeeIo};
+pub use loops::{LoopNode, LoopTree};
+pub use metrics::ProgramMetrics;
+pub use outcome::{RunOutcome, RuntimeError};
+pub use packed::{PackedPosition, PackedProgram};
+pub use patch::PatchError;
+pub use phases::CompilePipeline;
+pub use preview::{PreviewEnd, PreviewResult};
+pub use profile::Profile;
+pub use snapshot::SnapshotError;
+pub use source_map::SourceMap;
+pub use superinstruction::SuperinstructionCandidate;
+pub use verify::VerifyError;
+
+pub(crate) const MEMORY_MASK: usize = 0xffff;
 const MEMORY_SIZE: usize = MEMORY_MASK + 1;
 
+/// Brainfuck command, as recognized by the tokenizer
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Token {
@@ -19,7 +89,7 @@ pub enum Token {
 }
 
 #[repr(u8)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Opcode {
   Increment(isize, isize),
   Set(isize, u8),
@@ -29,9 +99,55 @@ pub enum Opcode {
   Output(isize),
   Input(isize),
   ScanZero(isize),
-  //Move(usize, ArrayVec::<usize, 16>),
+  /// Add the current cell multiplied by a factor to the cells at each offset, then clear the current cell
+  ///
+  /// Replaces copy and multiplication loops like `[->+>++<<]`
+  Move(Box<[(isize, u8)]>),
+  /// Superinstruction: `MovePointer` followed by `LoopStart`
+  MoveLoopStart(isize, usize),
+  /// Superinstruction: `MovePointer` followed by `LoopEnd`
+  MoveLoopEnd(isize, usize),
+  /// Superinstruction: straight-line `Increment`, `Set`, `MovePointer` and `Move` opcodes run with a single dispatch,
+  /// see [`Program::fuse_superinstructions`]
+  Fused(Box<[Opcode]>),
+  /// Flush buffered output, see [`CompileOptions::flush`]
+  Flush,
   Eof,
 }
+impl Opcode {
+  /// Name of the opcode kind, without operands
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Increment(..) => "Increment",
+      Self::Set(..) => "Set",
+      Self::MovePointer(_) => "MovePointer",
+      Self::LoopStart(_) => "LoopStart",
+      Self::LoopEnd(_) => "LoopEnd",
+      Self::Output(_) => "Output",
+      Self::Input(_) => "Input",
+      Self::ScanZero(_) => "ScanZero",
+      Self::Move(_) => "Move",
+      Self::MoveLoopStart(..) => "MoveLoopStart",
+      Self::MoveLoopEnd(..) => "MoveLoopEnd",
+      Self::Fused(_) => "Fused",
+      Self::Flush => "Flush",
+      Self::Eof => "Eof",
+    }
+  }
+
+  /// Whether the opcode can be part of a [`Opcode::Fused`] superinstruction
+  pub(crate) fn is_fusable(&self) -> bool {
+    matches!(self, Self::Increment(..) | Self::Set(..) | Self::MovePointer(_) | Self::Move(_))
+  }
+
+  /// The opcodes making up a [`Opcode::Fused`] superinstruction, or just the opcode itself otherwise
+  pub(crate) fn unfused(&self) -> &[Opcode] {
+    match self {
+      Self::Fused(ops) => ops,
+      op => slice::from_ref(op),
+    }
+  }
+}
 impl From<Token> for Opcode {
   fn from(value: Token) -> Self {
     match value {
@@ -47,20 +163,67 @@ impl From<Token> for Opcode {
   }
 }
 
-fn brainfuck_tokens(code: &str) -> impl Iterator<Item=Token> + '_ {
-  code.chars().filter_map(|x| match x {
-    '+' => Some(Token::Increment),
-    '-' => Some(Token::Decrement),
-    '<' => Some(Token::MovePointerLeft),
-    '>' => Some(Token::MovePointerRight),
-    '[' => Some(Token::LoopStart),
-    ']' => Some(Token::LoopEnd),
-    '.' => Some(Token::Output),
-    ',' => Some(Token::Input),
-    _ => None,
+impl Token {
+  /// Source character of the command
+  pub fn as_char(self) -> char {
+    match self {
+      Self::Increment => '+',
+      Self::Decrement => '-',
+      Self::MovePointerLeft => '<',
+      Self::MovePointerRight => '>',
+      Self::LoopStart => '[',
+      Self::LoopEnd => ']',
+      Self::Output => '.',
+      Self::Input => ',',
+    }
+  }
+}
+
+/// Location of a token in source code
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Span {
+  /// Byte range of the token
+  pub range: Range<usize>,
+  /// 1-based line number
+  pub line: usize,
+  /// 1-based column number, counted in characters
+  pub column: usize,
+}
+
+/// Tokenize source code, yielding each command along with its location
+///
+/// Every character other than the eight commands is a comment and is skipped
+pub fn brainfuck_tokens(code: &str) -> impl Iterator<Item=(Token, Span)> + '_ {
+  let (mut line, mut column) = (1, 0);
+  code.char_indices().filter_map(move |(index, x)| {
+    column += 1;
+    if x == '\n' {
+      (line, column) = (line + 1, 0);
+    }
+    let token = token(x)?;
+    Some((token, Span { range: index..index + 1, line, column }))
   })
 }
 
+fn token(x: char) -> Option<Token> {
+  Some(match x {
+    '+' => Token::Increment,
+    '-' => Token::Decrement,
+    '<' => Token::MovePointerLeft,
+    '>' => Token::MovePointerRight,
+    '[' => Token::LoopStart,
+    ']' => Token::LoopEnd,
+    '.' => Token::Output,
+    ',' => Token::Input,
+    _ => return None,
+  })
+}
+
+/// Tokenize source code, yielding the byte offset of each token along with it
+pub(crate) fn brainfuck_tokens_indexed(code: &str) -> impl Iterator<Item=(usize, Token)> + '_ {
+  code.char_indices().filter_map(|(index, x)| Some((index, token(x)?)))
+}
+
 #[derive(Clone, Copy)]
 pub struct BrainfuckState {
   pub memory: [u8; MEMORY_SIZE],
@@ -73,6 +236,70 @@ impl BrainfuckState {
       pointer: 0,
     }
   }
+
+  /// View of the whole tape
+  #[inline(always)]
+  pub fn tape(&self) -> &[u8] {
+    &self.memory
+  }
+
+  /// Mutable view of the whole tape
+  #[inline(always)]
+  pub fn tape_mut(&mut self) -> &mut [u8] {
+    &mut self.memory
+  }
+
+  /// Tape address of the cell under the pointer
+  #[inline]
+  pub fn pointer_address(&self) -> usize {
+    self.pointer & MEMORY_MASK
+  }
+
+  /// Smallest range of the tape containing every nonzero cell and the cell under the pointer
+  ///
+  /// Cells are not tracked individually, so cells that were written and then zeroed again don't count.
+  /// Programs going left of the first cell wrap around to the end of the tape, which makes the range span most of it
+  pub fn used_range(&self) -> Range<usize> {
+    let pointer = self.pointer_address();
+    let start = self.memory.iter().position(|&x| x != 0).map_or(pointer, |x| x.min(pointer));
+    let end = self.memory.iter().rposition(|&x| x != 0).map_or(pointer, |x| x.max(pointer));
+    start..(end + 1)
+  }
+
+  /// Tape addresses of every occurrence of `needle`, in increasing order
+  ///
+  /// Occurrences may overlap, and don't wrap around the end of the tape. An empty needle is never found
+  pub fn find_all<'a>(&'a self, needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    //Windows are never empty, so they never match an empty needle
+    self.memory.windows(needle.len().max(1)).enumerate().filter(move |(_, window)| *window == needle).map(|(address, _)| address)
+  }
+
+  /// Tape address of the first occurrence of `needle`, see [`BrainfuckState::find_all`]
+  pub fn find(&self, needle: &[u8]) -> Option<usize> {
+    self.find_all(needle).next()
+  }
+
+  /// Number of cells holding `value`
+  pub fn count(&self, value: u8) -> usize {
+    self.memory.iter().filter(|&&x| x == value).count()
+  }
+
+  /// Zero the whole tape and move the pointer back to the first cell, in place
+  ///
+  /// The state is then indistinguishable from a new one, so nothing written by a previous run can leak into the next
+  #[inline]
+  pub fn reset(&mut self) {
+    self.memory.fill(0);
+    self.pointer = 0;
+  }
+
+  /// Zero the cells in `range`, leaving the rest of the tape and the pointer as they are
+  ///
+  /// Panics if the range is out of the tape bounds
+  #[inline]
+  pub fn clear_range(&mut self, range: Range<usize>) {
+    self.memory[range].fill(0);
+  }
 }
 impl Default for BrainfuckState {
   fn default() -> Self {
@@ -80,222 +307,390 @@ impl Default for BrainfuckState {
   }
 }
 
+/// Compiler configuration
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+  /// Drop writes at the end of the program that are never read or output
+  ///
+  /// Program output is unaffected, but the final tape and pointer no longer match an unoptimized run
+  pub drop_dead_stores: bool,
+  /// Assume the program starts with a zeroed tape, as it does in a freshly created interpreter
+  ///
+  /// Allows evaluating the initial part of the program at compile time,
+  /// but makes the program misbehave if it is run on a dirty tape (e.g. run twice without resetting the state)
+  pub assume_zeroed_tape: bool,
+  /// Rebuild runs of balanced counted loops from their dataflow, merging loops that copy or multiply cells
+  ///
+  /// Experimental, and slower to compile
+  pub dataflow: bool,
+  /// Maximum time spent compiling, for services compiling untrusted programs
+  ///
+  /// Once it runs out, the pass in progress is finished and all remaining optimization passes are skipped,
+  /// which still yields a correct program
+  pub time_budget: Option<Duration>,
+  /// Comment convention of the source code, see [`CommentStyle`]
+  pub comments: CommentStyle,
+  /// Parse mode, strict or lenient about unmatched brackets, see [`BracketPolicy`]
+  pub brackets: BracketPolicy,
+  /// Dialect extension character compiled to [`Opcode::Flush`] (e.g. `;`), letting programs flush their output
+  /// at specific points, like right after an interactive prompt
+  pub flush: Option<char>,
+  /// Lint the source code before compiling it, failing if a denied lint finds an issue, see [`lint::check`](crate::lint::check)
+  pub lints: Option<LintOptions>,
+}
+
+/// Details of a compilation, see [`Program::compile_with_report`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompileReport {
+  /// Optimization passes that were skipped, in pipeline order,
+  /// because the time budget ran out or because they found an internal inconsistency
+  pub skipped_passes: Vec<&'static str>,
+  /// Pass outputs rejected by the miscompilation guards, which only run in debug builds
+  ///
+  /// Rejected outputs are discarded, as if the pass was skipped. Anything in here is a compiler bug
+  pub miscompilations: Vec<Miscompilation>,
+  /// Time spent in every phase and pass
+  pub stats: CompileStats,
+  /// Source location of every opcode of the program
+  pub source_map: SourceMap,
+}
+
+/// Time spent in every phase and optimization pass of a compilation, see [`CompileReport::stats`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompileStats {
+  /// Phases and passes in the order they ran, skipped passes excluded
+  ///
+  /// Passes include relinking their output. In debug builds, the miscompilation guard run after every pass
+  /// is listed separately as `guard`
+  pub timings: Vec<(&'static str, Duration)>,
+}
+impl CompileStats {
+  /// Time spent in all phases and passes
+  pub fn total(&self) -> Duration {
+    self.timings.iter().map(|&(_, time)| time).sum()
+  }
+}
+impl fmt::Display for CompileStats {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let total = self.total();
+    for &(name, time) in &self.timings {
+      let share = time.as_secs_f64() / total.as_secs_f64().max(f64::MIN_POSITIVE) * 100.;
+      writeln!(f, "{name:<20} {:>10.3} ms {share:>5.1}%", time.as_secs_f64() * 1000.)?;
+    }
+    writeln!(f, "{:<20} {:>10.3} ms", "total", total.as_secs_f64() * 1000.)
+  }
+}
+
+/// Error returned when compiling source code with unbalanced brackets, or issues found by denied lints
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompileError {
+  UnbalancedBrackets {
+    /// Byte offset of the unmatched bracket in the source code
+    offset: usize,
+    /// Unmatched bracket: [`Token::LoopStart`] if it is never closed, [`Token::LoopEnd`] if it closes nothing
+    bracket: Token,
+  },
+  /// Issues found by lints denied in [`CompileOptions::lints`], never empty
+  DeniedLints(Vec<Diagnostic>),
+}
+impl fmt::Display for CompileError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::UnbalancedBrackets { offset, bracket: Token::LoopStart } => write!(f, "unclosed `[` at byte {offset}"),
+      Self::UnbalancedBrackets { offset, .. } => write!(f, "unexpected `]` at byte {offset}"),
+      Self::DeniedLints(diagnostics) => {
+        let diagnostics: Vec<String> = diagnostics.iter().map(|x| format!("{x} at byte {}", x.span.start)).collect();
+        f.write_str(&diagnostics.join(", "))
+      },
+    }
+  }
+}
+impl std::error::Error for CompileError {}
+
+/// Internal inconsistency found by the optimizer, which makes compilation skip the optimizer pass
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OptimizerError {
+  /// Loop start at the given opcode index isn't linked to a matching loop end
+  UnlinkedLoop(usize),
+  /// Loop end at the given opcode index doesn't close the innermost loop
+  UnmatchedLoopEnd(usize),
+}
+impl fmt::Display for OptimizerError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::UnlinkedLoop(index) => write!(f, "loop start at opcode {index} is not linked to a loop end"),
+      Self::UnmatchedLoopEnd(index) => write!(f, "loop end at opcode {index} does not close the innermost loop"),
+    }
+  }
+}
+impl std::error::Error for OptimizerError {}
+
+/// Compiled brainfuck program
+///
+/// Compilation output is canonical, so equality and hashing can be used to detect equivalent sources
+/// (as long as no metadata was attached)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Program {
+  ops: Vec<Opcode>,
+  /// Arbitrary key-value pairs (author, license, dialect, ...) preserved through bytecode
+  metadata: BTreeMap<String, String>,
+}
+impl Program {
+  /// Compile brainfuck source code
+  ///
+  /// Fails if the brackets are unbalanced
+  pub fn compile(code: &str) -> Result<Self, CompileError> {
+    Self::compile_with_options(code, &CompileOptions::default())
+  }
+
+  /// Compile brainfuck source code using custom compiler options
+  ///
+  /// Compilation is deterministic: the same source and options always produce identical opcodes (and bytecode),
+  /// unless a [`CompileOptions::time_budget`] runs out.
+  /// Fails if the brackets are unbalanced under a strict [`CompileOptions::brackets`] policy
+  pub fn compile_with_options(code: &str, options: &CompileOptions) -> Result<Self, CompileError> {
+    Ok(Self::compile_with_report(code, options)?.0)
+  }
+
+  /// Like [`Program::compile_with_options`], also reporting which optimization passes were skipped
+  pub fn compile_with_report(code: &str, options: &CompileOptions) -> Result<(Self, CompileReport), CompileError> {
+    Ok(CompilePipeline::try_parse(code, options)?.link().optimize().relink().finalize())
+  }
+
+  /// Compile brainfuck source code without applying any optimizations
+  ///
+  /// Fails if the brackets are unbalanced
+  pub fn compile_without_optimizations(code: &str) -> Result<Self, CompileError> {
+    Ok(CompilePipeline::try_parse(code, &CompileOptions::default())?.link().finalize().0)
+  }
+
+  /// Check that every bracket of `code` is matched
+  fn check_brackets(code: &str) -> Result<(), CompileError> {
+    let mut open = Vec::new();
+    for (offset, token) in brainfuck_tokens_indexed(code) {
+      match token {
+        Token::LoopStart => open.push(offset),
+        Token::LoopEnd if open.pop().is_none() => return Err(CompileError::UnbalancedBrackets { offset, bracket: token }),
+        _ => (),
+      }
+    }
+    match open.pop() {
+      Some(offset) => Err(CompileError::UnbalancedBrackets { offset, bracket: Token::LoopStart }),
+      None => Ok(()),
+    }
+  }
+
+  /// Get the compiled opcodes
+  #[inline(always)]
+  pub fn opcodes(&self) -> &[Opcode] {
+    &self.ops
+  }
+
+  /// Get the metadata attached to the program
+  pub fn metadata(&self) -> &BTreeMap<String, String> {
+    &self.metadata
+  }
+
+  /// Get the metadata attached to the program for modification
+  pub fn metadata_mut(&mut self) -> &mut BTreeMap<String, String> {
+    &mut self.metadata
+  }
+
+  /// Fill in loop jump targets, returning the loop structure found on the way
+  ///
+  /// Fails if the loops are unbalanced, leaving the targets partially linked
+  fn link_loops(ops: &mut [Opcode]) -> Result<LoopTree, OptimizerError> {
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    let mut tree = LoopTree::default();
+    for index in 0..ops.len() {
+      //This is very hacky
+      let (output_ops_before, op) = ops.split_at_mut(index);
+      let op = &mut op[0];
+      match op {
+        Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) => {
+          let node = tree.open(index, stack.last().map(|x| x.1));
+          stack.push((index, node));
+        },
+        Opcode::LoopEnd(start) | Opcode::MoveLoopEnd(_, start) => {
+          let (start_index, node) = stack.pop().ok_or(OptimizerError::UnmatchedLoopEnd(index))?;
+          match &mut output_ops_before[start_index] {
+            Opcode::LoopStart(end) | Opcode::MoveLoopStart(_, end) => *end = index,
+            _ => unreachable!(),
+          }
+          *start = start_index;
+          tree.nodes[node].body.end = index;
+        }
+        _ => ()
+      }
+    }
+    match stack.pop() {
+      Some((index, _)) => Err(OptimizerError::UnlinkedLoop(index)),
+      None => Ok(tree),
+    }
+  }
+
+  fn parse(code: &str, flush: Option<char>) -> Vec<Opcode> {
+    let mut ops: Vec<Opcode> = match flush {
+      Some(flush) => code.chars().filter_map(|x| match x == flush {
+        true => Some(Opcode::Flush),
+        false => token(x).map(Opcode::from),
+      }).collect(),
+      None => brainfuck_tokens_indexed(code).map(|(_, token)| Opcode::from(token)).collect(),
+    };
+    ops.push(Opcode::Eof);
+    ops
+  }
+}
+
 /// Brainfuck interpreter
+///
+/// The state and program are shared between clones until one of them modifies them, see [`Brainfuck::fork`]
 #[derive(Clone)]
 pub struct Brainfuck {
-  state: BrainfuckState,
-  program: Vec<Opcode>
+  state: Arc<BrainfuckState>,
+  program: Arc<Program>,
+  /// How the program was compiled, see [`Brainfuck::config`]
+  compilation: Compilation,
+  eof: EofMode,
+  bounds: BoundsPolicy,
+  step_limit: Option<usize>,
+  output: buffered::OutputBuffer,
 }
 impl Brainfuck {
   /// Create a new brainfuck interpreter
   #[inline]
   pub fn new() -> Self {
-    Self {
-      state: BrainfuckState::new(),
-      program: Vec::new(),
-    }
+    Self::new_with_state(BrainfuckState::new())
   }
 
   /// Create  a new brainfuck interpreter using existing state
   #[inline]
   pub fn new_with_state(state: BrainfuckState) -> Self {
     Self {
-      state: state,
-      program: Vec::new(),
+      state: Arc::new(state),
+      program: Arc::default(),
+      compilation: Compilation::Unoptimized,
+      eof: EofMode::default(),
+      bounds: BoundsPolicy::default(),
+      step_limit: None,
+      output: Default::default(),
     }
   }
 
+  /// Create an independent interpreter continuing from the current state, with the same program and EOF mode
+  ///
+  /// Forking is cheap: the tape is only copied once either interpreter modifies it, and the program once either
+  /// is patched. Tools can fork before feeding a program different inputs to explore every branch
+  #[inline]
+  pub fn fork(&self) -> Self {
+    self.clone()
+  }
+
   /// Get an immutable reference to the interpreter state
   #[inline(always)]
   pub fn state(&self) -> &BrainfuckState {
     &self.state
   }
 
-  /// Get a mutable reference to the interpreter state
+  /// Get a mutable reference to the interpreter state, copying it first if it is shared with a fork
   #[inline(always)]
   pub fn state_mut(&mut self) -> &mut BrainfuckState {
-    &mut self.state
+    Arc::make_mut(&mut self.state)
+  }
+
+  /// Get an immutable reference to the compiled program
+  #[inline(always)]
+  pub fn program(&self) -> &Program {
+    &self.program
+  }
+
+  /// Effect of `,` when there is no more input, [`EofMode::Unchanged`] by default
+  #[inline(always)]
+  pub fn eof_mode(&self) -> EofMode {
+    self.eof
   }
 
-  fn optimize(ops: Vec<Opcode>) -> Vec<Opcode> {
-    let mut output_ops: Vec<Opcode> = Vec::new();
+  /// Set the effect of `,` when there is no more input, for programs written for another EOF convention
+  #[inline]
+  pub fn set_eof_mode(&mut self, eof: EofMode) {
+    self.eof = eof;
+  }
 
-    //TODO: check for eof token and add it
-    //TODO: recursive block compilation
+  /// What happens when the pointer leaves the tape, [`BoundsPolicy::Wrap`] by default
+  #[inline(always)]
+  pub fn bounds_policy(&self) -> BoundsPolicy {
+    self.bounds
+  }
 
-    //Optimize increments/ptr movements
-    {
-      #[derive(Clone, Copy, PartialEq, Eq)]
-      enum BlockEffect {
-        Increment(isize),
-        Set(u8),
-      }
-      trait BlockCommit {
-        fn opcode(&self) -> Option<Opcode>;
-        fn commit(&self, ops: &mut Vec<Opcode>);
-      }
-      impl BlockCommit for (&isize, &BlockEffect) {
-        fn opcode(&self) -> Option<Opcode> {
-          match self.1 {
-            BlockEffect::Increment(increment) => {
-              if *increment == 0 { return None }
-              Some(Opcode::Increment(*self.0, *increment))
-            },
-            BlockEffect::Set(value) => {
-              Some(Opcode::Set(*self.0, *value))
-            },
-          }
-        }
-        fn commit(&self, ops: &mut Vec<Opcode>) {
-          if let Some(op) = self.opcode() {
-            ops.push(op);
-          }
-        }
-      }
-      let mut block_effects: HashMap<isize, BlockEffect> = HashMap::new();
-      let mut ptr_offset: isize = 0;
-      let mut index = 0;
-
-      'opt: while index < ops.len() {
-        let op = &ops[index];
-        index += 1;
-        match op {
-          Opcode::Increment(offset, increment) => {
-            let existing_effect = block_effects.get_mut(&ptr_offset);
-            match existing_effect {
-              Some(BlockEffect::Increment(effect)) => {
-                *effect += increment;
-              },
-              Some(BlockEffect::Set(effect)) => {
-                //TODO: casting isize to i8 can cause unexpected behaviour here! (but it's not likely to break)
-                *effect = effect.wrapping_add_signed(*increment as i8);
-              },
-              None => {
-                block_effects.insert(offset + ptr_offset, BlockEffect::Increment(*increment));
-              }
-            }
-            //block_effects.insert(offset + ptr_offset, existing_value + increment);
-          }
-          Opcode::MovePointer(diff) => {
-            ptr_offset += *diff;
-          },
-          Opcode::Output(out_offset) | Opcode::Input(out_offset) => {
-            //THIS IS EXPERIMENTAL!
-            //Partial commit: commit only operations related to the current cell
-            //TODO: maybe do not remove the effect if its "Set"? (probably special value should be used to indicate that the set is already committed?)
-            let relative_pos = &(ptr_offset + out_offset);
-            if let Some(ref effect) = block_effects.remove(relative_pos) {
-              (relative_pos, effect).commit(&mut output_ops);
-            }
-            output_ops.push(match op {
-              Opcode::Output(_) => Opcode::Output(*relative_pos),
-              Opcode::Input(_) => Opcode::Input(*relative_pos),
-              _ => unreachable!()
-            })
-          }
-          Opcode::Eof | Opcode::LoopStart(_) | Opcode::LoopEnd(_) => {
-            //Detect [-]/[+] loops 
-            //TODO: compute block effects instead!
-            //TODO: at least compute pointer movement? (to allow [<->]) (recursive comp is preferable)
-            if let Opcode::LoopStart(_) = op {
-              if let Opcode::LoopEnd(_) = ops[index + 1] {
-                if let Opcode::Increment(pos, value) = ops[index] {
-                  if pos == 0 && value.abs() % 2 == 1 {
-                    index += 2;
-                    block_effects.insert(ptr_offset, BlockEffect::Set(0));
-                    continue
-                  }
-                }
-              }
-            }
-            //commit increments and pointer movements
-            for effect in &block_effects {
-              effect.commit(&mut output_ops);
-            }
-            block_effects.clear();
-            //commit pointer movements
-            if ptr_offset != 0 {
-              output_ops.push(Opcode::MovePointer(ptr_offset));
-              ptr_offset = 0;
-            }
-
-            //Detect zero-scan loops 
-            'outer: {
-              if let Opcode::LoopStart(end) = op {
-                let mut mov_sum = 0;
-                for op in &ops[index..*end] {
-                  match op {
-                    Opcode::MovePointer(mov) => {
-                      mov_sum += *mov;
-                    },
-                    _ => break 'outer
-                  }
-                }
-                if mov_sum == 0 {
-                  break 'outer
-                }
-                output_ops.push(Opcode::ScanZero(mov_sum)); 
-                index = end + 1;
-                continue 'opt
-              }
-            }
-
-            //Push original opcode
-            output_ops.push(op.clone()); 
-          },
-          _ => todo!()
-        }
-      }
+  /// Set what happens when the pointer leaves the tape, e.g. trapping for untrusted programs
+  ///
+  /// With [`BoundsPolicy::Trap`], [`Brainfuck::run`] and [`Brainfuck::run_with_input`] fail with
+  /// [`RuntimeError::Trap`], [`Brainfuck::into_future`] resolves with the trap, `Brainfuck::run_async` fails,
+  /// and the other runs stop at the instruction that left the tape
+  #[inline]
+  pub fn set_bounds_policy(&mut self, bounds: BoundsPolicy) {
+    self.bounds = bounds;
+  }
+
+  /// Reset the state to the one of a new interpreter (see [`BrainfuckState::reset`]), keeping the program
+  ///
+  /// Reusing an interpreter this way avoids reallocating the tape between runs
+  #[inline]
+  pub fn reset_state(&mut self) {
+    match Arc::get_mut(&mut self.state) {
+      Some(state) => state.reset(),
+      //Shared with a fork, which keeps the old state
+      None => self.state = Arc::default(),
     }
+  }
 
-    output_ops
+  /// Unload the program, leaving an empty one that does nothing when run, and keeping the state
+  #[inline]
+  pub fn reset_program(&mut self) {
+    self.program = Arc::default();
+    self.compilation = Compilation::Unoptimized;
   }
 
-  fn link_loops(ops: &mut Vec<Opcode>) {
-    let mut stack: Vec<usize> = Vec::new();
-    for index in 0..ops.len() {
-      //This is very hacky
-      let (output_ops_before, op) = ops.split_at_mut(index);
-      let op = &mut op[0];
-      match op {
-        Opcode::LoopStart(_) => {
-          stack.push(index);
-        },
-        Opcode::LoopEnd(_) => {
-          let start_index = stack.pop().expect("Unexpected loop end");
-          output_ops_before[start_index] = Opcode::LoopStart(index);
-          *op = Opcode::LoopEnd(start_index);
-        }
-        _ => ()
-      }
-    }
-    assert!(stack.is_empty(), "Unclosed loop");
+  /// Zero the cells in `range` of the tape, see [`BrainfuckState::clear_range`]
+  #[inline]
+  pub fn clear_tape_range(&mut self, range: Range<usize>) {
+    self.state_mut().clear_range(range);
   }
 
-  fn parse(code: &str) -> Vec<Opcode> {
-    let mut ops: Vec<Opcode> = brainfuck_tokens(code).map(Opcode::from).collect();
-    ops.push(Opcode::Eof);
-    ops
+  /// Replace the program with an already compiled one
+  #[inline]
+  pub fn load_program(&mut self, program: Program) {
+    self.program = Arc::new(program);
+    self.compilation = Compilation::Loaded;
   }
 
   /// Compile brainfuck source code
-  pub fn compile(&mut self, code: &str) {
-    let mut ops = Self::parse(code);
-    Self::link_loops(&mut ops);
-    let mut ops = Self::optimize(ops);
-    Self::link_loops(&mut ops);
-    self.program = ops;
+  ///
+  /// Fails if the brackets are unbalanced, keeping the previous program
+  pub fn compile(&mut self, code: &str) -> Result<(), CompileError> {
+    self.compile_with_options(code, &CompileOptions::default())
+  }
+
+  /// Compile brainfuck source code using custom compiler options
+  ///
+  /// Fails if the brackets are unbalanced, keeping the previous program
+  pub fn compile_with_options(&mut self, code: &str, options: &CompileOptions) -> Result<(), CompileError> {
+    self.program = Arc::new(Program::compile_with_report(code, options)?.0);
+    self.compilation = Compilation::Optimized(options.clone());
+    Ok(())
   }
 
   /// Compile brainfuck source code without applying any optimizations
-  pub fn compile_without_optimizations(&mut self, code: &str) {
-    let mut ops = Self::parse(code);
-    Self::link_loops(&mut ops);
-    self.program = ops;
+  ///
+  /// Fails if the brackets are unbalanced, keeping the previous program
+  pub fn compile_without_optimizations(&mut self, code: &str) -> Result<(), CompileError> {
+    self.program = Arc::new(Program::compile_without_optimizations(code)?);
+    self.compilation = Compilation::Unoptimized;
+    Ok(())
   }
 
   pub fn _debug(&self) {
-    println!("{:?}", &self.program);
+    println!("{:?}", &self.program.ops);
   }
 }
 impl Default for Brainfuck {
@@ -0,0 +1,171 @@
+//! Primitives accelerated with SIMD instructions picked at runtime, so generic builds still use the best the CPU has
+//!
+//! Every primitive has a scalar fallback for other targets and CPUs
+
+use super::{MEMORY_MASK, MEMORY_SIZE};
+
+/// Index of the first zero byte
+#[inline]
+fn find_zero_scalar(data: &[u8]) -> Option<usize> {
+  data.iter().position(|&x| x == 0)
+}
+
+/// Index of the last zero byte
+#[inline]
+fn rfind_zero_scalar(data: &[u8]) -> Option<usize> {
+  data.iter().rposition(|&x| x == 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+  use std::arch::x86_64::*;
+  use super::{find_zero_scalar, rfind_zero_scalar};
+
+  /// Generate a forward and a backward search comparing `$width` bytes at a time
+  macro_rules! searches {
+    ($feature: literal, $width: literal, $find: ident, $rfind: ident, |$chunk: ident| $mask: expr) => {
+      /// Index of the first zero byte
+      #[target_feature(enable = $feature)]
+      pub unsafe fn $find(data: &[u8]) -> Option<usize> {
+        let mut chunks = data.chunks_exact($width);
+        let mut offset = 0;
+        for $chunk in &mut chunks {
+          let mask = $mask;
+          if mask != 0 {
+            return Some(offset + mask.trailing_zeros() as usize)
+          }
+          offset += $width;
+        }
+        find_zero_scalar(chunks.remainder()).map(|x| offset + x)
+      }
+
+      /// Index of the last zero byte
+      #[target_feature(enable = $feature)]
+      pub unsafe fn $rfind(data: &[u8]) -> Option<usize> {
+        let mut chunks = data.rchunks_exact($width);
+        let mut end = data.len();
+        for $chunk in &mut chunks {
+          let mask = $mask;
+          if mask != 0 {
+            //Narrower masks only fill the low bits
+            return Some(end - 1 - (mask << (32 - $width)).leading_zeros() as usize)
+          }
+          end -= $width;
+        }
+        rfind_zero_scalar(chunks.remainder())
+      }
+    };
+  }
+
+  //SAFETY: chunks are exactly as wide as the unaligned loads
+  searches!("avx2", 32, find_zero_avx2, rfind_zero_avx2, |chunk| {
+    let bytes = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+    _mm256_movemask_epi8(_mm256_cmpeq_epi8(bytes, _mm256_setzero_si256())) as u32
+  });
+  searches!("sse2", 16, find_zero_sse2, rfind_zero_sse2, |chunk| {
+    let bytes = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+    _mm_movemask_epi8(_mm_cmpeq_epi8(bytes, _mm_setzero_si128())) as u32
+  });
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+  use std::arch::aarch64::*;
+  use super::{find_zero_scalar, rfind_zero_scalar};
+
+  /// Whether any of the 16 bytes at `chunk` is zero
+  #[inline(always)]
+  unsafe fn has_zero(chunk: &[u8]) -> bool {
+    vmaxvq_u8(vceqzq_u8(vld1q_u8(chunk.as_ptr()))) != 0
+  }
+
+  /// Index of the first zero byte
+  #[target_feature(enable = "neon")]
+  pub unsafe fn find_zero(data: &[u8]) -> Option<usize> {
+    let mut chunks = data.chunks_exact(16);
+    let mut offset = 0;
+    for chunk in &mut chunks {
+      //SAFETY: chunks are exactly 16 bytes long
+      if has_zero(chunk) {
+        return find_zero_scalar(chunk).map(|x| offset + x)
+      }
+      offset += 16;
+    }
+    find_zero_scalar(chunks.remainder()).map(|x| offset + x)
+  }
+
+  /// Index of the last zero byte
+  #[target_feature(enable = "neon")]
+  pub unsafe fn rfind_zero(data: &[u8]) -> Option<usize> {
+    let mut chunks = data.rchunks_exact(16);
+    let mut end = data.len();
+    for chunk in &mut chunks {
+      //SAFETY: chunks are exactly 16 bytes long
+      if has_zero(chunk) {
+        return rfind_zero_scalar(chunk).map(|x| end - 16 + x)
+      }
+      end -= 16;
+    }
+    rfind_zero_scalar(chunks.remainder())
+  }
+}
+
+/// Index of the first zero byte, using the widest search the CPU supports
+#[inline]
+pub(crate) fn find_zero(data: &[u8]) -> Option<usize> {
+  #[cfg(target_arch = "x86_64")]
+  {
+    //SAFETY: the required features were detected (SSE2 is part of the x86_64 baseline)
+    if is_x86_feature_detected!("avx2") {
+      return unsafe { x86::find_zero_avx2(data) }
+    }
+    unsafe { x86::find_zero_sse2(data) }
+  }
+  #[cfg(target_arch = "aarch64")]
+  {
+    if std::arch::is_aarch64_feature_detected!("neon") {
+      //SAFETY: the required feature was detected
+      return unsafe { neon::find_zero(data) }
+    }
+    find_zero_scalar(data)
+  }
+  #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+  find_zero_scalar(data)
+}
+
+/// Index of the last zero byte, using the widest search the CPU supports
+#[inline]
+pub(crate) fn rfind_zero(data: &[u8]) -> Option<usize> {
+  #[cfg(target_arch = "x86_64")]
+  {
+    //SAFETY: the required features were detected (SSE2 is part of the x86_64 baseline)
+    if is_x86_feature_detected!("avx2") {
+      return unsafe { x86::rfind_zero_avx2(data) }
+    }
+    unsafe { x86::rfind_zero_sse2(data) }
+  }
+  #[cfg(target_arch = "aarch64")]
+  {
+    if std::arch::is_aarch64_feature_detected!("neon") {
+      //SAFETY: the required feature was detected
+      return unsafe { neon::rfind_zero(data) }
+    }
+    rfind_zero_scalar(data)
+  }
+  #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+  rfind_zero_scalar(data)
+}
+
+/// Number of single-cell steps a `ScanZero` from `pointer` takes to reach a zero cell, wrapping around the tape
+///
+/// Returns `None` if the tape has no zero cell, in which case the scan never ends
+#[inline]
+pub(crate) fn scan_distance(memory: &[u8; MEMORY_SIZE], pointer: usize, forward: bool) -> Option<usize> {
+  let start = pointer & MEMORY_MASK;
+  match forward {
+    true => find_zero(&memory[start..]).or_else(|| find_zero(&memory[..start]).map(|x| x + MEMORY_SIZE - start)),
+    false => rfind_zero(&memory[..=start])
+      .map(|x| start - x)
+      .or_else(|| rfind_zero(&memory[start + 1..]).map(|x| MEMORY_SIZE - 1 - x)),
+  }
+}
@@ -0,0 +1,354 @@
+//! Abstract interpretation of cell values
+//!
+//! Cells are tracked as intervals of possible values, relative to the current pointer.
+//! The same domain backs the block effect bookkeeping of the optimizer and the value folding pass
+
+use std::collections::HashMap;
+use super::{Opcode, Program};
+
+/// Maximum number of cells tracked by an [`AbstractTape`], past which everything is forgotten
+///
+/// Keeps memory bounded for programs touching huge numbers of distinct cells
+const MAX_TRACKED_CELLS: usize = 1 << 12;
+/// Maximum loop nesting depth analyzed by the folding pass, deeper loops are copied as they are
+///
+/// Keeps recursion bounded for adversarially deep nesting
+const MAX_FOLD_DEPTH: usize = 64;
+/// Maximum number of distinct cells in a loop folded by [`counted_loop`]
+const MAX_COUNTED_CELLS: usize = 256;
+
+/// Range of values a cell may hold (`lo..=hi`, never wrapping around), all of them multiples of `2^aligned`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Interval {
+  pub lo: u8,
+  pub hi: u8,
+  /// Number of low bits known to be zero, which proves loops with an even step terminate
+  pub aligned: u32,
+}
+impl Interval {
+  pub const TOP: Self = Self { lo: 0, hi: u8::MAX, aligned: 0 };
+  pub const ZERO: Self = Self::constant(0);
+
+  pub const fn constant(value: u8) -> Self {
+    Self { lo: value, hi: value, aligned: value.trailing_zeros() }
+  }
+
+  pub fn as_constant(self) -> Option<u8> {
+    (self.lo == self.hi).then_some(self.lo)
+  }
+
+  /// Add a value to every element of the interval (wrapping)
+  pub fn add(self, value: u8) -> Self {
+    let (lo, lo_wrapped) = self.lo.overflowing_add(value);
+    let (hi, hi_wrapped) = self.hi.overflowing_add(value);
+    let aligned = self.aligned.min(value.trailing_zeros());
+    //The interval can only be shifted as a whole if either both or none of the bounds wrap
+    match lo_wrapped == hi_wrapped {
+      true if lo == hi => Self::constant(lo),
+      true => Self { lo, hi, aligned },
+      false => Self { aligned, ..Self::TOP },
+    }
+  }
+
+  /// Exclude zero from the interval, if possible
+  pub fn nonzero(self) -> Self {
+    match self.lo {
+      0 if self.hi > 0 => Self { lo: 1, ..self },
+      _ => self,
+    }
+  }
+}
+
+/// Accumulated effect of straight-line code on a single cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Effect {
+  /// Add a value to the cell (wrapping)
+  Increment(isize),
+  /// Overwrite the cell with a constant
+  Set(u8),
+}
+impl Effect {
+  /// Combine with an increment applied after this effect
+  pub fn then_increment(self, increment: isize) -> Self {
+    match self {
+      Self::Increment(value) => Self::Increment(value + increment),
+      //Truncating the increment is fine, as cell arithmetic wraps anyway
+      Self::Set(value) => Self::Set(value.wrapping_add(increment as u8)),
+    }
+  }
+
+  /// Apply the effect to an abstract cell value
+  pub fn apply(self, value: Interval) -> Interval {
+    match self {
+      Self::Increment(increment) => value.add(increment as u8),
+      Self::Set(value) => Interval::constant(value),
+    }
+  }
+
+  /// Opcode applying the effect to the cell at `offset`, or `None` if the effect does nothing
+  pub fn opcode(self, offset: isize) -> Option<Opcode> {
+    match self {
+      Self::Increment(0) => None,
+      Self::Increment(increment) => Some(Opcode::Increment(offset, increment)),
+      Self::Set(value) => Some(Opcode::Set(offset, value)),
+    }
+  }
+}
+
+/// Abstract tape, with cells addressed relative to the current pointer
+#[derive(Clone, Debug)]
+pub(crate) struct AbstractTape {
+  /// Cells keyed by their offset from the starting pointer, so moving the pointer doesn't rehash them
+  cells: HashMap<isize, Interval>,
+  /// Pointer offset from the starting pointer
+  pointer: isize,
+  /// Value of all cells not in `cells`
+  rest: Interval,
+}
+impl AbstractTape {
+  /// Tape with all cells zeroed, as in a fresh interpreter
+  pub fn zeroed() -> Self {
+    Self { cells: HashMap::new(), pointer: 0, rest: Interval::ZERO }
+  }
+
+  /// Tape with nothing known about any cell
+  pub fn unknown() -> Self {
+    Self { cells: HashMap::new(), pointer: 0, rest: Interval::TOP }
+  }
+
+  pub fn get(&self, offset: isize) -> Interval {
+    self.cells.get(&(self.pointer + offset)).copied().unwrap_or(self.rest)
+  }
+
+  /// Set the value of a cell, forgetting everything once too many cells are tracked
+  pub fn set(&mut self, offset: isize, value: Interval) {
+    if self.cells.len() >= MAX_TRACKED_CELLS && !self.cells.contains_key(&(self.pointer + offset)) {
+      *self = Self::unknown();
+    }
+    self.cells.insert(self.pointer + offset, value);
+  }
+
+  /// Move the pointer by `offset` cells
+  pub fn shift(&mut self, offset: isize) {
+    self.pointer += offset;
+  }
+}
+
+/// Offsets of all cells the loop starting at `ops[start]` may write to, relative to its condition cell
+///
+/// Returns `None` if the loop body or any loop nested in it moves the pointer by a non-static amount
+fn loop_writes(ops: &[Opcode], start: usize) -> Option<Vec<isize>> {
+  let Opcode::LoopStart(end) = ops[start] else { unreachable!() };
+  let mut writes = Vec::new();
+  let mut pointer = 0;
+  //Pointer at the start of every nested loop still open, which it must be back at when the loop ends
+  let mut nested = Vec::new();
+  for op in &ops[(start + 1)..end] {
+    match *op {
+      Opcode::Increment(offset, _) | Opcode::Set(offset, _) | Opcode::Input(offset) => {
+        writes.push(pointer + offset);
+      },
+      Opcode::MovePointer(offset) => pointer += offset,
+      Opcode::LoopStart(_) => nested.push(pointer),
+      Opcode::LoopEnd(_) => if nested.pop() != Some(pointer) {
+        return None
+      },
+      Opcode::Move(ref targets) => {
+        writes.push(pointer);
+        writes.extend(targets.iter().map(|x| pointer + x.0));
+      },
+      Opcode::ScanZero(_) => return None,
+      Opcode::Output(_) | Opcode::Flush | Opcode::Eof => (),
+      Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) | Opcode::Fused(_) => {
+        unreachable!("superinstructions are only fused after folding")
+      },
+    }
+  }
+  writes.sort_unstable();
+  writes.dedup();
+  (pointer == 0).then_some(writes)
+}
+
+/// Net effect of a loop with a known trip count whose body only consists of increments
+///
+/// Returns the increments of all cells except the condition cell, which always ends up zeroed,
+/// or `None` if the loop doesn't have this shape or never terminates
+fn counted_loop(ops: &[Opcode], start: usize, condition: u8) -> Option<Vec<(isize, u8)>> {
+  let Opcode::LoopStart(end) = ops[start] else { unreachable!() };
+  let mut increments: Vec<(isize, u8)> = Vec::new();
+  let mut step = 0u8;
+  for op in &ops[(start + 1)..end] {
+    let Opcode::Increment(offset, value) = *op else { return None };
+    if increments.len() >= MAX_COUNTED_CELLS {
+      return None
+    }
+    let value = value as u8;
+    match (offset, increments.iter_mut().find(|x| x.0 == offset)) {
+      (0, _) => step = step.wrapping_add(value),
+      (_, Some(increment)) => increment.1 = increment.1.wrapping_add(value),
+      (_, None) => increments.push((offset, value)),
+    }
+  }
+  //Solve condition + trips * step == 0 (mod 256)
+  let trips = (1..=u8::MAX).find(|&x| condition.wrapping_add(x.wrapping_mul(step)) == 0)?;
+  for increment in &mut increments {
+    increment.1 = increment.1.wrapping_mul(trips);
+  }
+  Some(increments)
+}
+
+/// Net step of a loop starting at `ops[start]` whose body only adds to its condition cell, like `[--]`
+fn clear_step(ops: &[Opcode], start: usize) -> Option<u8> {
+  let Opcode::LoopStart(end) = ops[start] else { unreachable!() };
+  ops[(start + 1)..end].iter().try_fold(0u8, |step, op| match *op {
+    Opcode::Increment(0, value) => Some(step.wrapping_add(value as u8)),
+    _ => None,
+  })
+}
+
+fn fold_range(ops: &[Opcode], start: usize, end: usize, depth: usize, tape: &mut AbstractTape, output: &mut Vec<Opcode>) {
+  let mut index = start;
+  while index < end {
+    match ops[index] {
+      Opcode::Increment(offset, value) => {
+        tape.set(offset, Effect::Increment(value).apply(tape.get(offset)));
+        output.push(ops[index].clone());
+      },
+      Opcode::Set(offset, value) => {
+        //Skip stores that don't change the cell
+        if tape.get(offset).as_constant() != Some(value) {
+          tape.set(offset, Effect::Set(value).apply(tape.get(offset)));
+          output.push(ops[index].clone());
+        }
+      },
+      Opcode::MovePointer(offset) => {
+        tape.shift(offset);
+        output.push(ops[index].clone());
+      },
+      Opcode::Input(offset) => {
+        tape.set(offset, Interval::TOP);
+        output.push(ops[index].clone());
+      },
+      Opcode::Output(_) | Opcode::Flush | Opcode::Eof => {
+        output.push(ops[index].clone());
+      },
+      Opcode::ScanZero(_) => {
+        //Scans starting at a zero cell don't move
+        if tape.get(0) != Interval::ZERO {
+          *tape = AbstractTape::unknown();
+          tape.set(0, Interval::ZERO);
+          output.push(ops[index].clone());
+        }
+      },
+      Opcode::Move(ref targets) => {
+        let condition = tape.get(0);
+        if condition == Interval::ZERO {
+          index += 1;
+          continue
+        }
+        match condition.as_constant() {
+          //Moves of known values are plain increments
+          Some(value) => {
+            for &(offset, factor) in &targets[..] {
+              let effect = Effect::Increment(value.wrapping_mul(factor) as isize);
+              tape.set(offset, effect.apply(tape.get(offset)));
+              output.extend(effect.opcode(offset));
+            }
+            output.push(Opcode::Set(0, 0));
+          },
+          None => {
+            //Multiples of the factor are added, so the low bits it has clear stay as they were
+            for &(offset, factor) in &targets[..] {
+              tape.set(offset, Interval { aligned: tape.get(offset).aligned.min(factor.trailing_zeros()), ..Interval::TOP });
+            }
+            output.push(ops[index].clone());
+          },
+        }
+        tape.set(0, Interval::ZERO);
+      },
+      Opcode::LoopStart(loop_end) => {
+        let condition = tape.get(0);
+        //Loops that are never entered
+        if condition == Interval::ZERO {
+          index = loop_end + 1;
+          continue
+        }
+        //Loops with a known trip count
+        if let Some(increments) = condition.as_constant().and_then(|x| counted_loop(ops, index, x)) {
+          for (offset, value) in increments {
+            if let Some(op) = Effect::Increment(value as isize).opcode(offset) {
+              tape.set(offset, Effect::Increment(value as isize).apply(tape.get(offset)));
+              output.push(op);
+            }
+          }
+          tape.set(0, Interval::ZERO);
+          output.push(Opcode::Set(0, 0));
+          index = loop_end + 1;
+          continue
+        }
+        //Loops with an even step, which only terminate for multiples of its power of two, proven by the alignment
+        if clear_step(ops, index).is_some_and(|step| step != 0 && condition.aligned >= step.trailing_zeros()) {
+          tape.set(0, Interval::ZERO);
+          output.push(Opcode::Set(0, 0));
+          index = loop_end + 1;
+          continue
+        }
+        //Loops nested too deeply are kept as they are, without knowing anything about the tape after them
+        if depth == MAX_FOLD_DEPTH {
+          output.extend_from_slice(&ops[index..=loop_end]);
+          *tape = AbstractTape::unknown();
+          tape.set(0, Interval::ZERO);
+          index = loop_end + 1;
+          continue
+        }
+        let writes = loop_writes(ops, index);
+        //Loops that are surely entered and never write to their condition cell never exit
+        let endless = condition.lo > 0 && writes.as_ref().is_some_and(|x| !x.contains(&0));
+        //Anything the loop writes to is unknown during and after it
+        match writes {
+          Some(writes) => for offset in writes {
+            tape.set(offset, Interval::TOP);
+          },
+          None => *tape = AbstractTape::unknown(),
+        }
+        let mut body = tape.clone();
+        body.set(0, body.get(0).nonzero());
+        output.push(Opcode::LoopStart(0));
+        fold_range(ops, index + 1, loop_end, depth + 1, &mut body, output);
+        output.push(Opcode::LoopEnd(0));
+        //Nothing after an endless loop is reachable
+        if endless {
+          return
+        }
+        tape.set(0, Interval::ZERO);
+        index = loop_end;
+      },
+      Opcode::LoopEnd(_) => unreachable!(),
+      Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) | Opcode::Fused(_) => {
+        unreachable!("superinstructions are only fused after folding")
+      },
+    }
+    index += 1;
+  }
+}
+
+impl Program {
+  /// Fold statically known cell values into the program
+  ///
+  /// Removes loops that are never entered, scans that don't move and stores that don't change the cell,
+  /// and replaces loops with a known trip count by their net effect, as well as clear loops with an even step,
+  /// like `[--]`, on cells known to hold a multiple of it.
+  /// Code following a loop that never exits is unreachable and trimmed, up to the end of the enclosing loop body
+  /// or the final `Eof`. Requires linked loops
+  ///
+  /// Work and memory are bounded for adversarial programs: loops nested deeper than [`MAX_FOLD_DEPTH`] are kept
+  /// as they are, and the abstract tape forgets everything once it tracks [`MAX_TRACKED_CELLS`] cells
+  pub(crate) fn fold_known_values(ops: &[Opcode], zeroed_tape: bool) -> Vec<Opcode> {
+    let mut tape = if zeroed_tape { AbstractTape::zeroed() } else { AbstractTape::unknown() };
+    let mut output = Vec::with_capacity(ops.len());
+    //The final Eof is kept even if it is unreachable
+    fold_range(ops, 0, ops.len() - 1, 0, &mut tape, &mut output);
+    output.push(Opcode::Eof);
+    output
+  }
+}
@@ -0,0 +1,207 @@
+//! Running programs on asynchronous I/O, for async hosts (requires the `async` feature)
+//!
+//! [`AsyncRead`] and [`AsyncWrite`] have the same methods as the traits of the same names in `futures-io`,
+//! so types implementing those (or tokio's, through its compat layer) only need forwarding impls
+
+use std::{future::Future, io, pin::Pin, sync::Arc, task::{Context, Poll}};
+use super::{interpreter, BoundsPolicy, Brainfuck, EofMode, Io, PackedPosition, PackedProgram, SliceEnd};
+
+/// Bytes requested from the input at once
+const READ_CHUNK: usize = 1 << 12;
+
+/// Source of bytes read without blocking
+pub trait AsyncRead {
+  /// Read into `buffer`, returning the number of bytes read (0 at EOF), or `Poll::Pending` if none are available yet
+  fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+/// Sink of bytes written without blocking
+pub trait AsyncWrite {
+  /// Write from `buffer`, returning the number of bytes written, or `Poll::Pending` if the sink is busy
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buffer: &[u8]) -> Poll<io::Result<usize>>;
+  /// Write out anything buffered by the sink
+  fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+impl<T: AsyncRead + Unpin + ?Sized> AsyncRead for &mut T {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<io::Result<usize>> {
+    Pin::new(&mut **self).poll_read(cx, buffer)
+  }
+}
+
+impl<T: AsyncWrite + Unpin + ?Sized> AsyncWrite for &mut T {
+  fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buffer: &[u8]) -> Poll<io::Result<usize>> {
+    Pin::new(&mut **self).poll_write(cx, buffer)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut **self).poll_flush(cx)
+  }
+}
+
+impl AsyncRead for &[u8] {
+  fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<io::Result<usize>> {
+    Poll::Ready(io::Read::read(&mut *self, buffer))
+  }
+}
+
+impl AsyncWrite for Vec<u8> {
+  fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buffer: &[u8]) -> Poll<io::Result<usize>> {
+    self.get_mut().extend_from_slice(buffer);
+    Poll::Ready(Ok(buffer.len()))
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+}
+
+/// [`Io`] of an async run, fed with the input read so far and collecting output until it is written
+#[derive(Default)]
+struct Buffers {
+  input: Vec<u8>,
+  read: usize,
+  /// Whether the input reached EOF
+  ended: bool,
+  output: Vec<u8>,
+  written: usize,
+  eof: EofMode,
+}
+impl Io for Buffers {
+  fn input(&mut self) -> Option<u8> {
+    match self.input.get(self.read) {
+      Some(&byte) => {
+        self.read += 1;
+        Some(byte)
+      },
+      None => match self.eof {
+        EofMode::Unchanged => None,
+        EofMode::Zero => Some(0),
+        EofMode::Max => Some(255),
+      },
+    }
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    self.output.push(byte);
+  }
+
+  fn input_ready(&mut self) -> bool {
+    self.read < self.input.len() || self.ended
+  }
+}
+
+/// Future running a program on asynchronous I/O, see [`Brainfuck::run_async`]
+///
+/// Resolves once the program reached its end and all of its output was written and flushed
+pub struct AsyncRun<'a, R: AsyncRead + Unpin, W: AsyncWrite + Unpin> {
+  brainfuck: &'a mut Brainfuck,
+  packed: Option<PackedProgram>,
+  position: PackedPosition,
+  /// Position of the enum interpreter, used when the program doesn't run packed
+  program_counter: usize,
+  input: R,
+  output: W,
+  buffers: Buffers,
+  fuel_per_poll: usize,
+  /// How the last slice ended, `None` if the next one can run right away
+  end: Option<SliceEnd>,
+}
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Future for AsyncRun<'_, R, W> {
+  type Output = io::Result<()>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    loop {
+      let buffers = &mut this.buffers;
+      while buffers.written < buffers.output.len() {
+        match Pin::new(&mut this.output).poll_write(cx, &buffers.output[buffers.written..]) {
+          Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+          Poll::Ready(Ok(length)) => buffers.written += length,
+          Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+          Poll::Pending => return Poll::Pending,
+        }
+      }
+      buffers.output.clear();
+      buffers.written = 0;
+      match this.end {
+        Some(SliceEnd::Halted) => return Pin::new(&mut this.output).poll_flush(cx),
+        Some(SliceEnd::OutOfFuel) => {
+          //Yield to the executor, asking to be polled again right away
+          this.end = None;
+          cx.waker().wake_by_ref();
+          return Poll::Pending
+        },
+        Some(SliceEnd::AwaitingInput) => {
+          //Prompts have to reach the user before the program waits for an answer
+          if let Poll::Ready(Err(error)) = Pin::new(&mut this.output).poll_flush(cx) {
+            return Poll::Ready(Err(error))
+          }
+          buffers.input.resize(READ_CHUNK, 0);
+          buffers.read = 0;
+          match Pin::new(&mut this.input).poll_read(cx, &mut buffers.input) {
+            Poll::Ready(Ok(length)) => {
+              buffers.input.truncate(length);
+              buffers.ended = length == 0;
+              this.end = None;
+            },
+            Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+            Poll::Pending => {
+              buffers.input.clear();
+              return Poll::Pending
+            },
+          }
+        },
+        None => (),
+      }
+      let brainfuck = &mut *this.brainfuck;
+      let state = Arc::make_mut(&mut brainfuck.state);
+      this.end = Some(match &this.packed {
+        Some(packed) => packed.resume(state, buffers, this.fuel_per_poll, &mut this.position),
+        //The buffers handle EOF themselves
+        None => match interpreter::resume_with_policies(
+          &brainfuck.program,
+          state,
+          buffers,
+          EofMode::Unchanged,
+          brainfuck.bounds,
+          this.fuel_per_poll,
+          &mut this.program_counter,
+        ) {
+          Ok(end) => end,
+          Err(trap) => return Poll::Ready(Err(io::Error::other(trap))),
+        },
+      });
+    }
+  }
+}
+
+impl Brainfuck {
+  /// Run the loaded program on asynchronous I/O, executing at most `fuel_per_poll` instructions every time
+  /// the future is polled
+  ///
+  /// The future yields to the executor between slices and while waiting for input, without blocking the
+  /// thread. Output is written after every slice, and flushed before waiting for input.
+  /// Programs run packed unless they're too large or the [`BoundsPolicy`] isn't [`BoundsPolicy::Wrap`], in which
+  /// case the slices run on the slower enum interpreter. Fails with an [`io::ErrorKind::Other`] error wrapping the
+  /// [`Trap`](super::Trap) if the program traps, without writing the output of the slice it trapped in
+  pub fn run_async<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    &mut self,
+    input: R,
+    output: W,
+    fuel_per_poll: usize,
+  ) -> AsyncRun<'_, R, W> {
+    AsyncRun {
+      packed: self.program.pack().filter(|_| self.bounds == BoundsPolicy::Wrap),
+      buffers: Buffers { eof: self.eof, ..Default::default() },
+      brainfuck: self,
+      position: PackedPosition::default(),
+      program_counter: 0,
+      input,
+      output,
+      fuel_per_poll,
+      end: None,
+    }
+  }
+}
@@ -0,0 +1,73 @@
+use std::{fmt, mem, str::FromStr};
+use super::{Opcode, Span};
+
+/// Parse mode: what the compiler does with unmatched brackets
+///
+/// Real-world programs come in both styles, some relying on interpreters that ignore stray `]` and run
+/// loops left open as if they were closed at the end of the program
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BracketPolicy {
+  /// Fail with a [`CompileError`](super::CompileError)
+  #[default]
+  Strict,
+  /// Ignore stray `]`, and close loops still open at the end of the program
+  Lenient,
+}
+
+impl BracketPolicy {
+  /// Balance parsed opcodes, along with their locations, if the policy is lenient
+  ///
+  /// Loops closed at the end are located at the end of the code
+  pub(super) fn balance(&self, ops: &mut Vec<Opcode>, spans: &mut Vec<Span>) {
+    if *self == Self::Strict {
+      return
+    }
+    let mut depth = 0usize;
+    let parsed = mem::take(ops).into_iter().zip(mem::take(spans));
+    for (op, span) in parsed {
+      match op {
+        Opcode::LoopStart(_) => depth += 1,
+        Opcode::LoopEnd(_) if depth == 0 => continue,
+        Opcode::LoopEnd(_) => depth -= 1,
+        Opcode::Eof => for _ in 0..mem::take(&mut depth) {
+          ops.push(Opcode::LoopEnd(0));
+          spans.push(span.clone());
+        },
+        _ => (),
+      }
+      ops.push(op);
+      spans.push(span);
+    }
+  }
+}
+
+impl fmt::Display for BracketPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Strict => "strict",
+      Self::Lenient => "lenient",
+    })
+  }
+}
+
+/// Error returned when parsing an unknown [`BracketPolicy`] name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseBracketPolicyError(String);
+impl fmt::Display for ParseBracketPolicyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unknown parse mode `{}` (expected strict or lenient)", self.0)
+  }
+}
+impl std::error::Error for ParseBracketPolicyError {}
+
+impl FromStr for BracketPolicy {
+  type Err = ParseBracketPolicyError;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "strict" => Ok(Self::Strict),
+      "lenient" => Ok(Self::Lenient),
+      _ => Err(ParseBracketPolicyError(value.into())),
+    }
+  }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, Read, Write};
+use super::{Brainfuck, Io};
+
+/// Output buffered before it is written out even if the flush policy doesn't ask for it
+const CAPACITY: usize = 1 << 13;
+
+/// When the output buffered by [`Brainfuck::run`] is written to stdout
+///
+/// Output is also written on [`Opcode::Flush`](super::Opcode::Flush), once the buffer is full, on
+/// [`Brainfuck::flush`] and when the interpreter is dropped
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+  /// Once the program reaches its end
+  Halt,
+  /// Before every `,`, so interactive programs show their prompts
+  Input,
+  /// After every `\n`, like the standard output of a terminal
+  #[default]
+  Newline,
+  /// Once this many bytes are buffered
+  Bytes(usize),
+}
+
+/// Output of an interpreter waiting to be written to stdout
+#[derive(Debug, Default)]
+pub(super) struct OutputBuffer {
+  buffer: Vec<u8>,
+  pub(super) policy: FlushPolicy,
+}
+impl OutputBuffer {
+  pub(super) fn flush(&mut self) -> io::Result<()> {
+    if self.buffer.is_empty() {
+      return Ok(())
+    }
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&self.buffer)?;
+    self.buffer.clear();
+    stdout.flush()
+  }
+
+  /// Buffered bytes after which the output is written out
+  fn limit(&self) -> usize {
+    match self.policy {
+      FlushPolicy::Bytes(bytes) => bytes.min(CAPACITY),
+      _ => CAPACITY,
+    }
+  }
+}
+//Forks start with an empty buffer, otherwise the pending output would be written twice
+impl Clone for OutputBuffer {
+  fn clone(&self) -> Self {
+    Self { buffer: Vec::new(), policy: self.policy }
+  }
+}
+impl Drop for OutputBuffer {
+  fn drop(&mut self) {
+    let _ = self.flush();
+  }
+}
+
+/// [`Io`] reading from `input` and writing to an [`OutputBuffer`]
+///
+/// Read errors are treated as EOF, write errors stop the program and are kept in `error`
+pub(super) struct BufferedIo<'a, R: Read> {
+  pub(super) input: R,
+  pub(super) output: &'a mut OutputBuffer,
+  /// Number of bytes written by the program
+  pub(super) written: u64,
+  pub(super) error: Option<io::Error>,
+}
+impl<'a, R: Read> BufferedIo<'a, R> {
+  pub(super) fn new(input: R, output: &'a mut OutputBuffer) -> Self {
+    Self { input, output, written: 0, error: None }
+  }
+
+  fn flush_output(&mut self) {
+    if let Err(error) = self.output.flush() {
+      self.error.get_or_insert(error);
+    }
+  }
+}
+impl<R: Read> Io for BufferedIo<'_, R> {
+  fn input(&mut self) -> Option<u8> {
+    if self.output.policy == FlushPolicy::Input {
+      self.flush_output();
+    }
+    let mut byte = 0;
+    match self.input.read(std::slice::from_mut(&mut byte)) {
+      Ok(1) => Some(byte),
+      _ => None,
+    }
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    self.output.buffer.push(byte);
+    self.written += 1;
+    if (byte == b'\n' && self.output.policy == FlushPolicy::Newline) || self.output.buffer.len() >= self.output.limit() {
+      self.flush_output();
+    }
+  }
+
+  fn flush(&mut self) {
+    self.flush_output();
+  }
+
+  #[inline]
+  fn failed(&mut self) -> bool {
+    self.error.is_some()
+  }
+}
+
+impl Brainfuck {
+  /// When output of [`Brainfuck::run`] and [`Brainfuck::run_with_input`] is written to stdout
+  #[inline(always)]
+  pub fn flush_policy(&self) -> FlushPolicy {
+    self.output.policy
+  }
+
+  /// Set when output of [`Brainfuck::run`] and [`Brainfuck::run_with_input`] is written to stdout
+  #[inline]
+  pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+    self.output.policy = policy;
+  }
+
+  /// Write the buffered output to stdout
+  pub fn flush(&mut self) -> io::Result<()> {
+    self.output.flush()
+  }
+}
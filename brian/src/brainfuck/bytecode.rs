@@ -0,0 +1,276 @@
+use std::{collections::BTreeMap, fmt};
+use super::{Opcode, Program, VerifyError};
+
+/// Magic bytes at the start of every bytecode file
+const MAGIC: &[u8; 4] = b"BFBC";
+/// Version of the bytecode format, bumped on every incompatible change
+///
+/// Version 2 added `Move`, version 3 added metadata, version 4 added `Flush`, version 5 added `Fused`,
+/// older versions are still accepted
+const VERSION: u8 = 5;
+
+/// Error encountered while loading bytecode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BytecodeError {
+  /// Data doesn't start with the bytecode magic bytes
+  BadMagic,
+  /// Bytecode was produced by an incompatible version of brian
+  UnsupportedVersion(u8),
+  /// Data ends in the middle of an opcode
+  Truncated,
+  /// Unknown opcode tag at the given byte offset
+  UnknownOpcode(usize),
+  /// Operand doesn't fit into the target integer type
+  Overflow,
+  /// Loop brackets don't match
+  UnbalancedLoops,
+  /// Program is not terminated by exactly one `Eof`
+  MissingEof,
+  /// Metadata key or value is not valid UTF-8
+  InvalidMetadata,
+  /// Opcodes break an invariant of compiled programs, see [`Program::verify`]
+  InvalidProgram(VerifyError),
+}
+impl fmt::Display for BytecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::BadMagic => write!(f, "not a brian bytecode file"),
+      Self::UnsupportedVersion(version) => write!(f, "unsupported bytecode version {version} (expected at most {VERSION})"),
+      Self::Truncated => write!(f, "unexpected end of bytecode"),
+      Self::UnknownOpcode(offset) => write!(f, "unknown opcode at byte {offset}"),
+      Self::Overflow => write!(f, "operand out of range"),
+      Self::UnbalancedLoops => write!(f, "unbalanced loops"),
+      Self::MissingEof => write!(f, "program is not terminated by Eof"),
+      Self::InvalidMetadata => write!(f, "metadata is not valid UTF-8"),
+      Self::InvalidProgram(error) => write!(f, "invalid program: {error}"),
+    }
+  }
+}
+impl std::error::Error for BytecodeError {}
+
+fn write_unsigned(output: &mut Vec<u8>, mut value: u64) {
+  loop {
+    let byte = (value & 0x7f) as u8;
+    value >>= 7;
+    if value == 0 {
+      output.push(byte);
+      return
+    }
+    output.push(byte | 0x80);
+  }
+}
+
+fn write_string(output: &mut Vec<u8>, value: &str) {
+  write_unsigned(output, value.len() as u64);
+  output.extend_from_slice(value.as_bytes());
+}
+
+fn write_signed(output: &mut Vec<u8>, value: isize) {
+  //Zigzag encoding keeps small negative numbers short
+  let value = value as i64;
+  write_unsigned(output, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_opcode(output: &mut Vec<u8>, op: &Opcode) {
+  match *op {
+    Opcode::Increment(offset, value) => {
+      output.push(0);
+      write_signed(output, offset);
+      write_signed(output, value);
+    },
+    Opcode::Set(offset, value) => {
+      output.push(1);
+      write_signed(output, offset);
+      output.push(value);
+    },
+    Opcode::MovePointer(offset) => {
+      output.push(2);
+      write_signed(output, offset);
+    },
+    Opcode::LoopStart(_) => output.push(3),
+    Opcode::LoopEnd(_) => output.push(4),
+    Opcode::Output(offset) => {
+      output.push(5);
+      write_signed(output, offset);
+    },
+    Opcode::Input(offset) => {
+      output.push(6);
+      write_signed(output, offset);
+    },
+    Opcode::ScanZero(direction) => {
+      output.push(7);
+      write_signed(output, direction);
+    },
+    Opcode::MoveLoopStart(offset, _) => {
+      output.push(8);
+      write_signed(output, offset);
+    },
+    Opcode::MoveLoopEnd(offset, _) => {
+      output.push(9);
+      write_signed(output, offset);
+    },
+    Opcode::Move(ref targets) => {
+      output.push(11);
+      write_unsigned(output, targets.len() as u64);
+      for &(offset, factor) in &targets[..] {
+        write_signed(output, offset);
+        output.push(factor);
+      }
+    },
+    Opcode::Flush => output.push(12),
+    Opcode::Fused(ref ops) => {
+      output.push(13);
+      write_unsigned(output, ops.len() as u64);
+      for op in &ops[..] {
+        write_opcode(output, op);
+      }
+    },
+    Opcode::Eof => output.push(10),
+  }
+}
+
+struct Reader<'a> {
+  data: &'a [u8],
+  position: usize,
+}
+impl Reader<'_> {
+  fn byte(&mut self) -> Result<u8, BytecodeError> {
+    let byte = *self.data.get(self.position).ok_or(BytecodeError::Truncated)?;
+    self.position += 1;
+    Ok(byte)
+  }
+
+  fn unsigned(&mut self) -> Result<u64, BytecodeError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+      let byte = self.byte()?;
+      //Only the lowest bit of the tenth byte fits into 64 bits
+      if shift == 63 && byte & 0x7e != 0 {
+        return Err(BytecodeError::Overflow)
+      }
+      value |= ((byte & 0x7f) as u64) << shift;
+      if byte & 0x80 == 0 {
+        return Ok(value)
+      }
+    }
+    Err(BytecodeError::Overflow)
+  }
+
+  /// Read an opcode, which can't be another `Fused` if `in_fused`
+  fn opcode(&mut self, in_fused: bool) -> Result<Opcode, BytecodeError> {
+    let position = self.position;
+    Ok(match self.byte()? {
+      0 => Opcode::Increment(self.signed()?, self.increment()?),
+      1 => Opcode::Set(self.signed()?, self.byte()?),
+      2 => Opcode::MovePointer(self.signed()?),
+      3 => Opcode::LoopStart(0),
+      4 => Opcode::LoopEnd(0),
+      5 => Opcode::Output(self.signed()?),
+      6 => Opcode::Input(self.signed()?),
+      7 => Opcode::ScanZero(self.signed()?),
+      8 => Opcode::MoveLoopStart(self.signed()?, 0),
+      9 => Opcode::MoveLoopEnd(self.signed()?, 0),
+      10 => Opcode::Eof,
+      11 => {
+        let count = self.unsigned()?;
+        let targets = (0..count)
+          .map(|_| Ok((self.signed()?, self.byte()?)))
+          .collect::<Result<Vec<_>, _>>()?;
+        Opcode::Move(targets.into())
+      },
+      12 => Opcode::Flush,
+      13 if !in_fused => {
+        let count = self.unsigned()?;
+        let ops = (0..count).map(|_| self.opcode(true)).collect::<Result<Vec<_>, _>>()?;
+        Opcode::Fused(ops.into())
+      },
+      _ => return Err(BytecodeError::UnknownOpcode(position)),
+    })
+  }
+
+  fn string(&mut self) -> Result<String, BytecodeError> {
+    let length = usize::try_from(self.unsigned()?).map_err(|_| BytecodeError::Overflow)?;
+    let end = self.position.checked_add(length).filter(|&x| x <= self.data.len()).ok_or(BytecodeError::Truncated)?;
+    let bytes = &self.data[self.position..end];
+    self.position = end;
+    String::from_utf8(bytes.to_vec()).map_err(|_| BytecodeError::InvalidMetadata)
+  }
+
+  fn signed(&mut self) -> Result<isize, BytecodeError> {
+    let value = self.unsigned()?;
+    let value = (value >> 1) as i64 ^ -((value & 1) as i64);
+    isize::try_from(value).map_err(|_| BytecodeError::Overflow)
+  }
+
+  /// Read the value added by an `Increment`, which has to fit into an `i8` or a `u8` like it does in compiled programs
+  fn increment(&mut self) -> Result<isize, BytecodeError> {
+    Some(self.signed()?).filter(|value| (-128..=255).contains(value)).ok_or(BytecodeError::Overflow)
+  }
+}
+
+impl Program {
+  /// Check whether `data` looks like bytecode produced by [`Program::to_bytecode`]
+  pub fn is_bytecode(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+  }
+
+  /// Serialize the compiled program into a compact binary format
+  ///
+  /// Loop targets are not stored, they are recomputed when loading the program. Metadata is stored as is
+  pub fn to_bytecode(&self) -> Vec<u8> {
+    let mut output = Vec::with_capacity(MAGIC.len() + 1 + self.ops.len() * 2);
+    output.extend_from_slice(MAGIC);
+    output.push(VERSION);
+    write_unsigned(&mut output, self.metadata.len() as u64);
+    for (key, value) in &self.metadata {
+      write_string(&mut output, key);
+      write_string(&mut output, value);
+    }
+    for op in &self.ops {
+      write_opcode(&mut output, op);
+    }
+    output
+  }
+
+  /// Load a program serialized with [`Program::to_bytecode`]
+  pub fn from_bytecode(data: &[u8]) -> Result<Self, BytecodeError> {
+    if !Self::is_bytecode(data) {
+      return Err(BytecodeError::BadMagic)
+    }
+    let mut reader = Reader { data, position: MAGIC.len() };
+    let version = reader.byte()?;
+    if version == 0 || version > VERSION {
+      return Err(BytecodeError::UnsupportedVersion(version))
+    }
+    let mut metadata = BTreeMap::new();
+    if version >= 3 {
+      for _ in 0..reader.unsigned()? {
+        metadata.insert(reader.string()?, reader.string()?);
+      }
+    }
+    let mut ops = Vec::new();
+    let mut depth = 0usize;
+    while reader.position < data.len() {
+      let op = reader.opcode(false)?;
+      match op {
+        Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) => depth += 1,
+        Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) => {
+          depth = depth.checked_sub(1).ok_or(BytecodeError::UnbalancedLoops)?;
+        },
+        Opcode::Eof if reader.position != data.len() => return Err(BytecodeError::MissingEof),
+        _ => (),
+      }
+      ops.push(op);
+    }
+    if depth != 0 {
+      return Err(BytecodeError::UnbalancedLoops)
+    }
+    if !matches!(ops.last(), Some(Opcode::Eof)) {
+      return Err(BytecodeError::MissingEof)
+    }
+    Self::link_loops(&mut ops).map_err(|_| BytecodeError::UnbalancedLoops)?;
+    let program = Self { ops, metadata };
+    program.verify().map_err(BytecodeError::InvalidProgram)?;
+    Ok(program)
+  }
+}
@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+use super::{analysis::Effect, Opcode, Program};
+
+/// Emit the combined effects and pointer movement of a straight-line run, sorted by offset
+fn flush(effects: &mut BTreeMap<isize, Effect>, movement: &mut isize, output: &mut Vec<Opcode>) {
+  for (offset, effect) in std::mem::take(effects) {
+    //Cell arithmetic wraps, so only the low byte of an increment matters
+    let effect = match effect {
+      Effect::Increment(value) => Effect::Increment(value as i8 as isize),
+      effect => effect,
+    };
+    output.extend(effect.opcode(offset));
+  }
+  if *movement != 0 {
+    output.push(Opcode::MovePointer(*movement));
+  }
+  *movement = 0;
+}
+
+impl Program {
+  /// Rewrite straight-line runs of increments, stores and pointer movements into a canonical form
+  ///
+  /// Each run becomes one opcode per touched cell, ordered by offset, followed by a single pointer movement.
+  /// Programs that only differ in the order of independent cell updates end up with identical opcodes
+  pub(crate) fn canonicalize_ops(ops: &[Opcode]) -> Vec<Opcode> {
+    let mut output = Vec::with_capacity(ops.len());
+    let mut effects: BTreeMap<isize, Effect> = BTreeMap::new();
+    let mut movement: isize = 0;
+    for op in ops {
+      match op {
+        Opcode::Increment(offset, value) => {
+          let effect = effects.get(&(movement + offset)).map_or(Effect::Increment(*value), |x| x.then_increment(*value));
+          effects.insert(movement + offset, effect);
+        },
+        Opcode::Set(offset, value) => {
+          effects.insert(movement + offset, Effect::Set(*value));
+        },
+        Opcode::MovePointer(offset) => movement += offset,
+        _ => {
+          flush(&mut effects, &mut movement, &mut output);
+          output.push(op.clone());
+        },
+      }
+    }
+    flush(&mut effects, &mut movement, &mut output);
+    output
+  }
+
+  /// Get the canonical form of the program
+  ///
+  /// Programs are already canonical after compilation, this is only needed for programs built by other means
+  pub fn canonicalize(&self) -> Self {
+    let mut ops = Self::canonicalize_ops(&self.ops);
+    Self::link_loops(&mut ops).expect("canonicalization keeps loops balanced");
+    Self { ops, metadata: self.metadata.clone() }
+  }
+}
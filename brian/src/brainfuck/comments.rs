@@ -0,0 +1,93 @@
+use std::{borrow::Cow, fmt, str::FromStr};
+
+/// Comment convention of a brainfuck dialect
+///
+/// Characters outside of commands are always comments. The other styles also skip commands inside
+/// comment regions, for programs embedding prose with `.` and `,` in it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CommentStyle {
+  /// Every command character is executed
+  #[default]
+  Standard,
+  /// `;` starts a comment running to the end of the line
+  Semicolon,
+  /// `{` and `}` delimit comment regions, which may be nested
+  Braces,
+}
+
+impl CommentStyle {
+  /// Blank out comment regions with spaces
+  ///
+  /// Newlines and the length of the code are kept, so byte offsets, lines and columns stay valid
+  pub fn strip<'a>(&self, code: &'a str) -> Cow<'a, str> {
+    let opening = match self {
+      Self::Standard => return Cow::Borrowed(code),
+      Self::Semicolon => ';',
+      Self::Braces => '{',
+    };
+    if !code.contains(opening) {
+      return Cow::Borrowed(code)
+    }
+    let mut output = String::with_capacity(code.len());
+    let mut depth = 0usize;
+    for x in code.chars() {
+      let comment = match (self, x) {
+        (_, '\n') => {
+          depth *= (*self == Self::Braces) as usize;
+          false
+        },
+        (Self::Semicolon, ';') => {
+          depth = 1;
+          true
+        },
+        (Self::Braces, '{') => {
+          depth += 1;
+          true
+        },
+        (Self::Braces, '}') if depth > 0 => {
+          depth -= 1;
+          true
+        },
+        _ => depth > 0,
+      };
+      match comment {
+        true => output.extend((0..x.len_utf8()).map(|_| ' ')),
+        false => output.push(x),
+      }
+    }
+    Cow::Owned(output)
+  }
+}
+
+impl fmt::Display for CommentStyle {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Standard => "standard",
+      Self::Semicolon => "semicolon",
+      Self::Braces => "braces",
+    })
+  }
+}
+
+/// Error returned when parsing an unknown [`CommentStyle`] name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseCommentStyleError(String);
+impl fmt::Display for ParseCommentStyleError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unknown comment style `{}` (expected standard, semicolon or braces)", self.0)
+  }
+}
+impl std::error::Error for ParseCommentStyleError {}
+
+impl FromStr for CommentStyle {
+  type Err = ParseCommentStyleError;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "standard" => Ok(Self::Standard),
+      "semicolon" => Ok(Self::Semicolon),
+      "braces" => Ok(Self::Braces),
+      _ => Err(ParseCommentStyleError(value.into())),
+    }
+  }
+}
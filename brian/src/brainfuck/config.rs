@@ -0,0 +1,348 @@
+use std::{fmt, str::FromStr};
+use super::{Brainfuck, CommentStyle, CompileOptions, FlushPolicy, MEMORY_SIZE};
+
+/// Number of cells of the tape in canonical brainfuck
+pub(crate) const CANONICAL_TAPE_LENGTH: usize = 30000;
+
+/// Effect of `,` when there is no more input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EofMode {
+  /// Leave the cell unchanged
+  #[default]
+  Unchanged,
+  /// Set the cell to 0
+  Zero,
+  /// Set the cell to 255
+  Max,
+}
+
+impl fmt::Display for EofMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Unchanged => "unchanged",
+      Self::Zero => "zero",
+      Self::Max => "max",
+    })
+  }
+}
+
+/// Error returned when parsing an unknown [`EofMode`] name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseEofModeError(String);
+impl fmt::Display for ParseEofModeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unknown EOF mode `{}` (expected unchanged, zero or max)", self.0)
+  }
+}
+impl std::error::Error for ParseEofModeError {}
+
+impl FromStr for EofMode {
+  type Err = ParseEofModeError;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "unchanged" => Ok(Self::Unchanged),
+      "zero" => Ok(Self::Zero),
+      "max" => Ok(Self::Max),
+      _ => Err(ParseEofModeError(value.into())),
+    }
+  }
+}
+
+/// Behavior of cell arithmetic leaving the `0..=255` range
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+  /// Wrap around
+  #[default]
+  Wrap,
+  /// Stop with [`TrapKind::CellOverflow`]
+  Trap,
+}
+
+/// Behavior of the pointer leaving the tape
+///
+/// Growing the tape on demand is not supported: the tape is a fixed array of 65536 cells, which every backend and the
+/// optimizer rely on to address cells with a mask. Asking for a `grow` policy fails with [`ParseBoundsPolicyError::Grow`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoundsPolicy {
+  /// Wrap around to the other end of the tape
+  #[default]
+  Wrap,
+  /// Stop with [`TrapKind::PointerOutOfBounds`]
+  Trap,
+  /// Stop with [`TrapKind::PointerOutOfBounds`] on leaving the first 30000 cells,
+  /// as if the tape was only as long as in [`Compatibility::Canonical`]
+  TrapCanonical,
+}
+
+impl fmt::Display for BoundsPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Wrap => "wrap",
+      Self::Trap => "trap",
+      Self::TrapCanonical => "trap-canonical",
+    })
+  }
+}
+
+/// Error returned when parsing a [`BoundsPolicy`] name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseBoundsPolicyError {
+  /// Not the name of any policy
+  Unknown(String),
+  /// The `grow` policy, which the fixed-length tape doesn't support
+  Grow,
+}
+impl fmt::Display for ParseBoundsPolicyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Unknown(value) => write!(f, "unknown bounds policy `{value}` (expected wrap, trap or trap-canonical)"),
+      Self::Grow => f.write_str("the tape has a fixed length, growing it is not supported (expected wrap, trap or trap-canonical)"),
+    }
+  }
+}
+impl std::error::Error for ParseBoundsPolicyError {}
+
+impl FromStr for BoundsPolicy {
+  type Err = ParseBoundsPolicyError;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "wrap" => Ok(Self::Wrap),
+      "trap" => Ok(Self::Trap),
+      "trap-canonical" => Ok(Self::TrapCanonical),
+      "grow" => Err(ParseBoundsPolicyError::Grow),
+      _ => Err(ParseBoundsPolicyError::Unknown(value.into())),
+    }
+  }
+}
+
+/// Reason for stopping a program early
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapKind {
+  CellOverflow,
+  PointerOutOfBounds,
+}
+
+/// Program stopped by [`OverflowPolicy::Trap`] or [`BoundsPolicy::Trap`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trap {
+  pub kind: TrapKind,
+  /// Index of the opcode that trapped
+  pub instruction: usize,
+}
+impl fmt::Display for Trap {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let kind = match self.kind {
+      TrapKind::CellOverflow => "cell overflow",
+      TrapKind::PointerOutOfBounds => "pointer out of bounds",
+    };
+    write!(f, "{kind} at instruction {}", self.instruction)
+  }
+}
+impl std::error::Error for Trap {}
+
+/// Interpreter configuration resolved at compile time
+///
+/// The interpreter is monomorphized for every configuration, so unused policies cost nothing in the hot loop.
+/// Note that the optimizer merges and reorders cell updates and pointer movements, so traps are only exact
+/// for programs compiled with [`Program::compile_without_optimizations`](crate::Program::compile_without_optimizations)
+pub trait Config {
+  const EOF: EofMode;
+  const OVERFLOW: OverflowPolicy;
+  const BOUNDS: BoundsPolicy;
+}
+
+/// Configuration used by [`Brainfuck::run`](crate::Brainfuck::run) and friends, unless another [`EofMode`] is set
+pub struct DefaultConfig;
+impl Config for DefaultConfig {
+  const EOF: EofMode = EofMode::Unchanged;
+  const OVERFLOW: OverflowPolicy = OverflowPolicy::Wrap;
+  const BOUNDS: BoundsPolicy = BoundsPolicy::Wrap;
+}
+
+/// Configuration built from const parameters, used to dispatch [`InterpreterConfig`]
+pub(crate) struct StaticConfig<const EOF: u8, const TRAP_OVERFLOW: bool, const BOUNDS: u8>;
+impl<const EOF: u8, const TRAP_OVERFLOW: bool, const BOUNDS: u8> Config for StaticConfig<EOF, TRAP_OVERFLOW, BOUNDS> {
+  const EOF: EofMode = match EOF {
+    0 => EofMode::Unchanged,
+    1 => EofMode::Zero,
+    _ => EofMode::Max,
+  };
+  const OVERFLOW: OverflowPolicy = if TRAP_OVERFLOW { OverflowPolicy::Trap } else { OverflowPolicy::Wrap };
+  const BOUNDS: BoundsPolicy = match BOUNDS {
+    0 => BoundsPolicy::Wrap,
+    1 => BoundsPolicy::Trap,
+    _ => BoundsPolicy::TrapCanonical,
+  };
+}
+
+/// Interpreter configuration chosen at run time
+///
+/// Dispatches to an interpreter monomorphized for the chosen policies, see [`Config`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InterpreterConfig {
+  pub eof: EofMode,
+  pub overflow: OverflowPolicy,
+  pub bounds: BoundsPolicy,
+}
+
+/// Preset matching the observable behavior of a reference implementation, for using brian as a drop-in replacement
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compatibility {
+  /// Defaults of brian: 65536 cells, with the pointer wrapping around the ends of the tape
+  #[default]
+  Brian,
+  /// Canonical brainfuck, as described on the Esolang wiki and implemented by Urban Müller's original interpreter:
+  /// 30000 8-bit wrapping cells with the pointer starting on the leftmost one, `,` leaving the cell unchanged at EOF,
+  /// and every other character (`#` and `!` included) ignored
+  ///
+  /// Moving off either end of the 30000 cells traps with [`TrapKind::PointerOutOfBounds`], see [`BoundsPolicy::TrapCanonical`]
+  Canonical,
+}
+
+impl Compatibility {
+  /// Number of cells guaranteed by the reference
+  pub const fn tape_length(self) -> usize {
+    match self {
+      Self::Brian => MEMORY_SIZE,
+      Self::Canonical => CANONICAL_TAPE_LENGTH,
+    }
+  }
+
+  /// Run time policies of the reference
+  pub fn interpreter_config(self) -> InterpreterConfig {
+    match self {
+      Self::Brian => InterpreterConfig::default(),
+      Self::Canonical => InterpreterConfig { eof: EofMode::Unchanged, overflow: OverflowPolicy::Wrap, bounds: BoundsPolicy::TrapCanonical },
+    }
+  }
+
+  /// Compiler options treating the source code like the reference does, with optimizations that keep its behavior
+  pub fn compile_options(self) -> CompileOptions {
+    CompileOptions { assume_zeroed_tape: true, comments: CommentStyle::Standard, flush: None, ..Default::default() }
+  }
+}
+
+impl fmt::Display for Compatibility {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Brian => "brian",
+      Self::Canonical => "canonical",
+    })
+  }
+}
+
+/// Error returned when parsing an unknown [`Compatibility`] name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseCompatibilityError(String);
+impl fmt::Display for ParseCompatibilityError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unknown compatibility preset `{}` (expected brian or canonical)", self.0)
+  }
+}
+impl std::error::Error for ParseCompatibilityError {}
+
+impl FromStr for Compatibility {
+  type Err = ParseCompatibilityError;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "brian" => Ok(Self::Brian),
+      "canonical" => Ok(Self::Canonical),
+      _ => Err(ParseCompatibilityError(value.into())),
+    }
+  }
+}
+
+/// How the program loaded into an interpreter was compiled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Compilation {
+  /// With optimizations, using these options
+  Optimized(CompileOptions),
+  /// Without optimizations, which also covers the empty program of a new interpreter
+  Unoptimized,
+  /// Compiled elsewhere and loaded with [`Brainfuck::load_program`], so the options are unknown
+  Loaded,
+}
+
+/// Full effective configuration of an interpreter, see [`Brainfuck::config`]
+///
+/// Displays as a canonical reproduction string, listing every setting in a fixed order with defaults spelled out,
+/// e.g. `brian 0.1.0 opt=on zeroed-tape=off dead-stores=off dataflow=off time-budget=none comments=standard
+/// brackets=strict flush-char=none eof=unchanged bounds=wrap flush=newline steps=none tape=65536`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunConfig {
+  pub compilation: Compilation,
+  pub eof: EofMode,
+  /// See [`Brainfuck::set_bounds_policy`]
+  pub bounds: BoundsPolicy,
+  pub flush: FlushPolicy,
+  /// See [`Brainfuck::set_step_limit`]
+  pub step_limit: Option<usize>,
+  /// Number of cells on the tape
+  pub tape_length: usize,
+}
+impl Default for RunConfig {
+  fn default() -> Self {
+    Self {
+      compilation: Compilation::Unoptimized,
+      eof: EofMode::default(),
+      bounds: BoundsPolicy::default(),
+      flush: FlushPolicy::default(),
+      step_limit: None,
+      tape_length: MEMORY_SIZE,
+    }
+  }
+}
+impl fmt::Display for RunConfig {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let switch = |x: bool| if x { "on" } else { "off" };
+    write!(f, "brian {}", env!("CARGO_PKG_VERSION"))?;
+    match &self.compilation {
+      Compilation::Optimized(options) => {
+        write!(f, " opt=on zeroed-tape={}", switch(options.assume_zeroed_tape))?;
+        write!(f, " dead-stores={} dataflow={}", switch(options.drop_dead_stores), switch(options.dataflow))?;
+        match options.time_budget {
+          Some(budget) => write!(f, " time-budget={}us", budget.as_micros())?,
+          None => f.write_str(" time-budget=none")?,
+        }
+        write!(f, " comments={} brackets={}", options.comments, options.brackets)?;
+        match options.flush {
+          Some(flush) => write!(f, " flush-char={flush:?}")?,
+          None => f.write_str(" flush-char=none")?,
+        }
+      },
+      Compilation::Unoptimized => f.write_str(" opt=off")?,
+      Compilation::Loaded => f.write_str(" opt=unknown")?,
+    }
+    write!(f, " eof={} bounds={} flush=", self.eof, self.bounds)?;
+    match self.flush {
+      FlushPolicy::Halt => f.write_str("halt")?,
+      FlushPolicy::Input => f.write_str("input")?,
+      FlushPolicy::Newline => f.write_str("newline")?,
+      FlushPolicy::Bytes(bytes) => write!(f, "bytes:{bytes}")?,
+    }
+    match self.step_limit {
+      Some(limit) => write!(f, " steps={limit}")?,
+      None => f.write_str(" steps=none")?,
+    }
+    write!(f, " tape={}", self.tape_length)
+  }
+}
+
+impl Brainfuck {
+  /// Get the full effective configuration of the interpreter
+  ///
+  /// Its [`Display`](fmt::Display) form is a reproduction string for bug reports, see [`RunConfig`]
+  pub fn config(&self) -> RunConfig {
+    RunConfig {
+      compilation: self.compilation.clone(),
+      eof: self.eof,
+      bounds: self.bounds,
+      flush: self.output.policy,
+      step_limit: self.step_limit,
+      tape_length: self.state.memory.len(),
+    }
+  }
+}
@@ -0,0 +1,67 @@
+use super::Opcode;
+
+/// Weights of opcode kinds for the virtual cycle count of [`Brainfuck::run_with_cost`](crate::Brainfuck::run_with_cost)
+///
+/// Virtual cycles don't depend on the hardware or on timing noise, so they can be compared exactly across machines and CI runs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CostModel {
+  pub increment: u64,
+  pub set: u64,
+  pub move_pointer: u64,
+  /// Cost of a loop bracket, including the fused forms
+  pub branch: u64,
+  /// Cost of starting a scan
+  pub scan: u64,
+  /// Cost of every cell visited by a scan
+  pub scan_step: u64,
+  pub output: u64,
+  pub input: u64,
+}
+impl CostModel {
+  /// Model charging one cycle for every opcode and scan step
+  pub const UNIT: Self = Self {
+    increment: 1,
+    set: 1,
+    move_pointer: 1,
+    branch: 1,
+    scan: 1,
+    scan_step: 1,
+    output: 1,
+    input: 1,
+  };
+
+  /// Cost of dispatching `op` (not including scan steps)
+  #[inline(always)]
+  pub fn cost(&self, op: &Opcode) -> u64 {
+    match op {
+      Opcode::Increment(..) => self.increment,
+      Opcode::Set(..) => self.set,
+      Opcode::MovePointer(_) => self.move_pointer,
+      Opcode::LoopStart(_) | Opcode::LoopEnd(_) => self.branch,
+      //Fused opcodes save a dispatch, but still do both jobs
+      Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) => self.move_pointer + self.branch,
+      Opcode::Fused(ops) => ops.iter().map(|op| self.cost(op)).sum(),
+      Opcode::ScanZero(_) => self.scan,
+      //Checks the cell like a loop, then updates every target and clears the cell
+      Opcode::Move(targets) => self.branch + self.increment * targets.len() as u64 + self.set,
+      Opcode::Output(_) | Opcode::Flush => self.output,
+      Opcode::Input(_) => self.input,
+      Opcode::Eof => 0,
+    }
+  }
+}
+impl Default for CostModel {
+  /// Rough model of a real interpreter: branches and I/O are more expensive than cell arithmetic
+  fn default() -> Self {
+    Self {
+      increment: 1,
+      set: 1,
+      move_pointer: 1,
+      branch: 2,
+      scan: 2,
+      scan_step: 1,
+      output: 20,
+      input: 20,
+    }
+  }
+}
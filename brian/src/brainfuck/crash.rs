@@ -0,0 +1,88 @@
+//! Context captured when a program traps, for bug reports and debugging
+
+use std::{fmt, ops::{ControlFlow, Range}};
+use super::{brainfuck_tokens_indexed, BrainfuckState, Hooks, Opcode, Program, StateView, Trap, MEMORY_MASK};
+
+/// Number of most recently executed opcodes kept in a [`CrashContext`]
+const HISTORY_LENGTH: usize = 16;
+/// Number of cells kept on each side of the pointer in a [`CrashContext`]
+const TAPE_WINDOW: usize = 8;
+
+/// [`Hooks`] remembering the indices of the most recently executed opcodes
+#[derive(Clone, Debug, Default)]
+pub(crate) struct History {
+  /// Ring buffer of opcode indices
+  recent: [usize; HISTORY_LENGTH],
+  executed: usize,
+}
+impl History {
+  /// Remembered opcode indices, oldest first
+  fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+    let kept = self.executed.min(HISTORY_LENGTH);
+    (self.executed - kept..self.executed).map(|x| self.recent[x % HISTORY_LENGTH])
+  }
+}
+impl Hooks for History {
+  #[inline(always)]
+  fn before(&mut self, program_counter: usize, _op: &Opcode, _state: StateView) -> ControlFlow<()> {
+    self.recent[self.executed % HISTORY_LENGTH] = program_counter;
+    self.executed += 1;
+    ControlFlow::Continue(())
+  }
+}
+
+/// State of a program at the moment it trapped, see [`InterpreterConfig::run_with_context`](super::InterpreterConfig::run_with_context)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrashContext {
+  pub trap: Trap,
+  /// Last executed opcodes with their indices, oldest first, ending with the one that trapped
+  pub history: Vec<(usize, Opcode)>,
+  /// Tape index the pointer was at, negative or past the end of the tape if it left it
+  pub pointer: isize,
+  /// Tape index of the first cell of [`CrashContext::tape`]
+  pub tape_start: usize,
+  /// Cells around the pointer
+  pub tape: Vec<u8>,
+}
+impl CrashContext {
+  pub(crate) fn capture(trap: Trap, history: &History, program: &Program, state: &BrainfuckState) -> Self {
+    //A pointer that left the tape is shown next to the edge it left through
+    let pointer = (state.pointer as isize).clamp(0, MEMORY_MASK as isize) as usize;
+    let tape_start = pointer.saturating_sub(TAPE_WINDOW);
+    let tape_end = (pointer + TAPE_WINDOW + 1).min(MEMORY_MASK + 1);
+    Self {
+      trap,
+      history: history.iter().map(|x| (x, program.ops[x].clone())).collect(),
+      pointer: state.pointer as isize,
+      tape_start,
+      tape: state.memory[tape_start..tape_end].to_vec(),
+    }
+  }
+
+  /// Byte range of the trapping command in `code`
+  ///
+  /// Only meaningful if the program was compiled from `code` with
+  /// [`Program::compile_without_optimizations`], which keeps one opcode per command
+  pub fn source_span(&self, code: &str) -> Option<Range<usize>> {
+    let (offset, _) = brainfuck_tokens_indexed(code).nth(self.trap.instruction)?;
+    Some(offset..(offset + 1))
+  }
+}
+impl fmt::Display for CrashContext {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{}", self.trap)?;
+    writeln!(f, "last executed opcodes:")?;
+    for (index, op) in &self.history {
+      writeln!(f, "  {index:>8}: {op:?}")?;
+    }
+    match usize::try_from(self.pointer) {
+      Ok(pointer) if pointer <= MEMORY_MASK => writeln!(f, "pointer: {pointer}")?,
+      _ => writeln!(f, "pointer: {} (outside the tape)", self.pointer)?,
+    }
+    let cells: Vec<String> = self.tape.iter().enumerate().map(|(index, cell)| match self.tape_start + index {
+      address if address as isize == self.pointer => format!("[{cell}]"),
+      _ => cell.to_string(),
+    }).collect();
+    write!(f, "tape from cell {}: {}", self.tape_start, cells.join(" "))
+  }
+}
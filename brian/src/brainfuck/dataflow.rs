@@ -0,0 +1,278 @@
+//! Dataflow intermediate representation of balanced regions
+//!
+//! A region is a run of cell updates, pointer movements, moves and balanced counted loops without I/O.
+//! Every cell update creates a new value in a hash-consed graph (so equal expressions share a single node),
+//! and loops are replaced by multiplications of their trip count instead of repeated additions.
+//! The final cell values are then lowered back into opcodes, which often needs fewer loops than the source
+
+use std::collections::{BTreeMap, HashMap};
+use super::{Opcode, Program};
+
+/// Index of a node in [`Region::nodes`]
+type Value = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Node {
+  /// Value of a cell at the start of the region
+  Cell(isize),
+  Const(u8),
+  Add(Value, Value),
+  Mul(Value, u8),
+}
+
+/// Linear combination of initial cell values (`constant + sum(cell * factor)`), all arithmetic wrapping
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Linear {
+  constant: u8,
+  terms: BTreeMap<isize, u8>,
+}
+
+/// Dataflow graph of a single region
+#[derive(Default)]
+struct Region {
+  nodes: Vec<Node>,
+  interned: HashMap<Node, Value>,
+  /// Current value of every cell touched so far, keyed by offset from the start of the region
+  cells: BTreeMap<isize, Value>,
+  /// Pointer position relative to the start of the region
+  pointer: isize,
+}
+impl Region {
+  /// Get the value of a node, reusing an existing one if the same expression was already built
+  fn intern(&mut self, node: Node) -> Value {
+    if let Some(&value) = self.interned.get(&node) {
+      return value
+    }
+    self.nodes.push(node);
+    self.interned.insert(node, self.nodes.len() - 1);
+    self.nodes.len() - 1
+  }
+
+  fn constant(&mut self, value: u8) -> Value {
+    self.intern(Node::Const(value))
+  }
+
+  fn add(&mut self, a: Value, b: Value) -> Value {
+    match (self.nodes[a], self.nodes[b]) {
+      (Node::Const(0), _) => b,
+      (_, Node::Const(0)) => a,
+      (Node::Const(x), Node::Const(y)) => self.constant(x.wrapping_add(y)),
+      //Operands are ordered, so that commuted additions are shared too
+      _ => self.intern(Node::Add(a.min(b), a.max(b))),
+    }
+  }
+
+  fn mul(&mut self, a: Value, factor: u8) -> Value {
+    match (self.nodes[a], factor) {
+      (_, 0) => self.constant(0),
+      (_, 1) => a,
+      (Node::Const(x), _) => self.constant(x.wrapping_mul(factor)),
+      (Node::Mul(inner, y), _) => self.mul(inner, y.wrapping_mul(factor)),
+      _ => self.intern(Node::Mul(a, factor)),
+    }
+  }
+
+  /// Current value of the cell at `offset` from the pointer
+  fn get(&mut self, offset: isize) -> Value {
+    let cell = self.pointer + offset;
+    match self.cells.get(&cell) {
+      Some(&value) => value,
+      None => self.intern(Node::Cell(cell)),
+    }
+  }
+
+  fn set(&mut self, offset: isize, value: Value) {
+    self.cells.insert(self.pointer + offset, value);
+  }
+
+  /// Apply a loop whose body only consists of increments and doesn't move the pointer
+  ///
+  /// Returns `false` if the loop doesn't terminate for every initial value of its condition cell
+  fn apply_loop(&mut self, body: &[(isize, u8)]) -> bool {
+    let step = body.iter().filter(|x| x.0 == 0).fold(0u8, |sum, x| sum.wrapping_add(x.1));
+    //Only odd steps reach zero from every value, in `-value * step^-1` trips
+    if step % 2 == 0 {
+      return false
+    }
+    let inverse = (0..=u8::MAX).find(|&x| x.wrapping_mul(step) == 1).unwrap();
+    let condition = self.get(0);
+    let trips = self.mul(condition, inverse.wrapping_neg());
+    for &(offset, increment) in body.iter().filter(|x| x.0 != 0) {
+      let value = self.get(offset);
+      let added = self.mul(trips, increment);
+      let value = self.add(value, added);
+      self.set(offset, value);
+    }
+    let zero = self.constant(0);
+    self.set(0, zero);
+    true
+  }
+
+  /// Express every node as a linear combination of initial cell values
+  fn linearize(&self) -> Vec<Linear> {
+    let mut linear: Vec<Linear> = Vec::with_capacity(self.nodes.len());
+    //Operands are always created before the nodes using them
+    for node in &self.nodes {
+      let value = match *node {
+        Node::Cell(cell) => Linear { constant: 0, terms: BTreeMap::from([(cell, 1)]) },
+        Node::Const(value) => Linear { constant: value, terms: BTreeMap::new() },
+        Node::Add(a, b) => {
+          let mut sum = linear[a].clone();
+          sum.constant = sum.constant.wrapping_add(linear[b].constant);
+          for (&cell, &factor) in &linear[b].terms {
+            let term = sum.terms.entry(cell).or_default();
+            *term = term.wrapping_add(factor);
+          }
+          sum.terms.retain(|_, factor| *factor != 0);
+          sum
+        },
+        Node::Mul(a, factor) => Linear {
+          constant: linear[a].constant.wrapping_mul(factor),
+          terms: linear[a].terms.iter()
+            .map(|(&cell, &x)| (cell, x.wrapping_mul(factor)))
+            .filter(|x| x.1 != 0)
+            .collect(),
+        },
+      };
+      linear.push(value);
+    }
+    linear
+  }
+
+  /// Lower the region back into opcodes, leaving the pointer at the end of the region
+  ///
+  /// Every cell read by other cells becomes the source of one move distributing its value,
+  /// so this only succeeds if such cells end up constant and aren't updated by other moves
+  fn lower(&self) -> Option<(Vec<Opcode>, usize)> {
+    let linear = self.linearize();
+    let finals: BTreeMap<isize, &Linear> = self.cells.iter().map(|(&cell, &value)| (cell, &linear[value])).collect();
+    let mut sources: BTreeMap<isize, Vec<(isize, u8)>> = BTreeMap::new();
+    for (&cell, value) in &finals {
+      for (&source, &factor) in value.terms.iter().filter(|x| *x.0 != cell) {
+        sources.entry(source).or_default().push((cell, factor));
+      }
+    }
+    //Loops can only distribute values of cells they end up overwriting
+    if sources.keys().any(|x| !finals.contains_key(x)) {
+      return None
+    }
+    let mut clears = Vec::new();
+    let mut updates = Vec::new();
+    for (&cell, value) in &finals {
+      let own = value.terms.get(&cell).copied().unwrap_or(0);
+      if sources.contains_key(&cell) {
+        //Moves zero their source, and reading it after another move added to it would be wrong
+        if !value.terms.is_empty() || sources.values().flatten().any(|x| x.0 == cell) {
+          return None
+        }
+        updates.push(Opcode::Increment(cell, value.constant as isize));
+        continue
+      }
+      let reads_sources = value.terms.len() > own.min(1) as usize;
+      match own {
+        0 if reads_sources => clears.push(Opcode::Set(cell, 0)),
+        0 => {
+          updates.push(Opcode::Set(cell, value.constant));
+          continue
+        },
+        1 => (),
+        _ => return None,
+      }
+      updates.push(Opcode::Increment(cell, value.constant as isize));
+    }
+
+    let mut output = clears;
+    let mut pointer = 0;
+    for (&source, targets) in &sources {
+      if source != pointer {
+        output.push(Opcode::MovePointer(source - pointer));
+        pointer = source;
+      }
+      output.push(Opcode::Move(targets.iter().map(|&(cell, factor)| (cell - source, factor)).collect()));
+    }
+    //Constant parts are applied last, relative to the final pointer of the moves
+    output.extend(updates.into_iter().filter_map(|op| match op {
+      Opcode::Increment(_, 0) => None,
+      Opcode::Increment(cell, value) => Some(Opcode::Increment(cell - pointer, value as u8 as i8 as isize)),
+      Opcode::Set(cell, value) => Some(Opcode::Set(cell - pointer, value)),
+      _ => unreachable!(),
+    }));
+    if self.pointer != pointer {
+      output.push(Opcode::MovePointer(self.pointer - pointer));
+    }
+    Some((output, sources.len()))
+  }
+}
+
+/// Body of a balanced loop consisting only of increments, starting at `ops[start]`
+///
+/// Returns the increments keyed by offset and the index of the matching `LoopEnd`
+fn simple_loop(ops: &[Opcode], start: usize) -> Option<(Vec<(isize, u8)>, usize)> {
+  let mut body = Vec::new();
+  let mut pointer = 0;
+  for (index, op) in ops.iter().enumerate().skip(start + 1) {
+    match *op {
+      Opcode::Increment(offset, value) => body.push((pointer + offset, value as u8)),
+      Opcode::MovePointer(offset) => pointer += offset,
+      Opcode::LoopEnd(_) if pointer == 0 => return Some((body, index)),
+      _ => return None,
+    }
+  }
+  None
+}
+
+impl Program {
+  /// Rebuild balanced regions from their dataflow representation
+  ///
+  /// Regions that can't be lowered, or wouldn't get fewer loops or opcodes, are kept as they are.
+  /// Doesn't require linked loops, and leaves them unlinked
+  pub(crate) fn optimize_dataflow(ops: &[Opcode]) -> Vec<Opcode> {
+    let mut output = Vec::with_capacity(ops.len());
+    let mut index = 0;
+    while index < ops.len() {
+      let start = index;
+      let mut region = Region::default();
+      let mut loops = 0;
+      while index < ops.len() {
+        match ops[index] {
+          Opcode::Increment(offset, value) => {
+            let cell = region.get(offset);
+            let increment = region.constant(value as u8);
+            let value = region.add(cell, increment);
+            region.set(offset, value);
+          },
+          Opcode::Set(offset, value) => {
+            let value = region.constant(value);
+            region.set(offset, value);
+          },
+          Opcode::MovePointer(offset) => region.pointer += offset,
+          Opcode::Move(ref targets) => {
+            let body: Vec<(isize, u8)> = [(0, u8::MAX)].into_iter().chain(targets.iter().copied()).collect();
+            region.apply_loop(&body);
+            loops += 1;
+          },
+          Opcode::LoopStart(_) => {
+            let Some((body, end)) = simple_loop(ops, index) else { break };
+            if !region.apply_loop(&body) { break }
+            loops += 1;
+            index = end;
+          },
+          _ => break,
+        }
+        index += 1;
+      }
+      match region.lower() {
+        Some((lowered, lowered_loops)) if loops > 0 && (lowered_loops, lowered.len()) < (loops, index - start) => {
+          output.extend(lowered);
+        },
+        _ => output.extend_from_slice(&ops[start..index]),
+      }
+      //The opcode ending the region is kept as is
+      if index < ops.len() {
+        output.push(ops[index].clone());
+        index += 1;
+      }
+    }
+    output
+  }
+}
@@ -0,0 +1,35 @@
+use std::fmt::Write as _;
+use super::{BrainfuckState, MEMORY_MASK};
+
+/// Cells shown on each row of [`BrainfuckState::dump_strided`]
+const ROW_CELLS: usize = 16;
+
+impl BrainfuckState {
+  /// Cells at `start`, `start + stride`, `start + 2 * stride`..., wrapping around the tape, paired with their addresses
+  pub fn strided(&self, start: usize, stride: usize) -> impl Iterator<Item = (usize, u8)> + '_ {
+    (0..).map(move |x: usize| {
+      let address = start.wrapping_add(x.wrapping_mul(stride)) & MEMORY_MASK;
+      (address, self.memory[address])
+    })
+  }
+
+  /// Hex dump of `rows` rows of 16 cells, sampling every `stride`-th cell from `start` (see [`BrainfuckState::strided`])
+  ///
+  /// A large stride gives an overview of a wide part of the tape, e.g. to see how far a runaway scan went.
+  /// Every row starts with the address of its first cell, the cell under the pointer is marked with `*`
+  pub fn dump_strided(&self, start: usize, stride: usize, rows: usize) -> String {
+    let pointer = self.pointer_address();
+    let mut cells = self.strided(start, stride);
+    let mut dump = String::new();
+    for _ in 0..rows {
+      let row: Vec<_> = cells.by_ref().take(ROW_CELLS).collect();
+      write!(dump, "{:>5}:", row[0].0).unwrap();
+      for (address, value) in row {
+        let marker = if address == pointer { '*' } else { ' ' };
+        write!(dump, "{marker}{value:02x}").unwrap();
+      }
+      dump.push('\n');
+    }
+    dump
+  }
+}
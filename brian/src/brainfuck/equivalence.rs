@@ -0,0 +1,15 @@
+use crate::stress::{self, Verification};
+use super::{Brainfuck, CompileError, Program};
+
+impl Brainfuck {
+  /// Run `code` both optimized and unoptimized on the same `input`, and compare the outputs and final tapes
+  ///
+  /// Both runs start from a fresh tape and use this interpreter's EOF mode. They execute at most
+  /// [`step_limit`](Brainfuck::step_limit) instructions each, giving [`Verification::Inconclusive`] if either
+  /// doesn't finish. A [`Verification::Diverged`] locates the first differing output byte in `code`, and lists the
+  /// cells that differ at the end. The interpreter itself is left untouched
+  pub fn check_equivalence(&self, code: &str, input: &[u8]) -> Result<Verification, CompileError> {
+    Program::check_brackets(code)?;
+    Ok(stress::verify_with_eof(code, input, self.step_limit.unwrap_or(usize::MAX), self.eof))
+  }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use super::{Opcode, Program};
+
+/// Tape cells a program can access, relative to the starting cell, found by static analysis
+///
+/// Loops moving the pointer by a varying or non-zero amount per iteration, and scans, are assumed to run for any
+/// number of iterations, which makes the footprint unbounded in the direction they move in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TapeFootprint {
+  /// Lowest accessed offset, or `None` if unbounded
+  pub lowest: Option<isize>,
+  /// Highest accessed offset, or `None` if unbounded
+  pub highest: Option<isize>,
+}
+impl TapeFootprint {
+  /// Number of cells between the lowest and the highest accessed offsets, or `None` if unbounded
+  pub fn cells(&self) -> Option<usize> {
+    Some(self.highest?.abs_diff(self.lowest?) + 1)
+  }
+
+  /// Whether every access provably stays within a tape of `length` cells, starting at the first one,
+  /// so the pointer never has to wrap around
+  ///
+  /// [`InterpreterConfig::run`](super::InterpreterConfig::run) doesn't check the bounds of programs that fit the tape
+  pub fn fits(&self, length: usize) -> bool {
+    matches!((self.lowest, self.highest), (Some(lowest), Some(highest)) if lowest >= 0 && highest.unsigned_abs() < length)
+  }
+}
+impl fmt::Display for TapeFootprint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(lowest) = self.lowest {
+      write!(f, "{lowest}")?;
+    }
+    f.write_str("..")?;
+    match self.highest {
+      Some(highest) => write!(f, "={highest}"),
+      None => Ok(()),
+    }
+  }
+}
+
+/// Range of offsets, unbounded on a side if `None` (also used for offsets too large for `isize`)
+#[derive(Clone, Copy)]
+struct Interval {
+  low: Option<isize>,
+  high: Option<isize>,
+}
+impl Interval {
+  const ZERO: Self = Self { low: Some(0), high: Some(0) };
+
+  fn offset(self, offset: isize) -> Self {
+    Self { low: self.low.and_then(|x| x.checked_add(offset)), high: self.high.and_then(|x| x.checked_add(offset)) }
+  }
+
+  /// Every sum of an offset in `self` and one in `other`
+  fn add(self, other: Self) -> Self {
+    let sum = |a: Option<isize>, b: Option<isize>| a?.checked_add(b?);
+    Self { low: sum(self.low, other.low), high: sum(self.high, other.high) }
+  }
+
+  fn union(self, other: Self) -> Self {
+    Self { low: self.low.zip(other.low).map(|(a, b)| a.min(b)), high: self.high.zip(other.high).map(|(a, b)| a.max(b)) }
+  }
+
+  /// Every sum of any number of offsets in `self`, including none
+  fn repeated(self) -> Self {
+    Self {
+      low: self.low.filter(|&x| x >= 0).map(|_| 0),
+      high: self.high.filter(|&x| x <= 0).map(|_| 0),
+    }
+  }
+}
+
+/// Accessed offsets and pointer movement of a block of opcodes, relative to the pointer on entry
+struct Block {
+  accessed: Option<Interval>,
+  shift: Interval,
+}
+impl Block {
+  const EMPTY: Self = Self { accessed: None, shift: Interval::ZERO };
+
+  /// Record accesses to `cells`, relative to the current pointer
+  fn touch(&mut self, cells: Interval) {
+    let cells = self.shift.add(cells);
+    self.accessed = Some(self.accessed.map_or(cells, |x| x.union(cells)));
+  }
+
+  fn touch_offset(&mut self, offset: isize) {
+    self.touch(Interval::ZERO.offset(offset));
+  }
+}
+
+impl Program {
+  /// Estimate the tape cells the program can access, see [`TapeFootprint`]
+  pub fn tape_footprint(&self) -> TapeFootprint {
+    //Blocks of the loops enclosing the current one, outermost first
+    let mut enclosing = Vec::new();
+    let mut block = Block::EMPTY;
+    for op in self.ops.iter().flat_map(Opcode::unfused) {
+      match *op {
+        Opcode::Increment(offset, _) | Opcode::Set(offset, _) | Opcode::Output(offset) | Opcode::Input(offset) => {
+          block.touch_offset(offset);
+        },
+        Opcode::MovePointer(shift) => block.shift = block.shift.offset(shift),
+        Opcode::Move(ref targets) => {
+          block.touch_offset(0);
+          targets.iter().for_each(|&(offset, _)| block.touch_offset(offset));
+        },
+        Opcode::ScanZero(step) => {
+          let distance = match step.signum() {
+            1 => Interval { low: Some(0), high: None },
+            -1 => Interval { low: None, high: Some(0) },
+            _ => Interval::ZERO,
+          };
+          block.touch(distance);
+          block.shift = block.shift.add(distance);
+        },
+        Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) => {
+          if let Opcode::MoveLoopStart(shift, _) = *op {
+            block.shift = block.shift.offset(shift);
+          }
+          block.touch_offset(0);
+          enclosing.push(std::mem::replace(&mut block, Block::EMPTY));
+        },
+        Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) => {
+          let Some(outer) = enclosing.pop() else { break };
+          let mut body = std::mem::replace(&mut block, outer);
+          if let Opcode::MoveLoopEnd(shift, _) = *op {
+            body.shift = body.shift.offset(shift);
+          }
+          //Every iteration starts wherever the previous ones left the pointer
+          block.shift = block.shift.add(body.shift.repeated());
+          if let Some(accessed) = body.accessed {
+            block.touch(accessed);
+          }
+          block.touch_offset(0);
+        },
+        Opcode::Flush => (),
+        Opcode::Fused(_) => unreachable!("superinstructions are unfused"),
+        Opcode::Eof => break,
+      }
+    }
+    let accessed = block.accessed.unwrap_or(Interval::ZERO);
+    TapeFootprint { lowest: accessed.low, highest: accessed.high }
+  }
+}
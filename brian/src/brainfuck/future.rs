@@ -0,0 +1,152 @@
+//! Running programs as futures, for async hosts
+
+use std::{future::Future, pin::Pin, sync::Arc, task::{Context, Poll}};
+#[cfg(all(feature = "async", feature = "std"))]
+use std::{
+  any::Any,
+  panic::{self, AssertUnwindSafe},
+  sync::{atomic::{AtomicBool, Ordering}, Mutex},
+  task::Waker,
+  thread,
+};
+use super::{interpreter, BoundsPolicy, Brainfuck, EofMode, Io, PackedPosition, PackedProgram, SliceEnd, Trap};
+
+/// Future running a program to completion in fuel-sized slices, see [`Brainfuck::into_future`]
+///
+/// Resolves to the interpreter, with the final state, the [`Io`] it ran with, and the trap that stopped the program
+/// early if any. Dropping the future cancels the run between two slices
+pub struct RunFuture<I: Io + Unpin> {
+  run: Option<(Brainfuck, I)>,
+  packed: Option<PackedProgram>,
+  position: PackedPosition,
+  /// Position of the enum interpreter, used when the program doesn't run packed
+  program_counter: usize,
+  fuel_per_poll: usize,
+}
+impl<I: Io + Unpin> Future for RunFuture<I> {
+  type Output = (Brainfuck, I, Result<(), Trap>);
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let (brainfuck, io) = this.run.as_mut().expect("RunFuture polled after completion");
+    let end = match &this.packed {
+      Some(packed) => Ok(packed.resume(Arc::make_mut(&mut brainfuck.state), io, this.fuel_per_poll, &mut this.position)),
+      None => interpreter::resume_with_policies(
+        &brainfuck.program,
+        Arc::make_mut(&mut brainfuck.state),
+        io,
+        brainfuck.eof,
+        brainfuck.bounds,
+        this.fuel_per_poll,
+        &mut this.program_counter,
+      ),
+    };
+    match end {
+      //Yield to the executor, asking to be polled again right away
+      Ok(SliceEnd::OutOfFuel | SliceEnd::AwaitingInput) => {
+        cx.waker().wake_by_ref();
+        Poll::Pending
+      },
+      end => {
+        let (brainfuck, io) = this.run.take().unwrap();
+        Poll::Ready((brainfuck, io, end.map(drop)))
+      },
+    }
+  }
+}
+
+impl Brainfuck {
+  /// Turn the interpreter into a future running the loaded program with `io`,
+  /// executing at most `fuel_per_poll` instructions every time it's polled
+  ///
+  /// The future yields to the executor between slices, so timeouts and cancellation through the usual
+  /// future combinators take effect within one slice. Only `io` that never blocks is supported, blocking `io` blocks
+  /// the executor thread; [`Brainfuck::into_blocking_future`] runs on a thread of its own instead.
+  /// The packed form always leaves the cell unchanged on EOF and wraps the pointer, so with another [`EofMode`]
+  /// or [`BoundsPolicy`] (or if the program is too large to pack) the slices run on the slower enum interpreter
+  pub fn into_future<I: Io + Unpin>(self, io: I, fuel_per_poll: usize) -> RunFuture<I> {
+    RunFuture {
+      packed: self.program.pack().filter(|_| self.eof == EofMode::Unchanged && self.bounds == BoundsPolicy::Wrap),
+      run: Some((self, io)),
+      position: PackedPosition::default(),
+      program_counter: 0,
+      fuel_per_poll,
+    }
+  }
+}
+
+/// Completion of a [`BlockingRun`], shared with its thread
+#[cfg(all(feature = "async", feature = "std"))]
+struct Slot<I> {
+  /// Output of the run
+  output: Option<(Brainfuck, I, Result<(), Trap>)>,
+  /// Payload of a panic on the thread
+  panic: Option<Box<dyn Any + Send>>,
+  /// Waker of the last poll, woken once the output is in
+  waker: Option<Waker>,
+}
+
+/// Future running a program on a thread of its own, see [`Brainfuck::into_blocking_future`]
+///
+/// Resolves like [`RunFuture`]. Dropping the future cancels the run between two slices
+#[cfg(all(feature = "async", feature = "std"))]
+pub struct BlockingRun<I: Io + Unpin + Send + 'static> {
+  slot: Arc<Mutex<Slot<I>>>,
+  cancelled: Arc<AtomicBool>,
+}
+#[cfg(all(feature = "async", feature = "std"))]
+impl<I: Io + Unpin + Send + 'static> Future for BlockingRun<I> {
+  type Output = (Brainfuck, I, Result<(), Trap>);
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let mut slot = self.slot.lock().unwrap();
+    if let Some(payload) = slot.panic.take() {
+      panic::resume_unwind(payload)
+    }
+    match slot.output.take() {
+      Some(output) => Poll::Ready(output),
+      None => {
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+      },
+    }
+  }
+}
+#[cfg(all(feature = "async", feature = "std"))]
+impl<I: Io + Unpin + Send + 'static> Drop for BlockingRun<I> {
+  fn drop(&mut self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+}
+
+#[cfg(all(feature = "async", feature = "std"))]
+impl Brainfuck {
+  /// Like [`Brainfuck::into_future`], but running the slices on a new thread, so `io` may block
+  ///
+  /// The future doesn't run anything when polled, it only waits for the thread to wake it up.
+  /// A panic on the thread is resumed when the future is polled. Dropping the future stops the thread after
+  /// the slice it is running, which a program blocked on input only finishes once `io` returns
+  pub fn into_blocking_future<I: Io + Unpin + Send + 'static>(self, io: I, fuel_per_slice: usize) -> BlockingRun<I> {
+    let slot = Arc::new(Mutex::new(Slot { output: None, panic: None, waker: None }));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (thread_slot, thread_cancelled) = (Arc::clone(&slot), Arc::clone(&cancelled));
+    thread::spawn(move || {
+      let output = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut future = self.into_future(io, fuel_per_slice);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+          if thread_cancelled.load(Ordering::Relaxed) { return None }
+          if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) { return Some(output) }
+        }
+      }));
+      let mut slot = thread_slot.lock().unwrap();
+      match output {
+        Ok(None) => return,
+        Ok(output) => slot.output = output,
+        Err(payload) => slot.panic = Some(payload),
+      }
+      slot.waker.take().into_iter().for_each(Waker::wake);
+    });
+    BlockingRun { slot, cancelled }
+  }
+}
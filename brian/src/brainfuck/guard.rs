@@ -0,0 +1,90 @@
+//! Miscompilation guards, run after every compiler pass in debug builds
+
+use std::fmt;
+use crate::rng::Rng;
+use super::{interpreter::run_with_fuel, BrainfuckState, CompileOptions, Opcode, Program, StreamIo, VerifyError};
+
+/// Instructions each spot check run may take
+const SPOT_CHECK_FUEL: usize = 1 << 16;
+/// Number of input bytes available to spot check runs
+const SPOT_CHECK_INPUT: usize = 16;
+/// Number of cells around the start of the tape randomized for spot checks on dirty tapes
+const SPOT_CHECK_CELLS: usize = 64;
+
+/// What a miscompilation guard found wrong with the output of a pass
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MiscompilationKind {
+  /// Pass output breaks an invariant of compiled programs, see [`Program::verify`]
+  Invalid(VerifyError),
+  /// Program printed something else than before the pass on a spot check run
+  OutputMismatch { seed: u64 },
+  /// Program left a different tape or pointer than before the pass on a spot check run
+  StateMismatch { seed: u64 },
+}
+
+/// Internal compiler error caught by a miscompilation guard, see [`CompileReport::miscompilations`](super::CompileReport::miscompilations)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Miscompilation {
+  /// Pass whose output was rejected
+  pub pass: &'static str,
+  pub kind: MiscompilationKind,
+}
+impl fmt::Display for Miscompilation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "miscompilation in pass {}: ", self.pass)?;
+    match self.kind {
+      MiscompilationKind::Invalid(error) => write!(f, "{error}"),
+      MiscompilationKind::OutputMismatch { seed } => write!(f, "output changed on spot check {seed}"),
+      MiscompilationKind::StateMismatch { seed } => write!(f, "final tape or pointer changed on spot check {seed}"),
+    }
+  }
+}
+impl std::error::Error for Miscompilation {}
+
+/// Run a program from a state seeded by `seed`, returning whether it halted, its output and its final state
+fn spot_run(ops: &[Opcode], seed: u64, dirty_tape: bool) -> (bool, Vec<u8>, Box<BrainfuckState>) {
+  let mut rng = Rng::new(seed);
+  let mut state = Box::new(BrainfuckState::new());
+  if dirty_tape {
+    for cell in &mut state.memory[..SPOT_CHECK_CELLS] {
+      *cell = rng.next_u64() as u8;
+    }
+  }
+  let input: Vec<u8> = (0..SPOT_CHECK_INPUT).map(|_| rng.next_u64() as u8).collect();
+  let program = Program { ops: ops.to_vec(), metadata: Default::default() };
+  let mut io = StreamIo::new(&input[..], Vec::new());
+  let halted = run_with_fuel(&program, &mut state, &mut io, SPOT_CHECK_FUEL);
+  (halted, io.output, state)
+}
+
+/// Compare runs of the program before and after a pass
+///
+/// Optimized programs take fewer instructions, so a run that ran out of fuel only has to print a prefix of the other one's output
+fn spot_check(before: &[Opcode], after: &[Opcode], seed: u64, options: &CompileOptions) -> Result<(), MiscompilationKind> {
+  let dirty_tape = !options.assume_zeroed_tape && seed % 2 == 1;
+  let (before_halted, before_output, before_state) = spot_run(before, seed, dirty_tape);
+  let (after_halted, after_output, after_state) = spot_run(after, seed, dirty_tape);
+  let output_matches = match (before_halted, after_halted) {
+    (true, true) => before_output == after_output,
+    _ => before_output.starts_with(&after_output) || after_output.starts_with(&before_output),
+  };
+  if !output_matches {
+    return Err(MiscompilationKind::OutputMismatch { seed })
+  }
+  //Dropped dead stores change the final state on purpose
+  let state_matches = before_state.memory == after_state.memory
+    && before_state.pointer_address() == after_state.pointer_address();
+  if before_halted && after_halted && !options.drop_dead_stores && !state_matches {
+    return Err(MiscompilationKind::StateMismatch { seed })
+  }
+  Ok(())
+}
+
+impl Program {
+  /// Check the output of a pass against its input, which must have passed the checks already
+  pub(crate) fn guard_pass(pass: &'static str, before: &[Opcode], after: &[Opcode], options: &CompileOptions) -> Result<(), Miscompilation> {
+    Self::verify_ops(after).map_err(MiscompilationKind::Invalid)
+      .and_then(|_| (0..4).try_for_each(|seed| spot_check(before, after, seed, options)))
+      .map_err(|kind| Miscompilation { pass, kind })
+  }
+}
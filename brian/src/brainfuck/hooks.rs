@@ -0,0 +1,61 @@
+use std::ops::ControlFlow;
+use super::{Opcode, MEMORY_MASK};
+
+/// Read-only view of the interpreter state, passed to [`Hooks`]
+#[derive(Clone, Copy, Debug)]
+pub struct StateView<'a> {
+  pub memory: &'a [u8],
+  /// Pointer as stored by the interpreter, which may be outside of the tape before wrapping
+  pub pointer: usize,
+}
+impl StateView<'_> {
+  /// Value of the cell at `offset` from the pointer
+  #[inline]
+  pub fn cell(&self, offset: isize) -> u8 {
+    self.memory[self.pointer.wrapping_add_signed(offset) & MEMORY_MASK]
+  }
+}
+
+/// Host callbacks invoked around every executed opcode
+///
+/// Tracing, watchpoints, coverage and the like are built on top of this. Returning [`ControlFlow::Break`]
+/// stops the program, which can't be resumed afterwards
+pub trait Hooks {
+  /// Whether the hooks are called at all; the interpreter compiles them out entirely if this is `false`
+  const ENABLED: bool = true;
+
+  /// Called before the opcode at `program_counter` is executed
+  #[inline(always)]
+  fn before(&mut self, _program_counter: usize, _op: &Opcode, _state: StateView) -> ControlFlow<()> {
+    ControlFlow::Continue(())
+  }
+
+  /// Called after the opcode at `program_counter` was executed (except for `Eof`)
+  #[inline(always)]
+  fn after(&mut self, _program_counter: usize, _op: &Opcode, _state: StateView) -> ControlFlow<()> {
+    ControlFlow::Continue(())
+  }
+}
+
+/// No hooks, used by all runs that don't ask for them
+pub struct NoHooks;
+impl Hooks for NoHooks {
+  const ENABLED: bool = false;
+}
+
+/// Both hooks, called in order; the program stops as soon as either of them asks to
+impl<A: Hooks, B: Hooks> Hooks for (A, B) {
+  const ENABLED: bool = A::ENABLED || B::ENABLED;
+
+  #[inline(always)]
+  fn before(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    self.0.before(program_counter, op, state)?;
+    self.1.before(program_counter, op, state)
+  }
+
+  #[inline(always)]
+  fn after(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    self.0.after(program_counter, op, state)?;
+    self.1.after(program_counter, op, state)
+  }
+}
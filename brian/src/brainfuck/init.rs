@@ -0,0 +1,48 @@
+use crate::rng::Rng;
+use super::BrainfuckState;
+
+/// Initial contents of the tape, for testing programs against dirty memory and for puzzles starting from a given tape
+///
+/// Programs run on a tape that isn't zeroed must not be compiled with
+/// [`CompileOptions::assume_zeroed_tape`](super::CompileOptions::assume_zeroed_tape)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TapeInit {
+  /// Every cell is 0, like in a fresh state
+  #[default]
+  Zero,
+  /// Every cell holds the same value (e.g. 0xff)
+  Fill(u8),
+  /// Cells hold pseudo-random values, the same seed always yields the same tape
+  Random(u64),
+  /// Cells starting at the first one hold the given bytes (cut off at the end of the tape), the rest are 0
+  Image(Vec<u8>),
+}
+
+impl BrainfuckState {
+  /// Create a state with the tape initialized according to `init`, and the pointer at the first cell
+  pub fn with_tape(init: &TapeInit) -> Self {
+    let mut state = Self::new();
+    state.initialize(init);
+    state
+  }
+
+  /// Initialize the whole tape according to `init` and move the pointer back to the first cell, in place
+  pub fn initialize(&mut self, init: &TapeInit) {
+    self.pointer = 0;
+    match init {
+      TapeInit::Zero => self.memory.fill(0),
+      TapeInit::Fill(value) => self.memory.fill(*value),
+      TapeInit::Random(seed) => {
+        let mut rng = Rng::new(*seed);
+        for chunk in self.memory.chunks_mut(8) {
+          chunk.copy_from_slice(&rng.next_u64().to_le_bytes()[..chunk.len()]);
+        }
+      },
+      TapeInit::Image(image) => {
+        let length = image.len().min(self.memory.len());
+        self.memory[..length].copy_from_slice(&image[..length]);
+        self.memory[length..].fill(0);
+      },
+    }
+  }
+}
@@ -1,61 +1,890 @@
-use std::io::{self, Write, Read};
-use super::{Brainfuck, Opcode, MEMORY_MASK};
+use std::{collections::VecDeque, io::{self, Write, Read}, slice, sync::Arc};
+use crate::rng::Rng;
+use super::{
+  accel,
+  buffered::{BufferedIo, FlushPolicy},
+  crash::{CrashContext, History},
+  hooks::{Hooks, NoHooks, StateView},
+  config::{BoundsPolicy, Config, CANONICAL_TAPE_LENGTH, DefaultConfig, EofMode, InterpreterConfig, OverflowPolicy, StaticConfig, Trap, TrapKind},
+  Brainfuck, BrainfuckState, CostModel, Opcode, Profile, Program, RunOutcome, RuntimeError, MEMORY_MASK, MEMORY_SIZE,
+};
+
+/// Byte-level input and output of a running program
+pub trait Io {
+  /// Read a single byte, or `None` at EOF
+  fn input(&mut self) -> Option<u8>;
+  /// Write a single byte
+  fn output(&mut self, byte: u8);
+  /// Flush buffered output, requested by [`Opcode::Flush`]
+  ///
+  /// Does nothing by default
+  #[inline]
+  fn flush(&mut self) {}
+  /// Whether [`input`](Self::input) can be called without blocking
+  ///
+  /// Fuel-limited runs pause before an input if this is `false`, leaving the position at the input
+  /// so that resuming retries it. Always `true` by default
+  #[inline]
+  fn input_ready(&mut self) -> bool {
+    true
+  }
+  /// Whether I/O failed for good, e.g. because the output was closed
+  ///
+  /// Programs stop right after an operation leaving this `true`, as if they reached their end. Never by default
+  #[inline]
+  fn failed(&mut self) -> bool {
+    false
+  }
+}
+
+/// Why a resumed run paused or ended, see [`PackedProgram::resume`](super::PackedProgram::resume)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliceEnd {
+  /// The program reached its end
+  Halted,
+  /// The program executed all of its fuel
+  OutOfFuel,
+  /// The program is about to read input that isn't ready yet, see [`Io::input_ready`]
+  AwaitingInput,
+}
+
+/// [`Io`] backed by a reader and a writer
+///
+/// Read errors are treated as EOF. Write errors stop the program, and the first one is kept in `error`
+#[derive(Debug, Default)]
+pub struct StreamIo<R: Read, W: Write> {
+  pub input: R,
+  pub output: W,
+  pub error: Option<io::Error>,
+}
+impl<R: Read, W: Write> StreamIo<R, W> {
+  pub fn new(input: R, output: W) -> Self {
+    Self { input, output, error: None }
+  }
+}
+impl<R: Read, W: Write> Io for StreamIo<R, W> {
+  #[inline]
+  fn input(&mut self) -> Option<u8> {
+    let mut byte = 0;
+    match self.input.read(slice::from_mut(&mut byte)) {
+      Ok(1) => Some(byte),
+      _ => None,
+    }
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    if let Err(error) = self.output.write_all(&[byte]) {
+      self.error.get_or_insert(error);
+    }
+  }
+
+  #[inline]
+  fn flush(&mut self) {
+    if let Err(error) = self.output.flush() {
+      self.error.get_or_insert(error);
+    }
+  }
+
+  #[inline]
+  fn failed(&mut self) -> bool {
+    self.error.is_some()
+  }
+}
+
+/// Input source yielding deterministic pseudo-random bytes from a seed, for use as the input of [`StreamIo`]
+///
+/// The same seed always yields the same bytes, so runs of programs consuming "random" input can be replayed
+#[derive(Clone, Debug)]
+pub struct RandomInput {
+  rng: Rng,
+  /// Bytes left before EOF, or `None` for endless input
+  remaining: Option<usize>,
+}
+impl RandomInput {
+  /// Endless input seeded by `seed`
+  pub fn new(seed: u64) -> Self {
+    Self { rng: Rng::new(seed), remaining: None }
+  }
+
+  /// Reach EOF after `length` bytes, for programs reading until EOF
+  pub fn with_length(self, length: usize) -> Self {
+    Self { remaining: Some(length), ..self }
+  }
+}
+impl Read for RandomInput {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let length = self.remaining.map_or(buf.len(), |x| x.min(buf.len()));
+    for byte in &mut buf[..length] {
+      *byte = self.rng.next_u64() as u8;
+    }
+    if let Some(remaining) = &mut self.remaining {
+      *remaining -= length;
+    }
+    Ok(length)
+  }
+}
+
+/// Input source yielding the bytes of an iterator, then EOF, for use as the input of [`StreamIo`]
+#[derive(Clone, Debug)]
+pub struct IterInput<I: Iterator<Item = u8>>(pub I);
+impl<I: Iterator<Item = u8>> Read for IterInput<I> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let mut length = 0;
+    for (slot, byte) in buf.iter_mut().zip(&mut self.0) {
+      *slot = byte;
+      length += 1;
+    }
+    Ok(length)
+  }
+}
+
+/// Line ending convention translated by [`NewlineIo`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Newlines {
+  /// Bytes are passed through unchanged
+  #[default]
+  Lf,
+  /// `\r\n` is read as `\n`, and `\n` is written as `\r\n`
+  Crlf,
+}
+
+/// [`Io`] translating line endings, for programs that assume `\n` line endings running on Windows terminals
+#[derive(Clone, Debug, Default)]
+pub struct NewlineIo<I: Io> {
+  inner: I,
+  input: Newlines,
+  output: Newlines,
+  /// Byte read while looking for the `\n` of a `\r\n`
+  pending: Option<u8>,
+}
+impl<I: Io> NewlineIo<I> {
+  /// Wrap `inner`, translating its input and output according to the given conventions
+  pub fn new(inner: I, input: Newlines, output: Newlines) -> Self {
+    Self { inner, input, output, pending: None }
+  }
+
+  pub fn into_inner(self) -> I {
+    self.inner
+  }
+}
+impl<I: Io> Io for NewlineIo<I> {
+  #[inline]
+  fn input(&mut self) -> Option<u8> {
+    if let Some(byte) = self.pending.take() {
+      return Some(byte)
+    }
+    let byte = self.inner.input()?;
+    if byte == b'\r' && self.input == Newlines::Crlf {
+      match self.inner.input() {
+        Some(b'\n') => return Some(b'\n'),
+        next => self.pending = next,
+      }
+    }
+    Some(byte)
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    if byte == b'\n' && self.output == Newlines::Crlf {
+      self.inner.output(b'\r');
+    }
+    self.inner.output(byte);
+  }
+
+  #[inline]
+  fn flush(&mut self) {
+    self.inner.flush();
+  }
+
+  #[inline]
+  fn failed(&mut self) -> bool {
+    self.inner.failed()
+  }
+}
+
+/// [`Io`] duplicating output to extra sinks (e.g. a file or a capture buffer) on top of the wrapped [`Io`]
+///
+/// Every sink keeps its own buffering, wrap sinks in a [`BufWriter`](std::io::BufWriter) where needed.
+/// Input is passed through. Write errors of the sinks stop the program, and the first one is kept
+/// until [`TeeIo::flush`] reports it
+pub struct TeeIo<I: Io> {
+  inner: I,
+  sinks: Vec<Box<dyn Write>>,
+  error: Option<io::Error>,
+}
+impl<I: Io> TeeIo<I> {
+  /// Wrap `inner` without any extra sinks
+  pub fn new(inner: I) -> Self {
+    Self { inner, sinks: Vec::new(), error: None }
+  }
+
+  /// Add a sink receiving every output byte after the wrapped [`Io`] and earlier sinks
+  pub fn with_sink(mut self, sink: impl Write + 'static) -> Self {
+    self.sinks.push(Box::new(sink));
+    self
+  }
+
+  /// Flush all sinks, failing with the first write error of the run or stopping at the first flush error
+  pub fn flush(&mut self) -> io::Result<()> {
+    match self.error.take() {
+      Some(error) => Err(error),
+      None => self.sinks.iter_mut().try_for_each(|sink| sink.flush()),
+    }
+  }
+
+  /// Get the wrapped [`Io`], dropping the sinks (call [`flush`](Self::flush) first to catch write errors)
+  pub fn into_inner(self) -> I {
+    self.inner
+  }
+}
+impl<I: Io> Io for TeeIo<I> {
+  #[inline]
+  fn input(&mut self) -> Option<u8> {
+    self.inner.input()
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    self.inner.output(byte);
+    if self.error.is_none() {
+      self.error = self.sinks.iter_mut().try_for_each(|sink| sink.write_all(&[byte])).err();
+    }
+  }
+
+  #[inline]
+  fn flush(&mut self) {
+    self.inner.flush();
+    if self.error.is_none() {
+      self.error = self.sinks.iter_mut().try_for_each(|sink| sink.flush()).err();
+    }
+  }
+
+  #[inline]
+  fn failed(&mut self) -> bool {
+    self.inner.failed() || self.error.is_some()
+  }
+}
+
+/// [`Io`] capturing output in numbered chunks on top of the wrapped [`Io`], for frontends polling a long run
+///
+/// Every call to [`CaptureIo::take_output_since`] closes the output written since the previous one into a new chunk,
+/// so pollers only fetch output they haven't seen yet. Input is passed through
+pub struct CaptureIo<I: Io> {
+  inner: I,
+  /// Closed chunks not acknowledged yet, with their sequence IDs
+  chunks: VecDeque<(u64, Vec<u8>)>,
+  open: Vec<u8>,
+  next_sequence: u64,
+}
+impl<I: Io> CaptureIo<I> {
+  /// Wrap `inner`, starting at sequence ID 0
+  pub fn new(inner: I) -> Self {
+    Self { inner, chunks: VecDeque::new(), open: Vec::new(), next_sequence: 0 }
+  }
+
+  /// Get the output of every chunk numbered `sequence` or later, along with the sequence ID to pass on the next poll
+  ///
+  /// Chunks numbered below `sequence` are considered seen and are dropped,
+  /// the others are kept so that a poll whose response got lost can be repeated
+  pub fn take_output_since(&mut self, sequence: u64) -> (Vec<u8>, u64) {
+    if !self.open.is_empty() {
+      self.chunks.push_back((self.next_sequence, std::mem::take(&mut self.open)));
+      self.next_sequence += 1;
+    }
+    while self.chunks.front().is_some_and(|&(id, _)| id < sequence) {
+      self.chunks.pop_front();
+    }
+    let output = self.chunks.iter().flat_map(|(_, chunk)| chunk).copied().collect();
+    (output, self.next_sequence)
+  }
+
+  /// Get the wrapped [`Io`], dropping all captured output
+  pub fn into_inner(self) -> I {
+    self.inner
+  }
+}
+impl<I: Io> Io for CaptureIo<I> {
+  #[inline]
+  fn input(&mut self) -> Option<u8> {
+    self.inner.input()
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    self.inner.output(byte);
+    self.open.push(byte);
+  }
+
+  #[inline]
+  fn flush(&mut self) {
+    self.inner.flush();
+  }
+
+  #[inline]
+  fn failed(&mut self) -> bool {
+    self.inner.failed()
+  }
+}
+
+/// Run a compiled program on an existing state, with I/O supplied by the caller
+///
+/// Stateless counterpart of [`Brainfuck::run`], for applications where the program, state and I/O are owned separately
+/// (e.g. a shared program run on pooled states). Runs with the [`DefaultConfig`], where cells and the pointer wrap,
+/// so the program can't trap; [`run_static`] and [`InterpreterConfig::run`] report traps of other policies
+#[inline]
+pub fn run(program: &Program, state: &mut BrainfuckState, io: &mut impl Io) {
+  execute::<DefaultConfig, _, _, false, false, false>(program, state, io, &mut NoHooks, &mut Meters::default());
+}
+
+/// Like [`run`], but executing at most `fuel` instructions
+/// Returns `false` if the program ran out of fuel before reaching the end
+#[inline]
+pub fn run_with_fuel(program: &Program, state: &mut BrainfuckState, io: &mut impl Io, fuel: usize) -> bool {
+  let mut meters = Meters { fuel, ..Default::default() };
+  execute::<DefaultConfig, _, _, true, false, false>(program, state, io, &mut NoHooks, &mut meters) == Exit::Halted
+}
+
+/// Run a compiled program with a configuration resolved at compile time, optionally executing at most `fuel` instructions
+///
+/// Returns `Ok(false)` if the program ran out of fuel before reaching the end
+pub fn run_static<C: Config>(program: &Program, state: &mut BrainfuckState, io: &mut impl Io, fuel: Option<usize>) -> Result<bool, Trap> {
+  run_static_with_hooks::<C>(program, state, io, &mut NoHooks, fuel)
+}
+
+/// [`run_static`] calling `hooks` around every executed opcode, which must not stop the program
+fn run_static_with_hooks<C: Config>(
+  program: &Program,
+  state: &mut BrainfuckState,
+  io: &mut impl Io,
+  hooks: &mut impl Hooks,
+  fuel: Option<usize>,
+) -> Result<bool, Trap> {
+  let exit = match fuel {
+    Some(fuel) => {
+      execute::<C, _, _, true, false, false>(program, state, io, hooks, &mut Meters { fuel, ..Default::default() })
+    },
+    None => execute::<C, _, _, false, false, false>(program, state, io, hooks, &mut Meters::default()),
+  };
+  exit.into_result()
+}
+
+/// Run a compiled program, calling `hooks` around every executed opcode
+///
+/// Returns `false` if a hook stopped the program before it reached the end.
+/// Runs with the [`DefaultConfig`] like [`run`], so the program can't trap
+pub fn run_with_hooks(program: &Program, state: &mut BrainfuckState, io: &mut impl Io, hooks: &mut impl Hooks) -> bool {
+  execute::<DefaultConfig, _, _, false, false, false>(program, state, io, hooks, &mut Meters::default()) == Exit::Halted
+}
+
+/// [`run_with_hooks`] with `eof` as the effect of `,` when there is no more input and the `bounds` policy
+///
+/// Fails with the trap stopping the program under [`BoundsPolicy::Trap`]
+pub(super) fn run_with_hooks_and_policies(
+  program: &Program,
+  state: &mut BrainfuckState,
+  io: &mut impl Io,
+  hooks: &mut impl Hooks,
+  eof: EofMode,
+  bounds: BoundsPolicy,
+) -> Result<bool, Trap> {
+  execute_with::<_, _, false, false, false>(eof, bounds, program, state, io, hooks, &mut Meters::default()).into_result()
+}
+
+/// Run at most `fuel` instructions of a program from `*program_counter`, with the `eof` and `bounds` policies,
+/// leaving it where the run paused so that it can be resumed, like [`PackedProgram::resume`](super::PackedProgram::resume)
+///
+/// Fails with the trap stopping the program
+pub(super) fn resume_with_policies(
+  program: &Program,
+  state: &mut BrainfuckState,
+  io: &mut impl Io,
+  eof: EofMode,
+  bounds: BoundsPolicy,
+  fuel: usize,
+  program_counter: &mut usize,
+) -> Result<SliceEnd, Trap> {
+  let mut meters = Meters { fuel, program_counter: *program_counter, ..Default::default() };
+  let exit = execute_with::<_, _, true, false, false>(eof, bounds, program, state, io, &mut NoHooks, &mut meters);
+  *program_counter = meters.program_counter;
+  match exit {
+    Exit::Halted => Ok(SliceEnd::Halted),
+    Exit::OutOfFuel => Ok(SliceEnd::OutOfFuel),
+    Exit::AwaitingInput => Ok(SliceEnd::AwaitingInput),
+    Exit::Trapped(trap) => Err(trap),
+    Exit::Stopped => unreachable!("hooks never stop the program"),
+  }
+}
+
+/// [`execute`] with `eof` as the effect of `,` when there is no more input and the `bounds` policy,
+/// and the default policies otherwise
+#[inline(always)]
+fn execute_with<I: Io, H: Hooks, const FUELED: bool, const PROFILED: bool, const COSTED: bool>(
+  eof: EofMode,
+  bounds: BoundsPolicy,
+  program: &Program,
+  state: &mut BrainfuckState,
+  io: &mut I,
+  hooks: &mut H,
+  meters: &mut Meters,
+) -> Exit {
+  match (eof, bounds) {
+    (EofMode::Unchanged, BoundsPolicy::Wrap) => execute::<DefaultConfig, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+    (EofMode::Zero, BoundsPolicy::Wrap) => execute::<StaticConfig<1, false, 0>, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+    (EofMode::Max, BoundsPolicy::Wrap) => execute::<StaticConfig<2, false, 0>, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+    (EofMode::Unchanged, BoundsPolicy::Trap) => execute::<StaticConfig<0, false, 1>, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+    (EofMode::Zero, BoundsPolicy::Trap) => execute::<StaticConfig<1, false, 1>, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+    (EofMode::Max, BoundsPolicy::Trap) => execute::<StaticConfig<2, false, 1>, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+    (EofMode::Unchanged, BoundsPolicy::TrapCanonical) => execute::<StaticConfig<0, false, 2>, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+    (EofMode::Zero, BoundsPolicy::TrapCanonical) => execute::<StaticConfig<1, false, 2>, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+    (EofMode::Max, BoundsPolicy::TrapCanonical) => execute::<StaticConfig<2, false, 2>, I, H, FUELED, PROFILED, COSTED>(program, state, io, hooks, meters),
+  }
+}
+
+impl InterpreterConfig {
+  /// Run a compiled program using this configuration, optionally executing at most `fuel` instructions
+  ///
+  /// Returns `Ok(false)` if the program ran out of fuel before reaching the end
+  pub fn run(&self, program: &Program, state: &mut BrainfuckState, io: &mut impl Io, fuel: Option<usize>) -> Result<bool, Trap> {
+    self.run_with_hooks(program, state, io, &mut NoHooks, fuel)
+  }
+
+  /// Like [`InterpreterConfig::run`], but capturing a [`CrashContext`] if the program traps
+  ///
+  /// Keeping track of the last executed opcodes makes the run slower
+  pub fn run_with_context(
+    &self,
+    program: &Program,
+    state: &mut BrainfuckState,
+    io: &mut impl Io,
+    fuel: Option<usize>,
+  ) -> Result<bool, Box<CrashContext>> {
+    let mut history = History::default();
+    self.run_with_hooks(program, state, io, &mut history, fuel)
+      .map_err(|trap| Box::new(CrashContext::capture(trap, &history, program, state)))
+  }
+
+  fn run_with_hooks(&self, program: &Program, state: &mut BrainfuckState, io: &mut impl Io, hooks: &mut impl Hooks, fuel: Option<usize>) -> Result<bool, Trap> {
+    match self.eof {
+      EofMode::Unchanged => self.run_with_eof::<0>(program, state, io, hooks, fuel),
+      EofMode::Zero => self.run_with_eof::<1>(program, state, io, hooks, fuel),
+      EofMode::Max => self.run_with_eof::<2>(program, state, io, hooks, fuel),
+    }
+  }
+
+  fn run_with_eof<const EOF: u8>(
+    &self,
+    program: &Program,
+    state: &mut BrainfuckState,
+    io: &mut impl Io,
+    hooks: &mut impl Hooks,
+    fuel: Option<usize>,
+  ) -> Result<bool, Trap> {
+    //Programs starting on the first cell whose footprint fits the tape can't leave it, so the bounds aren't checked
+    let bounds = match self.bounds {
+      BoundsPolicy::Trap if state.pointer == 0 && program.tape_footprint().fits(MEMORY_SIZE) => BoundsPolicy::Wrap,
+      BoundsPolicy::TrapCanonical if state.pointer == 0 && program.tape_footprint().fits(CANONICAL_TAPE_LENGTH) => BoundsPolicy::Wrap,
+      bounds => bounds,
+    };
+    match (self.overflow, bounds) {
+      (OverflowPolicy::Wrap, BoundsPolicy::Wrap) => run_static_with_hooks::<StaticConfig<EOF, false, 0>>(program, state, io, hooks, fuel),
+      (OverflowPolicy::Wrap, BoundsPolicy::Trap) => run_static_with_hooks::<StaticConfig<EOF, false, 1>>(program, state, io, hooks, fuel),
+      (OverflowPolicy::Wrap, BoundsPolicy::TrapCanonical) => run_static_with_hooks::<StaticConfig<EOF, false, 2>>(program, state, io, hooks, fuel),
+      (OverflowPolicy::Trap, BoundsPolicy::Wrap) => run_static_with_hooks::<StaticConfig<EOF, true, 0>>(program, state, io, hooks, fuel),
+      (OverflowPolicy::Trap, BoundsPolicy::Trap) => run_static_with_hooks::<StaticConfig<EOF, true, 1>>(program, state, io, hooks, fuel),
+      (OverflowPolicy::Trap, BoundsPolicy::TrapCanonical) => run_static_with_hooks::<StaticConfig<EOF, true, 2>>(program, state, io, hooks, fuel),
+    }
+  }
+}
 
 impl Brainfuck {
   ///Run brainfuck program after compilation
+  ///
+  /// Output is buffered, and written to stdout according to the [`FlushPolicy`].
+  /// Fails if writing the output fails, or if the program exceeds the step limit
   #[inline]
-  pub fn run(&mut self) {
-    let program_len = self.program.len();
-    let program = &self.program[..];
-    let memory = &mut self.state.memory;
-    let pointer = &mut self.state.pointer;
-    let mut program_counter = 0;
-    loop {
-      if program_counter >= program_len { break }
-      let op = &program[program_counter];
-      match op {
-        Opcode::Increment(rel_pos, rel_val) => {
-          let pos = pointer.wrapping_add_signed(*rel_pos);
-          memory[pos & MEMORY_MASK] = memory[pos & MEMORY_MASK].wrapping_add(*rel_val as u8);
-        },
-        Opcode::Set(rel_pos, val) => {
-          let pos = pointer.wrapping_add_signed(*rel_pos);
-          memory[pos & MEMORY_MASK] = *val;
-        },
-        Opcode::MovePointer(rel_pos) => {
-          *pointer = pointer.wrapping_add_signed(*rel_pos);
-        },
-        Opcode::LoopStart(end) => {
-          if memory[*pointer & MEMORY_MASK] == 0 {
-            program_counter = *end;
+  pub fn run(&mut self) -> Result<RunOutcome, RuntimeError> {
+    self.run_buffered(io::stdin())
+  }
+
+  /// Run brainfuck program, reading input from `input` and writing output to `output`
+  ///
+  /// Lets embedders (GUIs, servers) route program I/O anywhere, see [`Brainfuck::run_with_fuel`] for a bounded run.
+  /// The program stops once writing to `output` fails. Fails with the trap stopping the program, see
+  /// [`Brainfuck::set_bounds_policy`]
+  pub fn run_with_streams<R: Read, W: Write>(&mut self, input: R, output: W) -> Result<(), Trap> {
+    self.run_with(&mut StreamIo::new(input, output))
+  }
+
+  /// Run brainfuck program, reading input from `input` (e.g. a `Vec<u8>` or `bytes.iter().copied()`)
+  /// and writing output to stdout
+  ///
+  /// Programs reading past the end of the input see EOF. Output is buffered, and failures are reported,
+  /// like in [`Brainfuck::run`]
+  pub fn run_with_input<I: IntoIterator<Item = u8>>(&mut self, input: I) -> Result<RunOutcome, RuntimeError> {
+    self.run_buffered(IterInput(input.into_iter()))
+  }
+
+  /// Run brainfuck program, reading input from `input` and writing output to the buffer of the interpreter
+  fn run_buffered(&mut self, input: impl Read) -> Result<RunOutcome, RuntimeError> {
+    let mut io = BufferedIo::new(input, &mut self.output);
+    let state = Arc::make_mut(&mut self.state);
+    let mut meters = Meters { fuel: self.step_limit.unwrap_or(0), ..Default::default() };
+    let exit = match self.step_limit {
+      Some(_) => execute_with::<_, _, true, false, false>(self.eof, self.bounds, &self.program, state, &mut io, &mut NoHooks, &mut meters),
+      None => execute_with::<_, _, false, false, false>(self.eof, self.bounds, &self.program, state, &mut io, &mut NoHooks, &mut meters),
+    };
+    let outcome = RunOutcome { output_bytes: io.written, instructions: self.step_limit.map(|x| x - meters.fuel) };
+    if let Some(error) = io.error {
+      return Err(RuntimeError::Io(error))
+    }
+    match (exit, self.step_limit) {
+      (Exit::Trapped(trap), _) => return Err(RuntimeError::Trap(trap)),
+      (Exit::OutOfFuel, Some(limit)) => return Err(RuntimeError::StepLimitExceeded(limit)),
+      _ => (),
+    }
+    if self.output.policy == FlushPolicy::Halt {
+      self.output.flush()?;
+    }
+    Ok(outcome)
+  }
+
+  /// Run brainfuck program, with I/O handled by `io` (e.g. a [`CaptureIo`] or a custom [`Io`])
+  ///
+  /// Fails with the trap stopping the program, see [`Brainfuck::set_bounds_policy`]
+  pub fn run_with(&mut self, io: &mut impl Io) -> Result<(), Trap> {
+    execute_with::<_, _, false, false, false>(self.eof, self.bounds, &self.program, Arc::make_mut(&mut self.state), io, &mut NoHooks, &mut Meters::default())
+      .into_result()
+      .map(drop)
+  }
+
+  /// Run brainfuck program, returning everything it wrote instead of printing it
+  ///
+  /// The program sees EOF on every input, use [`Brainfuck::run_with_streams`] with a `Vec<u8>` output
+  /// to also feed it input. Fails with the trap stopping the program, see [`Brainfuck::set_bounds_policy`]
+  pub fn run_to_vec(&mut self) -> Result<Vec<u8>, Trap> {
+    let mut output = Vec::new();
+    self.run_with_streams(io::empty(), &mut output)?;
+    Ok(output)
+  }
+
+  /// Run brainfuck program, writing output to `output` and executing at most `fuel` instructions
+  /// Returns `Ok(false)` if the program ran out of fuel before reaching the end
+  ///
+  /// The program sees EOF on every input
+  pub fn run_with_fuel<W: Write>(&mut self, output: W, fuel: usize) -> Result<bool, Trap> {
+    self.run_with_io(io::empty(), output, fuel)
+  }
+
+  /// Run brainfuck program, reading input from `input`, writing output to `output` and executing at most `fuel` instructions
+  /// Returns `Ok(false)` if the program ran out of fuel before reaching the end. The program stops once writing to `output`
+  /// fails, which counts as reaching the end. Fails with the trap stopping the program, see [`Brainfuck::set_bounds_policy`]
+  pub fn run_with_io<R: Read, W: Write>(&mut self, input: R, output: W, fuel: usize) -> Result<bool, Trap> {
+    let mut meters = Meters { fuel, ..Default::default() };
+    let mut io = StreamIo::new(input, output);
+    execute_with::<_, _, true, false, false>(self.eof, self.bounds, &self.program, Arc::make_mut(&mut self.state), &mut io, &mut NoHooks, &mut meters)
+      .into_result()
+  }
+
+  /// Run brainfuck program, writing output to `output` and counting how many times each opcode is executed
+  ///
+  /// The program stops once writing to `output` fails. Fails with the trap stopping the program,
+  /// see [`Brainfuck::set_bounds_policy`]
+  pub fn run_profiled<W: Write>(&mut self, output: W) -> Result<Profile, Trap> {
+    let mut meters = Meters { counts: vec![0; self.program.ops.len()], ..Default::default() };
+    let mut io = StreamIo::new(io::stdin(), output);
+    execute_with::<_, _, false, true, false>(self.eof, self.bounds, &self.program, Arc::make_mut(&mut self.state), &mut io, &mut NoHooks, &mut meters)
+      .into_result()?;
+    Ok(Profile { counts: meters.counts })
+  }
+
+  /// Run brainfuck program, writing output to `output` and returning the number of virtual cycles spent according to `model`
+  ///
+  /// The program stops once writing to `output` fails. Fails with the trap stopping the program,
+  /// see [`Brainfuck::set_bounds_policy`]
+  pub fn run_with_cost<W: Write>(&mut self, output: W, model: &CostModel) -> Result<u64, Trap> {
+    let mut meters = Meters { model, ..Default::default() };
+    let mut io = StreamIo::new(io::stdin(), output);
+    execute_with::<_, _, false, false, true>(self.eof, self.bounds, &self.program, Arc::make_mut(&mut self.state), &mut io, &mut NoHooks, &mut meters)
+      .into_result()?;
+    Ok(meters.cycles)
+  }
+
+  #[inline(never)]
+  pub fn _mono_run(mut b: Brainfuck) {
+    b.run().unwrap();
+  }
+}
+
+/// Resources tracked during execution, each only used if enabled by the corresponding const parameter of [`execute`]
+struct Meters<'a> {
+  /// Remaining instructions (`FUELED`)
+  fuel: usize,
+  /// Opcode the run starts at, and the one it paused at if it returned [`Exit::OutOfFuel`] (`FUELED`)
+  program_counter: usize,
+  /// Execution count of each opcode (`PROFILED`)
+  counts: Vec<u64>,
+  /// Cost model and accumulated virtual cycles (`COSTED`)
+  model: &'a CostModel,
+  cycles: u64,
+}
+impl Default for Meters<'_> {
+  fn default() -> Self {
+    Self { fuel: 0, program_counter: 0, counts: Vec::new(), model: &CostModel::UNIT, cycles: 0 }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Exit {
+  Halted,
+  OutOfFuel,
+  /// Paused before an input that isn't ready
+  AwaitingInput,
+  Trapped(Trap),
+  /// Stopped by a hook
+  Stopped,
+}
+impl Exit {
+  /// Whether the program reached its end, rather than running out of fuel or being stopped by a hook,
+  /// or the trap stopping it
+  fn into_result(self) -> Result<bool, Trap> {
+    match self {
+      Self::Halted => Ok(true),
+      Self::OutOfFuel | Self::AwaitingInput | Self::Stopped => Ok(false),
+      Self::Trapped(trap) => Err(trap),
+    }
+  }
+}
+
+/// Map a (possibly out of range) pointer to a tape address according to the bounds policy
+#[inline(always)]
+fn address<C: Config>(pointer: usize) -> Option<usize> {
+  match C::BOUNDS {
+    BoundsPolicy::Wrap => Some(pointer & MEMORY_MASK),
+    BoundsPolicy::Trap => (pointer <= MEMORY_MASK).then_some(pointer),
+    BoundsPolicy::TrapCanonical => (pointer < CANONICAL_TAPE_LENGTH).then_some(pointer),
+  }
+}
+
+#[inline(always)]
+fn execute<C: Config, I: Io, H: Hooks, const FUELED: bool, const PROFILED: bool, const COSTED: bool>(
+  program: &Program,
+  state: &mut BrainfuckState,
+  io: &mut I,
+  hooks: &mut H,
+  meters: &mut Meters,
+) -> Exit {
+  let program_len = program.ops.len();
+  let program = &program.ops[..];
+  let memory = &mut state.memory;
+  let pointer = &mut state.pointer;
+  let mut program_counter = if FUELED { meters.program_counter } else { 0 };
+  let trap = 'run: loop {
+    if program_counter >= program_len { break None }
+    if FUELED {
+      if meters.fuel == 0 {
+        meters.program_counter = program_counter;
+        return Exit::OutOfFuel
+      }
+      meters.fuel -= 1;
+    }
+    if PROFILED {
+      meters.counts[program_counter] += 1;
+    }
+    let op = &program[program_counter];
+    if COSTED {
+      meters.cycles += meters.model.cost(op);
+    }
+    if H::ENABLED && hooks.before(program_counter, op, StateView { memory: &memory[..], pointer: *pointer }).is_break() {
+      return Exit::Stopped
+    }
+    let index = program_counter;
+    match op {
+      Opcode::Increment(rel_pos, rel_val) => {
+        let Some(pos) = address::<C>(pointer.wrapping_add_signed(*rel_pos)) else {
+          break Some(TrapKind::PointerOutOfBounds)
+        };
+        memory[pos] = match C::OVERFLOW {
+          OverflowPolicy::Wrap => memory[pos].wrapping_add(*rel_val as u8),
+          OverflowPolicy::Trap => match (memory[pos] as isize).checked_add(*rel_val).and_then(|x| u8::try_from(x).ok()) {
+            Some(value) => value,
+            None => break Some(TrapKind::CellOverflow),
+          },
+        };
+      },
+      Opcode::Set(rel_pos, val) => {
+        let Some(pos) = address::<C>(pointer.wrapping_add_signed(*rel_pos)) else {
+          break Some(TrapKind::PointerOutOfBounds)
+        };
+        memory[pos] = *val;
+      },
+      Opcode::MovePointer(rel_pos) => {
+        *pointer = pointer.wrapping_add_signed(*rel_pos);
+        if address::<C>(*pointer).is_none() { break Some(TrapKind::PointerOutOfBounds) }
+      },
+      Opcode::Fused(ops) => {
+        for op in &ops[..] {
+          if let Err(kind) = straight_line::<C>(op, memory, pointer) { break 'run Some(kind) }
+        }
+      },
+      Opcode::LoopStart(end) => {
+        if memory[*pointer & MEMORY_MASK] == 0 {
+          program_counter = *end;
+        }
+      },
+      Opcode::LoopEnd(start) => {
+        if memory[*pointer & MEMORY_MASK] != 0 {
+          program_counter = *start;
+        }
+      },
+      Opcode::MoveLoopStart(rel_pos, end) => {
+        *pointer = pointer.wrapping_add_signed(*rel_pos);
+        if address::<C>(*pointer).is_none() { break Some(TrapKind::PointerOutOfBounds) }
+        if memory[*pointer & MEMORY_MASK] == 0 {
+          program_counter = *end;
+        }
+      },
+      Opcode::MoveLoopEnd(rel_pos, start) => {
+        *pointer = pointer.wrapping_add_signed(*rel_pos);
+        if address::<C>(*pointer).is_none() { break Some(TrapKind::PointerOutOfBounds) }
+        if memory[*pointer & MEMORY_MASK] != 0 {
+          program_counter = *start;
+        }
+      },
+      Opcode::ScanZero(direction) => {
+        //Single-cell scans on a wrapping tape jump straight to the zero cell, the slow path finishes the rest
+        if matches!(C::BOUNDS, BoundsPolicy::Wrap) && direction.unsigned_abs() == 1 && memory[*pointer & MEMORY_MASK] != 0 {
+          let distance = accel::scan_distance(memory, *pointer, *direction > 0);
+          if let Some(distance) = distance.filter(|&x| !FUELED || x <= meters.fuel) {
+            if FUELED {
+              meters.fuel -= distance;
+            }
+            if COSTED {
+              meters.cycles += meters.model.scan_step * distance as u64;
+            }
+            *pointer = pointer.wrapping_add_signed(distance as isize * direction);
           }
-        },
-        Opcode::LoopEnd(start) => {
-          if memory[*pointer & MEMORY_MASK] != 0 {
-            program_counter = *start;
+        }
+        while memory[*pointer & MEMORY_MASK] != 0 {
+          //Scans never terminate on a tape without zero cells, so charge fuel for every step
+          if FUELED {
+            if meters.fuel == 0 {
+              //Resume with the rest of the scan
+              meters.program_counter = program_counter;
+              return Exit::OutOfFuel
+            }
+            meters.fuel -= 1;
           }
-        },
-        Opcode::ScanZero(direction) => {
-          while memory[*pointer & MEMORY_MASK] != 0 {
-            *pointer = pointer.wrapping_add_signed(*direction);
+          if COSTED {
+            meters.cycles += meters.model.scan_step;
           }
+          *pointer = pointer.wrapping_add_signed(*direction);
+          if address::<C>(*pointer).is_none() { break 'run Some(TrapKind::PointerOutOfBounds) }
         }
-        Opcode::Output(rel_pos) => {
-          let pos = pointer.wrapping_add_signed(*rel_pos);
-          io::stdout().write(&[memory[pos & MEMORY_MASK]]).unwrap();
-        },
-        Opcode::Input(rel_pos) => {
-          let pos = pointer.wrapping_add_signed(*rel_pos);
-          io::stdin().read_exact(&mut memory[(pos & MEMORY_MASK)..(pos & MEMORY_MASK)]).unwrap();
-        },
-        Opcode::Eof => break,
       }
-      program_counter += 1;
+      Opcode::Move(targets) => {
+        let Some(source) = address::<C>(*pointer) else { break Some(TrapKind::PointerOutOfBounds) };
+        let value = memory[source];
+        if value != 0 {
+          for &(rel_pos, factor) in &targets[..] {
+            let Some(pos) = address::<C>(pointer.wrapping_add_signed(rel_pos)) else {
+              break 'run Some(TrapKind::PointerOutOfBounds)
+            };
+            memory[pos] = match C::OVERFLOW {
+              OverflowPolicy::Wrap => memory[pos].wrapping_add(value.wrapping_mul(factor)),
+              //Factors above 127 come from decrements
+              OverflowPolicy::Trap => match u8::try_from(memory[pos] as isize + value as isize * factor as i8 as isize) {
+                Ok(value) => value,
+                Err(_) => break 'run Some(TrapKind::CellOverflow),
+              },
+            };
+          }
+          memory[source] = 0;
+        }
+      },
+      Opcode::Output(rel_pos) => {
+        let Some(pos) = address::<C>(pointer.wrapping_add_signed(*rel_pos)) else {
+          break Some(TrapKind::PointerOutOfBounds)
+        };
+        io.output(memory[pos]);
+        if io.failed() { break None }
+      },
+      Opcode::Flush => {
+        io.flush();
+        if io.failed() { break None }
+      },
+      Opcode::Input(rel_pos) => {
+        if FUELED && !io.input_ready() {
+          //Give back the fuel, the input is retried on resume
+          meters.fuel += 1;
+          meters.program_counter = program_counter;
+          return Exit::AwaitingInput
+        }
+        let Some(pos) = address::<C>(pointer.wrapping_add_signed(*rel_pos)) else {
+          break Some(TrapKind::PointerOutOfBounds)
+        };
+        match (io.input(), C::EOF) {
+          (Some(byte), _) => memory[pos] = byte,
+          (None, EofMode::Unchanged) => (),
+          (None, EofMode::Zero) => memory[pos] = 0,
+          (None, EofMode::Max) => memory[pos] = u8::MAX,
+        }
+        if io.failed() { break None }
+      },
+      Opcode::Eof => break None,
+    }
+    if H::ENABLED && hooks.after(index, op, StateView { memory: &memory[..], pointer: *pointer }).is_break() {
+      return Exit::Stopped
     }
+    program_counter += 1;
+  };
+  match trap {
+    Some(kind) => Exit::Trapped(Trap { kind, instruction: program_counter }),
+    None => Exit::Halted,
   }
+}
 
-  #[inline(never)]
-  pub fn _mono_run(mut b: Brainfuck) {
-    b.run()
+/// Execute an opcode that always falls through to the next one and doesn't touch the I/O,
+/// the only kinds making up [`Opcode::Fused`]
+///
+/// Mirrors the corresponding arms of [`execute`], which stay inlined there for speed
+///
+/// Fails with the reason for stopping if the opcode traps
+#[inline(always)]
+fn straight_line<C: Config>(op: &Opcode, memory: &mut [u8; MEMORY_SIZE], pointer: &mut usize) -> Result<(), TrapKind> {
+  let address = |pointer: usize| address::<C>(pointer).ok_or(TrapKind::PointerOutOfBounds);
+  match op {
+    Opcode::Increment(rel_pos, rel_val) => {
+      let pos = address(pointer.wrapping_add_signed(*rel_pos))?;
+      memory[pos] = match C::OVERFLOW {
+        OverflowPolicy::Wrap => memory[pos].wrapping_add(*rel_val as u8),
+        OverflowPolicy::Trap => {
+          (memory[pos] as isize).checked_add(*rel_val).and_then(|x| u8::try_from(x).ok()).ok_or(TrapKind::CellOverflow)?
+        },
+      };
+    },
+    Opcode::Set(rel_pos, val) => memory[address(pointer.wrapping_add_signed(*rel_pos))?] = *val,
+    Opcode::MovePointer(rel_pos) => {
+      *pointer = pointer.wrapping_add_signed(*rel_pos);
+      address(*pointer)?;
+    },
+    Opcode::Move(targets) => {
+      let source = address(*pointer)?;
+      let value = memory[source];
+      if value != 0 {
+        for &(rel_pos, factor) in &targets[..] {
+          let pos = address(pointer.wrapping_add_signed(rel_pos))?;
+          memory[pos] = match C::OVERFLOW {
+            OverflowPolicy::Wrap => memory[pos].wrapping_add(value.wrapping_mul(factor)),
+            //Factors above 127 come from decrements
+            OverflowPolicy::Trap => u8::try_from(memory[pos] as isize + value as isize * factor as i8 as isize)
+              .map_err(|_| TrapKind::CellOverflow)?,
+          };
+        }
+        memory[source] = 0;
+      }
+    },
+    _ => unreachable!("not a straight-line opcode"),
   }
+  Ok(())
 }
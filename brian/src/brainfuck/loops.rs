@@ -0,0 +1,78 @@
+use std::ops::Range;
+use super::{brainfuck_tokens_indexed, Program};
+
+/// Single loop of a [`LoopTree`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoopNode {
+  /// Byte range of the loop in the source code, brackets included
+  ///
+  /// Only known for trees built with [`LoopTree::from_source`]
+  pub span: Option<Range<usize>>,
+  /// Opcode indices of the loop body, brackets excluded
+  pub body: Range<usize>,
+  /// Index of the enclosing loop
+  pub parent: Option<usize>,
+  /// Indices of the directly nested loops, in program order
+  pub children: Vec<usize>,
+}
+
+/// Nesting structure of the loops of a program
+///
+/// Nodes are stored in the order of their loop starts, so parents always come before their children
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoopTree {
+  pub nodes: Vec<LoopNode>,
+  /// Indices of the loops not nested in any other loop
+  pub roots: Vec<usize>,
+}
+impl LoopTree {
+  /// Build the loop tree of the unoptimized program, including source spans
+  ///
+  /// Body ranges refer to opcodes of [`Program::compile_without_optimizations`].
+  /// Returns `None` if the brackets are unbalanced
+  pub fn from_source(code: &str) -> Option<Self> {
+    let mut ops = Program::parse(code, None);
+    let mut tree = Program::link_loops(&mut ops).ok()?;
+    //Unoptimized opcodes map one-to-one to tokens
+    let offsets: Vec<usize> = brainfuck_tokens_indexed(code).map(|x| x.0).collect();
+    for node in &mut tree.nodes {
+      node.span = Some(offsets[node.body.start - 1]..(offsets[node.body.end] + 1));
+    }
+    Some(tree)
+  }
+
+  /// Number of loops enclosing the loop `node`
+  pub fn depth(&self, node: usize) -> usize {
+    std::iter::successors(self.nodes[node].parent, |&x| self.nodes[x].parent).count()
+  }
+
+  /// Innermost loop whose body contains the opcode at `index`
+  pub fn innermost(&self, index: usize) -> Option<usize> {
+    let mut children = &self.roots;
+    let mut found = None;
+    while let Some(&node) = children.iter().find(|&&x| self.nodes[x].body.contains(&index)) {
+      found = Some(node);
+      children = &self.nodes[node].children;
+    }
+    found
+  }
+
+  /// Record a loop starting at opcode `start`, nested in the currently open loop `parent`
+  pub(crate) fn open(&mut self, start: usize, parent: Option<usize>) -> usize {
+    let node = self.nodes.len();
+    match parent {
+      Some(parent) => self.nodes[parent].children.push(node),
+      None => self.roots.push(node),
+    }
+    self.nodes.push(LoopNode { span: None, body: (start + 1)..(start + 1), parent, children: Vec::new() });
+    node
+  }
+}
+
+impl Program {
+  /// Get the nesting structure of the program loops
+  pub fn loop_tree(&self) -> LoopTree {
+    Self::link_loops(&mut self.ops.clone()).expect("programs have balanced loops")
+  }
+}
+
@@ -0,0 +1,68 @@
+use super::BrainfuckState;
+
+macro_rules! integer_accessors {
+  ($($ty: ty => $write: ident, $read: ident;)*) => {$(
+    #[doc = concat!("Store a little-endian `", stringify!($ty), "` at `address`")]
+    ///
+    /// Panics if it doesn't fit on the tape
+    #[inline]
+    pub fn $write(&mut self, address: usize, value: $ty) {
+      self.write_bytes(address, &value.to_le_bytes());
+    }
+
+    #[doc = concat!("Load a little-endian `", stringify!($ty), "` from `address`")]
+    ///
+    /// Panics if it doesn't fit on the tape
+    #[inline]
+    pub fn $read(&self, address: usize) -> $ty {
+      const SIZE: usize = std::mem::size_of::<$ty>();
+      let mut bytes = [0; SIZE];
+      bytes.copy_from_slice(self.read_bytes(address, SIZE));
+      <$ty>::from_le_bytes(bytes)
+    }
+  )*};
+}
+
+/// Helpers for passing host data to programs through the tape before a run, and reading results back after it
+///
+/// Addresses are absolute tape addresses, independent of the pointer
+impl BrainfuckState {
+  /// Copy `bytes` to the tape, starting at `address`
+  ///
+  /// Panics if they don't fit on the tape
+  #[inline]
+  pub fn write_bytes(&mut self, address: usize, bytes: &[u8]) {
+    self.memory[address..(address + bytes.len())].copy_from_slice(bytes);
+  }
+
+  /// Borrow `len` bytes of the tape, starting at `address`
+  ///
+  /// Panics if they don't fit on the tape
+  #[inline]
+  pub fn read_bytes(&self, address: usize, len: usize) -> &[u8] {
+    &self.memory[address..(address + len)]
+  }
+
+  /// Store a zero-terminated string at `address`
+  ///
+  /// Panics if the string (including its terminator) doesn't fit on the tape
+  pub fn write_c_string(&mut self, address: usize, string: &[u8]) {
+    self.write_bytes(address, string);
+    self.memory[address + string.len()] = 0;
+  }
+
+  /// Borrow the zero-terminated string at `address`, without its terminator
+  ///
+  /// Returns `None` if the tape ends before the terminator
+  pub fn read_c_string(&self, address: usize) -> Option<&[u8]> {
+    let tail = self.memory.get(address..)?;
+    let len = tail.iter().position(|&x| x == 0)?;
+    Some(&tail[..len])
+  }
+
+  integer_accessors! {
+    u16 => write_u16, read_u16;
+    u32 => write_u32, read_u32;
+    u64 => write_u64, read_u64;
+  }
+}
@@ -0,0 +1,47 @@
+use std::{collections::BTreeMap, mem::size_of};
+use super::{BrainfuckState, Opcode, Program, TapeFootprint};
+
+/// Static metrics of a compiled program
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramMetrics {
+  /// Number of opcodes, including the trailing `Eof`
+  pub length: usize,
+  /// Number of loops
+  pub loop_count: usize,
+  /// Deepest loop nesting level (0 if there are no loops)
+  pub max_nesting_depth: usize,
+  /// Number of occurrences of each opcode kind, keyed by [`Opcode::name`]
+  pub opcode_frequencies: BTreeMap<&'static str, usize>,
+  /// Estimated memory needed to run the program (opcode storage plus interpreter state), in bytes
+  pub memory_footprint: usize,
+  /// Tape cells the program can access, see [`Program::tape_footprint`]
+  pub tape_footprint: TapeFootprint,
+}
+
+impl Program {
+  /// Compute static metrics of the program
+  pub fn metrics(&self) -> ProgramMetrics {
+    let mut metrics = ProgramMetrics {
+      length: self.ops.len(),
+      memory_footprint: self.ops.len() * size_of::<Opcode>() + size_of::<BrainfuckState>(),
+      tape_footprint: self.tape_footprint(),
+      ..Default::default()
+    };
+    let mut depth = 0;
+    for op in &self.ops {
+      *metrics.opcode_frequencies.entry(op.name()).or_default() += 1;
+      match op {
+        Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) => {
+          metrics.loop_count += 1;
+          depth += 1;
+          metrics.max_nesting_depth = metrics.max_nesting_depth.max(depth);
+        },
+        Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) => {
+          depth -= 1;
+        },
+        _ => (),
+      }
+    }
+    metrics
+  }
+}
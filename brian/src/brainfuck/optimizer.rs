@@ -0,0 +1,228 @@
+//! Main optimizer pass: merges straight-line code into block effects and replaces loop idioms
+//!
+//! Works on regions (loop bodies, and the whole program) kept on an explicit stack instead of recursing,
+//! so every loop body is optimized independently of the code around it, whatever the nesting depth
+
+use std::collections::BTreeMap;
+use super::{analysis::Effect, CompileOptions, Opcode, OptimizerError, Program};
+
+/// Straight-line code not emitted yet: cell effects, ordered by offset so that they are always committed
+/// in the same order, and pointer movement
+#[derive(Default)]
+struct Block {
+  effects: BTreeMap<isize, Effect>,
+  ptr_offset: isize,
+}
+impl Block {
+  fn increment(&mut self, offset: isize, increment: isize) {
+    let offset = offset + self.ptr_offset;
+    let effect = self.effects.get(&offset).map_or(Effect::Increment(increment), |x| x.then_increment(increment));
+    self.effects.insert(offset, effect);
+  }
+
+  fn set(&mut self, offset: isize, value: u8) {
+    self.effects.insert(offset + self.ptr_offset, Effect::Set(value));
+  }
+
+  /// Partial commit: emit only the effect on the cell at `offset`, which is about to be read or written by I/O.
+  /// Returns the offset of that cell relative to the committed pointer
+  fn commit_cell(&mut self, offset: isize, output: &mut Vec<Opcode>) -> isize {
+    let offset = offset + self.ptr_offset;
+    output.extend(self.effects.remove(&offset).and_then(|x| x.opcode(offset)));
+    offset
+  }
+
+  /// Emit the pending effects, ordered by offset, followed by the pending pointer movement
+  fn commit(&mut self, output: &mut Vec<Opcode>) {
+    output.extend(self.effects.iter().filter_map(|(offset, effect)| effect.opcode(*offset)));
+    self.effects.clear();
+    if self.ptr_offset != 0 {
+      output.push(Opcode::MovePointer(self.ptr_offset));
+      self.ptr_offset = 0;
+    }
+  }
+}
+
+/// Loop body, or the whole program, being optimized
+struct Region {
+  /// Index of the opcode closing the region: the loop end, or the length of the program
+  end: usize,
+  /// Index of the output opcode opening the region, if it is a plain loop
+  start: Option<usize>,
+  block: Block,
+}
+
+/// Loop replaced as a whole
+pub(super) enum LoopIdiom {
+  /// Loop clearing the current cell, like `[-]`
+  Clear,
+  /// Loop only adding to cells, like `[<+>-]` or `[->+>++<<]`, see [`Opcode::Move`]
+  Move(Box<[(isize, u8)]>),
+  /// Loop moving the pointer until it finds a zero cell, like `[>]`
+  Scan(isize),
+}
+
+/// Find the idiom implemented by a loop body without nested loops
+pub(super) fn loop_idiom(body: &[Opcode]) -> Option<LoopIdiom> {
+  if let Some(mut increments) = balanced_increments(body) {
+    let step = increments.remove(&0).unwrap_or(0);
+    increments.retain(|_, value| *value != 0);
+    if let Some(targets) = scaled_increments(step, increments) {
+      return Some(if targets.is_empty() { LoopIdiom::Clear } else { LoopIdiom::Move(targets) })
+    }
+  }
+  let mut step = 0;
+  for op in body {
+    match op {
+      Opcode::MovePointer(shift) => step += shift,
+      _ => return None,
+    }
+  }
+  (step != 0).then_some(LoopIdiom::Scan(step))
+}
+
+/// Increments of a loop body with the given step on its condition cell, multiplied by the number of iterations
+/// taken to clear it, as [`Opcode::Move`] targets
+///
+/// Only odd steps reach zero from every value, after `-value * step^-1` iterations.
+/// Loops with an even step never terminate for some values, so they are left for value folding
+fn scaled_increments(step: u8, increments: BTreeMap<isize, u8>) -> Option<Box<[(isize, u8)]>> {
+  if step % 2 != 1 {
+    return None
+  }
+  let inverse = (0..=u8::MAX).find(|&x| x.wrapping_mul(step) == 1).unwrap();
+  let trips = inverse.wrapping_neg();
+  Some(increments.into_iter().map(|(offset, value)| (offset, value.wrapping_mul(trips))).collect())
+}
+
+/// Body of a loop consisting of a single block, replaced by one that clears the condition cell in one iteration
+///
+/// Cells set by the block hold the same value after any number of iterations, and increments scale with the number
+/// of iterations, so the sets followed by a move are equivalent. Returns `None` unless the block is balanced, sets
+/// some cell and has an odd step on the condition cell
+fn single_iteration(block: &Block) -> Option<Vec<Opcode>> {
+  let Some(&Effect::Increment(step)) = block.effects.get(&0) else { return None };
+  if block.ptr_offset != 0 || !block.effects.values().any(|x| matches!(x, Effect::Set(_))) {
+    return None
+  }
+  let increments = block.effects.iter().filter_map(|(&offset, effect)| match *effect {
+    Effect::Increment(value) if offset != 0 && value as u8 != 0 => Some((offset, value as u8)),
+    _ => None,
+  }).collect();
+  let targets = scaled_increments(step as u8, increments)?;
+  let mut body: Vec<Opcode> = block.effects.iter().filter_map(|(&offset, effect)| match *effect {
+    Effect::Set(value) => Some(Opcode::Set(offset, value)),
+    Effect::Increment(_) => None,
+  }).collect();
+  body.push(if targets.is_empty() { Opcode::Set(0, 0) } else { Opcode::Move(targets) });
+  Some(body)
+}
+
+/// Net increments of a loop body, keyed by offset from the condition cell
+///
+/// Returns `None` unless the body only consists of increments and pointer movements, and moves the pointer back to where it started
+fn balanced_increments(body: &[Opcode]) -> Option<BTreeMap<isize, u8>> {
+  let mut increments: BTreeMap<isize, u8> = BTreeMap::new();
+  let mut pointer = 0;
+  for op in body {
+    match *op {
+      Opcode::Increment(offset, value) => {
+        let increment = increments.entry(pointer + offset).or_default();
+        *increment = increment.wrapping_add(value as u8);
+      },
+      Opcode::MovePointer(offset) => pointer += offset,
+      _ => return None,
+    }
+  }
+  (pointer == 0).then_some(increments)
+}
+
+impl Program {
+  /// Merge increments and pointer movements into block effects, and replace clear, move and scan loops
+  ///
+  /// Requires linked loops
+  pub(super) fn optimize(ops: &[Opcode], options: &CompileOptions) -> Result<Vec<Opcode>, OptimizerError> {
+    let mut output = Vec::new();
+    //Regions being optimized, innermost last
+    let mut regions = vec![Region { end: ops.len(), start: None, block: Block::default() }];
+    let mut index = 0;
+    while let Some(region) = regions.last_mut() {
+      if index == region.end {
+        //Bodies made up of a single block, but setting cells and so not a loop idiom, like `[>[-]<-]`
+        if region.start.is_some_and(|start| start + 1 == output.len()) {
+          if let Some(effects) = single_iteration(&region.block) {
+            output.extend(effects);
+            region.block = Block::default();
+          }
+        }
+        //Effects are committed at every loop boundary, so the enclosing region continues from a clean block
+        region.block.commit(&mut output);
+        output.extend(ops.get(index).cloned());
+        regions.pop();
+        index += 1;
+        continue
+      }
+      let op = &ops[index];
+      index += 1;
+      let block = &mut region.block;
+      match *op {
+        Opcode::Increment(offset, increment) => block.increment(offset, increment),
+        Opcode::Set(offset, value) => block.set(offset, value),
+        Opcode::MovePointer(shift) => block.ptr_offset += shift,
+        Opcode::Output(offset) => {
+          let offset = block.commit_cell(offset, &mut output);
+          output.push(Opcode::Output(offset));
+        },
+        Opcode::Input(offset) => {
+          let offset = block.commit_cell(offset, &mut output);
+          output.push(Opcode::Input(offset));
+        },
+        //Flushing doesn't observe the tape, so pending effects stay pending
+        Opcode::Flush => output.push(Opcode::Flush),
+        Opcode::LoopStart(end) | Opcode::MoveLoopStart(_, end) => {
+          if end < index || !matches!(ops.get(end), Some(Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..))) {
+            return Err(OptimizerError::UnlinkedLoop(index - 1))
+          }
+          //Loops merged into a superinstruction by another pass are kept as they are
+          let idiom = match op {
+            Opcode::LoopStart(_) => loop_idiom(&ops[index..end]),
+            _ => None,
+          };
+          if let Some(LoopIdiom::Clear) = idiom {
+            block.set(0, 0);
+            index = end + 1;
+            continue
+          }
+          block.commit(&mut output);
+          match idiom {
+            Some(LoopIdiom::Move(targets)) => output.push(Opcode::Move(targets)),
+            Some(LoopIdiom::Scan(step)) => output.push(Opcode::ScanZero(step)),
+            _ => {
+              let start = matches!(op, Opcode::LoopStart(_)).then_some(output.len());
+              output.push(op.clone());
+              regions.push(Region { end, start, block: Block::default() });
+              continue
+            },
+          }
+          index = end + 1;
+        },
+        //Loop ends are handled when their region is closed, so this one doesn't close the innermost loop
+        Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) => return Err(OptimizerError::UnmatchedLoopEnd(index - 1)),
+        Opcode::Eof => {
+          //Effects still pending at the end of the program are never read
+          if options.drop_dead_stores {
+            *block = Block::default();
+          }
+          block.commit(&mut output);
+          output.push(Opcode::Eof);
+        },
+        //Opcodes produced by other passes are kept as they are, the pointer and cells they access are committed first
+        Opcode::ScanZero(_) | Opcode::Move(_) | Opcode::Fused(_) => {
+          block.commit(&mut output);
+          output.push(op.clone());
+        },
+      }
+    }
+    Ok(output)
+  }
+}
@@ -0,0 +1,60 @@
+use std::{fmt, io};
+use super::{Brainfuck, Trap};
+
+/// Summary of a finished run of [`Brainfuck::run`] or [`Brainfuck::run_with_input`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunOutcome {
+  /// Number of bytes written by the program
+  pub output_bytes: u64,
+  /// Number of instructions executed, only counted if a step limit is set
+  pub instructions: Option<usize>,
+}
+
+/// Error stopping a run of [`Brainfuck::run`] or [`Brainfuck::run_with_input`]
+#[derive(Debug)]
+pub enum RuntimeError {
+  /// Writing the output failed, which stopped the program right after the write
+  Io(io::Error),
+  /// Program executed this many instructions without reaching its end, see [`Brainfuck::set_step_limit`]
+  StepLimitExceeded(usize),
+  /// Pointer left the tape, see [`Brainfuck::set_bounds_policy`]
+  Trap(Trap),
+}
+impl fmt::Display for RuntimeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(error) => write!(f, "failed to write output: {error}"),
+      Self::StepLimitExceeded(limit) => write!(f, "step limit of {limit} instructions exceeded"),
+      Self::Trap(trap) => write!(f, "{trap}"),
+    }
+  }
+}
+impl std::error::Error for RuntimeError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Io(error) => Some(error),
+      Self::StepLimitExceeded(_) | Self::Trap(_) => None,
+    }
+  }
+}
+impl From<io::Error> for RuntimeError {
+  fn from(error: io::Error) -> Self {
+    Self::Io(error)
+  }
+}
+
+impl Brainfuck {
+  /// Maximum number of instructions executed by [`Brainfuck::run`] and [`Brainfuck::run_with_input`],
+  /// unlimited by default
+  #[inline(always)]
+  pub fn step_limit(&self) -> Option<usize> {
+    self.step_limit
+  }
+
+  /// Make [`Brainfuck::run`] and [`Brainfuck::run_with_input`] fail with [`RuntimeError::StepLimitExceeded`]
+  /// once the program executes more than `limit` instructions, for programs that may never halt
+  #[inline]
+  pub fn set_step_limit(&mut self, limit: Option<usize>) {
+    self.step_limit = limit;
+  }
+}
@@ -0,0 +1,458 @@
+use std::mem::size_of;
+use super::{accel, interpreter::{Io, SliceEnd}, BrainfuckState, Opcode, Profile, Program, MEMORY_MASK, MEMORY_SIZE};
+
+const INCREMENT: u8 = 0;
+const SET: u8 = 1;
+const MOVE_POINTER: u8 = 2;
+const LOOP_START: u8 = 3;
+const LOOP_END: u8 = 4;
+const OUTPUT: u8 = 5;
+const INPUT: u8 = 6;
+const SCAN_ZERO: u8 = 7;
+const MOVE_LOOP_START: u8 = 8;
+const MOVE_LOOP_END: u8 = 9;
+const MOVE: u8 = 10;
+const FLUSH: u8 = 11;
+const FUSED: u8 = 12;
+const EOF: u8 = 13;
+
+/// Log2 of the number of iterations assumed per loop when estimating opcode frequencies statically
+const STATIC_LOOP_WEIGHT: u32 = 4;
+
+/// Compiled program in a compact form: a stream of one-byte opcode tags and a side table of 32-bit operands
+///
+/// Instructions consume their operands from the table in program order, jumps carry both the target instruction
+/// and the target position in the operand table. Compared to [`Program`], the hot part of huge programs takes
+/// several times less memory, at the cost of being opaque; use [`Program`] when debugging
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PackedProgram {
+  code: Vec<u8>,
+  operands: Vec<i32>,
+  /// Tag of the opcode tested for before the full dispatch, or `EOF` for none
+  hot: u8,
+}
+/// Position of a paused [`PackedProgram`] run, see [`PackedProgram::resume`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PackedPosition {
+  program_counter: usize,
+  cursor: usize,
+}
+impl PackedPosition {
+  /// Index of the next instruction to execute, matching the opcode index in the original [`Program`]
+  pub fn program_counter(&self) -> usize {
+    self.program_counter
+  }
+}
+
+impl Default for PackedProgram {
+  fn default() -> Self {
+    Self { code: Vec::new(), operands: Vec::new(), hot: EOF }
+  }
+}
+impl PackedProgram {
+  /// Estimated memory taken by the packed program, in bytes
+  pub fn size(&self) -> usize {
+    self.code.len() + self.operands.len() * size_of::<i32>()
+  }
+
+  /// Name of the opcode kind the dispatch is tuned for, if any
+  pub fn hot_opcode(&self) -> Option<&'static str> {
+    const NAMES: [&str; EOF as usize] = [
+      "Increment", "Set", "MovePointer", "LoopStart", "LoopEnd", "Output", "Input", "ScanZero", "MoveLoopStart", "MoveLoopEnd",
+      "Move", "Flush", "Fused",
+    ];
+    NAMES.get(self.hot as usize).copied()
+  }
+
+  /// Disable dispatch tuning, testing for every opcode kind in the same way
+  pub fn without_dispatch_tuning(self) -> Self {
+    Self { hot: EOF, ..self }
+  }
+
+  /// Run the program on an existing state, with I/O supplied by the caller
+  #[inline]
+  pub fn run(&self, state: &mut BrainfuckState, io: &mut impl Io) {
+    self.execute::<_, false>(state, io, 0, &mut PackedPosition::default());
+  }
+
+  /// Like [`PackedProgram::run`], but executing at most `fuel` instructions
+  /// Returns `false` if the program ran out of fuel before reaching the end
+  #[inline]
+  pub fn run_with_fuel(&self, state: &mut BrainfuckState, io: &mut impl Io, fuel: usize) -> bool {
+    self.execute::<_, true>(state, io, fuel, &mut PackedPosition::default()) == SliceEnd::Halted
+  }
+
+  /// Continue running the program from `position` for at most `fuel` instructions, updating `position`
+  /// to where it stopped, so that long runs can be split into slices. Also stops before an input `io` isn't
+  /// ready for, see [`Io::input_ready`]
+  ///
+  /// Returns [`SliceEnd::Halted`] once the program reached its end
+  #[inline]
+  pub fn resume(&self, state: &mut BrainfuckState, io: &mut impl Io, fuel: usize, position: &mut PackedPosition) -> SliceEnd {
+    self.execute::<_, true>(state, io, fuel, position)
+  }
+
+  #[inline(always)]
+  fn execute<I: Io, const FUELED: bool>(
+    &self,
+    state: &mut BrainfuckState,
+    io: &mut I,
+    fuel: usize,
+    position: &mut PackedPosition,
+  ) -> SliceEnd {
+    match self.hot {
+      INCREMENT => self.execute_with_hot::<_, FUELED, INCREMENT>(state, io, fuel, position),
+      SET => self.execute_with_hot::<_, FUELED, SET>(state, io, fuel, position),
+      MOVE_POINTER => self.execute_with_hot::<_, FUELED, MOVE_POINTER>(state, io, fuel, position),
+      LOOP_START => self.execute_with_hot::<_, FUELED, LOOP_START>(state, io, fuel, position),
+      LOOP_END => self.execute_with_hot::<_, FUELED, LOOP_END>(state, io, fuel, position),
+      OUTPUT => self.execute_with_hot::<_, FUELED, OUTPUT>(state, io, fuel, position),
+      INPUT => self.execute_with_hot::<_, FUELED, INPUT>(state, io, fuel, position),
+      SCAN_ZERO => self.execute_with_hot::<_, FUELED, SCAN_ZERO>(state, io, fuel, position),
+      MOVE_LOOP_START => self.execute_with_hot::<_, FUELED, MOVE_LOOP_START>(state, io, fuel, position),
+      MOVE_LOOP_END => self.execute_with_hot::<_, FUELED, MOVE_LOOP_END>(state, io, fuel, position),
+      MOVE => self.execute_with_hot::<_, FUELED, MOVE>(state, io, fuel, position),
+      FLUSH => self.execute_with_hot::<_, FUELED, FLUSH>(state, io, fuel, position),
+      FUSED => self.execute_with_hot::<_, FUELED, FUSED>(state, io, fuel, position),
+      _ => self.execute_with_hot::<_, FUELED, EOF>(state, io, fuel, position),
+    }
+  }
+
+  /// Interpreter loop testing for the `HOT` opcode before dispatching on anything else
+  ///
+  /// The dedicated comparison gets its own branch predictor entry, which predicts much better than
+  /// the shared indirect jump of the full dispatch when a single opcode dominates the execution
+  #[inline(always)]
+  fn execute_with_hot<I: Io, const FUELED: bool, const HOT: u8>(
+    &self,
+    state: &mut BrainfuckState,
+    io: &mut I,
+    mut fuel: usize,
+    position: &mut PackedPosition,
+  ) -> SliceEnd {
+    let code = &self.code[..];
+    let operands = &self.operands[..];
+    let memory = &mut state.memory;
+    let pointer = &mut state.pointer;
+    let PackedPosition { mut program_counter, mut cursor } = *position;
+    macro_rules! dispatch {
+      ($tag: expr) => {
+        match $tag {
+          INCREMENT => {
+            let pos = pointer.wrapping_add_signed(operands[cursor] as isize) & MEMORY_MASK;
+            memory[pos] = memory[pos].wrapping_add(operands[cursor + 1] as u8);
+            cursor += 2;
+          },
+          SET => {
+            let pos = pointer.wrapping_add_signed(operands[cursor] as isize) & MEMORY_MASK;
+            memory[pos] = operands[cursor + 1] as u8;
+            cursor += 2;
+          },
+          MOVE_POINTER => {
+            *pointer = pointer.wrapping_add_signed(operands[cursor] as isize);
+            cursor += 1;
+          },
+          FUSED => {
+            //Operand count of the fused instructions, then a tag and the operands of each
+            let end = cursor + 1 + operands[cursor] as usize;
+            cursor += 1;
+            while cursor < end {
+              let tag = operands[cursor] as u8;
+              cursor += 1;
+              straight_line(tag, operands, &mut cursor, memory, pointer);
+            }
+          },
+          LOOP_START => {
+            if memory[*pointer & MEMORY_MASK] == 0 {
+              (program_counter, cursor) = jump(operands, cursor);
+            } else {
+              cursor += 2;
+            }
+          },
+          LOOP_END => {
+            if memory[*pointer & MEMORY_MASK] != 0 {
+              (program_counter, cursor) = jump(operands, cursor);
+            } else {
+              cursor += 2;
+            }
+          },
+          MOVE_LOOP_START => {
+            *pointer = pointer.wrapping_add_signed(operands[cursor] as isize);
+            if memory[*pointer & MEMORY_MASK] == 0 {
+              (program_counter, cursor) = jump(operands, cursor + 1);
+            } else {
+              cursor += 3;
+            }
+          },
+          MOVE_LOOP_END => {
+            *pointer = pointer.wrapping_add_signed(operands[cursor] as isize);
+            if memory[*pointer & MEMORY_MASK] != 0 {
+              (program_counter, cursor) = jump(operands, cursor + 1);
+            } else {
+              cursor += 3;
+            }
+          },
+          SCAN_ZERO => {
+            let direction = operands[cursor] as isize;
+            //Single-cell scans jump straight to the zero cell, the slow path finishes the rest
+            if direction.unsigned_abs() == 1 && memory[*pointer & MEMORY_MASK] != 0 {
+              let distance = accel::scan_distance(memory, *pointer, direction > 0);
+              if let Some(distance) = distance.filter(|&x| !FUELED || x <= fuel) {
+                if FUELED {
+                  fuel -= distance;
+                }
+                *pointer = pointer.wrapping_add_signed(distance as isize * direction);
+              }
+            }
+            while memory[*pointer & MEMORY_MASK] != 0 {
+              //Scans never terminate on a tape without zero cells, so charge fuel for every step
+              if FUELED {
+                if fuel == 0 {
+                  //Resume with the rest of the scan
+                  *position = PackedPosition { program_counter: program_counter - 1, cursor };
+                  return SliceEnd::OutOfFuel
+                }
+                fuel -= 1;
+              }
+              *pointer = pointer.wrapping_add_signed(direction);
+            }
+            cursor += 1;
+          },
+          MOVE => {
+            let count = operands[cursor] as usize;
+            let source = *pointer & MEMORY_MASK;
+            let value = memory[source];
+            if value != 0 {
+              for target in operands[(cursor + 1)..(cursor + 1 + 2 * count)].chunks_exact(2) {
+                let pos = pointer.wrapping_add_signed(target[0] as isize) & MEMORY_MASK;
+                memory[pos] = memory[pos].wrapping_add(value.wrapping_mul(target[1] as u8));
+              }
+              memory[source] = 0;
+            }
+            cursor += 1 + 2 * count;
+          },
+          OUTPUT => {
+            io.output(memory[pointer.wrapping_add_signed(operands[cursor] as isize) & MEMORY_MASK]);
+            cursor += 1;
+            if io.failed() { break }
+          },
+          INPUT => {
+            if FUELED && !io.input_ready() {
+              *position = PackedPosition { program_counter: program_counter - 1, cursor };
+              return SliceEnd::AwaitingInput
+            }
+            if let Some(byte) = io.input() {
+              memory[pointer.wrapping_add_signed(operands[cursor] as isize) & MEMORY_MASK] = byte;
+            }
+            cursor += 1;
+            if io.failed() { break }
+          },
+          FLUSH => {
+            io.flush();
+            if io.failed() { break }
+          },
+          EOF => break,
+          _ => unreachable!("invalid opcode tag"),
+        }
+      };
+    }
+    while let Some(&tag) = code.get(program_counter) {
+      if FUELED {
+        if fuel == 0 {
+          *position = PackedPosition { program_counter, cursor };
+          return SliceEnd::OutOfFuel
+        }
+        fuel -= 1;
+      }
+      program_counter += 1;
+      if HOT != EOF && tag == HOT {
+        dispatch!(HOT)
+      } else {
+        dispatch!(tag)
+      }
+    }
+    *position = PackedPosition { program_counter, cursor };
+    SliceEnd::Halted
+  }
+}
+
+/// Execute an instruction that always falls through to the next one and doesn't touch the I/O,
+/// the only kinds making up `FUSED`
+#[inline(always)]
+fn straight_line(tag: u8, operands: &[i32], cursor: &mut usize, memory: &mut [u8; MEMORY_SIZE], pointer: &mut usize) {
+  match tag {
+    INCREMENT => {
+      let pos = pointer.wrapping_add_signed(operands[*cursor] as isize) & MEMORY_MASK;
+      memory[pos] = memory[pos].wrapping_add(operands[*cursor + 1] as u8);
+      *cursor += 2;
+    },
+    SET => {
+      let pos = pointer.wrapping_add_signed(operands[*cursor] as isize) & MEMORY_MASK;
+      memory[pos] = operands[*cursor + 1] as u8;
+      *cursor += 2;
+    },
+    MOVE_POINTER => {
+      *pointer = pointer.wrapping_add_signed(operands[*cursor] as isize);
+      *cursor += 1;
+    },
+    MOVE => {
+      let count = operands[*cursor] as usize;
+      let source = *pointer & MEMORY_MASK;
+      let value = memory[source];
+      if value != 0 {
+        for target in operands[(*cursor + 1)..(*cursor + 1 + 2 * count)].chunks_exact(2) {
+          let pos = pointer.wrapping_add_signed(target[0] as isize) & MEMORY_MASK;
+          memory[pos] = memory[pos].wrapping_add(value.wrapping_mul(target[1] as u8));
+        }
+        memory[source] = 0;
+      }
+      *cursor += 1 + 2 * count;
+    },
+    _ => unreachable!("not a straight-line opcode tag"),
+  }
+}
+
+/// Append the operands of an opcode [`Opcode::is_fusable`] to `operands`, returning its tag
+///
+/// Returns `None` if an operand doesn't fit into 32 bits
+fn pack_straight_line(op: &Opcode, operands: &mut Vec<i32>) -> Option<u8> {
+  let small = |value: isize| i32::try_from(value).ok();
+  Some(match *op {
+    Opcode::Increment(offset, value) => {
+      operands.extend_from_slice(&[small(offset)?, value as i8 as i32]);
+      INCREMENT
+    },
+    Opcode::Set(offset, value) => {
+      operands.extend_from_slice(&[small(offset)?, value as i32]);
+      SET
+    },
+    Opcode::MovePointer(offset) => {
+      operands.push(small(offset)?);
+      MOVE_POINTER
+    },
+    Opcode::Move(ref targets) => {
+      operands.push(small(targets.len() as isize)?);
+      for &(offset, factor) in &targets[..] {
+        operands.extend_from_slice(&[small(offset)?, factor as i32]);
+      }
+      MOVE
+    },
+    _ => unreachable!("not a straight-line opcode"),
+  })
+}
+
+/// Read a jump target (instruction and operand table position) stored at `cursor`
+#[inline(always)]
+fn jump(operands: &[i32], cursor: usize) -> (usize, usize) {
+  (operands[cursor] as u32 as usize, operands[cursor + 1] as u32 as usize)
+}
+
+/// Number of operands taken by an opcode in the packed form
+fn operand_count(op: &Opcode) -> usize {
+  match op {
+    Opcode::Increment(..) | Opcode::Set(..) | Opcode::LoopStart(_) | Opcode::LoopEnd(_) => 2,
+    Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) => 3,
+    Opcode::MovePointer(_) | Opcode::Output(_) | Opcode::Input(_) | Opcode::ScanZero(_) => 1,
+    //Target count, then an offset and a factor per target
+    Opcode::Move(targets) => 1 + 2 * targets.len(),
+    //Operand count, then a tag and the operands of every fused opcode
+    Opcode::Fused(ops) => 1 + ops.iter().map(|op| 1 + operand_count(op)).sum::<usize>(),
+    Opcode::Flush | Opcode::Eof => 0,
+  }
+}
+
+impl Program {
+  /// Convert the program into the compact [`PackedProgram`] form
+  ///
+  /// Dispatch is tuned for the opcode kind estimated to be executed most often, assuming every loop runs
+  /// a few times. Returns `None` if an operand or jump target doesn't fit into 32 bits
+  pub fn pack(&self) -> Option<PackedProgram> {
+    let mut depth = 0;
+    let weights: Vec<u64> = self.ops.iter().map(|op| {
+      match op {
+        Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) => depth += 1,
+        Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) => depth -= 1,
+        _ => (),
+      }
+      1 << (STATIC_LOOP_WEIGHT * depth).min(48)
+    }).collect();
+    self.pack_with_weights(&weights)
+  }
+
+  /// Convert the program into the compact [`PackedProgram`] form, tuning dispatch using a profile of the program
+  ///
+  /// Returns `None` if the profile was not recorded for this program, or if an operand or jump target
+  /// doesn't fit into 32 bits
+  pub fn pack_with_profile(&self, profile: &Profile) -> Option<PackedProgram> {
+    if profile.counts.len() != self.ops.len() {
+      return None
+    }
+    self.pack_with_weights(&profile.counts)
+  }
+
+  fn pack_with_weights(&self, weights: &[u64]) -> Option<PackedProgram> {
+    //Position of every instruction in the operand table, plus one past the end
+    let mut cursors = Vec::with_capacity(self.ops.len() + 1);
+    let mut cursor = 0;
+    for op in &self.ops {
+      cursors.push(cursor);
+      cursor += operand_count(op);
+    }
+    cursors.push(cursor);
+
+    let small = |value: isize| i32::try_from(value).ok();
+    //Jumps resume right after the matching bracket, like in the enum interpreter
+    let target = |index: usize| Some([small((index + 1) as isize)?, small(cursors[index + 1] as isize)?]);
+    let mut packed = PackedProgram {
+      code: Vec::with_capacity(self.ops.len()),
+      operands: Vec::with_capacity(cursor),
+      hot: EOF,
+    };
+    for op in &self.ops {
+      if op.is_fusable() {
+        packed.code.push(pack_straight_line(op, &mut packed.operands)?);
+        continue
+      }
+      if let Opcode::Fused(ref ops) = *op {
+        packed.code.push(FUSED);
+        packed.operands.push(small((operand_count(op) - 1) as isize)?);
+        for op in &ops[..] {
+          let at = packed.operands.len();
+          packed.operands.push(0);
+          packed.operands[at] = pack_straight_line(op, &mut packed.operands)? as i32;
+        }
+        continue
+      }
+      let (tag, operands): (u8, &[i32]) = match *op {
+        Opcode::LoopStart(end) => (LOOP_START, &target(end)?),
+        Opcode::LoopEnd(start) => (LOOP_END, &target(start)?),
+        Opcode::Output(offset) => (OUTPUT, &[small(offset)?]),
+        Opcode::Input(offset) => (INPUT, &[small(offset)?]),
+        Opcode::ScanZero(direction) => (SCAN_ZERO, &[small(direction)?]),
+        Opcode::MoveLoopStart(offset, end) => {
+          let [index, cursor] = target(end)?;
+          (MOVE_LOOP_START, &[small(offset)?, index, cursor])
+        },
+        Opcode::MoveLoopEnd(offset, start) => {
+          let [index, cursor] = target(start)?;
+          (MOVE_LOOP_END, &[small(offset)?, index, cursor])
+        },
+        Opcode::Flush => (FLUSH, &[]),
+        Opcode::Eof => (EOF, &[]),
+        _ => unreachable!("packed above"),
+      };
+      packed.code.push(tag);
+      packed.operands.extend_from_slice(operands);
+    }
+    let mut frequencies = [0u64; EOF as usize];
+    for (&tag, &weight) in packed.code.iter().zip(weights) {
+      if tag != EOF {
+        frequencies[tag as usize] = frequencies[tag as usize].saturating_add(weight);
+      }
+    }
+    if let Some((tag, _)) = frequencies.iter().enumerate().filter(|x| *x.1 > 0).max_by_key(|x| *x.1) {
+      packed.hot = tag as u8;
+    }
+    Some(packed)
+  }
+}
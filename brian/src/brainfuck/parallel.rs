@@ -0,0 +1,67 @@
+use std::{ops::Range, thread};
+use super::{CompileOptions, Opcode, OptimizerError, Program};
+
+/// Programs with fewer opcodes are optimized on the calling thread
+const PARALLEL_THRESHOLD: usize = 1 << 20;
+/// Minimum number of opcodes per segment, segments end at the first top-level loop end after it
+///
+/// Segment boundaries only depend on the program, so that the output doesn't depend on the number of threads
+const SEGMENT_SIZE: usize = 1 << 16;
+
+impl Program {
+  /// Run the optimizer, splitting huge programs into segments at top-level loop boundaries
+  /// and optimizing them in parallel
+  ///
+  /// Block effects can't be merged across segment boundaries, so the output may differ slightly from
+  /// [`Program::optimize`] on the whole program. Requires linked loops
+  pub(crate) fn optimize_parallel(ops: &[Opcode], options: &CompileOptions) -> Result<Vec<Opcode>, OptimizerError> {
+    if ops.len() < PARALLEL_THRESHOLD {
+      return Self::optimize(ops, options)
+    }
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+    for (index, op) in ops.iter().enumerate() {
+      match op {
+        Opcode::LoopStart(_) => depth += 1,
+        Opcode::LoopEnd(_) => depth -= 1,
+        _ => (),
+      }
+      if depth == 0 && index + 1 - start >= SEGMENT_SIZE && index + 1 < ops.len() {
+        segments.push(start..(index + 1));
+        start = index + 1;
+      }
+    }
+    segments.push(start..ops.len());
+
+    let last = segments.len() - 1;
+    let optimize_segment = |(number, range): (usize, &Range<usize>)| {
+      let mut segment = ops[range.clone()].to_vec();
+      //Every segment but the last gets a temporary Eof, which commits the pending block effects
+      if number == last {
+        Self::link_loops(&mut segment)?;
+        return Self::optimize(&segment, options)
+      }
+      segment.push(Opcode::Eof);
+      Self::link_loops(&mut segment)?;
+      let options = CompileOptions { drop_dead_stores: false, ..options.clone() };
+      let mut optimized = Self::optimize(&segment, &options)?;
+      optimized.pop();
+      Ok(optimized)
+    };
+    let threads = thread::available_parallelism().map_or(1, |x| x.get());
+    let chunk_size = segments.len().div_ceil(threads);
+    let numbered: Vec<(usize, &Range<usize>)> = segments.iter().enumerate().collect();
+    let optimized: Vec<Vec<Result<Vec<Opcode>, OptimizerError>>> = thread::scope(|scope| {
+      let workers: Vec<_> = numbered.chunks(chunk_size).map(|chunk| {
+        scope.spawn(|| chunk.iter().copied().map(optimize_segment).collect())
+      }).collect();
+      workers.into_iter().map(|x| x.join().unwrap()).collect()
+    });
+    let mut output = Vec::with_capacity(ops.len());
+    for segment in optimized.into_iter().flatten() {
+      output.extend(segment?);
+    }
+    Ok(output)
+  }
+}
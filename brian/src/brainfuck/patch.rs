@@ -0,0 +1,51 @@
+use std::{fmt, sync::Arc};
+use super::{Brainfuck, Opcode, Program};
+
+/// Error returned by [`Program::patch`], which leaves the program unchanged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatchError {
+  /// Index is past the end of the program
+  OutOfRange(usize),
+  /// Loop brackets wouldn't match anymore
+  UnbalancedLoops,
+  /// Program wouldn't be terminated by exactly one `Eof`
+  MissingEof,
+}
+impl fmt::Display for PatchError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::OutOfRange(index) => write!(f, "opcode index {index} is out of range"),
+      Self::UnbalancedLoops => write!(f, "patch would unbalance loops"),
+      Self::MissingEof => write!(f, "patch would leave the program without a single trailing Eof"),
+    }
+  }
+}
+impl std::error::Error for PatchError {}
+
+
+impl Program {
+  /// Replace the opcode at `program_counter`, returning the previous one
+  ///
+  /// Loop targets of `op` are ignored, all loops are relinked after the change.
+  /// Fails if the program would end up with unbalanced loops or without its trailing `Eof`
+  pub fn patch(&mut self, program_counter: usize, op: Opcode) -> Result<Opcode, PatchError> {
+    if program_counter >= self.ops.len() {
+      return Err(PatchError::OutOfRange(program_counter))
+    }
+    if matches!(op, Opcode::Eof) != (program_counter == self.ops.len() - 1) {
+      return Err(PatchError::MissingEof)
+    }
+    let mut ops = self.ops.clone();
+    let previous = std::mem::replace(&mut ops[program_counter], op);
+    Self::link_loops(&mut ops).map_err(|_| PatchError::UnbalancedLoops)?;
+    self.ops = ops;
+    Ok(previous)
+  }
+}
+
+impl Brainfuck {
+  /// Replace the opcode at `program_counter` of the loaded program, see [`Program::patch`]
+  pub fn patch(&mut self, program_counter: usize, op: Opcode) -> Result<Opcode, PatchError> {
+    Arc::make_mut(&mut self.program).patch(program_counter, op)
+  }
+}
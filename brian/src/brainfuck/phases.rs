@@ -0,0 +1,165 @@
+//! Compilation split into explicit phases: parse → link → optimize → relink → finalize
+//!
+//! [`Program::compile_with_report`] runs all of them in order, [`CompilePipeline`] lets tools inspect or modify
+//! the opcodes in between
+
+use std::{collections::BTreeMap, time::Instant};
+use crate::lint::{self, Diagnostic, Severity};
+use super::{source_map, BracketPolicy, CompileError, CompileOptions, CompileReport, LoopTree, Opcode, Program, SourceMap, Span};
+
+/// Compilation in progress, advanced one phase at a time
+///
+/// Loop targets must be linked before optimizing and finalizing, so opcodes modified through
+/// [`CompilePipeline::opcodes_mut`] have to be relinked before either
+#[derive(Clone, Debug)]
+pub struct CompilePipeline<'a> {
+  options: &'a CompileOptions,
+  ops: Vec<Opcode>,
+  linked: bool,
+  /// Opcodes as parsed and their locations, which the final opcodes are mapped back to
+  parsed: Vec<Opcode>,
+  spans: Vec<Span>,
+  /// Whether the main optimizer pass ran, see [`SourceMap::build`]
+  optimized: bool,
+  report: CompileReport,
+  start: Instant,
+}
+impl<'a> CompilePipeline<'a> {
+  /// Parse phase: turn source code into opcodes, with loop targets not linked yet
+  ///
+  /// Brackets are balanced by a lenient [`CompileOptions::brackets`] policy. Otherwise they aren't checked,
+  /// so linking panics if they are unbalanced, see [`CompilePipeline::try_parse`]
+  pub fn parse(code: &str, options: &'a CompileOptions) -> Self {
+    let start = Instant::now();
+    let code = options.comments.strip(code);
+    let mut ops = Program::parse(&code, options.flush);
+    let mut spans = source_map::parse_spans(&code, options.flush);
+    options.brackets.balance(&mut ops, &mut spans);
+    let mut report = CompileReport::default();
+    report.stats.timings.push(("parse", start.elapsed()));
+    Self { options, parsed: ops.clone(), ops, linked: false, spans, optimized: false, report, start }
+  }
+
+  /// Like [`CompilePipeline::parse`], but failing if the brackets are unbalanced under a strict
+  /// [`CompileOptions::brackets`] policy, so linking can't panic, or if a lint denied by
+  /// [`CompileOptions::lints`] finds an issue
+  pub fn try_parse(code: &str, options: &'a CompileOptions) -> Result<Self, CompileError> {
+    if options.brackets == BracketPolicy::Strict {
+      Program::check_brackets(&options.comments.strip(code))?;
+    }
+    if let Some(lints) = &options.lints {
+      let denied: Vec<Diagnostic> = lint::check(&options.comments.strip(code), lints)
+        .into_iter().filter(|x| x.severity == Severity::Error).collect();
+      if !denied.is_empty() {
+        return Err(CompileError::DeniedLints(denied))
+      }
+    }
+    Ok(Self::parse(code, options))
+  }
+
+  /// Link phase: fill in loop jump targets
+  ///
+  /// Panics if the loops are unbalanced
+  pub fn link(mut self) -> Self {
+    self.timed("link", Self::relink_loops);
+    self
+  }
+
+  /// Optimize phase: run the optimization passes enabled by the options, recording skipped and rejected ones
+  /// in the report
+  pub fn optimize(mut self) -> Self {
+    assert!(self.linked, "loops must be linked before optimizing");
+    type Pass = fn(&[Opcode], &CompileOptions) -> Option<Vec<Opcode>>;
+    let options = self.options;
+    let mut passes: Vec<(&'static str, Pass)> = vec![
+      //The program is still correct without optimizations, so internal inconsistencies only skip the pass
+      ("optimize", |ops, options| Program::optimize_parallel(ops, options).ok()),
+      ("fold_known_values", |ops, options| Some(Program::fold_known_values(ops, options.assume_zeroed_tape))),
+    ];
+    if options.dataflow {
+      passes.push(("dataflow", |ops, _| Some(Program::optimize_dataflow(ops))));
+    }
+    passes.push(("canonicalize", |ops, _| Some(Program::canonicalize_ops(ops))));
+
+    //Every pass expects linked loops, and compilation can stop after any of them with a valid program
+    for (name, pass) in passes {
+      let pass_start = Instant::now();
+      let optimized = match options.time_budget {
+        Some(budget) if self.start.elapsed() >= budget => None,
+        _ => pass(&self.ops, options),
+      };
+      let Some(mut optimized) = optimized else {
+        self.report.skipped_passes.push(name);
+        continue
+      };
+      //Unbalanced loops are an internal inconsistency too
+      if Program::link_loops(&mut optimized).is_err() {
+        self.report.skipped_passes.push(name);
+        continue
+      }
+      self.report.stats.timings.push((name, pass_start.elapsed()));
+      if cfg!(debug_assertions) {
+        let guard_start = Instant::now();
+        let guarded = Program::guard_pass(name, &self.ops, &optimized, options);
+        self.report.stats.timings.push(("guard", guard_start.elapsed()));
+        if let Err(miscompilation) = guarded {
+          self.report.miscompilations.push(miscompilation);
+          continue
+        }
+      }
+      self.ops = optimized;
+      self.optimized |= name == "optimize";
+    }
+    self
+  }
+
+  /// Relink phase: link loop targets again, after the opcodes were modified
+  ///
+  /// Panics if the loops are unbalanced
+  pub fn relink(mut self) -> Self {
+    self.timed("relink", Self::relink_loops);
+    self
+  }
+
+  /// Finalize phase: build the program, along with the report of all phases and the source map
+  pub fn finalize(mut self) -> (Program, CompileReport) {
+    assert!(self.linked, "loops must be linked before finalizing");
+    let start = Instant::now();
+    self.report.source_map = SourceMap::build(&self.parsed, &self.spans, &self.ops, self.optimized);
+    self.report.stats.timings.push(("source_map", start.elapsed()));
+    (Program { ops: self.ops, metadata: BTreeMap::new() }, self.report)
+  }
+
+  /// Get the opcodes as of the last phase
+  pub fn opcodes(&self) -> &[Opcode] {
+    &self.ops
+  }
+
+  /// Get the opcodes for modification, which requires relinking them afterwards
+  pub fn opcodes_mut(&mut self) -> &mut Vec<Opcode> {
+    self.linked = false;
+    &mut self.ops
+  }
+
+  /// Loop structure of the opcodes, or `None` if they aren't linked
+  pub fn loop_tree(&self) -> Option<LoopTree> {
+    self.linked.then(|| Program::link_loops(&mut self.ops.clone()).ok()).flatten()
+  }
+
+  /// Get the report of the passes run so far
+  pub fn report(&self) -> &CompileReport {
+    &self.report
+  }
+
+  /// Run `phase`, recording how long it took under `name`
+  fn timed(&mut self, name: &'static str, phase: impl FnOnce(&mut Self)) {
+    let start = Instant::now();
+    phase(self);
+    self.report.stats.timings.push((name, start.elapsed()));
+  }
+
+  fn relink_loops(&mut self) {
+    Program::link_loops(&mut self.ops).unwrap_or_else(|error| panic!("{error}"));
+    self.linked = true;
+  }
+}
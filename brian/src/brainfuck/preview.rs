@@ -0,0 +1,99 @@
+use std::ops::ControlFlow;
+use super::{interpreter, Brainfuck, Hooks, Io, Opcode, StateView, Trap};
+
+/// Why a preview stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewEnd {
+  /// The program reached its end within the budget
+  Halted,
+  /// The program executed `max_steps` instructions
+  StepLimit,
+  /// The program wrote `max_output` bytes
+  OutputLimit,
+  /// The program was stopped by the bounds policy, see [`Brainfuck::set_bounds_policy`]
+  Trapped(Trap),
+}
+
+/// Output produced by [`Brainfuck::preview`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewResult {
+  /// First bytes written by the program, at most `max_output` of them
+  pub output: Vec<u8>,
+  /// Number of instructions executed
+  pub steps: usize,
+  pub end: PreviewEnd,
+}
+
+/// [`Io`] collecting output, with EOF on every input
+struct PreviewIo(Vec<u8>);
+impl Io for PreviewIo {
+  fn input(&mut self) -> Option<u8> {
+    None
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    self.0.push(byte);
+  }
+}
+
+/// Hooks enforcing the budget; every `Output` writes exactly one byte, so output is counted here as well
+struct Budget {
+  steps: usize,
+  max_steps: usize,
+  output: usize,
+  max_output: usize,
+  end: Option<PreviewEnd>,
+}
+impl Hooks for Budget {
+  #[inline(always)]
+  fn before(&mut self, _program_counter: usize, op: &Opcode, _state: StateView) -> ControlFlow<()> {
+    //Reaching the end takes no budget
+    if matches!(op, Opcode::Eof) {
+      return ControlFlow::Continue(())
+    }
+    if self.steps == self.max_steps {
+      self.end = Some(PreviewEnd::StepLimit);
+      return ControlFlow::Break(())
+    }
+    self.steps += 1;
+    ControlFlow::Continue(())
+  }
+
+  #[inline(always)]
+  fn after(&mut self, _program_counter: usize, op: &Opcode, _state: StateView) -> ControlFlow<()> {
+    if matches!(op, Opcode::Output(_)) {
+      self.output += 1;
+      if self.output == self.max_output {
+        self.end = Some(PreviewEnd::OutputLimit);
+        return ControlFlow::Break(())
+      }
+    }
+    ControlFlow::Continue(())
+  }
+}
+
+impl Brainfuck {
+  /// Run the program on a copy of the current state until it ends, executes `max_steps` instructions
+  /// or writes `max_output` bytes, returning the output so far
+  ///
+  /// The interpreter itself is left untouched, so a playground can show the first bytes quickly and then do the full
+  /// run. The program sees EOF on every input
+  pub fn preview(&self, max_steps: usize, max_output: usize) -> PreviewResult {
+    let mut budget = Budget { steps: 0, max_steps, output: 0, max_output, end: None };
+    let mut io = PreviewIo(Vec::new());
+    if max_output == 0 {
+      budget.end = Some(PreviewEnd::OutputLimit);
+    } else {
+      let mut state = Box::new(*self.state);
+      if let Err(trap) = interpreter::run_with_hooks_and_policies(&self.program, &mut state, &mut io, &mut budget, self.eof, self.bounds) {
+        budget.end = Some(PreviewEnd::Trapped(trap));
+      }
+    }
+    PreviewResult {
+      output: io.0,
+      steps: budget.steps,
+      end: budget.end.unwrap_or(PreviewEnd::Halted),
+    }
+  }
+}
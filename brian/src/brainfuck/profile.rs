@@ -0,0 +1,96 @@
+use std::{cmp::Reverse, fmt};
+use super::{Opcode, PackedProgram, Program};
+
+const PROFILE_HEADER: &str = "brian-profile 1";
+
+/// Loops iterating less than `1 / HOT_RATIO` times as often as the hottest loop are considered cold
+const HOT_RATIO: u64 = 1000;
+
+/// Number of times each opcode of a program was executed, recorded by [`Brainfuck::run_profiled`](crate::Brainfuck::run_profiled)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+  /// Execution count of each opcode, indexed like [`Program::opcodes`]
+  pub counts: Vec<u64>,
+}
+impl Profile {
+  /// Loops ordered by the number of times their body was executed, hottest first
+  ///
+  /// Returns pairs of loop start index and iteration count, none if the profile was not recorded for this program
+  pub fn hot_loops(&self, program: &Program) -> Vec<(usize, u64)> {
+    if self.counts.len() != program.ops.len() {
+      return Vec::new()
+    }
+    let mut loops: Vec<(usize, u64)> = program.ops.iter().enumerate().filter_map(|(index, op)| match op {
+      //Every iteration ends with a jump back from (or a fallthrough past) the loop end
+      Opcode::LoopStart(end) | Opcode::MoveLoopStart(_, end) => Some((index, self.counts[*end])),
+      _ => None,
+    }).collect();
+    loops.sort_by_key(|x| Reverse(x.1));
+    loops
+  }
+
+  /// Parse a profile from the text format produced by the `Display` implementation
+  pub fn from_text(text: &str) -> Option<Self> {
+    let mut lines = text.lines();
+    if lines.next()? != PROFILE_HEADER { return None }
+    let counts = lines.map(|x| x.trim().parse().ok()).collect::<Option<Vec<u64>>>()?;
+    Some(Self { counts })
+  }
+}
+impl fmt::Display for Profile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{PROFILE_HEADER}")?;
+    for count in &self.counts {
+      writeln!(f, "{count}")?;
+    }
+    Ok(())
+  }
+}
+
+impl Program {
+  /// Recompile the program into a [`PackedProgram`] using a profile recorded by running it
+  ///
+  /// Pointer movements directly followed by a bracket of a hot loop are fused into `MoveLoopStart` and `MoveLoopEnd`
+  /// (see [`Program::fuse_hot_loop_brackets`]), the `superinstructions` most profitable straight-line sequences
+  /// are fused (see [`Program::fuse_superinstructions`]) and dispatch is tuned for the opcode kind executed most.
+  /// Returns `None` if the profile was not recorded for this program, or if it doesn't fit into the packed form
+  pub fn recompile_with_profile(&self, profile: &Profile, superinstructions: usize) -> Option<PackedProgram> {
+    let mut runs = vec![1; self.ops.len()];
+    self.fuse_hot_loop_runs(profile, &mut runs)?;
+    self.fuse_runs(profile, superinstructions, &mut runs)?;
+    //A superinstruction is executed as often as its first opcode
+    let counts = runs.iter().zip(&profile.counts).filter(|(&length, _)| length > 0).map(|(_, &count)| count).collect();
+    self.with_runs(&runs).pack_with_profile(&Profile { counts })
+  }
+
+  /// Fuse pointer movements directly followed by a bracket of a hot loop into `MoveLoopStart` and `MoveLoopEnd`,
+  /// using the loop iteration counts of a profile recorded by running the program
+  ///
+  /// Saves one dispatch per iteration of the hot loops, cold loops are left untouched.
+  /// Returns `None` if the profile was not recorded for this program
+  pub fn fuse_hot_loop_brackets(&self, profile: &Profile) -> Option<Program> {
+    let mut runs = vec![1; self.ops.len()];
+    self.fuse_hot_loop_runs(profile, &mut runs)?;
+    Some(self.with_runs(&runs))
+  }
+
+  /// Mark the pointer movements fused with a hot loop bracket in `runs`, see [`Program::fuse_runs`]
+  fn fuse_hot_loop_runs(&self, profile: &Profile, runs: &mut [usize]) -> Option<()> {
+    if profile.counts.len() != self.ops.len() {
+      return None
+    }
+    let loops = profile.hot_loops(self);
+    let threshold = loops.first().map_or(0, |x| x.1) / HOT_RATIO;
+    for (start, _) in loops.into_iter().take_while(|&(_, iterations)| iterations > 0 && iterations >= threshold) {
+      let Opcode::LoopStart(end) = self.ops[start] else { continue };
+      //Jumps always land right after a loop bracket, so a bracket preceded by a pointer movement is only reached through it
+      for bracket in [start, end] {
+        if bracket > 0 && matches!(self.ops[bracket - 1], Opcode::MovePointer(_)) {
+          runs[bracket - 1] = 2;
+          runs[bracket] = 0;
+        }
+      }
+    }
+    Some(())
+  }
+}
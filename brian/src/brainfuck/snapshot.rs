@@ -0,0 +1,69 @@
+use std::fmt;
+use super::{BrainfuckState, MEMORY_SIZE};
+
+/// Magic bytes at the start of every state snapshot
+const MAGIC: &[u8; 4] = b"BFST";
+/// Size of the header: magic, pointer, start and length of the stored part of the tape
+const HEADER_SIZE: usize = MAGIC.len() + 8 + 4 + 4;
+
+/// Error encountered while restoring a state snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+  /// Data doesn't start with the snapshot magic bytes
+  BadMagic,
+  /// Data is shorter than the snapshot it describes
+  Truncated,
+  /// Stored part of the tape doesn't fit on the tape
+  OutOfBounds,
+}
+impl fmt::Display for SnapshotError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::BadMagic => write!(f, "not a brian state snapshot"),
+      Self::Truncated => write!(f, "unexpected end of snapshot"),
+      Self::OutOfBounds => write!(f, "snapshot tape is out of bounds"),
+    }
+  }
+}
+impl std::error::Error for SnapshotError {}
+
+impl BrainfuckState {
+  /// Serialize the tape and pointer, for saving interactive sessions and long-running jobs
+  ///
+  /// Only [`BrainfuckState::used_range`] is stored, the rest of the tape is zero when restored
+  pub fn snapshot(&self) -> Vec<u8> {
+    let range = self.used_range();
+    let mut output = Vec::with_capacity(HEADER_SIZE + range.len());
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&(self.pointer as u64).to_le_bytes());
+    output.extend_from_slice(&(range.start as u32).to_le_bytes());
+    output.extend_from_slice(&(range.len() as u32).to_le_bytes());
+    output.extend_from_slice(&self.memory[range]);
+    output
+  }
+
+  /// Restore a state serialized with [`BrainfuckState::snapshot`], returning the number of bytes it took up
+  ///
+  /// The state is left untouched on error
+  pub fn restore(&mut self, data: &[u8]) -> Result<usize, SnapshotError> {
+    if !data.starts_with(MAGIC) {
+      return Err(SnapshotError::BadMagic)
+    }
+    let header = data.get(..HEADER_SIZE).ok_or(SnapshotError::Truncated)?;
+    let field = |range: std::ops::Range<usize>| {
+      let mut bytes = [0; 8];
+      bytes[..range.len()].copy_from_slice(&header[range]);
+      u64::from_le_bytes(bytes)
+    };
+    let pointer = field(4..12) as usize;
+    let (start, length) = (field(12..16) as usize, field(16..20) as usize);
+    if start + length > MEMORY_SIZE {
+      return Err(SnapshotError::OutOfBounds)
+    }
+    let tape = data.get(HEADER_SIZE..HEADER_SIZE + length).ok_or(SnapshotError::Truncated)?;
+    self.reset();
+    self.pointer = pointer;
+    self.memory[start..start + length].copy_from_slice(tape);
+    Ok(HEADER_SIZE + length)
+  }
+}
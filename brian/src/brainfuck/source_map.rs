@@ -0,0 +1,223 @@
+//! Mapping compiled opcodes back to the source code they came from
+//!
+//! Rather than threading locations through every optimization pass, the compiled opcodes are aligned with the parsed
+//! ones by their loops and I/O, which passes may drop but never reorder. Those keep the location of their command,
+//! the opcodes in between get the location of the code between the aligned commands around them
+
+use super::{optimizer::loop_idiom, token, Opcode, Span};
+
+/// Sibling pairs compared at most when aligning a loop body, larger bodies are aligned greedily
+const MAX_ALIGNMENT_CELLS: usize = 1 << 20;
+/// Siblings skipped at most when looking for a match in a greedy alignment
+const GREEDY_LOOKAHEAD: usize = 64;
+
+/// Source location of every opcode of a compiled program, see [`CompileReport::source_map`](super::CompileReport)
+///
+/// Locations are exact for loop brackets, I/O and flushes. Other opcodes are located at the whole straight-line code
+/// they were built from, which also covers loops replaced by a single opcode, like `[-]`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+  spans: Vec<Span>,
+}
+impl SourceMap {
+  /// Location of the code compiled to the opcode at `instruction`
+  ///
+  /// Opcodes not coming from the code right around them, like pointer movements deferred past an output,
+  /// get an empty range where they are executed
+  pub fn span(&self, instruction: usize) -> Option<&Span> {
+    self.spans.get(instruction)
+  }
+
+  /// Locations of all opcodes, in program order
+  pub fn spans(&self) -> &[Span] {
+    &self.spans
+  }
+
+  /// Map `ops`, compiled from the `parsed` opcodes located at `spans`
+  ///
+  /// `optimized` tells whether the main optimizer pass ran, which replaces every clear, move and scan loop
+  pub(super) fn build(parsed: &[Opcode], spans: &[Span], ops: &[Opcode], optimized: bool) -> Self {
+    let (old, new) = (anchors(parsed, optimized), anchors(ops, false));
+    //Index of the parsed opcode every aligned anchor comes from
+    let mut origins = vec![None; ops.len()];
+    for (x, y) in align(&old, &new) {
+      origins[new[y].start] = Some(old[x].start);
+      origins[new[y].end] = Some(old[x].end);
+    }
+    let mut next = vec![parsed.len(); ops.len() + 1];
+    for index in (0..ops.len()).rev() {
+      next[index] = origins[index].unwrap_or(next[index + 1]);
+    }
+    let mut previous = None;
+    let spans = (0..ops.len()).map(|index| match origins[index] {
+      Some(origin) => {
+        previous = Some(origin);
+        spans[origin].clone()
+      },
+      None => between(spans, previous.map_or(0, |x| x + 1), next[index]),
+    }).collect();
+    Self { spans }
+  }
+}
+
+/// Location of every opcode produced by [`Program::parse`](super::Program::parse), including the final `Eof`
+pub(super) fn parse_spans(code: &str, flush: Option<char>) -> Vec<Span> {
+  let (mut line, mut column) = (1, 0);
+  let mut spans = Vec::new();
+  for (index, x) in code.char_indices() {
+    column += 1;
+    if x == '\n' {
+      (line, column) = (line + 1, 0);
+    }
+    if Some(x) == flush || token(x).is_some() {
+      spans.push(Span { range: index..(index + x.len_utf8()), line, column });
+    }
+  }
+  spans.push(Span { range: code.len()..code.len(), line, column: column + 1 });
+  spans
+}
+
+/// Location covering the parsed opcodes `first..last`, or an empty range at `first` if there are none
+fn between(spans: &[Span], first: usize, last: usize) -> Span {
+  if first < last {
+    return Span { range: spans[first].range.start..spans[last - 1].range.end, ..spans[first].clone() }
+  }
+  let at = &spans[first.min(spans.len() - 1)];
+  Span { range: at.range.start..at.range.start, ..at.clone() }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+  Loop,
+  Output,
+  Input,
+  Flush,
+  Eof,
+}
+
+/// Loop or I/O opcode, which keeps its place through optimization
+struct Anchor {
+  kind: Kind,
+  /// Index of the opcode, the loop start for loops
+  start: usize,
+  /// Index of the loop end, or `start`
+  end: usize,
+  /// Anchors nested in a loop
+  children: Vec<usize>,
+  /// Number of anchors in the subtree, this one included
+  size: usize,
+  /// Whether the loop is always replaced by the optimizer, so it can't be aligned with a compiled loop
+  replaced: bool,
+}
+
+/// Anchors of a program, the top-level ones being the children of the root at index 0
+fn anchors(ops: &[Opcode], optimized: bool) -> Vec<Anchor> {
+  let anchor = |kind, start| Anchor { kind, start, end: start, children: Vec::new(), size: 1, replaced: false };
+  let mut anchors = vec![anchor(Kind::Loop, 0)];
+  //Loops not closed yet, innermost last
+  let mut open = vec![0];
+  let close = |anchors: &mut Vec<Anchor>, id: usize, end: usize| {
+    let size = 1 + anchors[id].children.iter().map(|&x| anchors[x].size).sum::<usize>();
+    let node = &mut anchors[id];
+    node.replaced = optimized && node.children.is_empty() && loop_idiom(&ops[(node.start + 1)..end]).is_some();
+    (node.end, node.size) = (end, size);
+  };
+  for (index, op) in ops.iter().enumerate() {
+    let kind = match op {
+      Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) => Kind::Loop,
+      Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) => {
+        if open.len() > 1 {
+          let id = open.pop().unwrap();
+          close(&mut anchors, id, index);
+        }
+        continue
+      },
+      Opcode::Output(_) => Kind::Output,
+      Opcode::Input(_) => Kind::Input,
+      Opcode::Flush => Kind::Flush,
+      Opcode::Eof => Kind::Eof,
+      _ => continue,
+    };
+    let id = anchors.len();
+    anchors.push(anchor(kind, index));
+    anchors[*open.last().unwrap()].children.push(id);
+    if kind == Kind::Loop {
+      open.push(id);
+    }
+  }
+  //Only the root is left open in linked programs
+  while let Some(id) = open.pop() {
+    close(&mut anchors, id, ops.len());
+  }
+  anchors
+}
+
+/// Weight of aligning two anchors, or 0 if they can't be the same opcode
+///
+/// Passes only drop anchors, so a compiled loop never holds more anchors than the parsed one it comes from
+fn affinity(old: &Anchor, new: &Anchor) -> usize {
+  match old.kind == new.kind && !old.replaced && new.size <= old.size {
+    true => new.size,
+    false => 0,
+  }
+}
+
+/// Pairs of aligned anchors, parsed one first, excluding the roots
+///
+/// Loop bodies are aligned once their loops are, on an explicit stack so deep nesting can't overflow
+fn align(old: &[Anchor], new: &[Anchor]) -> Vec<(usize, usize)> {
+  let mut pairs = Vec::new();
+  let mut pending = vec![(0, 0)];
+  while let Some((x, y)) = pending.pop() {
+    for (x, y) in align_siblings(old, &old[x].children, new, &new[y].children) {
+      pairs.push((x, y));
+      if old[x].kind == Kind::Loop {
+        pending.push((x, y));
+      }
+    }
+  }
+  pairs
+}
+
+/// Align two lists of sibling anchors, maximizing the total [`affinity`]
+///
+/// Ties go to the latest parsed anchors, as the passes mostly drop code near the start, where cell values are known
+fn align_siblings(old: &[Anchor], xs: &[usize], new: &[Anchor], ys: &[usize]) -> Vec<(usize, usize)> {
+  let affinity = |i: usize, j: usize| affinity(&old[xs[i]], &new[ys[j]]);
+  let mut pairs = Vec::new();
+  let width = ys.len() + 1;
+  if (xs.len() + 1) * width > MAX_ALIGNMENT_CELLS {
+    let mut i = 0;
+    for &y in ys {
+      let end = xs.len().min(i + GREEDY_LOOKAHEAD);
+      if let Some(offset) = xs[i..end].iter().position(|&x| self::affinity(&old[x], &new[y]) > 0) {
+        pairs.push((xs[i + offset], y));
+        i += offset + 1;
+      }
+    }
+    return pairs
+  }
+  let mut scores = vec![0; (xs.len() + 1) * width];
+  for i in 1..=xs.len() {
+    for j in 1..=ys.len() {
+      let skip = scores[(i - 1) * width + j].max(scores[i * width + j - 1]);
+      scores[i * width + j] = match affinity(i - 1, j - 1) {
+        0 => skip,
+        weight => skip.max(scores[(i - 1) * width + j - 1] + weight),
+      };
+    }
+  }
+  let (mut i, mut j) = (xs.len(), ys.len());
+  while i > 0 && j > 0 {
+    let weight = affinity(i - 1, j - 1);
+    if weight > 0 && scores[i * width + j] == scores[(i - 1) * width + j - 1] + weight {
+      pairs.push((xs[i - 1], ys[j - 1]));
+      (i, j) = (i - 1, j - 1);
+    } else if scores[i * width + j] == scores[(i - 1) * width + j] {
+      i -= 1;
+    } else {
+      j -= 1;
+    }
+  }
+  pairs
+}
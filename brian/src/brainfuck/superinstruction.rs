@@ -0,0 +1,122 @@
+use std::{cmp::Reverse, collections::HashMap};
+use super::{Opcode, Profile, Program};
+
+/// Longest opcode sequence considered by [`Program::fuse_superinstructions`]
+const MAX_FUSED_LENGTH: usize = 4;
+
+/// Sequence of opcode kinds that is frequently executed back to back, as found by [`Profile::superinstruction_candidates`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SuperinstructionCandidate {
+  /// Opcode kinds making up the sequence, as returned by [`Opcode::name`]
+  pub pattern: Vec<&'static str>,
+  /// Number of places in the program the sequence occurs at
+  pub sites: usize,
+  /// Number of times the sequence was executed
+  pub executions: u64,
+  /// Dispatches a fused handler would save (one per opcode after the first, for every execution)
+  pub saved_dispatches: u64,
+  /// Share of all dispatches in the profiled run that would be saved
+  pub saved_fraction: f64,
+}
+
+/// Whether control can leave the opcode other than by falling through to the next one
+#[inline]
+fn branches(op: &Opcode) -> bool {
+  matches!(op,
+    Opcode::LoopStart(_) | Opcode::LoopEnd(_) |
+    Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) |
+    Opcode::Eof
+  )
+}
+
+impl Profile {
+  /// Find the opcode sequences (of 2 up to `max_length` opcodes) that would benefit most from a fused handler
+  ///
+  /// Only sequences that always run to completion once entered are considered, so branching opcodes may only
+  /// appear at their end. Execution counts are therefore exact and can be read straight off the profile.
+  /// Candidates are ordered by estimated savings, best first.
+  /// Savings of overlapping candidates are not additive.
+  /// Returns `None` if the profile was not recorded for this program
+  pub fn superinstruction_candidates(&self, program: &Program, max_length: usize) -> Option<Vec<SuperinstructionCandidate>> {
+    if self.counts.len() != program.ops.len() {
+      return None
+    }
+    let total: u64 = self.counts.iter().sum();
+    let mut found: HashMap<Vec<&'static str>, (usize, u64)> = HashMap::new();
+    for start in 0..program.ops.len() {
+      if self.counts[start] == 0 { continue }
+      for end in (start + 1)..program.ops.len().min(start + max_length) {
+        //Jumps always land right after a branch, so the opcodes following `start` are only reached through it
+        if branches(&program.ops[end - 1]) { break }
+        let pattern = program.ops[start..=end].iter().map(Opcode::name).collect();
+        let entry = found.entry(pattern).or_default();
+        entry.0 += 1;
+        entry.1 += self.counts[start];
+      }
+    }
+    let mut candidates: Vec<SuperinstructionCandidate> = found.into_iter().map(|(pattern, (sites, executions))| {
+      let saved_dispatches = executions * (pattern.len() as u64 - 1);
+      SuperinstructionCandidate {
+        pattern,
+        sites,
+        executions,
+        saved_dispatches,
+        saved_fraction: saved_dispatches as f64 / total.max(1) as f64,
+      }
+    }).collect();
+    //Break ties by pattern to keep the order deterministic
+    candidates.sort_by(|a, b| (Reverse(a.saved_dispatches), &a.pattern).cmp(&(Reverse(b.saved_dispatches), &b.pattern)));
+    Some(candidates)
+  }
+}
+
+impl Program {
+  /// Fuse the `count` most profitable straight-line candidates found in a profile into [`Opcode::Fused`] superinstructions
+  ///
+  /// Only candidates made up of `Increment`, `Set`, `MovePointer` and `Move` opcodes are fused. Where candidates overlap,
+  /// the better ranked one wins. Every occurrence of a fused pattern is replaced, cold ones included, which costs nothing.
+  /// Returns `None` if the profile was not recorded for this program
+  pub fn fuse_superinstructions(&self, profile: &Profile, count: usize) -> Option<Program> {
+    let mut runs = vec![1; self.ops.len()];
+    self.fuse_runs(profile, count, &mut runs)?;
+    Some(self.with_runs(&runs))
+  }
+
+  /// Mark the sites of the `count` best straight-line candidates in `runs`, which holds the number of opcodes fused
+  /// into a superinstruction starting at each opcode, zero for opcodes fused into an earlier one
+  ///
+  /// Opcodes already part of a superinstruction are left alone
+  pub(crate) fn fuse_runs(&self, profile: &Profile, count: usize, runs: &mut [usize]) -> Option<()> {
+    let candidates = profile.superinstruction_candidates(self, MAX_FUSED_LENGTH)?;
+    let fusable = |pattern: &[&str]| pattern.iter().all(|x| matches!(*x, "Increment" | "Set" | "MovePointer" | "Move"));
+    for candidate in candidates.iter().filter(|x| fusable(&x.pattern)).take(count) {
+      let length = candidate.pattern.len();
+      for start in 0..self.ops.len().saturating_sub(length - 1) {
+        let run = start..(start + length);
+        if runs[run.clone()].iter().all(|&x| x == 1) && self.ops[run.clone()].iter().map(Opcode::name).eq(candidate.pattern.iter().copied()) {
+          runs[run].fill(0);
+          runs[start] = length;
+        }
+      }
+    }
+    Some(())
+  }
+
+  /// Replace the runs marked by [`Program::fuse_runs`] with superinstructions
+  ///
+  /// A pointer movement fused with the loop bracket following it becomes `MoveLoopStart` or `MoveLoopEnd`
+  pub(crate) fn with_runs(&self, runs: &[usize]) -> Program {
+    let mut ops = Vec::with_capacity(self.ops.len());
+    for (index, &length) in runs.iter().enumerate() {
+      match (length, &self.ops[index..]) {
+        (0, _) => (),
+        (1, [op, ..]) => ops.push(op.clone()),
+        (2, [Opcode::MovePointer(offset), Opcode::LoopStart(_), ..]) => ops.push(Opcode::MoveLoopStart(*offset, 0)),
+        (2, [Opcode::MovePointer(offset), Opcode::LoopEnd(_), ..]) => ops.push(Opcode::MoveLoopEnd(*offset, 0)),
+        (_, run) => ops.push(Opcode::Fused(run[..length].into())),
+      }
+    }
+    Self::link_loops(&mut ops).expect("fusing opcodes into superinstructions keeps loops balanced");
+    Self { ops, metadata: self.metadata.clone() }
+  }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use super::{Opcode, Program};
+
+/// Broken invariant of a program, found by [`Program::verify`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+  /// Loop bracket at the given opcode index jumps past the end of the program
+  TargetOutOfRange { index: usize, target: usize },
+  /// Loop bracket at the given opcode index isn't linked to its matching bracket
+  BadLoopLink(usize),
+  /// `Eof` at the given opcode index, before the end of the program
+  EarlyEof(usize),
+  /// Program doesn't end with `Eof`
+  MissingEof,
+  /// Scan at the given opcode index doesn't move the pointer, so it never ends on a nonzero cell
+  EmptyScan(usize),
+  /// Move at the given opcode index adds to the cell it clears
+  SelfMove(usize),
+  /// Superinstruction at the given opcode index fuses fewer than two opcodes, or ones that can't be fused
+  BadFusion(usize),
+}
+impl fmt::Display for VerifyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::TargetOutOfRange { index, target } => write!(f, "loop bracket at opcode {index} jumps to {target}, past the end"),
+      Self::BadLoopLink(index) => write!(f, "loop bracket at opcode {index} is not linked to its match"),
+      Self::EarlyEof(index) => write!(f, "Eof at opcode {index} is not at the end of the program"),
+      Self::MissingEof => write!(f, "program is not terminated by Eof"),
+      Self::EmptyScan(index) => write!(f, "scan at opcode {index} doesn't move the pointer"),
+      Self::SelfMove(index) => write!(f, "move at opcode {index} targets the cell it clears"),
+      Self::BadFusion(index) => write!(f, "superinstruction at opcode {index} doesn't fuse straight-line opcodes"),
+    }
+  }
+}
+impl std::error::Error for VerifyError {}
+
+impl Program {
+  /// Check the invariants every compiled program holds: loop brackets linked to their matches,
+  /// `Eof` at the end and only there, and superinstructions with meaningful operands
+  ///
+  /// Compilation runs it after every optimization pass in debug builds, see
+  /// [`CompileReport::miscompilations`](super::CompileReport::miscompilations).
+  /// Useful on demand for programs built or modified by other means
+  pub fn verify(&self) -> Result<(), VerifyError> {
+    Self::verify_ops(&self.ops)
+  }
+
+  pub(crate) fn verify_ops(ops: &[Opcode]) -> Result<(), VerifyError> {
+    for (index, op) in ops.iter().enumerate() {
+      let linked = match *op {
+        Opcode::LoopStart(target) | Opcode::MoveLoopStart(_, target) |
+        Opcode::LoopEnd(target) | Opcode::MoveLoopEnd(_, target) if target >= ops.len() => {
+          return Err(VerifyError::TargetOutOfRange { index, target })
+        },
+        Opcode::LoopStart(end) | Opcode::MoveLoopStart(_, end) => {
+          end > index && matches!(ops[end], Opcode::LoopEnd(x) | Opcode::MoveLoopEnd(_, x) if x == index)
+        },
+        Opcode::LoopEnd(start) | Opcode::MoveLoopEnd(_, start) => {
+          start < index && matches!(ops[start], Opcode::LoopStart(x) | Opcode::MoveLoopStart(_, x) if x == index)
+        },
+        Opcode::Eof if index + 1 != ops.len() => return Err(VerifyError::EarlyEof(index)),
+        Opcode::ScanZero(0) => return Err(VerifyError::EmptyScan(index)),
+        Opcode::Move(ref targets) if targets.iter().any(|&(offset, _)| offset == 0) => {
+          return Err(VerifyError::SelfMove(index))
+        },
+        Opcode::Fused(ref fused) if fused.len() < 2 || !fused.iter().all(Opcode::is_fusable) => {
+          return Err(VerifyError::BadFusion(index))
+        },
+        Opcode::Fused(ref fused) if fused.iter().any(|op| matches!(op, Opcode::Move(targets) if targets.iter().any(|&(offset, _)| offset == 0))) => {
+          return Err(VerifyError::SelfMove(index))
+        },
+        _ => true,
+      };
+      if !linked {
+        return Err(VerifyError::BadLoopLink(index))
+      }
+    }
+    match ops.last() {
+      Some(Opcode::Eof) => Ok(()),
+      _ => Err(VerifyError::MissingEof),
+    }
+  }
+}
@@ -0,0 +1,160 @@
+//! Breakpoints with conditions on the tape and pointer
+//!
+//! Stops programs exactly when a data structure on the tape reaches an interesting state,
+//! e.g. `pointer == 1234` or `cell[42] > 100`
+
+use std::{fmt, ops::ControlFlow, str::FromStr};
+use crate::{brainfuck::MEMORY_MASK, Hooks, Opcode, StateView};
+
+/// Comparison operator of a [`Condition`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+  Equal,
+  NotEqual,
+  Less,
+  LessOrEqual,
+  Greater,
+  GreaterOrEqual,
+}
+impl Comparison {
+  const ALL: [(&'static str, Self); 6] = [
+    //Two-character operators first, so `<=` isn't taken for `<`
+    ("==", Self::Equal),
+    ("!=", Self::NotEqual),
+    ("<=", Self::LessOrEqual),
+    (">=", Self::GreaterOrEqual),
+    ("<", Self::Less),
+    (">", Self::Greater),
+  ];
+
+  fn symbol(self) -> &'static str {
+    Self::ALL.iter().find(|(_, x)| *x == self).unwrap().0
+  }
+
+  #[inline]
+  fn holds(self, left: usize, right: usize) -> bool {
+    match self {
+      Self::Equal => left == right,
+      Self::NotEqual => left != right,
+      Self::Less => left < right,
+      Self::LessOrEqual => left <= right,
+      Self::Greater => left > right,
+      Self::GreaterOrEqual => left >= right,
+    }
+  }
+}
+
+/// Value tested by a [`Condition`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subject {
+  /// Tape index the pointer is at
+  Pointer,
+  /// Value of the cell at the given tape index
+  Cell(usize),
+}
+
+/// Condition on the state, parsed from strings like `pointer == 1234` or `cell[42] > 100`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+  pub subject: Subject,
+  pub comparison: Comparison,
+  pub value: usize,
+}
+impl Condition {
+  /// Whether the condition holds for `state`
+  #[inline]
+  pub fn holds(&self, state: StateView) -> bool {
+    let left = match self.subject {
+      Subject::Pointer => state.pointer & MEMORY_MASK,
+      Subject::Cell(index) => state.memory[index] as usize,
+    };
+    self.comparison.holds(left, self.value)
+  }
+}
+impl fmt::Display for Condition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.subject {
+      Subject::Pointer => write!(f, "pointer")?,
+      Subject::Cell(index) => write!(f, "cell[{index}]")?,
+    }
+    write!(f, " {} {}", self.comparison.symbol(), self.value)
+  }
+}
+
+/// Error returned when parsing an invalid [`Condition`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseConditionError(String);
+impl fmt::Display for ParseConditionError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid breakpoint condition: {}", self.0)
+  }
+}
+impl std::error::Error for ParseConditionError {}
+
+impl FromStr for Condition {
+  type Err = ParseConditionError;
+
+  fn from_str(condition: &str) -> Result<Self, Self::Err> {
+    let error = |message: &str| ParseConditionError(message.into());
+    let (index, symbol, comparison) = Comparison::ALL.iter()
+      .filter_map(|&(symbol, comparison)| condition.find(symbol).map(|index| (index, symbol, comparison)))
+      .min_by_key(|&(index, ..)| index)
+      .ok_or_else(|| error("expected one of == != < <= > >="))?;
+    let subject = match condition[..index].trim() {
+      "pointer" => Subject::Pointer,
+      subject => {
+        let index = subject.strip_prefix("cell[")
+          .and_then(|x| x.strip_suffix(']'))
+          .ok_or_else(|| error("expected `pointer` or `cell[INDEX]`"))?;
+        match index.trim().parse() {
+          Ok(index) if index <= MEMORY_MASK => Subject::Cell(index),
+          _ => return Err(error("cell index must be a tape index")),
+        }
+      },
+    };
+    let value = condition[index + symbol.len()..].trim().parse().map_err(|_| error("expected a number to compare with"))?;
+    Ok(Self { subject, comparison, value })
+  }
+}
+
+/// Breakpoint stopping the program before an opcode, if the opcode index and condition match
+///
+/// A breakpoint without an opcode index is checked before every opcode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Breakpoint {
+  pub program_counter: Option<usize>,
+  pub condition: Option<Condition>,
+}
+
+/// [`Hooks`] stopping the program at the first matching [`Breakpoint`]
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+  pub breakpoints: Vec<Breakpoint>,
+  hit: Option<(usize, usize)>,
+}
+impl Breakpoints {
+  pub fn new(breakpoints: Vec<Breakpoint>) -> Self {
+    Self { breakpoints, hit: None }
+  }
+
+  /// Index of the breakpoint that stopped the program and the opcode index it stopped before, if any
+  pub fn hit(&self) -> Option<(usize, usize)> {
+    self.hit
+  }
+}
+impl Hooks for Breakpoints {
+  #[inline]
+  fn before(&mut self, program_counter: usize, _op: &Opcode, state: StateView) -> ControlFlow<()> {
+    let matches = |breakpoint: &Breakpoint| {
+      breakpoint.program_counter.is_none_or(|x| x == program_counter)
+        && breakpoint.condition.is_none_or(|x| x.holds(state))
+    };
+    match self.breakpoints.iter().position(matches) {
+      Some(index) => {
+        self.hit = Some((index, program_counter));
+        ControlFlow::Break(())
+      },
+      None => ControlFlow::Continue(()),
+    }
+  }
+}
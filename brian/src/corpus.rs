@@ -0,0 +1,201 @@
+//! Parallel corpus runner
+//!
+//! Discovers brainfuck programs in a directory tree and runs them on a [`Pool`] with sandbox limits.
+//! A program `name.b` may be accompanied by `name.in` (input fed to the program) and `name.out` (expected output);
+//! programs without expected output pass as long as they halt
+
+use std::{
+  fmt::Write as _,
+  fs, io,
+  path::{Path, PathBuf},
+  sync::Arc,
+  thread,
+  time::{Duration, Instant},
+};
+use crate::{
+  pool::{Job, Outcome, Pool, Sandbox},
+  CompileOptions, Program,
+};
+
+/// Corpus runner configuration
+#[derive(Clone, Debug)]
+pub struct CorpusOptions {
+  /// File extensions of programs to run
+  pub extensions: Vec<String>,
+  /// Number of worker threads
+  pub threads: usize,
+  /// Limits applied to every program
+  pub sandbox: Sandbox,
+}
+impl Default for CorpusOptions {
+  fn default() -> Self {
+    Self {
+      extensions: vec!["b".into(), "bf".into()],
+      threads: thread::available_parallelism().map_or(1, |x| x.get()),
+      sandbox: Sandbox::default(),
+    }
+  }
+}
+
+/// Result of a single corpus program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+  /// Program halted with the expected output (or without expectations)
+  Passed,
+  /// Program halted with unexpected output, or exceeded the output limit
+  Failed,
+  /// Program ran out of fuel
+  TimedOut,
+  /// Program could not be loaded
+  Error(String),
+}
+impl Status {
+  fn name(&self) -> &'static str {
+    match self {
+      Self::Passed => "passed",
+      Self::Failed => "failed",
+      Self::TimedOut => "timed-out",
+      Self::Error(_) => "error",
+    }
+  }
+}
+
+/// Outcome of running one program of the corpus
+#[derive(Clone, Debug)]
+pub struct ProgramResult {
+  pub path: PathBuf,
+  pub status: Status,
+  /// Number of bytes written by the program
+  pub output_length: usize,
+  /// Time spent running the program
+  pub duration: Duration,
+}
+
+/// Aggregated results of a corpus run
+#[derive(Clone, Debug, Default)]
+pub struct CorpusSummary {
+  /// Results of all programs, ordered by path
+  pub results: Vec<ProgramResult>,
+  pub passed: usize,
+  pub failed: usize,
+  pub timed_out: usize,
+  pub errors: usize,
+  /// Wall-clock time of the whole run
+  pub duration: Duration,
+}
+impl CorpusSummary {
+  /// Whether every program passed
+  pub fn success(&self) -> bool {
+    self.passed == self.results.len()
+  }
+
+  /// Render the summary as a JSON report
+  pub fn to_json(&self) -> String {
+    let mut json = String::new();
+    let _ = write!(json,
+      "{{\"passed\":{},\"failed\":{},\"timed_out\":{},\"errors\":{},\"duration_ms\":{},\"results\":[",
+      self.passed, self.failed, self.timed_out, self.errors, self.duration.as_millis());
+    for (index, result) in self.results.iter().enumerate() {
+      if index > 0 { json.push(',') }
+      let _ = write!(json,
+        "{{\"path\":{},\"status\":\"{}\",\"output_length\":{},\"duration_ms\":{}",
+        json_string(&result.path.to_string_lossy()), result.status.name(), result.output_length, result.duration.as_millis());
+      if let Status::Error(message) = &result.status {
+        let _ = write!(json, ",\"error\":{}", json_string(message));
+      }
+      json.push('}');
+    }
+    json.push_str("]}");
+    json
+  }
+}
+
+fn json_string(value: &str) -> String {
+  let mut json = String::with_capacity(value.len() + 2);
+  json.push('"');
+  for char in value.chars() {
+    match char {
+      '"' => json.push_str("\\\""),
+      '\\' => json.push_str("\\\\"),
+      '\n' => json.push_str("\\n"),
+      char if (char as u32) < 0x20 => {
+        let _ = write!(json, "\\u{:04x}", char as u32);
+      },
+      char => json.push(char),
+    }
+  }
+  json.push('"');
+  json
+}
+
+/// Recursively collect program files, sorted by path
+fn discover(dir: &Path, extensions: &[String], programs: &mut Vec<PathBuf>) -> io::Result<()> {
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.is_dir() {
+      discover(&path, extensions, programs)?;
+    } else if path.extension().is_some_and(|x| extensions.iter().any(|e| x == e.as_str())) {
+      programs.push(path);
+    }
+  }
+  Ok(())
+}
+
+/// Read an optional companion file (`.in`/`.out`) of a program
+fn companion(path: &Path, extension: &str) -> io::Result<Option<Vec<u8>>> {
+  match fs::read(path.with_extension(extension)) {
+    Ok(data) => Ok(Some(data)),
+    Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(error) => Err(error),
+  }
+}
+
+/// Discover all programs in `dir` and run them in parallel
+///
+/// Fails only if the directory can't be read; problems with individual programs are reported as [`Status::Error`]
+pub fn run_corpus(dir: &Path, options: &CorpusOptions) -> io::Result<CorpusSummary> {
+  let start = Instant::now();
+  let mut paths = Vec::new();
+  discover(dir, &options.extensions, &mut paths)?;
+  paths.sort();
+
+  let pool = Pool::new(options.threads.max(1), options.threads.max(1) * 2);
+  //Compile and queue everything first, so that the workers stay busy
+  let compile_options = CompileOptions { assume_zeroed_tape: true, ..Default::default() };
+  let pending: Vec<_> = paths.into_iter().map(|path| {
+    let load = || -> Result<_, String> {
+      let code = fs::read_to_string(&path).map_err(|x| x.to_string())?;
+      let input = companion(&path, "in").map_err(|x| x.to_string())?.unwrap_or_default();
+      let expected = companion(&path, "out").map_err(|x| x.to_string())?;
+      let program = Arc::new(Program::compile_with_options(&code, &compile_options).map_err(|x| x.to_string())?);
+      Ok((pool.submit(Job { program, input, sandbox: options.sandbox.clone() }), expected))
+    };
+    (load(), path)
+  }).collect();
+
+  let mut summary = CorpusSummary::default();
+  for (loaded, path) in pending {
+    let result = match loaded {
+      Ok((handle, expected)) => {
+        let result = handle.wait();
+        let status = match result.outcome {
+          Outcome::OutOfFuel => Status::TimedOut,
+          Outcome::OutputLimitExceeded => Status::Failed,
+          Outcome::Halted if expected.is_some_and(|x| x != result.output) => Status::Failed,
+          Outcome::Halted => Status::Passed,
+        };
+        ProgramResult { path, status, output_length: result.output.len(), duration: result.duration }
+      },
+      Err(message) => ProgramResult { path, status: Status::Error(message), output_length: 0, duration: Duration::ZERO },
+    };
+    match result.status {
+      Status::Passed => summary.passed += 1,
+      Status::Failed => summary.failed += 1,
+      Status::TimedOut => summary.timed_out += 1,
+      Status::Error(_) => summary.errors += 1,
+    }
+    summary.results.push(result);
+  }
+  summary.duration = start.elapsed();
+  Ok(summary)
+}
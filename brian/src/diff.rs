@@ -0,0 +1,110 @@
+//! Comparison of two programs
+//!
+//! Compares programs at the command level, at the optimized opcode level and by a bounded differential run,
+//! for verifying refactors of hand-written programs and reviewing changes to generated code
+
+use std::fmt;
+use crate::{brainfuck::brainfuck_tokens_indexed, Brainfuck, Opcode, Program};
+
+/// Where the first differing command of two programs is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandDiff {
+  /// Index of the command, comments excluded
+  pub index: usize,
+  /// Byte offset of the command in the first and second source, or `None` past its last command
+  pub offsets: (Option<usize>, Option<usize>),
+}
+
+/// Observable behavior of two programs on the same input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Behavior {
+  /// Both programs halted with the same output
+  Equal { output_length: usize },
+  /// Outputs agree so far, but a program ran out of fuel before finishing
+  Inconclusive { output_length: usize },
+  /// Outputs differ at the given byte, holding what each program wrote there (if anything)
+  Diverged { output_index: usize, bytes: (Option<u8>, Option<u8>) },
+}
+
+/// Differences between two programs, see [`diff`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramDiff {
+  /// First differing command, `None` if the commands are identical
+  pub commands: Option<CommandDiff>,
+  /// Index of the first differing opcode of the optimized programs, with the opcodes there (if any)
+  ///
+  /// `None` if the optimized programs are identical, in which case they behave identically
+  pub opcodes: Option<(usize, Option<Opcode>, Option<Opcode>)>,
+  pub behavior: Behavior,
+}
+impl fmt::Display for ProgramDiff {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.commands {
+      Some(CommandDiff { index, .. }) => writeln!(f, "commands: first difference at command {index}")?,
+      None => writeln!(f, "commands: identical")?,
+    }
+    match &self.opcodes {
+      Some((index, a, b)) => {
+        let op = |x: &Option<Opcode>| x.as_ref().map_or("end of program".into(), |x| format!("{x:?}"));
+        writeln!(f, "opcodes: first difference at opcode {index}: {} vs {}", op(a), op(b))?
+      },
+      None => writeln!(f, "opcodes: identical")?,
+    }
+    let byte = |x: Option<u8>| x.map_or("nothing".into(), |x| format!("{x:#04x}"));
+    match self.behavior {
+      Behavior::Equal { output_length } => write!(f, "behavior: same output ({output_length} bytes)"),
+      Behavior::Inconclusive { output_length } => {
+        write!(f, "behavior: same output so far ({output_length} bytes), but a run ran out of fuel")
+      },
+      Behavior::Diverged { output_index, bytes: (a, b) } => {
+        write!(f, "behavior: output byte {output_index} differs: {} vs {}", byte(a), byte(b))
+      },
+    }
+  }
+}
+
+fn first_difference<T: PartialEq>(a: &[T], b: &[T]) -> Option<usize> {
+  match a.iter().zip(b).position(|(a, b)| a != b) {
+    Some(index) => Some(index),
+    None if a.len() != b.len() => Some(a.len().min(b.len())),
+    None => None,
+  }
+}
+
+/// Compare two programs, running both on `input` for at most `fuel` instructions each
+///
+/// Programs with unbalanced brackets are not compared
+pub fn diff(a: &str, b: &str, input: &[u8], fuel: usize) -> Option<ProgramDiff> {
+  let (Ok(program_a), Ok(program_b)) = (Program::compile(a), Program::compile(b)) else {
+    return None
+  };
+  let (tokens_a, tokens_b): (Vec<_>, Vec<_>) = (brainfuck_tokens_indexed(a).collect(), brainfuck_tokens_indexed(b).collect());
+  let commands_a: Vec<_> = tokens_a.iter().map(|(_, token)| token).collect();
+  let commands_b: Vec<_> = tokens_b.iter().map(|(_, token)| token).collect();
+  let commands = first_difference(&commands_a, &commands_b).map(|index| CommandDiff {
+    index,
+    offsets: (tokens_a.get(index).map(|x| x.0), tokens_b.get(index).map(|x| x.0)),
+  });
+
+  let opcodes = first_difference(program_a.opcodes(), program_b.opcodes())
+    .map(|index| (index, program_a.opcodes().get(index).cloned(), program_b.opcodes().get(index).cloned()));
+
+  let run = |program: Program| {
+    let mut brainfuck = Brainfuck::new();
+    brainfuck.load_program(program);
+    let mut output = Vec::new();
+    //The default bounds policy wraps, so the run never traps
+    let halted = brainfuck.run_with_io(input, &mut output, fuel) == Ok(true);
+    (halted, output)
+  };
+  let ((halted_a, output_a), (halted_b, output_b)) = (run(program_a), run(program_b));
+  let shorter = output_a.len().min(output_b.len());
+  let behavior = match first_difference(&output_a, &output_b) {
+    None if halted_a && halted_b => Behavior::Equal { output_length: shorter },
+    //A run that ran out of fuel may still have printed the rest
+    Some(index) if index == shorter && !(halted_a && halted_b) => Behavior::Inconclusive { output_length: shorter },
+    None => Behavior::Inconclusive { output_length: shorter },
+    Some(index) => Behavior::Diverged { output_index: index, bytes: (output_a.get(index).copied(), output_b.get(index).copied()) },
+  };
+  Some(ProgramDiff { commands, opcodes, behavior })
+}
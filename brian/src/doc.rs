@@ -0,0 +1,161 @@
+//! Program documentation reports
+//!
+//! Combines metrics, loop structure, lint results and a formatted listing of a program into a single markdown
+//! document, for sharing analyses of complex programs
+
+use std::fmt::Write as _;
+use crate::{lint::{self, LintOptions}, LoopTree, Program, ProgramMetrics};
+
+/// Maximum number of loops listed in the loop structure section
+const MAX_LOOPS: usize = 200;
+/// Maximum length of loop snippets, in commands
+const SNIPPET_LENGTH: usize = 32;
+/// Width the formatted listing wraps at, not counting indentation
+const LISTING_WIDTH: usize = 72;
+
+/// Convert a byte offset into 1-based line and column numbers
+fn line_column(code: &str, offset: usize) -> (usize, usize) {
+  let before = &code[..offset];
+  let line = before.matches('\n').count() + 1;
+  let column = before.chars().rev().take_while(|&x| x != '\n').count() + 1;
+  (line, column)
+}
+
+/// Commands of `code`, with comments stripped
+fn commands(code: &str) -> String {
+  code.chars().filter(|x| "+-<>[].,".contains(*x)).collect()
+}
+
+/// Format the commands of a program, one loop level per indentation step
+///
+/// Loops without nested loops are kept on a single line, long runs of commands are wrapped
+pub fn format(code: &str) -> String {
+  let code = commands(code);
+  let code = code.as_bytes();
+  let mut listing = String::new();
+  let mut line = String::new();
+  let mut depth = 0;
+  let push_line = |listing: &mut String, line: &mut String, depth: usize| {
+    if !line.is_empty() {
+      let _ = writeln!(listing, "{:indent$}{line}", "", indent = depth * 2);
+      line.clear();
+    }
+  };
+  let mut index = 0;
+  while index < code.len() {
+    if line.len() >= LISTING_WIDTH {
+      push_line(&mut listing, &mut line, depth);
+    }
+    match code[index] {
+      b'[' => {
+        let end = code[index + 1..].iter().position(|&x| x == b'[' || x == b']').map(|x| x + index + 1);
+        match end {
+          //Innermost loop
+          Some(end) if code[end] == b']' => {
+            line.push_str(std::str::from_utf8(&code[index..=end]).unwrap());
+            index = end;
+          },
+          _ => {
+            push_line(&mut listing, &mut line, depth);
+            line.push('[');
+            push_line(&mut listing, &mut line, depth);
+            depth += 1;
+          },
+        }
+      },
+      b']' => {
+        push_line(&mut listing, &mut line, depth);
+        depth = depth.saturating_sub(1);
+        line.push(']');
+        push_line(&mut listing, &mut line, depth);
+      },
+      command => line.push(command as char),
+    }
+    index += 1;
+  }
+  push_line(&mut listing, &mut line, depth);
+  listing
+}
+
+fn write_metrics(document: &mut String, unoptimized: &ProgramMetrics, optimized: &ProgramMetrics) {
+  document.push_str("## Metrics\n\n|  | unoptimized | optimized |\n|---|---:|---:|\n");
+  let mut row = |name: &str, field: fn(&ProgramMetrics) -> usize| {
+    let _ = writeln!(document, "| {name} | {} | {} |", field(unoptimized), field(optimized));
+  };
+  row("length", |x| x.length);
+  row("loops", |x| x.loop_count);
+  row("max nesting depth", |x| x.max_nesting_depth);
+  row("memory footprint", |x| x.memory_footprint);
+  let _ = writeln!(document, "| tape footprint | `{}` | `{}` |", unoptimized.tape_footprint, optimized.tape_footprint);
+  let mut kinds: Vec<_> = unoptimized.opcode_frequencies.keys().chain(optimized.opcode_frequencies.keys()).collect();
+  kinds.sort();
+  kinds.dedup();
+  for kind in kinds {
+    let count = |x: &ProgramMetrics| x.opcode_frequencies.get(kind).copied().unwrap_or(0);
+    let _ = writeln!(document, "| `{kind}` | {} | {} |", count(unoptimized), count(optimized));
+  }
+  document.push('\n');
+}
+
+fn write_loops(document: &mut String, code: &str, tree: &LoopTree) {
+  document.push_str("## Loop structure\n\n");
+  if tree.nodes.is_empty() {
+    document.push_str("No loops.\n\n");
+    return
+  }
+  //Depth first, in program order
+  let mut stack: Vec<usize> = tree.roots.iter().rev().copied().collect();
+  let mut listed = 0;
+  while let Some(node) = stack.pop() {
+    if listed == MAX_LOOPS {
+      let _ = writeln!(document, "- ... {} more loops", tree.nodes.len() - listed);
+      break
+    }
+    let loop_node = &tree.nodes[node];
+    let span = loop_node.span.clone().unwrap();
+    let (line, column) = line_column(code, span.start);
+    let snippet = commands(&code[span]);
+    let snippet = match snippet.len() > SNIPPET_LENGTH {
+      true => format!("{}...", &snippet[..SNIPPET_LENGTH]),
+      false => snippet,
+    };
+    let opcodes = match loop_node.body.len() {
+      1 => "1 opcode".to_string(),
+      length => format!("{length} opcodes"),
+    };
+    let _ = writeln!(document, "{:indent$}- `{snippet}` at {line}:{column}, {opcodes}", "", indent = tree.depth(node) * 2);
+    stack.extend(loop_node.children.iter().rev());
+    listed += 1;
+  }
+  document.push('\n');
+}
+
+fn write_lints(document: &mut String, code: &str) {
+  document.push_str("## Lint findings\n\n");
+  let diagnostics = lint::check(code, &LintOptions::default());
+  if diagnostics.is_empty() {
+    document.push_str("None.\n\n");
+    return
+  }
+  for diagnostic in &diagnostics {
+    let (line, column) = line_column(code, diagnostic.span.start);
+    let _ = writeln!(document, "- {line}:{column}: {diagnostic}");
+  }
+  document.push('\n');
+}
+
+/// Render a markdown report describing the structure of a program
+pub fn document(name: &str, code: &str) -> String {
+  let mut document = format!("# {name}\n\n");
+  match (LoopTree::from_source(code), Program::compile_without_optimizations(code), Program::compile(code)) {
+    (Some(tree), Ok(unoptimized), Ok(optimized)) => {
+      let (unoptimized, optimized) = (unoptimized.metrics(), optimized.metrics());
+      write_metrics(&mut document, &unoptimized, &optimized);
+      write_loops(&mut document, code, &tree);
+      write_lints(&mut document, code);
+    },
+    _ => document.push_str("Brackets are unbalanced, so the program can't be analyzed.\n\n"),
+  }
+  let _ = write!(document, "## Listing\n\n```brainfuck\n{}```\n", format(code));
+  document
+}
@@ -0,0 +1,117 @@
+//! Random brainfuck program generation
+//!
+//! Produces program corpora with known properties for fuzzing, benchmarking and teaching
+
+use crate::rng::Rng;
+
+/// Configurable random program generator
+///
+/// Programs are fully determined by the generator configuration and the seed passed to [`ProgramGenerator::generate`]
+#[derive(Clone, Debug)]
+pub struct ProgramGenerator {
+  /// Number of commands to generate before closing loops that are still open
+  pub length: usize,
+  /// Probability of opening a loop at each command (and of closing one, if any are open)
+  pub loop_probability: f64,
+  /// Maximum loop nesting depth
+  pub max_depth: usize,
+  /// Probability of each command being `.` or `,`
+  pub io_density: f64,
+  /// Generate programs with matching brackets
+  ///
+  /// If disabled, stray `]` may appear and loops are not closed at the end of the program
+  pub balanced: bool,
+  /// Only generate programs that are guaranteed to terminate, regardless of input (implies `balanced`)
+  ///
+  /// Every loop decrements its condition cell exactly once per iteration and never touches it otherwise,
+  /// so execution takes at most roughly `length * 256^max_depth` steps
+  pub terminating: bool,
+}
+impl ProgramGenerator {
+  /// Create a generator producing balanced programs of the given length
+  pub fn new(length: usize) -> Self {
+    Self {
+      length,
+      ..Default::default()
+    }
+  }
+
+  /// Generate a program from the seed
+  pub fn generate(&self, seed: u64) -> String {
+    let mut rng = Rng::new(seed);
+    let mut code = String::with_capacity(self.length);
+    //Pointer offset relative to the start of the program
+    let mut offset: isize = 0;
+    //Offsets of the condition cells of all open loops
+    let mut loops: Vec<isize> = Vec::new();
+
+    for _ in 0..self.length {
+      //Cells that must not be modified, as they control a loop in terminating mode
+      let protected = self.terminating && loops.contains(&offset);
+      if rng.chance(self.io_density) {
+        if protected || rng.chance(0.5) {
+          code.push('.');
+        } else {
+          code.push(',');
+        }
+      } else if rng.chance(self.loop_probability) && !protected && loops.len() < self.max_depth {
+        code.push('[');
+        loops.push(offset);
+      } else if !loops.is_empty() && rng.chance(self.loop_probability) {
+        Self::close_loop(&mut code, &mut offset, &mut loops, self.terminating);
+      } else if !(self.balanced || self.terminating) && rng.chance(self.loop_probability / 2.) {
+        //Stray loop end
+        code.push(']');
+      } else {
+        match rng.below(4) {
+          0 | 1 if protected => {
+            code.push('>');
+            offset += 1;
+          },
+          0 => code.push('+'),
+          1 => code.push('-'),
+          2 => {
+            code.push('<');
+            offset -= 1;
+          },
+          _ => {
+            code.push('>');
+            offset += 1;
+          },
+        }
+      }
+    }
+
+    if self.balanced || self.terminating {
+      while !loops.is_empty() {
+        Self::close_loop(&mut code, &mut offset, &mut loops, self.terminating);
+      }
+    }
+    code
+  }
+
+  fn close_loop(code: &mut String, offset: &mut isize, loops: &mut Vec<isize>, terminating: bool) {
+    let cell = loops.pop().unwrap();
+    if terminating {
+      //Return to the condition cell and count it down
+      let distance = cell - *offset;
+      let direction = if distance < 0 { "<" } else { ">" };
+      code.push_str(&direction.repeat(distance.unsigned_abs()));
+      *offset = cell;
+      code.push('-');
+    }
+    code.push(']');
+  }
+}
+impl Default for ProgramGenerator {
+  fn default() -> Self {
+    Self {
+      length: 64,
+      loop_probability: 0.1,
+      max_depth: 4,
+      io_density: 0.1,
+      balanced: true,
+      terminating: false,
+    }
+  }
+}
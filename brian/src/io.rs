@@ -0,0 +1,130 @@
+//! Ready-made inputs and outputs for [`StreamIo`](crate::StreamIo) and the `run_with_*` methods of
+//! [`Brainfuck`](crate::Brainfuck)
+//!
+//! Covers what benchmarks, tests and servers keep needing: no input, endless zeros, discarded output,
+//! counted output and captured output with an optional size limit
+
+use std::io::{self, Read, Write};
+
+/// Input that is always at EOF
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullInput;
+impl Read for NullInput {
+  #[inline]
+  fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+    Ok(0)
+  }
+}
+
+/// Input yielding zeros forever, for programs that stop on a zero byte instead of EOF
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ZeroInput;
+impl Read for ZeroInput {
+  #[inline]
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    buf.fill(0);
+    Ok(buf.len())
+  }
+}
+
+/// Output discarding everything written
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SinkOutput;
+impl Write for SinkOutput {
+  #[inline]
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    Ok(buf.len())
+  }
+
+  #[inline]
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Output discarding everything written, but counting the bytes and flushes
+///
+/// Pass it by reference (`&mut counter`) to read the counts after the run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CountingOutput {
+  bytes: u64,
+  flushes: u64,
+}
+impl CountingOutput {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Number of bytes written
+  pub fn bytes(&self) -> u64 {
+    self.bytes
+  }
+
+  /// Number of flushes requested
+  pub fn flushes(&self) -> u64 {
+    self.flushes
+  }
+}
+impl Write for CountingOutput {
+  #[inline]
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.bytes += buf.len() as u64;
+    Ok(buf.len())
+  }
+
+  #[inline]
+  fn flush(&mut self) -> io::Result<()> {
+    self.flushes += 1;
+    Ok(())
+  }
+}
+
+/// Output collecting everything written, up to an optional limit
+///
+/// Bytes past the limit are dropped rather than failing the write, so runaway programs
+/// can't exhaust memory and still run to completion
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VecOutput {
+  bytes: Vec<u8>,
+  limit: Option<usize>,
+  truncated: bool,
+}
+impl VecOutput {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Keep at most `limit` bytes
+  pub fn with_limit(limit: usize) -> Self {
+    Self { limit: Some(limit), ..Self::default() }
+  }
+
+  /// Bytes collected so far
+  pub fn bytes(&self) -> &[u8] {
+    &self.bytes
+  }
+
+  /// Whether bytes were dropped because of the limit
+  pub fn truncated(&self) -> bool {
+    self.truncated
+  }
+
+  pub fn into_bytes(self) -> Vec<u8> {
+    self.bytes
+  }
+}
+impl Write for VecOutput {
+  #[inline]
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let room = self.limit.map_or(buf.len(), |limit| limit.saturating_sub(self.bytes.len()));
+    let kept = buf.len().min(room);
+    self.bytes.extend_from_slice(&buf[..kept]);
+    self.truncated |= kept < buf.len();
+    Ok(buf.len())
+  }
+
+  #[inline]
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
@@ -0,0 +1,98 @@
+//! Ordered log of the I/O operations of a run
+//!
+//! Lets tests of interactive programs check the order in which they prompt, read and answer,
+//! not just the bytes they end up writing
+
+use std::{cell::{Cell, RefCell}, ops::ControlFlow};
+use crate::{run_with_hooks, BrainfuckState, Hooks, Io, Opcode, Program, StateView};
+
+/// Kind of an I/O operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoEventKind {
+  /// Byte was read, or `None` at EOF
+  Input(Option<u8>),
+  /// Byte was written
+  Output(u8),
+  /// Output was flushed
+  Flush,
+}
+
+/// Single I/O operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoEvent {
+  /// Number of instructions executed before the operation
+  pub step: u64,
+  /// Opcode that did the operation
+  pub program_counter: usize,
+  pub kind: IoEventKind,
+}
+
+/// Recorder of the I/O operations of a run, in the order they were done
+#[derive(Debug, Default)]
+pub struct IoLog {
+  events: RefCell<Vec<IoEvent>>,
+  program_counter: Cell<usize>,
+  steps: Cell<u64>,
+}
+impl IoLog {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Run `program` with I/O handled by `io`, appending its operations to the log
+  pub fn run(&mut self, program: &Program, state: &mut BrainfuckState, io: &mut impl Io) {
+    let mut io = LoggedIo { log: self, inner: io };
+    run_with_hooks(program, state, &mut io, &mut &*self);
+  }
+
+  /// Recorded operations, oldest first
+  pub fn events(&self) -> Vec<IoEvent> {
+    self.events.borrow().clone()
+  }
+
+  /// Recorded operations without their positions, for comparing against an expected interaction
+  pub fn kinds(&self) -> Vec<IoEventKind> {
+    self.events.borrow().iter().map(|event| event.kind).collect()
+  }
+
+  fn record(&self, kind: IoEventKind) {
+    let event = IoEvent { step: self.steps.get() - 1, program_counter: self.program_counter.get(), kind };
+    self.events.borrow_mut().push(event);
+  }
+}
+//Keeps track of the opcode doing the I/O, which `Io` doesn't see
+impl Hooks for &IoLog {
+  #[inline(always)]
+  fn before(&mut self, program_counter: usize, _op: &Opcode, _state: StateView) -> ControlFlow<()> {
+    self.program_counter.set(program_counter);
+    self.steps.set(self.steps.get() + 1);
+    ControlFlow::Continue(())
+  }
+}
+
+/// [`Io`] recording every operation passed on to `inner`
+struct LoggedIo<'a, I: Io> {
+  log: &'a IoLog,
+  inner: &'a mut I,
+}
+impl<I: Io> Io for LoggedIo<'_, I> {
+  fn input(&mut self) -> Option<u8> {
+    let byte = self.inner.input();
+    self.log.record(IoEventKind::Input(byte));
+    byte
+  }
+
+  fn output(&mut self, byte: u8) {
+    self.inner.output(byte);
+    self.log.record(IoEventKind::Output(byte));
+  }
+
+  fn flush(&mut self) {
+    self.inner.flush();
+    self.log.record(IoEventKind::Flush);
+  }
+
+  fn failed(&mut self) -> bool {
+    self.inner.failed()
+  }
+}
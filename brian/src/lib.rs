@@ -1,5 +1,67 @@
 //! # brian
 //! Brainfuck interpreter crate optimized for performance
+//!
+//! ## Features
+//! Features only add items, so they can be combined freely. Only `std` is on by default
+//!
+//! | Feature    | Enables                                                                                        |
+//! |------------|------------------------------------------------------------------------------------------------|
+//! | `std`      | `brian::io` and the threaded `pool`, `corpus`, `pipeline` and `throttle` modules               |
+//! | `symbolic` | Experimental symbolic execution engine, in `brian::symbolic`                                   |
+//! | `programs` | Canonical example programs embedded as constants, in `brian::programs`                         |
+//! | `async`    | Running programs on asynchronous I/O, see `Brainfuck::run_async`, and on threads with `std`    |
+//! | `full`     | All of the above                                                                               |
+//!
+//! The interpreter, compiler and analysis tools are always available. The crate always links the standard library:
+//! the interpreter talks to the world through [`std::io`], and compilation budgets rely on [`std::time`].
+//! There are no `no_std`/`alloc`, `wasm`, `jit`, `serde` or `cli-support` tiers, those are out of scope for now
+//! (the command line tool lives in `brian-cli`)
 
 mod brainfuck;
-pub use brainfuck::{Brainfuck, BrainfuckState};
+mod rng;
+pub mod breakpoint;
+#[cfg(feature = "std")]
+pub mod corpus;
+pub mod diff;
+pub mod doc;
+pub mod generator;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod io_log;
+pub mod lint;
+pub mod loop_limit;
+#[cfg(feature = "std")]
+pub mod pipeline;
+pub mod pointer_history;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "programs")]
+pub mod programs;
+pub mod recording;
+pub mod run_stats;
+pub mod sanitizer;
+pub mod session;
+pub mod stress;
+#[cfg(feature = "symbolic")]
+pub mod symbolic;
+pub mod teaching;
+#[cfg(feature = "std")]
+pub mod throttle;
+pub mod timeline;
+pub mod translate;
+pub mod verification;
+pub use brainfuck::{
+  brainfuck_tokens, run, run_static, run_with_fuel, run_with_hooks, BracketPolicy, Brainfuck, BrainfuckState,
+  BytecodeError, CaptureIo, CommentStyle, CompileError, CompileOptions, CompilePipeline, CompileReport, CompileStats,
+  CostModel, CrashContext, FlushPolicy, Hooks, Io, IterInput, LoopNode, LoopTree, Miscompilation, MiscompilationKind,
+  NewlineIo, Newlines, NoHooks, PackedPosition, PackedProgram, ParseBracketPolicyError, ParseCommentStyleError,
+  PatchError, PreviewEnd, PreviewResult, Program, ProgramMetrics, Profile, RandomInput, RunFuture, RunOutcome,
+  RuntimeError, SliceEnd, SnapshotError, SourceMap, Span, StateView, StreamIo, SuperinstructionCandidate, TapeFootprint,
+  TapeInit, TeeIo, Token, VerifyError, Opcode,
+  BoundsPolicy, Compatibility, Compilation, Config, DefaultConfig, EofMode, InterpreterConfig, OverflowPolicy,
+  ParseBoundsPolicyError, ParseCompatibilityError, ParseEofModeError, RunConfig, Trap, TrapKind,
+};
+#[cfg(feature = "async")]
+pub use brainfuck::{AsyncRead, AsyncRun, AsyncWrite};
+#[cfg(all(feature = "async", feature = "std"))]
+pub use brainfuck::BlockingRun;
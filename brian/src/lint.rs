@@ -0,0 +1,292 @@
+//! Static analysis of brainfuck source code
+//!
+//! Reports suspicious constructs with byte spans pointing into the original source
+
+use std::{collections::{HashMap, HashSet}, fmt, ops::Range, str::FromStr};
+use crate::brainfuck::{brainfuck_tokens_indexed, Token};
+
+/// Kind of issue detected by the linter
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+  /// Loop that can never terminate once entered, as its body never modifies the condition cell
+  InfiniteLoop,
+  /// Cell written at the end of the program that is never read or output afterwards
+  ///
+  /// Such writes can be removed by the optimizer with [`CompileOptions::drop_dead_stores`](crate::CompileOptions::drop_dead_stores)
+  UnreadWrite,
+}
+impl Lint {
+  /// Every lint, in the order they are documented
+  pub const ALL: [Lint; 2] = [Self::InfiniteLoop, Self::UnreadWrite];
+
+  /// Name of the lint, as shown in diagnostics
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::InfiniteLoop => "infinite-loop",
+      Self::UnreadWrite => "unread-write",
+    }
+  }
+}
+
+/// Error returned when parsing an unknown [`Lint`] name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseLintError(String);
+impl fmt::Display for ParseLintError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let names: Vec<&str> = Lint::ALL.iter().map(Lint::name).collect();
+    write!(f, "unknown lint `{}` (expected {})", self.0, names.join(", "))
+  }
+}
+impl std::error::Error for ParseLintError {}
+
+impl FromStr for Lint {
+  type Err = ParseLintError;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    Self::ALL.into_iter().find(|x| x.name() == value).ok_or_else(|| ParseLintError(value.into()))
+  }
+}
+
+/// How a lint is reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+  /// Not reported at all
+  Allow,
+  /// Reported as a warning
+  Warn,
+  /// Reported as an error, e.g. to reject submitted programs
+  Deny,
+}
+
+/// Severity of a diagnostic
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+  Warning,
+  Error,
+}
+
+/// Issue found in the source code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+  pub lint: Lint,
+  pub severity: Severity,
+  /// Byte range of the offending code in the source
+  pub span: Range<usize>,
+  pub message: String,
+}
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let severity = match self.severity {
+      Severity::Warning => "warning",
+      Severity::Error => "error",
+    };
+    write!(f, "{severity}[{}]: {}", self.lint.name(), self.message)
+  }
+}
+
+/// Linter configuration
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LintOptions {
+  /// Report all issues as errors instead of warnings, unless their lint has a level in `levels`
+  pub strict: bool,
+  /// Level of individual lints, overriding the default (warn, or deny if `strict`)
+  pub levels: HashMap<Lint, LintLevel>,
+}
+impl LintOptions {
+  /// Level `lint` is reported at
+  pub fn level(&self, lint: Lint) -> LintLevel {
+    match self.levels.get(&lint) {
+      Some(&level) => level,
+      None if self.strict => LintLevel::Deny,
+      None => LintLevel::Warn,
+    }
+  }
+}
+
+/// Net effect of a loop body, relative to the condition cell
+struct LoopSummary {
+  /// Pointer movement per iteration
+  movement: isize,
+  /// Offsets of all cells the body may modify
+  writes: HashSet<isize>,
+  /// Whether the body performs any I/O
+  io: bool,
+}
+
+/// Abstract tape tracking cells with statically known values
+#[derive(Clone, Default)]
+struct Tape {
+  cells: HashMap<isize, Option<u8>>,
+  pointer: isize,
+  /// Cells that were never touched are known to be zero (only true at the start of the program)
+  pristine: bool,
+}
+impl Tape {
+  fn get(&self, offset: isize) -> Option<u8> {
+    match self.cells.get(&offset) {
+      Some(value) => *value,
+      None => self.pristine.then_some(0),
+    }
+  }
+
+  fn set(&mut self, offset: isize, value: Option<u8>) {
+    self.cells.insert(offset, value);
+  }
+}
+
+struct Linter<'a> {
+  tokens: &'a [(usize, Token)],
+  /// Index of the matching bracket for each bracket token
+  matching: Vec<usize>,
+  options: &'a LintOptions,
+  diagnostics: Vec<Diagnostic>,
+}
+impl Linter<'_> {
+  fn report(&mut self, lint: Lint, span: Range<usize>, message: String) {
+    let severity = match self.options.level(lint) {
+      LintLevel::Allow => return,
+      LintLevel::Warn => Severity::Warning,
+      LintLevel::Deny => Severity::Error,
+    };
+    self.diagnostics.push(Diagnostic { lint, severity, span, message });
+  }
+
+  /// Span of the loop starting at token `start`, including both brackets
+  fn loop_span(&self, start: usize) -> Range<usize> {
+    self.tokens[start].0..(self.tokens[self.matching[start]].0 + 1)
+  }
+
+  /// Summarize the body of the loop starting at token `start`
+  ///
+  /// Returns `None` if the pointer movement of the body is not static
+  fn summarize(&self, start: usize) -> Option<LoopSummary> {
+    let mut summary = LoopSummary { movement: 0, writes: HashSet::new(), io: false };
+    let mut index = start + 1;
+    while index < self.matching[start] {
+      match self.tokens[index].1 {
+        Token::Increment | Token::Decrement => {
+          summary.writes.insert(summary.movement);
+        },
+        Token::MovePointerLeft => summary.movement -= 1,
+        Token::MovePointerRight => summary.movement += 1,
+        Token::Output => summary.io = true,
+        Token::Input => {
+          summary.io = true;
+          summary.writes.insert(summary.movement);
+        },
+        Token::LoopStart => {
+          let inner = self.summarize(index)?;
+          if inner.movement != 0 { return None }
+          summary.io |= inner.io;
+          summary.writes.extend(inner.writes.iter().map(|x| x + summary.movement));
+          index = self.matching[index];
+        },
+        Token::LoopEnd => unreachable!(),
+      }
+      index += 1;
+    }
+    Some(summary)
+  }
+
+  /// Report writes in the straight-line code after the last loop that are never output
+  fn unread_writes(&mut self) {
+    let tail = self.tokens.iter().rposition(|x| x.1 == Token::LoopEnd).map_or(0, |x| x + 1);
+    //Pointer offset relative to the start of the tail, and token indices of unread writes to each cell
+    let mut pointer: isize = 0;
+    let mut writes: HashMap<isize, Vec<usize>> = HashMap::new();
+    for index in tail..self.tokens.len() {
+      match self.tokens[index].1 {
+        Token::Increment | Token::Decrement => writes.entry(pointer).or_default().push(index),
+        Token::MovePointerLeft => pointer -= 1,
+        Token::MovePointerRight => pointer += 1,
+        Token::Output => {
+          writes.remove(&pointer);
+        },
+        Token::Input => (),
+        Token::LoopStart | Token::LoopEnd => unreachable!(),
+      }
+    }
+    for indices in writes.into_values() {
+      let span = self.tokens[indices[0]].0..(self.tokens[*indices.last().unwrap()].0 + 1);
+      self.report(Lint::UnreadWrite, span, "cell is written but never read afterwards".into());
+    }
+  }
+
+  fn walk(&mut self, range: Range<usize>, tape: &mut Tape) {
+    let mut index = range.start;
+    while index < range.end {
+      match self.tokens[index].1 {
+        Token::Increment => {
+          tape.set(tape.pointer, tape.get(tape.pointer).map(|x| x.wrapping_add(1)));
+        },
+        Token::Decrement => {
+          tape.set(tape.pointer, tape.get(tape.pointer).map(|x| x.wrapping_sub(1)));
+        },
+        Token::MovePointerLeft => tape.pointer -= 1,
+        Token::MovePointerRight => tape.pointer += 1,
+        Token::Input => tape.set(tape.pointer, None),
+        Token::Output => (),
+        Token::LoopStart => {
+          let end = self.matching[index];
+          let condition = tape.get(tape.pointer);
+          //Loops that are never entered (such as comment loops) are not analyzed
+          if condition == Some(0) {
+            index = end + 1;
+            continue
+          }
+          let summary = self.summarize(index);
+          if let Some(summary) = &summary {
+            if summary.movement == 0 && !summary.writes.contains(&0) && !summary.io {
+              let message = match condition {
+                Some(_) => "infinite loop: condition cell is nonzero and never modified by the loop body",
+                None => "loop never terminates once entered: condition cell is never modified by the loop body",
+              };
+              self.report(Lint::InfiniteLoop, self.loop_span(index), message.into());
+            }
+          }
+          self.walk((index + 1)..end, &mut Tape::default());
+          match summary {
+            Some(summary) if summary.movement == 0 => {
+              for offset in summary.writes {
+                tape.set(tape.pointer + offset, None);
+              }
+            },
+            _ => *tape = Tape::default(),
+          }
+          tape.set(tape.pointer, Some(0));
+          index = end;
+        },
+        Token::LoopEnd => unreachable!(),
+      }
+      index += 1;
+    }
+  }
+}
+
+/// Analyze source code and report suspicious constructs
+///
+/// Programs with unbalanced brackets are not analyzed
+pub fn check(code: &str, options: &LintOptions) -> Vec<Diagnostic> {
+  let tokens: Vec<(usize, Token)> = brainfuck_tokens_indexed(code).collect();
+  let mut matching = vec![0; tokens.len()];
+  let mut stack = Vec::new();
+  for (index, (_, token)) in tokens.iter().enumerate() {
+    match token {
+      Token::LoopStart => stack.push(index),
+      Token::LoopEnd => {
+        let Some(start) = stack.pop() else { return Vec::new() };
+        matching[start] = index;
+        matching[index] = start;
+      },
+      _ => (),
+    }
+  }
+  if !stack.is_empty() {
+    return Vec::new()
+  }
+  let mut linter = Linter { tokens: &tokens, matching, options, diagnostics: Vec::new() };
+  linter.walk(0..tokens.len(), &mut Tape { pristine: true, ..Default::default() });
+  linter.unread_writes();
+  linter.diagnostics.sort_by_key(|x| x.span.start);
+  linter.diagnostics
+}
@@ -0,0 +1,56 @@
+//! Per-loop iteration limits
+//!
+//! Stops programs stuck in a single runaway loop, identifying the loop,
+//! which is a more helpful diagnostic than running out of a global step limit
+
+use std::ops::ControlFlow;
+use crate::{Hooks, Opcode, StateView};
+
+/// [`Hooks`] stopping the program once a loop iterates more than a given number of times without being left
+///
+/// Counts restart every time a loop is entered, so inner loops of long running outer loops aren't stopped.
+/// Run an unoptimized program ([`Program::compile_without_optimizations`](crate::Program::compile_without_optimizations))
+/// to count source loops, and map the offending loop back to the source with [`LoopTree::from_source`](crate::LoopTree::from_source)
+#[derive(Clone, Debug)]
+pub struct LoopLimit {
+  max_iterations: u64,
+  /// Iterations since entering the loop, indexed by the opcode index of its start
+  iterations: Vec<u64>,
+  exceeded: Option<usize>,
+}
+impl LoopLimit {
+  pub fn new(max_iterations: u64) -> Self {
+    Self { max_iterations, iterations: Vec::new(), exceeded: None }
+  }
+
+  /// Opcode index of the start of the loop that exceeded the limit, if the program was stopped
+  pub fn exceeded(&self) -> Option<usize> {
+    self.exceeded
+  }
+
+  fn iterations(&mut self, start: usize) -> &mut u64 {
+    if start >= self.iterations.len() {
+      self.iterations.resize(start + 1, 0);
+    }
+    &mut self.iterations[start]
+  }
+}
+impl Hooks for LoopLimit {
+  fn after(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    match *op {
+      //Loop starts are only executed when entering a loop, jumping back skips them
+      Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) => *self.iterations(program_counter) = 1,
+      Opcode::LoopEnd(start) | Opcode::MoveLoopEnd(_, start) if state.cell(0) != 0 => {
+        let max_iterations = self.max_iterations;
+        let iterations = self.iterations(start);
+        *iterations += 1;
+        if *iterations > max_iterations {
+          self.exceeded = Some(start);
+          return ControlFlow::Break(())
+        }
+      },
+      _ => (),
+    }
+    ControlFlow::Continue(())
+  }
+}
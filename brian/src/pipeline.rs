@@ -0,0 +1,186 @@
+//! Pipelines of programs, each reading the output of the previous one
+//!
+//! Every stage runs in its own thread with its own interpreter, connected to its neighbours by channels,
+//! so output streams through the pipeline as it is produced. All stages draw from a single fuel budget
+
+use std::{
+  io::{Read, Write},
+  mem,
+  sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc},
+  thread,
+};
+use crate::{brainfuck::interpreter, BrainfuckState, Io, PackedPosition, Program, SliceEnd};
+
+/// Instructions a stage executes before drawing more fuel from the shared budget
+const SLICE: usize = 1 << 16;
+/// Output buffered by a stage before it is passed on
+const CHUNK: usize = 1 << 12;
+
+/// How a pipeline ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PipelineOutcome {
+  /// Every stage reached its end
+  Halted,
+  /// The shared fuel budget ran out before every stage reached its end
+  OutOfFuel,
+}
+
+/// Reader yielding the chunks sent by the previous stage, then EOF once it finished
+struct ChannelReader {
+  receiver: mpsc::Receiver<Vec<u8>>,
+  chunk: Vec<u8>,
+  position: usize,
+}
+impl Read for ChannelReader {
+  fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+    while self.position == self.chunk.len() {
+      let Ok(chunk) = self.receiver.recv() else { return Ok(0) };
+      (self.chunk, self.position) = (chunk, 0);
+    }
+    let length = buffer.len().min(self.chunk.len() - self.position);
+    buffer[..length].copy_from_slice(&self.chunk[self.position..self.position + length]);
+    self.position += length;
+    Ok(length)
+  }
+}
+
+/// Writer sending every write to the next stage as a chunk
+struct ChannelWriter(mpsc::Sender<Vec<u8>>);
+impl Write for ChannelWriter {
+  fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+    //The next stage may have finished without reading everything, the rest is discarded
+    let _ = self.0.send(buffer.to_vec());
+    Ok(buffer.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+/// [`Io`] of a stage, which passes on its output before blocking on input,
+/// so interactive pipelines don't wait for a full chunk
+///
+/// Read errors are treated as EOF, write errors stop the stage
+struct StageIo<'a> {
+  input: Box<dyn Read + Send + 'a>,
+  output: Box<dyn Write + Send + 'a>,
+  buffer: Vec<u8>,
+  failed: bool,
+}
+impl StageIo<'_> {
+  fn pass_on(&mut self) {
+    if !self.buffer.is_empty() {
+      self.failed |= self.output.write_all(&self.buffer).is_err();
+      self.buffer.clear();
+    }
+    self.failed |= self.output.flush().is_err();
+  }
+}
+impl Io for StageIo<'_> {
+  fn input(&mut self) -> Option<u8> {
+    self.pass_on();
+    let mut byte = 0;
+    match self.input.read(std::slice::from_mut(&mut byte)) {
+      Ok(1) => Some(byte),
+      _ => None,
+    }
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    self.buffer.push(byte);
+    if self.buffer.len() >= CHUNK {
+      self.pass_on();
+    }
+  }
+
+  fn flush(&mut self) {
+    self.pass_on();
+  }
+
+  #[inline]
+  fn failed(&mut self) -> bool {
+    self.failed
+  }
+}
+
+/// Draw up to `SLICE` instructions from the shared budget, returning 0 once it is empty
+fn draw(fuel: &AtomicUsize) -> usize {
+  let previous = fuel.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| Some(x - x.min(SLICE))).unwrap();
+  previous.min(SLICE)
+}
+
+/// Run a stage on a fresh tape, returning `false` if it ran out of fuel
+fn run_stage(program: &Program, io: &mut StageIo, fuel: &AtomicUsize, exhausted: &AtomicBool) -> bool {
+  let mut state = Box::new(BrainfuckState::new());
+  let halted = match program.pack() {
+    Some(program) => {
+      let mut position = PackedPosition::default();
+      loop {
+        let slice = match exhausted.load(Ordering::Relaxed) {
+          true => 0,
+          false => draw(fuel),
+        };
+        if slice == 0 {
+          break false
+        }
+        if program.resume(&mut state, io, slice, &mut position) == SliceEnd::Halted {
+          break true
+        }
+      }
+    },
+    //Programs too large for the packed form can't be resumed, so they take the whole remaining budget at once
+    None => {
+      let slice = fuel.swap(0, Ordering::Relaxed);
+      interpreter::run_with_fuel(program, &mut state, io, slice)
+    },
+  };
+  if !halted {
+    exhausted.store(true, Ordering::Relaxed);
+  }
+  io.pass_on();
+  halted
+}
+
+/// Run `programs` as a pipeline, feeding `input` to the first one and writing the output of the last one to `output`
+///
+/// Stages execute at most `fuel` instructions in total. A stage finishing closes the input of the next one (EOF),
+/// and once the budget runs out every stage stops. The last stage stops once writing to `output` fails.
+/// An empty pipeline copies nothing
+pub fn run_pipeline(
+  programs: &[Program],
+  input: impl Read + Send,
+  output: impl Write + Send,
+  fuel: usize,
+) -> PipelineOutcome {
+  let Some(last) = programs.len().checked_sub(1) else {
+    return PipelineOutcome::Halted
+  };
+  //Every stage but the last writes into a channel read by the next one
+  let mut stages = Vec::with_capacity(programs.len());
+  let mut next_input: Box<dyn Read + Send + '_> = Box::new(input);
+  for program in &programs[..last] {
+    let (sender, receiver) = mpsc::channel();
+    let reader = Box::new(ChannelReader { receiver, chunk: Vec::new(), position: 0 });
+    let writer: Box<dyn Write + Send + '_> = Box::new(ChannelWriter(sender));
+    stages.push((program, mem::replace(&mut next_input, reader), writer));
+  }
+  stages.push((&programs[last], next_input, Box::new(output)));
+  let fuel = AtomicUsize::new(fuel);
+  let exhausted = AtomicBool::new(false);
+  let halted = thread::scope(|scope| {
+    let handles: Vec<_> = stages.into_iter().map(|(program, input, output)| {
+      let (fuel, exhausted) = (&fuel, &exhausted);
+      scope.spawn(move || {
+        let mut io = StageIo { input, output, buffer: Vec::new(), failed: false };
+        run_stage(program, &mut io, fuel, exhausted)
+      })
+    }).collect();
+    handles.into_iter().fold(true, |halted, handle| handle.join().unwrap() & halted)
+  });
+  match halted {
+    true => PipelineOutcome::Halted,
+    false => PipelineOutcome::OutOfFuel,
+  }
+}
@@ -0,0 +1,63 @@
+//! Bounded history of pointer positions
+//!
+//! Shows where a program has been wandering on the tape before it stopped or hung,
+//! e.g. a `ScanZero` running away because the cell it looks for is never cleared
+
+use std::{collections::VecDeque, ops::ControlFlow};
+use crate::{brainfuck::MEMORY_MASK, Hooks, Opcode, StateView};
+
+/// Pointer position reached by an executed opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PointerMove {
+  /// Opcode that moved the pointer
+  pub program_counter: usize,
+  /// Tape address the pointer ended up at
+  pub address: usize,
+}
+
+/// [`Hooks`] keeping the last pointer moves in a ring buffer
+///
+/// Scans are recorded once, at the cell they stopped at
+#[derive(Clone, Debug)]
+pub struct PointerHistory {
+  moves: VecDeque<PointerMove>,
+  capacity: usize,
+  total: u64,
+}
+impl PointerHistory {
+  /// Create a history keeping the last `capacity` moves
+  pub fn new(capacity: usize) -> Self {
+    Self { moves: VecDeque::with_capacity(capacity), capacity, total: 0 }
+  }
+
+  /// Recorded moves, oldest first
+  pub fn moves(&self) -> impl ExactSizeIterator<Item = &PointerMove> + DoubleEndedIterator {
+    self.moves.iter()
+  }
+
+  /// Number of moves made over the whole run, including the ones no longer kept
+  pub fn total(&self) -> u64 {
+    self.total
+  }
+
+  /// Smallest and largest address among the recorded moves
+  pub fn bounds(&self) -> Option<(usize, usize)> {
+    let addresses = self.moves.iter().map(|x| x.address);
+    Some((addresses.clone().min()?, addresses.max()?))
+  }
+}
+impl Hooks for PointerHistory {
+  fn after(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    if let Opcode::MovePointer(_) | Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) | Opcode::ScanZero(_) = op {
+      self.total += 1;
+      if self.capacity == 0 {
+        return ControlFlow::Continue(())
+      }
+      if self.moves.len() == self.capacity {
+        self.moves.pop_front();
+      }
+      self.moves.push_back(PointerMove { program_counter, address: state.pointer & MEMORY_MASK });
+    }
+    ControlFlow::Continue(())
+  }
+}
@@ -0,0 +1,137 @@
+//! Worker pool for serving many concurrent runs
+//!
+//! Jobs are queued into a bounded channel and picked up by a fixed number of worker threads,
+//! each of which owns a reusable interpreter state. Every job carries its own sandbox limits
+
+use std::{
+  sync::{mpsc, Arc, Mutex},
+  thread::{self, JoinHandle},
+  time::{Duration, Instant},
+};
+use crate::{brainfuck::interpreter, io::VecOutput, BrainfuckState, Program, StreamIo};
+
+/// Resource limits of a single job
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+  /// Maximum number of instructions to execute
+  pub fuel: usize,
+  /// Maximum number of output bytes kept, anything past it is discarded
+  pub max_output: usize,
+}
+impl Default for Sandbox {
+  fn default() -> Self {
+    Self {
+      fuel: 100_000_000,
+      max_output: 1 << 20,
+    }
+  }
+}
+
+/// Program run submitted to a [`Pool`]
+#[derive(Clone, Debug)]
+pub struct Job {
+  /// Program to run, on a fresh (zeroed) tape
+  pub program: Arc<Program>,
+  /// Bytes available to `,`, followed by EOF
+  pub input: Vec<u8>,
+  pub sandbox: Sandbox,
+}
+
+/// How a job ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+  /// Program reached its end
+  Halted,
+  /// Program ran out of fuel
+  OutOfFuel,
+  /// Program halted, but wrote more output than allowed
+  OutputLimitExceeded,
+}
+
+/// Result of a finished job
+#[derive(Clone, Debug)]
+pub struct JobResult {
+  pub outcome: Outcome,
+  /// Output written by the program (truncated to [`Sandbox::max_output`])
+  pub output: Vec<u8>,
+  /// Time spent running the program, excluding time spent in the queue
+  pub duration: Duration,
+}
+
+/// Handle to a submitted job
+#[derive(Debug)]
+pub struct JobHandle {
+  receiver: mpsc::Receiver<JobResult>,
+}
+impl JobHandle {
+  /// Block until the job is finished
+  pub fn wait(self) -> JobResult {
+    self.receiver.recv().expect("worker exited without finishing the job")
+  }
+
+  /// Get the result if the job is already finished
+  pub fn try_result(&self) -> Option<JobResult> {
+    self.receiver.try_recv().ok()
+  }
+}
+
+fn execute(job: &Job, state: &mut BrainfuckState) -> JobResult {
+  let start = Instant::now();
+  state.reset();
+  let mut io = StreamIo::new(&job.input[..], VecOutput::with_limit(job.sandbox.max_output));
+  let halted = interpreter::run_with_fuel(&job.program, state, &mut io, job.sandbox.fuel);
+  let outcome = match (halted, io.output.truncated()) {
+    (false, _) => Outcome::OutOfFuel,
+    (true, true) => Outcome::OutputLimitExceeded,
+    (true, false) => Outcome::Halted,
+  };
+  JobResult { outcome, output: io.output.into_bytes(), duration: start.elapsed() }
+}
+
+type Task = (Job, mpsc::Sender<JobResult>);
+
+/// Bounded pool of interpreter workers
+///
+/// Dropping the pool finishes all queued jobs and joins the workers
+pub struct Pool {
+  sender: Option<mpsc::SyncSender<Task>>,
+  workers: Vec<JoinHandle<()>>,
+}
+impl Pool {
+  /// Create a pool of `workers` threads, with room for `queue` jobs waiting to be picked up
+  pub fn new(workers: usize, queue: usize) -> Self {
+    assert!(workers > 0, "pool needs at least one worker");
+    let (sender, receiver) = mpsc::sync_channel::<Task>(queue);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let workers = (0..workers).map(|_| {
+      let receiver = Arc::clone(&receiver);
+      thread::spawn(move || {
+        let mut state = Box::new(BrainfuckState::new());
+        loop {
+          //The lock is released before the job runs, so other workers can pick up jobs meanwhile
+          let task = receiver.lock().unwrap().recv();
+          let Ok((job, result)) = task else { break };
+          //The submitter may have dropped its handle, which is fine
+          let _ = result.send(execute(&job, &mut state));
+        }
+      })
+    }).collect();
+    Self { sender: Some(sender), workers }
+  }
+
+  /// Queue a job, blocking while the queue is full
+  pub fn submit(&self, job: Job) -> JobHandle {
+    let (sender, receiver) = mpsc::channel();
+    self.sender.as_ref().unwrap().send((job, sender)).expect("all workers exited");
+    JobHandle { receiver }
+  }
+}
+impl Drop for Pool {
+  fn drop(&mut self) {
+    //Closing the channel makes workers exit once the queue is drained
+    self.sender.take();
+    for worker in self.workers.drain(..) {
+      let _ = worker.join();
+    }
+  }
+}
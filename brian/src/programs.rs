@@ -0,0 +1,51 @@
+//! Canonical brainfuck programs bundled with the crate (requires the `programs` feature)
+//!
+//! Useful for benchmarks, tests and demos that would otherwise have to vendor their own copies
+
+/// Program bundled with the crate, along with metadata describing how to run it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BundledProgram {
+  /// Short name, matching the file name in `programs/`
+  pub name: &'static str,
+  /// Source code, including the attribution comment at the top
+  pub source: &'static str,
+  /// Whether the program reads input
+  pub needs_input: bool,
+  /// Output of a run on a fresh tape, for programs that don't read input
+  pub expected_output: Option<&'static [u8]>,
+}
+
+/// Prints `Hello World!`
+pub const HELLO_WORLD: BundledProgram = BundledProgram {
+  name: "hello",
+  source: include_str!("../programs/hello.b"),
+  needs_input: false,
+  expected_output: Some(include_bytes!("../programs/hello.out")),
+};
+
+/// Applies ROT13 to its input until EOF
+pub const ROT13: BundledProgram = BundledProgram {
+  name: "rot13",
+  source: include_str!("../programs/rot13.b"),
+  needs_input: true,
+  expected_output: None,
+};
+
+/// Renders the mandelbrot set as ASCII art, by Erik Bosman
+pub const MANDELBROT: BundledProgram = BundledProgram {
+  name: "mandelbrot",
+  source: include_str!("../programs/mandelbrot.b"),
+  needs_input: false,
+  expected_output: Some(include_bytes!("../programs/mandelbrot.out")),
+};
+
+/// Brainfuck self-interpreter by Daniel B Cristofani, reading a program terminated by `!` followed by its input
+pub const DBFI: BundledProgram = BundledProgram {
+  name: "dbfi",
+  source: include_str!("../programs/dbfi.b"),
+  needs_input: true,
+  expected_output: None,
+};
+
+/// All bundled programs
+pub const ALL: &[BundledProgram] = &[HELLO_WORLD, ROT13, MANDELBROT, DBFI];
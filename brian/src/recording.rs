@@ -0,0 +1,169 @@
+//! Recording the I/O of a run, and replaying it
+//!
+//! A recording holds every byte a program read and wrote. Replaying it feeds the program the same input and checks
+//! that it writes the same output, which makes bug reports reproducible and interactive programs testable
+
+use std::fmt;
+use crate::Io;
+
+/// Magic bytes at the start of every serialized recording
+const MAGIC: &[u8; 4] = b"BFRC";
+
+/// Error returned when loading an invalid recording
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingError {
+  /// Data doesn't start with the recording magic bytes
+  BadMagic,
+  /// Data is shorter than the recording it describes
+  Truncated,
+}
+impl fmt::Display for RecordingError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::BadMagic => write!(f, "not a brian I/O recording"),
+      Self::Truncated => write!(f, "unexpected end of recording"),
+    }
+  }
+}
+impl std::error::Error for RecordingError {}
+
+/// Bytes read and written by a run, see [`RecordingIo`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+  pub input: Vec<u8>,
+  pub output: Vec<u8>,
+}
+impl Recording {
+  /// Serialize the recording, for attaching to bug reports or storing next to tests
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    for bytes in [&self.input, &self.output] {
+      data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+      data.extend_from_slice(bytes);
+    }
+    data
+  }
+
+  /// Load a recording serialized with [`Recording::to_bytes`]
+  pub fn from_bytes(data: &[u8]) -> Result<Self, RecordingError> {
+    let mut rest = data.strip_prefix(MAGIC).ok_or(RecordingError::BadMagic)?;
+    let mut take = || {
+      let (length, tail) = rest.split_first_chunk::<4>().ok_or(RecordingError::Truncated)?;
+      let (bytes, tail) = tail.split_at_checked(u32::from_le_bytes(*length) as usize).ok_or(RecordingError::Truncated)?;
+      rest = tail;
+      Ok(bytes.to_vec())
+    };
+    Ok(Self { input: take()?, output: take()? })
+  }
+
+  /// Start a replay of the recording
+  pub fn replay(&self) -> ReplayIo<'_> {
+    ReplayIo { recording: self, read: 0, output: Vec::new() }
+  }
+}
+
+/// [`Io`] passing every operation on to `inner`, recording the bytes read and written
+#[derive(Clone, Debug)]
+pub struct RecordingIo<I: Io> {
+  inner: I,
+  recording: Recording,
+}
+impl<I: Io> RecordingIo<I> {
+  pub fn new(inner: I) -> Self {
+    Self { inner, recording: Recording::default() }
+  }
+
+  /// Get the recording so far
+  pub fn recording(&self) -> &Recording {
+    &self.recording
+  }
+
+  /// Get the wrapped [`Io`] and the recording
+  pub fn finish(self) -> (I, Recording) {
+    (self.inner, self.recording)
+  }
+}
+impl<I: Io> Io for RecordingIo<I> {
+  #[inline]
+  fn input(&mut self) -> Option<u8> {
+    let byte = self.inner.input();
+    self.recording.input.extend(byte);
+    byte
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    self.inner.output(byte);
+    self.recording.output.push(byte);
+  }
+
+  #[inline]
+  fn flush(&mut self) {
+    self.inner.flush();
+  }
+
+  #[inline]
+  fn input_ready(&mut self) -> bool {
+    self.inner.input_ready()
+  }
+
+  #[inline]
+  fn failed(&mut self) -> bool {
+    self.inner.failed()
+  }
+}
+
+/// First difference between the output of a replay and the recorded output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+  /// Offset of the first differing output byte
+  pub offset: usize,
+  /// Recorded byte, or `None` if the replay wrote more
+  pub expected: Option<u8>,
+  /// Replayed byte, or `None` if the replay wrote less
+  pub actual: Option<u8>,
+}
+impl fmt::Display for Divergence {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let byte = |x: Option<u8>| x.map_or("end of output".to_string(), |x| format!("byte {x}"));
+    write!(f, "output diverged at offset {}: expected {}, got {}", self.offset, byte(self.expected), byte(self.actual))
+  }
+}
+impl std::error::Error for Divergence {}
+
+/// [`Io`] feeding a program the recorded input, then EOF, and collecting its output
+#[derive(Clone, Debug)]
+pub struct ReplayIo<'a> {
+  recording: &'a Recording,
+  read: usize,
+  output: Vec<u8>,
+}
+impl ReplayIo<'_> {
+  /// Output written so far
+  pub fn output(&self) -> &[u8] {
+    &self.output
+  }
+
+  /// Compare the output with the recorded one
+  pub fn finish(self) -> Result<Vec<u8>, Divergence> {
+    let expected = &self.recording.output;
+    let offset = expected.iter().zip(&self.output).take_while(|(a, b)| a == b).count();
+    match offset == expected.len() && offset == self.output.len() {
+      true => Ok(self.output),
+      false => Err(Divergence { offset, expected: expected.get(offset).copied(), actual: self.output.get(offset).copied() }),
+    }
+  }
+}
+impl Io for ReplayIo<'_> {
+  #[inline]
+  fn input(&mut self) -> Option<u8> {
+    let byte = self.recording.input.get(self.read).copied();
+    self.read += byte.is_some() as usize;
+    byte
+  }
+
+  #[inline]
+  fn output(&mut self, byte: u8) {
+    self.output.push(byte);
+  }
+}
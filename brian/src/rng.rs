@@ -0,0 +1,31 @@
+/// Small deterministic xorshift64* generator, good enough for test program generation
+#[derive(Clone, Debug)]
+pub(crate) struct Rng(u64);
+impl Rng {
+  pub fn new(seed: u64) -> Self {
+    //Scramble the seed with splitmix64 so that neighbouring seeds produce unrelated sequences
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    //xorshift state must never be zero
+    Self(z.max(1))
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    self.0 ^= self.0 >> 12;
+    self.0 ^= self.0 << 25;
+    self.0 ^= self.0 >> 27;
+    self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+  }
+
+  /// Random number in `0..bound`
+  pub fn below(&mut self, bound: usize) -> usize {
+    (self.next_u64() % bound as u64) as usize
+  }
+
+  /// Returns `true` with the given probability
+  pub fn chance(&mut self, probability: f64) -> bool {
+    ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+  }
+}
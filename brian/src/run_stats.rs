@@ -0,0 +1,60 @@
+//! Runtime loop nesting statistics
+//!
+//! Tracks how deep loops nest while a program runs and how many iterations run at each depth,
+//! which shows how close a program gets to pathological recursion-like behavior
+
+use std::ops::ControlFlow;
+use crate::{Hooks, Opcode, StateView};
+
+/// Loop statistics of a run, see [`RunStatsRecorder`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunStats {
+  /// Deepest loop nesting reached (0 if no loop was entered)
+  pub max_nesting_depth: usize,
+  /// Number of loop body iterations started at each nesting depth, starting with top-level loops at index 0
+  pub iterations_per_depth: Vec<u64>,
+}
+
+/// [`Hooks`] recording [`RunStats`]
+///
+/// Loops removed by the optimizer aren't seen, use an unoptimized program
+/// ([`Program::compile_without_optimizations`](crate::Program::compile_without_optimizations)) to track source loops
+#[derive(Clone, Debug, Default)]
+pub struct RunStatsRecorder {
+  stats: RunStats,
+  depth: usize,
+}
+impl RunStatsRecorder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Get the recorded statistics
+  pub fn finish(self) -> RunStats {
+    self.stats
+  }
+
+  fn iteration(&mut self) {
+    let level = self.depth - 1;
+    if level >= self.stats.iterations_per_depth.len() {
+      self.stats.iterations_per_depth.resize(level + 1, 0);
+    }
+    self.stats.iterations_per_depth[level] += 1;
+  }
+}
+impl Hooks for RunStatsRecorder {
+  fn after(&mut self, _program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    let condition = state.cell(0);
+    match op {
+      Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) if condition != 0 => {
+        self.depth += 1;
+        self.stats.max_nesting_depth = self.stats.max_nesting_depth.max(self.depth);
+        self.iteration();
+      },
+      Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) if condition != 0 => self.iteration(),
+      Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) => self.depth -= 1,
+      _ => (),
+    }
+    ControlFlow::Continue(())
+  }
+}
@@ -0,0 +1,145 @@
+//! Sanitizer for behavior that differs across interpreters
+//!
+//! Flags cell overflow wrapping, pointer wraparound at the tape edges and reads of cells that were never written,
+//! as they happen, similarly to UBSan. Programs relying on them may misbehave on interpreters with other cell sizes,
+//! tape lengths or initial tape contents
+
+use std::{collections::HashSet, fmt, ops::ControlFlow};
+use crate::{brainfuck::MEMORY_MASK, Hooks, Opcode, StateView};
+
+/// Kind of suspicious behavior flagged by the [`Sanitizer`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FindingKind {
+  /// Cell arithmetic wrapped around `0..=255`
+  CellOverflow,
+  /// Pointer went past an edge of the tape and wrapped around to the other one
+  PointerWraparound,
+  /// Cell that was never written was output, tested by a loop or moved
+  UninitializedRead,
+}
+impl fmt::Display for FindingKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::CellOverflow => "cell value wrapped around",
+      Self::PointerWraparound => "pointer wrapped around the tape edge",
+      Self::UninitializedRead => "read of a cell that was never written",
+    })
+  }
+}
+
+/// Suspicious behavior flagged by the [`Sanitizer`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Finding {
+  pub kind: FindingKind,
+  /// Index of the opcode that behaved suspiciously
+  pub instruction: usize,
+  /// Tape address of the cell involved, or the pointer before wrapping around
+  pub address: usize,
+}
+
+/// Tape address at `offset` from `address`, and whether getting there wraps around the tape
+fn offset_address(address: usize, offset: isize) -> (usize, bool) {
+  let target = address as isize + offset;
+  (target as usize & MEMORY_MASK, !(0..=MEMORY_MASK as isize).contains(&target))
+}
+
+/// [`Hooks`] passing every [`Finding`] to a callback as it happens
+///
+/// Each kind of finding is reported once per opcode. Use an unoptimized program
+/// ([`Program::compile_without_optimizations`](crate::Program::compile_without_optimizations)),
+/// the optimizer reorders and merges the operations being checked
+pub struct Sanitizer<F: FnMut(Finding)> {
+  callback: F,
+  written: Vec<bool>,
+  reported: HashSet<(FindingKind, usize)>,
+  /// Address of the pointer before the opcode ran
+  pointer: usize,
+}
+impl<F: FnMut(Finding)> Sanitizer<F> {
+  pub fn new(callback: F) -> Self {
+    Self { callback, written: vec![false; MEMORY_MASK + 1], reported: HashSet::new(), pointer: 0 }
+  }
+
+  fn report(&mut self, kind: FindingKind, instruction: usize, address: usize) {
+    if self.reported.insert((kind, instruction)) {
+      (self.callback)(Finding { kind, instruction, address });
+    }
+  }
+
+  /// Check access to the cell at `offset` from the pointer, returning its address
+  fn access(&mut self, instruction: usize, offset: isize) -> usize {
+    let (address, wrapped) = offset_address(self.pointer, offset);
+    if wrapped {
+      self.report(FindingKind::PointerWraparound, instruction, self.pointer);
+    }
+    address
+  }
+
+  fn read(&mut self, instruction: usize, address: usize) {
+    if !self.written[address] {
+      self.report(FindingKind::UninitializedRead, instruction, address);
+    }
+  }
+
+  fn add(&mut self, instruction: usize, address: usize, value: u8, delta: isize) {
+    if !(value as isize).checked_add(delta).is_some_and(|x| (0..=255).contains(&x)) {
+      self.report(FindingKind::CellOverflow, instruction, address);
+    }
+    self.written[address] = true;
+  }
+}
+impl<F: FnMut(Finding)> Hooks for Sanitizer<F> {
+  fn before(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    self.pointer = state.pointer & MEMORY_MASK;
+    match *op {
+      Opcode::Increment(offset, delta) => {
+        let address = self.access(program_counter, offset);
+        self.add(program_counter, address, state.memory[address], delta);
+      },
+      Opcode::Set(offset, _) | Opcode::Input(offset) => {
+        let address = self.access(program_counter, offset);
+        self.written[address] = true;
+      },
+      Opcode::Output(offset) => {
+        let address = self.access(program_counter, offset);
+        self.read(program_counter, address);
+      },
+      Opcode::LoopStart(_) | Opcode::LoopEnd(_) => self.read(program_counter, self.pointer),
+      Opcode::MovePointer(offset) => {
+        self.access(program_counter, offset);
+      },
+      Opcode::MoveLoopStart(offset, _) | Opcode::MoveLoopEnd(offset, _) => {
+        let address = self.access(program_counter, offset);
+        self.read(program_counter, address);
+      },
+      Opcode::Move(ref targets) => {
+        let value = state.memory[self.pointer];
+        self.read(program_counter, self.pointer);
+        if value != 0 {
+          for &(offset, factor) in &targets[..] {
+            let address = self.access(program_counter, offset);
+            //Factors above 127 come from decrements
+            self.add(program_counter, address, state.memory[address], value as isize * factor as i8 as isize);
+          }
+          self.written[self.pointer] = true;
+        }
+      },
+      //Checked once the scan is done
+      Opcode::ScanZero(_) => (),
+      //Only fused from a profile, so never part of the unoptimized programs this checks
+      Opcode::Fused(_) => (),
+      Opcode::Flush | Opcode::Eof => (),
+    }
+    ControlFlow::Continue(())
+  }
+
+  fn after(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    if let Opcode::ScanZero(step) = *op {
+      let end = state.pointer & MEMORY_MASK;
+      if (step > 0 && end < self.pointer) || (step < 0 && end > self.pointer) {
+        self.report(FindingKind::PointerWraparound, program_counter, self.pointer);
+      }
+    }
+    ControlFlow::Continue(())
+  }
+}
@@ -0,0 +1,103 @@
+//! Sessions of several named programs sharing one interpreter state
+//!
+//! For programs authored as cooperating phases (e.g. "init", then "step" repeatedly, then "render"),
+//! where each phase picks up the tape and pointer left by the previous one
+
+use std::fmt;
+use crate::{brainfuck::interpreter, BrainfuckState, CompileError, Io, Program};
+
+/// Error returned when running a program that isn't part of the [`Session`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownProgram(pub String);
+impl fmt::Display for UnknownProgram {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "no program named {:?} in the session", self.0)
+  }
+}
+impl std::error::Error for UnknownProgram {}
+
+/// Named compiled programs sharing one tape and pointer
+///
+/// Nothing runs unless requested, and the state is never reset between runs.
+/// Programs added with [`Session::add`] must not be compiled with
+/// [`CompileOptions::assume_zeroed_tape`](crate::CompileOptions::assume_zeroed_tape) or
+/// [`CompileOptions::drop_dead_stores`](crate::CompileOptions::drop_dead_stores), as the state they leave behind matters
+#[derive(Clone, Default)]
+pub struct Session {
+  programs: Vec<(String, Program)>,
+  state: Box<BrainfuckState>,
+}
+impl Session {
+  /// Create a session without programs, on a zeroed tape
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a compiled program, returning the program previously registered under the same name
+  pub fn add(&mut self, name: impl Into<String>, program: Program) -> Option<Program> {
+    let name = name.into();
+    match self.programs.iter_mut().find(|(x, _)| *x == name) {
+      Some((_, existing)) => Some(std::mem::replace(existing, program)),
+      None => {
+        self.programs.push((name, program));
+        None
+      },
+    }
+  }
+
+  /// Compile source code with the default options and add it, see [`Session::add`]
+  ///
+  /// Fails without touching the session if the brackets are unbalanced
+  pub fn compile(&mut self, name: impl Into<String>, code: &str) -> Result<Option<Program>, CompileError> {
+    Ok(self.add(name, Program::compile(code)?))
+  }
+
+  /// Remove a program from the session
+  pub fn remove(&mut self, name: &str) -> Option<Program> {
+    let index = self.programs.iter().position(|(x, _)| x == name)?;
+    Some(self.programs.remove(index).1)
+  }
+
+  /// Get a program by name
+  pub fn program(&self, name: &str) -> Option<&Program> {
+    self.programs.iter().find(|(x, _)| x == name).map(|(_, program)| program)
+  }
+
+  /// Names of all programs, in the order they were added
+  pub fn names(&self) -> impl Iterator<Item = &str> {
+    self.programs.iter().map(|(name, _)| name.as_str())
+  }
+
+  /// Run a program to completion on the shared state
+  pub fn run(&mut self, name: &str, io: &mut impl Io) -> Result<(), UnknownProgram> {
+    let program = find(&self.programs, name)?;
+    interpreter::run(program, &mut self.state, io);
+    Ok(())
+  }
+
+  /// Run a program on the shared state, executing at most `fuel` instructions
+  ///
+  /// Returns `Ok(false)` if the program ran out of fuel before reaching the end,
+  /// in which case the state is left wherever it stopped
+  pub fn run_with_fuel(&mut self, name: &str, io: &mut impl Io, fuel: usize) -> Result<bool, UnknownProgram> {
+    let program = find(&self.programs, name)?;
+    Ok(interpreter::run_with_fuel(program, &mut self.state, io, fuel))
+  }
+
+  /// Shared state, as left by the last run
+  pub fn state(&self) -> &BrainfuckState {
+    &self.state
+  }
+
+  pub fn state_mut(&mut self) -> &mut BrainfuckState {
+    &mut self.state
+  }
+}
+
+//Takes the program list rather than the session, so the state can be borrowed mutably alongside the program
+fn find<'a>(programs: &'a [(String, Program)], name: &str) -> Result<&'a Program, UnknownProgram> {
+  match programs.iter().find(|(x, _)| x == name) {
+    Some((_, program)) => Ok(program),
+    None => Err(UnknownProgram(name.into())),
+  }
+}
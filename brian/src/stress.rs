@@ -0,0 +1,325 @@
+//! Optimizer stress testing
+//!
+//! Generates random programs, runs them through both the optimized and the reference
+//! (unoptimized) pipelines and shrinks any program that makes them disagree
+
+use std::{fmt, ops::Range};
+use crate::{
+  brainfuck::{brainfuck_tokens_indexed, MEMORY_MASK},
+  generator::ProgramGenerator,
+  Brainfuck, CompileOptions, EofMode, Opcode, Program,
+};
+
+/// Outcome of running the same program through both pipelines
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Comparison {
+  /// Both pipelines finished with identical output and state
+  Equal,
+  /// At least one pipeline ran out of fuel, nothing to compare
+  Inconclusive,
+  /// Pipelines finished with different output or state
+  Diverged,
+  /// Brackets are unbalanced, so the program can't be compiled
+  Unbalanced,
+}
+
+/// Run `code` with both pipelines and compare output, pointer and memory
+pub fn compare(code: &str, fuel: usize) -> Comparison {
+  let mut reference = Brainfuck::new();
+  if reference.compile_without_optimizations(code).is_err() {
+    return Comparison::Unbalanced
+  }
+  let mut reference_output = Vec::new();
+  let reference_halted = reference.run_with_fuel(&mut reference_output, fuel) == Ok(true);
+
+  //Both runs start with a fresh tape, so the optimizer can rely on it
+  let mut optimized = Brainfuck::new();
+  optimized.compile_with_options(code, &CompileOptions { assume_zeroed_tape: true, ..Default::default() })
+    .expect("brackets are checked by the reference compilation");
+  let mut optimized_output = Vec::new();
+  let optimized_halted = optimized.run_with_fuel(&mut optimized_output, fuel) == Ok(true);
+
+  if !(reference_halted && optimized_halted) {
+    return Comparison::Inconclusive
+  }
+  let equal =
+    reference_output == optimized_output &&
+    reference.state().pointer == optimized.state().pointer &&
+    reference.state().memory == optimized.state().memory;
+  if equal { Comparison::Equal } else { Comparison::Diverged }
+}
+
+/// First observable difference between the reference and optimized runs of a program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+  /// Index of the first differing output byte (equal to the output length if only the final state differs)
+  pub output_index: usize,
+  /// Byte written by the reference run at `output_index`, if any
+  pub reference_output: Option<u8>,
+  /// Byte written by the optimized run at `output_index`, if any
+  pub optimized_output: Option<u8>,
+  /// Index of the reference (unoptimized) instruction that wrote the differing byte
+  pub instruction: Option<usize>,
+  /// Byte range of that instruction in the source
+  pub span: Option<Range<usize>>,
+  /// Final pointers of the reference and optimized runs
+  pub pointers: (usize, usize),
+  /// Cells that differ at the end of both runs, as (address, reference value, optimized value)
+  pub cells: Vec<(usize, u8, u8)>,
+}
+impl fmt::Display for Mismatch {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let byte = |x: Option<u8>| x.map_or("nothing".into(), |x| format!("{x:#04x}"));
+    if self.reference_output != self.optimized_output {
+      write!(f, "output byte {} differs: reference wrote {}, optimized wrote {}",
+        self.output_index, byte(self.reference_output), byte(self.optimized_output))?;
+      if let (Some(instruction), Some(span)) = (&self.instruction, &self.span) {
+        write!(f, " (instruction {instruction}, source bytes {span:?})")?;
+      }
+    } else {
+      write!(f, "output matches ({} bytes), but the final state differs", self.output_index)?;
+    }
+    if self.pointers.0 != self.pointers.1 {
+      write!(f, "\npointer: reference {}, optimized {}", self.pointers.0, self.pointers.1)?;
+    }
+    for (address, reference, optimized) in &self.cells {
+      write!(f, "\ncell {address}: reference {reference}, optimized {optimized}")?;
+    }
+    Ok(())
+  }
+}
+
+/// Outcome of [`verify`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verification {
+  /// Both pipelines finished with identical output and state
+  Equal,
+  /// At least one pipeline ran out of fuel, nothing to compare
+  Inconclusive,
+  /// Pipelines finished with different output or state
+  Diverged(Mismatch),
+}
+
+/// Index of the unoptimized instruction writing output byte `index`, found by replaying the reference run
+fn locate_output(ops: &[Opcode], input: &[u8], eof: EofMode, index: usize) -> Option<usize> {
+  let mut memory = vec![0u8; MEMORY_MASK + 1];
+  let mut pointer = 0usize;
+  let mut input = input.iter();
+  let mut written = 0;
+  let mut program_counter = 0;
+  loop {
+    match ops[program_counter] {
+      Opcode::Increment(_, value) => memory[pointer] = memory[pointer].wrapping_add(value as u8),
+      Opcode::MovePointer(offset) => pointer = pointer.wrapping_add_signed(offset) & MEMORY_MASK,
+      Opcode::LoopStart(end) => if memory[pointer] == 0 {
+        program_counter = end;
+      },
+      Opcode::LoopEnd(start) => if memory[pointer] != 0 {
+        program_counter = start;
+      },
+      Opcode::Output(_) => {
+        if written == index {
+          return Some(program_counter)
+        }
+        written += 1;
+      },
+      Opcode::Input(_) => match (input.next(), eof) {
+        (Some(&byte), _) => memory[pointer] = byte,
+        (None, EofMode::Unchanged) => (),
+        (None, EofMode::Zero) => memory[pointer] = 0,
+        (None, EofMode::Max) => memory[pointer] = u8::MAX,
+      },
+      Opcode::Eof => return None,
+      _ => unreachable!("unoptimized programs only contain plain commands"),
+    }
+    program_counter += 1;
+  }
+}
+
+/// Run `code` with both pipelines on the same input and report the first difference
+///
+/// Unlike [`compare`], this points at the reference instruction responsible for the first differing output byte,
+/// so it is suitable for reporting optimizer bugs in real programs. Panics if the brackets are unbalanced
+pub fn verify(code: &str, input: &[u8], fuel: usize) -> Verification {
+  verify_with_eof(code, input, fuel, EofMode::default())
+}
+
+/// [`verify`] with both runs handling the end of input according to `eof`
+pub(crate) fn verify_with_eof(code: &str, input: &[u8], fuel: usize, eof: EofMode) -> Verification {
+  let reference_program = Program::compile_without_optimizations(code).unwrap_or_else(|error| panic!("{error}"));
+  let mut reference = Brainfuck::new();
+  reference.set_eof_mode(eof);
+  reference.load_program(reference_program.clone());
+  let mut reference_output = Vec::new();
+  let reference_halted = reference.run_with_io(input, &mut reference_output, fuel) == Ok(true);
+
+  let mut optimized = Brainfuck::new();
+  optimized.set_eof_mode(eof);
+  optimized.compile_with_options(code, &CompileOptions { assume_zeroed_tape: true, ..Default::default() })
+    .expect("brackets are checked by the reference compilation");
+  let mut optimized_output = Vec::new();
+  let optimized_halted = optimized.run_with_io(input, &mut optimized_output, fuel) == Ok(true);
+
+  if !(reference_halted && optimized_halted) {
+    return Verification::Inconclusive
+  }
+  let (reference_state, optimized_state) = (reference.state(), optimized.state());
+  let cells: Vec<(usize, u8, u8)> = reference_state.memory.iter().zip(&optimized_state.memory).enumerate()
+    .filter(|(_, (a, b))| a != b)
+    .map(|(address, (a, b))| (address, *a, *b))
+    .collect();
+  let pointers = (reference_state.pointer, optimized_state.pointer);
+  if reference_output == optimized_output && pointers.0 == pointers.1 && cells.is_empty() {
+    return Verification::Equal
+  }
+  let output_index = reference_output.iter().zip(&optimized_output).position(|(a, b)| a != b)
+    .unwrap_or(reference_output.len().min(optimized_output.len()));
+  let reference_byte = reference_output.get(output_index).copied();
+  let instruction = reference_byte.and_then(|_| locate_output(reference_program.opcodes(), input, eof, output_index));
+  let span = instruction.map(|x| {
+    let offset = brainfuck_tokens_indexed(code).nth(x).unwrap().0;
+    offset..(offset + 1)
+  });
+  Verification::Diverged(Mismatch {
+    output_index,
+    reference_output: reference_byte,
+    optimized_output: optimized_output.get(output_index).copied(),
+    instruction,
+    span,
+    pointers,
+    cells,
+  })
+}
+
+pub(crate) fn is_balanced(code: &[u8]) -> bool {
+  let mut depth = 0usize;
+  for &char in code {
+    match char {
+      b'[' => depth += 1,
+      b']' => match depth.checked_sub(1) {
+        Some(x) => depth = x,
+        None => return false,
+      },
+      _ => (),
+    }
+  }
+  depth == 0
+}
+
+/// Shrink a program while `still_fails` holds for it
+///
+/// Uses delta debugging over the token stream (removing progressively smaller runs of commands),
+/// followed by unwrapping loops (removing matching bracket pairs while keeping the body).
+/// Candidates with unbalanced brackets are never passed to `still_fails`
+pub fn shrink(code: &str, mut still_fails: impl FnMut(&str) -> bool) -> String {
+  let mut tokens: Vec<u8> = code.bytes().filter(|x| b"+-<>[].,".contains(x)).collect();
+  let mut test = |candidate: &[u8]| {
+    //Only ASCII command characters are kept, so this never fails
+    is_balanced(candidate) && still_fails(std::str::from_utf8(candidate).unwrap())
+  };
+  loop {
+    let before = tokens.len();
+
+    //Remove chunks at every position, starting with halves and going down to single tokens
+    let mut chunk = (tokens.len() / 2).max(1);
+    while chunk > 0 {
+      let mut start = 0;
+      while start + chunk <= tokens.len() {
+        let mut candidate = tokens.clone();
+        candidate.drain(start..(start + chunk));
+        if test(&candidate) {
+          tokens = candidate;
+        } else {
+          start += 1;
+        }
+      }
+      chunk /= 2;
+    }
+
+    //Unwrap loops
+    let mut index = 0;
+    while index < tokens.len() {
+      if tokens[index] == b'[' {
+        let mut depth = 0usize;
+        let end = (index..tokens.len()).find(|&i| {
+          match tokens[i] {
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            _ => (),
+          }
+          depth == 0
+        });
+        if let Some(end) = end {
+          let mut candidate = tokens.clone();
+          candidate.remove(end);
+          candidate.remove(index);
+          if test(&candidate) {
+            tokens = candidate;
+            continue
+          }
+        }
+      }
+      index += 1;
+    }
+
+    if tokens.len() == before { break }
+  }
+  String::from_utf8(tokens).unwrap()
+}
+
+/// A program that made the optimized and reference pipelines diverge
+#[derive(Clone, Debug)]
+pub struct Divergence {
+  /// Seed of the iteration that produced the program
+  pub seed: u64,
+  /// Program as generated
+  pub program: String,
+  /// Minimal program that still diverges
+  pub minimized: String,
+}
+impl fmt::Display for Divergence {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "optimizer divergence (seed {})", self.seed)?;
+    writeln!(f, "minimized ({} commands):", self.minimized.len())?;
+    writeln!(f, "{}", self.minimized)?;
+    writeln!(f, "original ({} commands):", self.program.len())?;
+    write!(f, "{}", self.program)
+  }
+}
+
+/// Randomized differential test of the optimizer
+#[derive(Clone, Debug)]
+pub struct StressTest {
+  /// Number of programs to generate
+  pub iterations: usize,
+  /// Seed of the first program, incremented for every iteration
+  pub seed: u64,
+  /// Generator used to produce test programs
+  pub generator: ProgramGenerator,
+  /// Maximum number of instructions to execute per run
+  pub fuel: usize,
+}
+impl StressTest {
+  /// Generate and test programs until one diverges or all iterations pass
+  pub fn run(&self) -> Option<Divergence> {
+    for iteration in 0..self.iterations {
+      let seed = self.seed.wrapping_add(iteration as u64);
+      let program = self.generator.generate(seed);
+      if compare(&program, self.fuel) == Comparison::Diverged {
+        let minimized = shrink(&program, |code| compare(code, self.fuel) == Comparison::Diverged);
+        return Some(Divergence { seed, program, minimized })
+      }
+    }
+    None
+  }
+}
+impl Default for StressTest {
+  fn default() -> Self {
+    Self {
+      iterations: 1000,
+      seed: 0,
+      generator: ProgramGenerator::default(),
+      fuel: 100_000,
+    }
+  }
+}
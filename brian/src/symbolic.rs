@@ -0,0 +1,284 @@
+//! Experimental symbolic execution engine (requires the `symbolic` feature)
+//!
+//! Every byte read by `,` becomes a symbol. Cells hold either a concrete byte or a symbol plus a constant,
+//! which is all brainfuck arithmetic can produce, and every branch on a symbolic cell forks the execution
+//! with an equality or inequality constraint on the symbol. This is enough to answer questions like
+//! "what input makes this program print X?" for small programs.
+
+use std::collections::HashMap;
+use crate::{brainfuck::MEMORY_MASK, CompileError, Opcode, Program};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Value {
+  Concrete(u8),
+  /// Input byte `symbol` plus `offset` (wrapping)
+  Symbolic { symbol: usize, offset: u8 },
+}
+impl Value {
+  fn add(self, value: u8) -> Self {
+    match self {
+      Self::Concrete(x) => Self::Concrete(x.wrapping_add(value)),
+      Self::Symbolic { symbol, offset } => Self::Symbolic { symbol, offset: offset.wrapping_add(value) },
+    }
+  }
+}
+
+/// Set of byte values a symbol may still take
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Domain([u64; 4]);
+impl Domain {
+  const FULL: Self = Self([u64::MAX; 4]);
+
+  fn contains(&self, value: u8) -> bool {
+    self.0[value as usize / 64] & (1 << (value % 64)) != 0
+  }
+
+  fn only(value: u8) -> Self {
+    let mut domain = Self([0; 4]);
+    domain.0[value as usize / 64] = 1 << (value % 64);
+    domain
+  }
+
+  fn without(mut self, value: u8) -> Self {
+    self.0[value as usize / 64] &= !(1 << (value % 64));
+    self
+  }
+
+  fn is_empty(&self) -> bool {
+    self.0.iter().all(|&x| x == 0)
+  }
+
+  /// The only value in the domain, if there is exactly one
+  fn single(&self) -> Option<u8> {
+    (self.0.iter().map(|x| x.count_ones()).sum::<u32>() == 1).then(|| self.first().unwrap())
+  }
+
+  fn first(&self) -> Option<u8> {
+    (0..=255).find(|&x| self.contains(x))
+  }
+}
+
+#[derive(Clone)]
+struct Path {
+  tape: HashMap<usize, Value>,
+  pointer: usize,
+  program_counter: usize,
+  steps: usize,
+  /// Domains of all symbols read so far, indexed by symbol
+  symbols: Vec<Domain>,
+  /// Number of expected output bytes already matched
+  matched: usize,
+}
+impl Path {
+  fn address(&self, offset: isize) -> usize {
+    self.pointer.wrapping_add_signed(offset) & MEMORY_MASK
+  }
+
+  fn get(&self, offset: isize) -> Value {
+    let value = self.tape.get(&self.address(offset)).copied().unwrap_or(Value::Concrete(0));
+    //Symbols constrained to a single value are concrete
+    match value {
+      Value::Symbolic { symbol, offset } => match self.symbols[symbol].single() {
+        Some(x) => Value::Concrete(x.wrapping_add(offset)),
+        None => value,
+      },
+      _ => value,
+    }
+  }
+
+  fn set(&mut self, offset: isize, value: Value) {
+    let address = self.address(offset);
+    self.tape.insert(address, value);
+  }
+
+  /// Constrain `value` to be equal (or not equal) to `target`, returning `false` if that is infeasible
+  fn constrain(&mut self, value: Value, target: u8, equal: bool) -> bool {
+    match value {
+      Value::Concrete(x) => (x == target) == equal,
+      Value::Symbolic { symbol, offset } => {
+        let symbol_value = target.wrapping_sub(offset);
+        let domain = &mut self.symbols[symbol];
+        let constrained = match equal {
+          true if domain.contains(symbol_value) => Domain::only(symbol_value),
+          true => return false,
+          false => domain.without(symbol_value),
+        };
+        *domain = constrained;
+        !constrained.is_empty()
+      },
+    }
+  }
+
+  /// Check whether the current cell is zero, forking the path if it is symbolic
+  ///
+  /// The current path prefers the zero outcome (leaving loops early, so that shorter executions are explored first)
+  /// and the nonzero outcome is pushed to `pending`. Returns `None` if neither outcome is feasible
+  fn branch(&mut self, pending: &mut Vec<Path>) -> Option<bool> {
+    let value = self.get(0);
+    if let Value::Concrete(x) = value {
+      return Some(x == 0)
+    }
+    let mut fork = self.clone();
+    let nonzero = fork.constrain(value, 0, false);
+    let zero = self.constrain(value, 0, true);
+    match (zero, nonzero) {
+      (true, true) => {
+        pending.push(fork);
+        Some(true)
+      },
+      (true, false) => Some(true),
+      (false, true) => {
+        *self = fork;
+        Some(false)
+      },
+      (false, false) => None,
+    }
+  }
+
+  /// Value of the current cell, forking the path for every other value it may take if it is symbolic
+  ///
+  /// Needed where a symbol gets multiplied, which can't be represented
+  fn concretize(&mut self, pending: &mut Vec<Path>) -> u8 {
+    let value = self.get(0);
+    let (symbol, offset) = match value {
+      Value::Concrete(x) => return x,
+      Value::Symbolic { symbol, offset } => (symbol, offset),
+    };
+    let mut values = (0..=u8::MAX).filter(|&x| self.symbols[symbol].contains(x)).map(|x| x.wrapping_add(offset));
+    //Domains are never empty
+    let first = values.next().unwrap();
+    //Smaller inputs are explored first, like with the equivalent loop
+    for x in values.rev() {
+      let mut fork = self.clone();
+      fork.constrain(value, x, true);
+      pending.push(fork);
+    }
+    self.constrain(value, first, true);
+    first
+  }
+
+  /// Concrete input satisfying all constraints collected on this path
+  fn input(&self) -> Vec<u8> {
+    self.symbols.iter().map(|x| x.first().unwrap()).collect()
+  }
+}
+
+/// Result of an input search
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Search {
+  /// Input that makes the program produce the expected output
+  Found(Vec<u8>),
+  /// All paths were explored and none produces the expected output
+  Impossible,
+  /// Exploration was cut short by the step or path limits
+  GaveUp,
+}
+
+/// Symbolic executor configuration
+#[derive(Clone, Debug)]
+pub struct SymbolicExecutor {
+  /// Maximum number of instructions executed on a single path
+  pub max_steps: usize,
+  /// Maximum number of paths explored
+  pub max_paths: usize,
+}
+impl SymbolicExecutor {
+  /// Find an input that makes the program print `expected` (as a prefix of its output)
+  ///
+  /// Input is assumed to be long enough for every `,` to read a byte. Fails if the brackets are unbalanced
+  pub fn find_input(&self, code: &str, expected: &[u8]) -> Result<Search, CompileError> {
+    let program = Program::compile(code)?;
+    let ops = program.opcodes();
+    let mut pending = vec![Path {
+      tape: HashMap::new(),
+      pointer: 0,
+      program_counter: 0,
+      steps: 0,
+      symbols: Vec::new(),
+      matched: 0,
+    }];
+    let mut paths = 0;
+    let mut exhaustive = true;
+    'explore: while let Some(mut path) = pending.pop() {
+      paths += 1;
+      loop {
+        if path.matched == expected.len() {
+          return Ok(Search::Found(path.input()))
+        }
+        if paths + pending.len() > self.max_paths {
+          return Ok(Search::GaveUp)
+        }
+        if path.steps == self.max_steps {
+          exhaustive = false;
+          continue 'explore
+        }
+        path.steps += 1;
+        match ops[path.program_counter] {
+          Opcode::Increment(offset, value) => {
+            path.set(offset, path.get(offset).add(value as u8));
+          },
+          Opcode::Set(offset, value) => {
+            path.set(offset, Value::Concrete(value));
+          },
+          Opcode::MovePointer(offset) => {
+            path.pointer = path.pointer.wrapping_add_signed(offset);
+          },
+          Opcode::LoopStart(end) => {
+            let Some(zero) = path.branch(&mut pending) else { continue 'explore };
+            if zero {
+              path.program_counter = end;
+            }
+          },
+          Opcode::LoopEnd(start) => {
+            let Some(zero) = path.branch(&mut pending) else { continue 'explore };
+            if !zero {
+              path.program_counter = start;
+            }
+          },
+          Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) | Opcode::Fused(_) => {
+            unreachable!("superinstructions are only fused with a profile")
+          },
+          Opcode::ScanZero(direction) => {
+            let Some(zero) = path.branch(&mut pending) else { continue 'explore };
+            if !zero {
+              //Move and scan again
+              path.pointer = path.pointer.wrapping_add_signed(direction);
+              continue
+            }
+          },
+          Opcode::Move(ref targets) => {
+            let value = path.concretize(&mut pending);
+            for &(offset, factor) in &targets[..] {
+              path.set(offset, path.get(offset).add(value.wrapping_mul(factor)));
+            }
+            path.set(0, Value::Concrete(0));
+          },
+          Opcode::Output(offset) => {
+            let value = path.get(offset);
+            if !path.constrain(value, expected[path.matched], true) {
+              continue 'explore
+            }
+            path.matched += 1;
+          },
+          Opcode::Input(offset) => {
+            let symbol = path.symbols.len();
+            path.symbols.push(Domain::FULL);
+            path.set(offset, Value::Symbolic { symbol, offset: 0 });
+          },
+          Opcode::Flush => (),
+          Opcode::Eof => continue 'explore,
+        }
+        path.program_counter += 1;
+      }
+    }
+    Ok(if exhaustive { Search::Impossible } else { Search::GaveUp })
+  }
+}
+impl Default for SymbolicExecutor {
+  fn default() -> Self {
+    Self {
+      max_steps: 100_000,
+      max_paths: 10_000,
+    }
+  }
+}
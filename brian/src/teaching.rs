@@ -0,0 +1,109 @@
+//! Teaching mode
+//!
+//! Describes every executed instruction in plain English ("add 3 to cell 7 (now 42)"),
+//! for educators showing how brainfuck programs and interpreters work
+
+use std::ops::ControlFlow;
+use crate::{brainfuck::MEMORY_MASK, Hooks, Opcode, StateView};
+
+/// Tape index of the cell at `offset` from `pointer`
+fn cell_index(pointer: usize, offset: isize) -> usize {
+  pointer.wrapping_add_signed(offset) & MEMORY_MASK
+}
+
+fn describe_byte(value: u8) -> String {
+  match value {
+    0x20..=0x7e => format!("{value}, {:?}", value as char),
+    _ => value.to_string(),
+  }
+}
+
+/// Explain an opcode that was just executed
+///
+/// `pointer` is the pointer before the opcode ran, `state` is the state right after it
+pub fn explain(op: &Opcode, pointer: usize, state: StateView) -> String {
+  let here = cell_index(pointer, 0);
+  let now = cell_index(state.pointer, 0);
+  let direction = |offset: isize| if offset < 0 { "left" } else { "right" };
+  let cells = |offset: isize| match offset.unsigned_abs() {
+    1 => "1 cell".to_string(),
+    count => format!("{count} cells"),
+  };
+  let loop_check = |entering: bool| match (state.memory[now], entering) {
+    (0, true) => format!("cell {now} is 0, so skip the loop"),
+    (value, true) => format!("cell {now} is {value}, so enter the loop"),
+    (0, false) => format!("cell {now} is 0, so leave the loop"),
+    (value, false) => format!("cell {now} is {value}, so jump back to the start of the loop"),
+  };
+  match *op {
+    Opcode::Increment(offset, value) => {
+      let cell = cell_index(pointer, offset);
+      let verb = if value < 0 { "subtract" } else { "add" };
+      let preposition = if value < 0 { "from" } else { "to" };
+      format!("{verb} {} {preposition} cell {cell} (now {})", value.unsigned_abs(), state.memory[cell])
+    },
+    Opcode::Set(offset, value) => format!("set cell {} to {value}", cell_index(pointer, offset)),
+    Opcode::MovePointer(offset) => {
+      format!("move the pointer {} {} (to cell {now})", cells(offset), direction(offset))
+    },
+    Opcode::LoopStart(_) => loop_check(true),
+    Opcode::LoopEnd(_) => loop_check(false),
+    Opcode::MoveLoopStart(offset, _) => {
+      format!("move the pointer {} {}, then {}", cells(offset), direction(offset), loop_check(true))
+    },
+    Opcode::MoveLoopEnd(offset, _) => {
+      format!("move the pointer {} {}, then {}", cells(offset), direction(offset), loop_check(false))
+    },
+    Opcode::ScanZero(_) if here == now => format!("cell {here} is already 0, so the scan stays put"),
+    Opcode::ScanZero(step) => {
+      format!("scan {} from cell {here} in steps of {}, stopping at cell {now} which is 0", direction(step), cells(step))
+    },
+    Opcode::Move(ref targets) => {
+      let targets: Vec<String> = targets.iter().map(|&(offset, factor)| {
+        let cell = cell_index(pointer, offset);
+        match factor {
+          1 => format!("to cell {cell} (now {})", state.memory[cell]),
+          _ => format!("{factor} times to cell {cell} (now {})", state.memory[cell]),
+        }
+      }).collect();
+      format!("add cell {here} {}, then clear it", targets.join(" and "))
+    },
+    Opcode::Output(offset) => {
+      let cell = cell_index(pointer, offset);
+      format!("output cell {cell} ({})", describe_byte(state.memory[cell]))
+    },
+    Opcode::Input(offset) => {
+      let cell = cell_index(pointer, offset);
+      format!("read a byte of input into cell {cell} (now {})", describe_byte(state.memory[cell]))
+    },
+    Opcode::Fused(ref ops) => {
+      let names: Vec<&str> = ops.iter().map(Opcode::name).collect();
+      format!("run {} at once, ending at cell {now}", names.join(", "))
+    },
+    Opcode::Flush => "flush the output".into(),
+    Opcode::Eof => "end of the program".into(),
+  }
+}
+
+/// [`Hooks`] passing an explanation of every executed instruction to a callback,
+/// along with the index of the instruction
+pub struct Explainer<F: FnMut(usize, String)> {
+  callback: F,
+  pointer: usize,
+}
+impl<F: FnMut(usize, String)> Explainer<F> {
+  pub fn new(callback: F) -> Self {
+    Self { callback, pointer: 0 }
+  }
+}
+impl<F: FnMut(usize, String)> Hooks for Explainer<F> {
+  fn before(&mut self, _program_counter: usize, _op: &Opcode, state: StateView) -> ControlFlow<()> {
+    self.pointer = state.pointer;
+    ControlFlow::Continue(())
+  }
+
+  fn after(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    (self.callback)(program_counter, explain(op, self.pointer, state));
+    ControlFlow::Continue(())
+  }
+}
@@ -0,0 +1,49 @@
+//! Execution rate limiting
+//!
+//! Slows programs down to a fixed number of instructions per second, so visualizations and teaching tools
+//! can animate execution at a human-watchable speed
+
+use std::{ops::ControlFlow, thread, time::{Duration, Instant}};
+use crate::{Hooks, Opcode, StateView};
+
+/// Number of times per second the throttle checks the clock at high rates
+const CHECKS_PER_SECOND: u64 = 100;
+
+/// [`Hooks`] sleeping between instructions to keep execution at a given rate
+///
+/// The rate is kept on average since the first instruction, so time spent by the host (e.g. in other hooks
+/// or printing) is made up for rather than added on top
+#[derive(Clone, Debug)]
+pub struct Throttle {
+  instructions_per_second: u64,
+  /// Instructions executed between clock checks
+  batch: u64,
+  instructions: u64,
+  start: Option<Instant>,
+}
+impl Throttle {
+  /// Limit execution to `instructions_per_second`, which must not be zero
+  pub fn new(instructions_per_second: u64) -> Self {
+    assert!(instructions_per_second > 0, "throttle rate must not be zero");
+    Self {
+      instructions_per_second,
+      batch: (instructions_per_second / CHECKS_PER_SECOND).max(1),
+      instructions: 0,
+      start: None,
+    }
+  }
+}
+impl Hooks for Throttle {
+  #[inline]
+  fn before(&mut self, _program_counter: usize, _op: &Opcode, _state: StateView) -> ControlFlow<()> {
+    let start = *self.start.get_or_insert_with(Instant::now);
+    self.instructions += 1;
+    if self.instructions.is_multiple_of(self.batch) {
+      let due = Duration::from_secs_f64(self.instructions as f64 / self.instructions_per_second as f64);
+      if let Some(ahead) = due.checked_sub(start.elapsed()) {
+        thread::sleep(ahead);
+      }
+    }
+    ControlFlow::Continue(())
+  }
+}
@@ -0,0 +1,101 @@
+//! Execution timeline recording
+//!
+//! Records loop entries and exits and I/O events, timestamped by the number of instructions executed so far,
+//! for rendering "flame charts" of program execution in external viewers
+
+use std::{fmt::Write as _, ops::ControlFlow};
+use crate::{Hooks, Opcode, StateView};
+
+/// Kind of a timeline event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+  /// Loop starting at the given opcode index was entered
+  LoopEnter(usize),
+  /// Loop starting at the given opcode index was left
+  LoopExit(usize),
+  /// Byte was read (or the cell kept at EOF)
+  Input(u8),
+  /// Byte was written
+  Output(u8),
+}
+
+/// Single timeline event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+  /// Number of instructions executed before the event
+  pub time: u64,
+  pub kind: EventKind,
+}
+
+/// Recorded execution timeline
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timeline {
+  pub events: Vec<Event>,
+  /// Total number of instructions executed
+  pub instructions: u64,
+  /// Whether events were dropped because the event limit was reached
+  pub truncated: bool,
+}
+impl Timeline {
+  /// Render the timeline as JSON
+  pub fn to_json(&self) -> String {
+    let mut json = String::new();
+    let _ = write!(json, "{{\"instructions\":{},\"truncated\":{},\"events\":[", self.instructions, self.truncated);
+    for (index, event) in self.events.iter().enumerate() {
+      if index > 0 { json.push(',') }
+      let (kind, key, value) = match event.kind {
+        EventKind::LoopEnter(start) => ("enter", "loop", start),
+        EventKind::LoopExit(start) => ("exit", "loop", start),
+        EventKind::Input(byte) => ("input", "byte", byte as usize),
+        EventKind::Output(byte) => ("output", "byte", byte as usize),
+      };
+      let _ = write!(json, "{{\"time\":{},\"kind\":\"{kind}\",\"{key}\":{value}}}", event.time);
+    }
+    json.push_str("]}");
+    json
+  }
+}
+
+/// [`Hooks`] recording a [`Timeline`]
+#[derive(Clone, Debug)]
+pub struct TimelineRecorder {
+  timeline: Timeline,
+  max_events: usize,
+}
+impl TimelineRecorder {
+  /// Create a recorder keeping at most `max_events` events
+  pub fn new(max_events: usize) -> Self {
+    Self { timeline: Timeline::default(), max_events }
+  }
+
+  /// Get the recorded timeline
+  pub fn finish(self) -> Timeline {
+    self.timeline
+  }
+
+  fn record(&mut self, kind: EventKind) {
+    if self.timeline.events.len() < self.max_events {
+      self.timeline.events.push(Event { time: self.timeline.instructions, kind });
+    } else {
+      self.timeline.truncated = true;
+    }
+  }
+}
+impl Hooks for TimelineRecorder {
+  fn after(&mut self, program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    let condition = state.cell(0);
+    match *op {
+      Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) if condition != 0 => {
+        self.record(EventKind::LoopEnter(program_counter));
+      },
+      Opcode::LoopEnd(start) | Opcode::MoveLoopEnd(_, start) if condition == 0 => {
+        self.record(EventKind::LoopExit(start));
+      },
+      Opcode::Input(offset) => self.record(EventKind::Input(state.cell(offset))),
+      Opcode::Output(offset) => self.record(EventKind::Output(state.cell(offset))),
+      _ => (),
+    }
+    self.timeline.instructions += 1;
+    ControlFlow::Continue(())
+  }
+}
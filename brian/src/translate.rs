@@ -0,0 +1,411 @@
+//! Translation of compiled programs into standalone C, Rust and WebAssembly programs
+//!
+//! Translated programs keep the interpreter's default semantics: wrapping 8-bit cells on a wrapping tape of
+//! 65536 cells, with cells left unchanged on EOF. Output is buffered and flushed before reading input,
+//! on [`Opcode::Flush`] and at the end
+
+use std::fmt::Write as _;
+use crate::{Opcode, Program};
+
+/// Number of cells of the tape, matching the interpreter
+const TAPE_SIZE: i64 = 1 << 16;
+
+/// Offset `rel` as the equivalent non-negative offset on the wrapping tape
+fn wrap(rel: isize) -> i64 {
+  (rel as i64).rem_euclid(TAPE_SIZE)
+}
+
+/// Translate opcodes into structured statements, `emit` is called with the loop depth of every statement
+///
+/// Loop jumps map to `while` loops: moves of `MoveLoopStart` happen once before the loop, moves of `MoveLoopEnd`
+/// at the end of every iteration
+fn structured(program: &Program, mut emit: impl FnMut(usize, Statement)) {
+  let mut depth = 0;
+  for op in program.opcodes() {
+    match op {
+      Opcode::LoopStart(_) => {
+        emit(depth, Statement::LoopStart);
+        depth += 1;
+      },
+      Opcode::MoveLoopStart(rel, _) => {
+        emit(depth, Statement::Op(&Opcode::MovePointer(*rel)));
+        emit(depth, Statement::LoopStart);
+        depth += 1;
+      },
+      Opcode::LoopEnd(_) => {
+        depth -= 1;
+        emit(depth, Statement::LoopEnd);
+      },
+      Opcode::MoveLoopEnd(rel, _) => {
+        emit(depth, Statement::Op(&Opcode::MovePointer(*rel)));
+        depth -= 1;
+        emit(depth, Statement::LoopEnd);
+      },
+      Opcode::Fused(ops) => ops.iter().for_each(|op| emit(depth, Statement::Op(op))),
+      Opcode::Eof => break,
+      op => emit(depth, Statement::Op(op)),
+    }
+  }
+}
+
+/// Statement of a structured program, see [`structured`]
+enum Statement<'a> {
+  LoopStart,
+  LoopEnd,
+  /// Any opcode but loop jumps and `Eof`
+  Op(&'a Opcode),
+}
+
+/// Translate a program into C source code, reading stdin and writing stdout
+pub fn to_c(program: &Program) -> String {
+  let mut code = String::from("\
+#include <stdint.h>
+#include <stdio.h>
+
+static uint8_t t[65536];
+
+int main(void) {
+  uint16_t p = 0;
+  int c;
+");
+  let cell = |rel: isize| match rel {
+    0 => "t[p]".to_string(),
+    rel => format!("t[(uint16_t)(p + {})]", wrap(rel)),
+  };
+  structured(program, |depth, statement| {
+    let indent = "  ".repeat(depth + 1);
+    match statement {
+      Statement::LoopStart => writeln!(code, "{indent}while (t[p]) {{"),
+      Statement::LoopEnd => writeln!(code, "{indent}}}"),
+      Statement::Op(op) => match op {
+        Opcode::Increment(rel, value) => writeln!(code, "{indent}{} += {};", cell(*rel), *value as u8),
+        Opcode::Set(rel, value) => writeln!(code, "{indent}{} = {value};", cell(*rel)),
+        Opcode::MovePointer(rel) => writeln!(code, "{indent}p += {};", wrap(*rel)),
+        Opcode::ScanZero(rel) => writeln!(code, "{indent}while (t[p]) p += {};", wrap(*rel)),
+        Opcode::Move(targets) => {
+          writeln!(code, "{indent}if (t[p]) {{").unwrap();
+          for &(rel, factor) in &targets[..] {
+            writeln!(code, "{indent}  {} += t[p] * {factor};", cell(rel)).unwrap();
+          }
+          writeln!(code, "{indent}  t[p] = 0;\n{indent}}}")
+        },
+        Opcode::Output(rel) => writeln!(code, "{indent}putchar({});", cell(*rel)),
+        Opcode::Input(rel) => {
+          writeln!(code, "{indent}fflush(stdout);").unwrap();
+          writeln!(code, "{indent}if ((c = getchar()) != EOF) {} = c;", cell(*rel))
+        },
+        Opcode::Flush => writeln!(code, "{indent}fflush(stdout);"),
+        _ => unreachable!("loop jumps are structured"),
+      },
+    }.unwrap();
+  });
+  code.push_str("  (void)c;\n  return 0;\n}\n");
+  code
+}
+
+/// Translate a program into Rust source code, reading stdin and writing stdout
+pub fn to_rust(program: &Program) -> String {
+  let mut code = String::from("\
+#![allow(unused)]
+use std::io::{Read, Write};
+
+fn main() {
+  let mut t = vec![0u8; 65536];
+  let mut p: u16 = 0;
+  let mut input = std::io::stdin().lock().bytes();
+  let mut output = std::io::BufWriter::new(std::io::stdout().lock());
+");
+  let cell = |rel: isize| match rel {
+    0 => "t[p as usize]".to_string(),
+    rel => format!("t[p.wrapping_add({}) as usize]", wrap(rel)),
+  };
+  structured(program, |depth, statement| {
+    let indent = "  ".repeat(depth + 1);
+    match statement {
+      Statement::LoopStart => writeln!(code, "{indent}while t[p as usize] != 0 {{"),
+      Statement::LoopEnd => writeln!(code, "{indent}}}"),
+      Statement::Op(op) => match op {
+        Opcode::Increment(rel, value) => {
+          let cell = cell(*rel);
+          writeln!(code, "{indent}{cell} = {cell}.wrapping_add({});", *value as u8)
+        },
+        Opcode::Set(rel, value) => writeln!(code, "{indent}{} = {value};", cell(*rel)),
+        Opcode::MovePointer(rel) => writeln!(code, "{indent}p = p.wrapping_add({});", wrap(*rel)),
+        Opcode::ScanZero(rel) => {
+          writeln!(code, "{indent}while t[p as usize] != 0 {{ p = p.wrapping_add({}); }}", wrap(*rel))
+        },
+        Opcode::Move(targets) => {
+          writeln!(code, "{indent}let value = t[p as usize];").unwrap();
+          writeln!(code, "{indent}if value != 0 {{").unwrap();
+          for &(rel, factor) in &targets[..] {
+            let cell = cell(rel);
+            writeln!(code, "{indent}  {cell} = {cell}.wrapping_add(value.wrapping_mul({factor}));").unwrap();
+          }
+          writeln!(code, "{indent}  t[p as usize] = 0;\n{indent}}}")
+        },
+        Opcode::Output(rel) => writeln!(code, "{indent}output.write_all(&[{}]).unwrap();", cell(*rel)),
+        Opcode::Input(rel) => {
+          writeln!(code, "{indent}output.flush().unwrap();").unwrap();
+          writeln!(code, "{indent}if let Some(Ok(byte)) = input.next() {{ {} = byte; }}", cell(*rel))
+        },
+        Opcode::Flush => writeln!(code, "{indent}output.flush().unwrap();"),
+        _ => unreachable!("loop jumps are structured"),
+      },
+    }.unwrap();
+  });
+  code.push_str("  output.flush().unwrap();\n}\n");
+  code
+}
+
+/// WebAssembly encoding helpers
+mod wasm {
+  pub const BLOCK: u8 = 0x02;
+  pub const LOOP: u8 = 0x03;
+  pub const IF: u8 = 0x04;
+  pub const END: u8 = 0x0b;
+  pub const BR: u8 = 0x0c;
+  pub const BR_IF: u8 = 0x0d;
+  pub const CALL: u8 = 0x10;
+  pub const DROP: u8 = 0x1a;
+  pub const LOCAL_GET: u8 = 0x20;
+  pub const LOCAL_SET: u8 = 0x21;
+  pub const LOCAL_TEE: u8 = 0x22;
+  pub const I32_LOAD: u8 = 0x28;
+  pub const I32_LOAD8_U: u8 = 0x2d;
+  pub const I32_STORE: u8 = 0x36;
+  pub const I32_STORE8: u8 = 0x3a;
+  pub const I32_CONST: u8 = 0x41;
+  pub const I32_EQZ: u8 = 0x45;
+  pub const I32_EQ: u8 = 0x46;
+  pub const I32_ADD: u8 = 0x6a;
+  pub const I32_MUL: u8 = 0x6c;
+  pub const I32_AND: u8 = 0x71;
+  /// Block type of blocks without results
+  pub const EMPTY: u8 = 0x40;
+  pub const TYPE_I32: u8 = 0x7f;
+
+  pub fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+      let byte = (value & 0x7f) as u8;
+      value >>= 7;
+      if value == 0 {
+        out.push(byte);
+        break
+      }
+      out.push(byte | 0x80);
+    }
+  }
+
+  pub fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+      let byte = (value & 0x7f) as u8;
+      value >>= 7;
+      if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+        out.push(byte);
+        break
+      }
+      out.push(byte | 0x80);
+    }
+  }
+
+  /// Length-prefixed byte vector
+  pub fn bytes(out: &mut Vec<u8>, data: &[u8]) {
+    unsigned(out, data.len() as u64);
+    out.extend_from_slice(data);
+  }
+
+  pub fn section(out: &mut Vec<u8>, id: u8, data: &[u8]) {
+    out.push(id);
+    bytes(out, data);
+  }
+}
+
+/// Address of the fd_read/fd_write I/O vector, right after the tape
+const IOVEC: i32 = 1 << 16;
+/// Address where fd_read/fd_write store the number of bytes transferred
+const TRANSFERRED: i32 = IOVEC + 8;
+/// Address of the output buffer, also used to read input bytes into once it was flushed
+const BUFFER: i32 = IOVEC + 16;
+/// Size of the output buffer
+const BUFFER_SIZE: i32 = 4096;
+
+/// Function bodies being assembled, with the local variables shared by all of them
+struct Assembler(Vec<u8>);
+impl Assembler {
+  //Locals of the entry point
+  const P: u8 = 0;
+  const ADDRESS: u8 = 1;
+  const VALUE: u8 = 2;
+  const BUFFERED: u8 = 3;
+  //Function indices
+  const FD_READ: u8 = 0;
+  const FD_WRITE: u8 = 1;
+  const FLUSH: u8 = 3;
+
+  fn op(&mut self, op: u8) -> &mut Self {
+    self.0.push(op);
+    self
+  }
+
+  fn op_with(&mut self, op: u8, immediate: u8) -> &mut Self {
+    self.0.extend_from_slice(&[op, immediate]);
+    self
+  }
+
+  fn i32_const(&mut self, value: i64) -> &mut Self {
+    self.0.push(wasm::I32_CONST);
+    wasm::signed(&mut self.0, value as i32 as i64);
+    self
+  }
+
+  /// Memory access with natural alignment, `align` being its log2
+  fn memory(&mut self, op: u8, align: u8) -> &mut Self {
+    self.0.extend_from_slice(&[op, align, 0]);
+    self
+  }
+
+  /// Push the tape address at `rel` from the pointer
+  fn address(&mut self, rel: isize) -> &mut Self {
+    self.op_with(wasm::LOCAL_GET, Self::P);
+    if rel != 0 {
+      self.i32_const(wrap(rel)).op(wasm::I32_ADD).i32_const(TAPE_SIZE - 1).op(wasm::I32_AND);
+    }
+    self
+  }
+
+  /// Push the value of the current cell
+  fn current(&mut self) -> &mut Self {
+    self.op_with(wasm::LOCAL_GET, Self::P).memory(wasm::I32_LOAD8_U, 0)
+  }
+
+  fn move_pointer(&mut self, rel: isize) -> &mut Self {
+    self.address(rel).op_with(wasm::LOCAL_SET, Self::P)
+  }
+
+  /// Add the value left on the stack by `value` to the cell at `rel`
+  fn add(&mut self, rel: isize, value: impl FnOnce(&mut Self)) -> &mut Self {
+    self.address(rel).op_with(wasm::LOCAL_TEE, Self::ADDRESS);
+    self.op_with(wasm::LOCAL_GET, Self::ADDRESS).memory(wasm::I32_LOAD8_U, 0);
+    value(self);
+    self.op(wasm::I32_ADD).memory(wasm::I32_STORE8, 0)
+  }
+
+  /// Write out and empty the output buffer
+  fn flush(&mut self) -> &mut Self {
+    self.op_with(wasm::LOCAL_GET, Self::BUFFERED).op_with(wasm::CALL, Self::FLUSH);
+    self.i32_const(0).op_with(wasm::LOCAL_SET, Self::BUFFERED)
+  }
+
+  fn statement(&mut self, statement: Statement) {
+    match statement {
+      Statement::LoopStart => {
+        self.op_with(wasm::BLOCK, wasm::EMPTY).current().op(wasm::I32_EQZ).op_with(wasm::BR_IF, 0);
+        self.op_with(wasm::LOOP, wasm::EMPTY);
+      },
+      Statement::LoopEnd => {
+        self.current().op_with(wasm::BR_IF, 0).op(wasm::END).op(wasm::END);
+      },
+      Statement::Op(op) => match op {
+        Opcode::Increment(rel, value) => {
+          self.add(*rel, |x| { x.i32_const(*value as u8 as i64); });
+        },
+        Opcode::Set(rel, value) => {
+          self.address(*rel).i32_const(*value as i64).memory(wasm::I32_STORE8, 0);
+        },
+        Opcode::MovePointer(rel) => {
+          self.move_pointer(*rel);
+        },
+        Opcode::ScanZero(rel) => {
+          self.op_with(wasm::BLOCK, wasm::EMPTY).op_with(wasm::LOOP, wasm::EMPTY);
+          self.current().op(wasm::I32_EQZ).op_with(wasm::BR_IF, 1);
+          self.move_pointer(*rel).op_with(wasm::BR, 0).op(wasm::END).op(wasm::END);
+        },
+        Opcode::Move(targets) => {
+          self.current().op_with(wasm::LOCAL_TEE, Self::VALUE).op_with(wasm::IF, wasm::EMPTY);
+          for &(rel, factor) in &targets[..] {
+            self.add(rel, |x| {
+              x.op_with(wasm::LOCAL_GET, Self::VALUE).i32_const(factor as i64).op(wasm::I32_MUL);
+            });
+          }
+          self.address(0).i32_const(0).memory(wasm::I32_STORE8, 0).op(wasm::END);
+        },
+        Opcode::Output(rel) => {
+          self.i32_const(BUFFER as i64).op_with(wasm::LOCAL_GET, Self::BUFFERED).op(wasm::I32_ADD);
+          self.address(*rel).memory(wasm::I32_LOAD8_U, 0).memory(wasm::I32_STORE8, 0);
+          self.op_with(wasm::LOCAL_GET, Self::BUFFERED).i32_const(1).op(wasm::I32_ADD);
+          self.op_with(wasm::LOCAL_TEE, Self::BUFFERED).i32_const(BUFFER_SIZE as i64).op(wasm::I32_EQ);
+          self.op_with(wasm::IF, wasm::EMPTY).flush().op(wasm::END);
+        },
+        Opcode::Input(rel) => {
+          //The buffer is empty after flushing, so the byte is read into it
+          self.flush();
+          self.i32_const((IOVEC + 4) as i64).i32_const(1).memory(wasm::I32_STORE, 2);
+          self.i32_const(0).i32_const(IOVEC as i64).i32_const(1).i32_const(TRANSFERRED as i64);
+          self.op_with(wasm::CALL, Self::FD_READ).op(wasm::DROP);
+          self.i32_const(TRANSFERRED as i64).memory(wasm::I32_LOAD, 2).op_with(wasm::IF, wasm::EMPTY);
+          self.address(*rel).i32_const(BUFFER as i64).memory(wasm::I32_LOAD8_U, 0).memory(wasm::I32_STORE8, 0);
+          self.op(wasm::END);
+        },
+        Opcode::Flush => {
+          self.flush();
+        },
+        _ => unreachable!("loop jumps are structured"),
+      },
+    }
+  }
+}
+
+/// Translate a program into a WebAssembly module for WASI, reading stdin and writing stdout
+///
+/// The module exports its memory and a `_start` function, so it runs as is on WASI runtimes
+/// (e.g. `wasmtime program.wasm`)
+pub fn to_wasm(program: &Program) -> Vec<u8> {
+  //Types: fd_read/fd_write, _start, flush
+  let mut types = vec![3];
+  types.extend_from_slice(&[0x60, 4, wasm::TYPE_I32, wasm::TYPE_I32, wasm::TYPE_I32, wasm::TYPE_I32]);
+  types.extend_from_slice(&[1, wasm::TYPE_I32]);
+  types.extend_from_slice(&[0x60, 0, 0]);
+  types.extend_from_slice(&[0x60, 1, wasm::TYPE_I32, 0]);
+
+  let mut imports = vec![2];
+  for name in ["fd_read", "fd_write"] {
+    wasm::bytes(&mut imports, b"wasi_snapshot_preview1");
+    wasm::bytes(&mut imports, name.as_bytes());
+    imports.extend_from_slice(&[0x00, 0]);
+  }
+
+  let functions = [2, 1, 2];
+  //The tape takes the first page, the I/O vector and the buffer the second one
+  let memory = [1, 0x00, 2];
+  let mut exports = vec![2];
+  wasm::bytes(&mut exports, b"memory");
+  exports.extend_from_slice(&[0x02, 0]);
+  wasm::bytes(&mut exports, b"_start");
+  exports.extend_from_slice(&[0x00, 2]);
+
+  //_start: locals p, address, value, buffered
+  let mut start = Assembler(vec![1, 4, wasm::TYPE_I32]);
+  start.i32_const(IOVEC as i64).i32_const(BUFFER as i64).memory(wasm::I32_STORE, 2);
+  structured(program, |_, statement| start.statement(statement));
+  start.flush().op(wasm::END);
+
+  //flush(length): write `length` bytes from the buffer to stdout
+  let mut flush = Assembler(vec![0]);
+  flush.op_with(wasm::LOCAL_GET, 0).op(wasm::I32_EQZ).op_with(wasm::IF, wasm::EMPTY).op(0x0f).op(wasm::END);
+  flush.i32_const((IOVEC + 4) as i64).op_with(wasm::LOCAL_GET, 0).memory(wasm::I32_STORE, 2);
+  flush.i32_const(1).i32_const(IOVEC as i64).i32_const(1).i32_const(TRANSFERRED as i64);
+  flush.op_with(wasm::CALL, Assembler::FD_WRITE).op(wasm::DROP).op(wasm::END);
+
+  let mut code = vec![2];
+  wasm::bytes(&mut code, &start.0);
+  wasm::bytes(&mut code, &flush.0);
+
+  let mut module = b"\0asm\x01\0\0\0".to_vec();
+  wasm::section(&mut module, 1, &types);
+  wasm::section(&mut module, 2, &imports);
+  wasm::section(&mut module, 3, &functions);
+  wasm::section(&mut module, 5, &memory);
+  wasm::section(&mut module, 7, &exports);
+  wasm::section(&mut module, 10, &code);
+  module
+}
@@ -0,0 +1,160 @@
+//! Bounded verification of small programs
+//!
+//! Exhaustively explores every execution of a program for all inputs up to a given length,
+//! which is enough to prove simple properties of small programs without an SMT solver
+
+use std::collections::HashMap;
+use crate::{brainfuck::MEMORY_MASK, CompileError, Opcode, Program};
+
+/// Property to check
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Property {
+  /// The pointer never moves left of the cell it started at
+  PointerNeverLeftOfStart,
+  /// The program halts within the given number of steps (one step per brainfuck command)
+  TerminatesWithin(usize),
+}
+
+/// Result of a bounded check
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+  /// The property holds for all explored executions
+  Holds {
+    /// Number of distinct executions explored
+    executions: usize,
+  },
+  /// The property is violated for the given input
+  Violated {
+    /// Input bytes read before the violation (reads past its end see EOF)
+    input: Vec<u8>,
+    /// Step at which the violation occurred
+    step: usize,
+  },
+  /// Some execution exceeded the step bound before the property could be decided
+  Unknown {
+    /// Input bytes read by the undecided execution
+    input: Vec<u8>,
+  },
+}
+
+/// Exhaustive bounded model checker
+#[derive(Clone, Debug)]
+pub struct BoundedChecker {
+  /// Maximum number of input bytes; executions reading past it see EOF
+  pub max_input_length: usize,
+  /// Byte values tried for every input position
+  pub input_alphabet: Vec<u8>,
+  /// Maximum number of steps explored per execution
+  pub max_steps: usize,
+}
+
+#[derive(Clone)]
+struct Execution {
+  tape: HashMap<usize, u8>,
+  /// Unmasked pointer, relative to the starting cell
+  pointer: isize,
+  program_counter: usize,
+  steps: usize,
+  input: Vec<u8>,
+  eof: bool,
+}
+impl Execution {
+  fn cell(&mut self, offset: isize) -> &mut u8 {
+    let address = (self.pointer + offset) as usize & MEMORY_MASK;
+    self.tape.entry(address).or_insert(0)
+  }
+}
+
+impl BoundedChecker {
+  /// Create a checker exploring all byte values for inputs of up to `max_input_length` bytes
+  pub fn new(max_input_length: usize, max_steps: usize) -> Self {
+    Self {
+      max_input_length,
+      input_alphabet: (0..=255).collect(),
+      max_steps,
+    }
+  }
+
+  /// Check whether the property holds for every execution of the program
+  ///
+  /// EOF leaves the cell unchanged, matching the interpreter. Fails if the brackets are unbalanced
+  pub fn check(&self, code: &str, property: Property) -> Result<Verdict, CompileError> {
+    let program = Program::compile_without_optimizations(code)?;
+    let ops = program.opcodes();
+    let max_steps = match property {
+      Property::TerminatesWithin(steps) => steps.min(self.max_steps),
+      Property::PointerNeverLeftOfStart => self.max_steps,
+    };
+    let mut pending = vec![Execution {
+      tape: HashMap::new(),
+      pointer: 0,
+      program_counter: 0,
+      steps: 0,
+      input: Vec::new(),
+      eof: false,
+    }];
+    let mut executions = 0;
+    'explore: while let Some(mut execution) = pending.pop() {
+      loop {
+        if let Opcode::Eof = ops[execution.program_counter] {
+          executions += 1;
+          continue 'explore
+        }
+        if execution.steps == max_steps {
+          return Ok(match property {
+            Property::TerminatesWithin(steps) if steps <= self.max_steps => {
+              Verdict::Violated { input: execution.input, step: execution.steps }
+            },
+            _ => Verdict::Unknown { input: execution.input },
+          })
+        }
+        execution.steps += 1;
+        match ops[execution.program_counter] {
+          Opcode::Increment(offset, value) => {
+            let cell = execution.cell(offset);
+            *cell = cell.wrapping_add(value as u8);
+          },
+          Opcode::Set(offset, value) => {
+            *execution.cell(offset) = value;
+          },
+          Opcode::MovePointer(offset) => {
+            execution.pointer += offset;
+            if property == Property::PointerNeverLeftOfStart && execution.pointer < 0 {
+              return Ok(Verdict::Violated { input: execution.input, step: execution.steps })
+            }
+          },
+          Opcode::LoopStart(end) => {
+            if *execution.cell(0) == 0 {
+              execution.program_counter = end;
+            }
+          },
+          Opcode::LoopEnd(start) => {
+            if *execution.cell(0) != 0 {
+              execution.program_counter = start;
+            }
+          },
+          Opcode::Output(_) | Opcode::Flush => (),
+          Opcode::Input(offset) => {
+            if !execution.eof && execution.input.len() < self.max_input_length {
+              //Fork for every possible byte, the current execution continues with EOF
+              for &byte in &self.input_alphabet {
+                let mut fork = execution.clone();
+                *fork.cell(offset) = byte;
+                fork.input.push(byte);
+                fork.program_counter += 1;
+                pending.push(fork);
+              }
+            }
+            execution.eof = true;
+          },
+          Opcode::ScanZero(_) | Opcode::Move(_) | Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) | Opcode::Fused(_) => {
+            unreachable!("unoptimized programs contain no scans, moves or superinstructions")
+          },
+          Opcode::Eof => unreachable!(),
+        }
+        execution.program_counter += 1;
+      }
+    }
+    Ok(Verdict::Holds { executions })
+  }
+}
@@ -0,0 +1,108 @@
+//! Linter diagnostics, bounded verification and symbolic input search on small programs
+
+use std::ops::Range;
+use brian::{
+  lint::{check, Lint, LintLevel, LintOptions, Severity},
+  verification::{BoundedChecker, Property, Verdict},
+  CommentStyle, CompileError, CompileOptions, Program, Token,
+};
+
+fn lints(code: &str, options: &LintOptions) -> Vec<(Lint, Severity, Range<usize>)> {
+  check(code, options).into_iter().map(|x| (x.lint, x.severity, x.span)).collect()
+}
+
+#[test]
+fn infinite_loop_lint() {
+  let options = LintOptions::default();
+  assert_eq!(lints("+[].", &options), [(Lint::InfiniteLoop, Severity::Warning, 1..3)]);
+  //Spans point into the source, comments included
+  assert_eq!(lints(",[-]+ loop [ ]", &options), [(Lint::InfiniteLoop, Severity::Warning, 11..14)]);
+  //Loops that may be skipped, change their condition cell or move away from it end
+  assert_eq!(lints(",[.]", &options), []);
+  assert_eq!(lints("+[-].", &options), []);
+  assert_eq!(lints("+[>]", &options), []);
+}
+
+#[test]
+fn unread_write_lint() {
+  let options = LintOptions::default();
+  assert_eq!(lints("+.>+", &options), [(Lint::UnreadWrite, Severity::Warning, 3..4)]);
+  assert_eq!(lints("+>++", &options), [
+    (Lint::UnreadWrite, Severity::Warning, 0..1),
+    (Lint::UnreadWrite, Severity::Warning, 2..4),
+  ]);
+  assert_eq!(lints("+>++<.>.", &options), []);
+  let diagnostic = &check("+.>+", &options)[0];
+  assert_eq!(diagnostic.to_string(), format!("warning[unread-write]: {}", diagnostic.message));
+}
+
+#[test]
+fn lint_levels() {
+  let code = ",[-]+[]>+";
+  let strict = LintOptions { strict: true, ..Default::default() };
+  assert_eq!(lints(code, &strict), [
+    (Lint::InfiniteLoop, Severity::Error, 5..7),
+    (Lint::UnreadWrite, Severity::Error, 8..9),
+  ]);
+  //Levels of individual lints override the default and strict mode
+  let options = LintOptions { strict: true, levels: [(Lint::UnreadWrite, LintLevel::Warn)].into() };
+  assert_eq!(lints(code, &options), [
+    (Lint::InfiniteLoop, Severity::Error, 5..7),
+    (Lint::UnreadWrite, Severity::Warning, 8..9),
+  ]);
+  let options = LintOptions { levels: [(Lint::InfiniteLoop, LintLevel::Allow)].into(), ..Default::default() };
+  assert_eq!(lints(code, &options), [(Lint::UnreadWrite, Severity::Warning, 8..9)]);
+  assert_eq!(options.level(Lint::InfiniteLoop), LintLevel::Allow);
+  assert_eq!(options.level(Lint::UnreadWrite), LintLevel::Warn);
+  assert_eq!("infinite-loop".parse(), Ok(Lint::InfiniteLoop));
+  assert!("unknown".parse::<Lint>().is_err());
+  //Unbalanced programs aren't analyzed
+  assert_eq!(lints("+[[]", &strict), []);
+}
+
+#[test]
+fn denied_lints_fail_compilation() {
+  let code = ",[-]+[]>+";
+  let deny = |lint| CompileOptions { lints: Some(LintOptions { levels: [(lint, LintLevel::Deny)].into(), ..Default::default() }), ..Default::default() };
+  let Err(CompileError::DeniedLints(diagnostics)) = Program::compile_with_options(code, &deny(Lint::InfiniteLoop)) else {
+    panic!("infinite loop is denied")
+  };
+  assert_eq!(diagnostics.iter().map(|x| (x.lint, x.span.clone())).collect::<Vec<_>>(), [(Lint::InfiniteLoop, 5..7)]);
+  assert!(CompileError::DeniedLints(diagnostics).to_string().ends_with(" at byte 5"));
+  //Warnings don't fail, and neither do lints without options
+  let warn = CompileOptions { lints: Some(LintOptions::default()), ..Default::default() };
+  assert!(Program::compile_with_options(code, &warn).is_ok());
+  assert!(Program::compile(code).is_ok());
+  let strict = CompileOptions { lints: Some(LintOptions { strict: true, ..Default::default() }), ..Default::default() };
+  assert!(matches!(Program::compile_with_options(code, &strict), Err(CompileError::DeniedLints(x)) if x.len() == 2));
+  //Comments are blanked out before linting, keeping the spans
+  let comments = CompileOptions { comments: CommentStyle::Semicolon, ..deny(Lint::UnreadWrite) };
+  assert_eq!(Program::compile_with_options("+. ;[]>+", &comments).map(|_| ()), Ok(()));
+  assert!(matches!(Program::compile_with_options("+>+ ;[]", &comments), Err(CompileError::DeniedLints(x)) if x.len() == 2 && x[1].span == (2..3)));
+  //Unbalanced brackets are reported first
+  assert_eq!(
+    Program::compile_with_options("+[]>+]", &deny(Lint::UnreadWrite)),
+    Err(CompileError::UnbalancedBrackets { offset: 5, bracket: Token::LoopEnd }),
+  );
+}
+
+#[test]
+fn bounded_verification() {
+  let checker = BoundedChecker::new(1, 100);
+  assert_eq!(checker.check("<", Property::PointerNeverLeftOfStart), Ok(Verdict::Violated { input: vec![], step: 1 }));
+  assert!(matches!(checker.check(",[<]", Property::PointerNeverLeftOfStart), Ok(Verdict::Violated { input, .. }) if input != [0]));
+  //Every input byte, and EOF
+  assert_eq!(checker.check(">,<", Property::PointerNeverLeftOfStart), Ok(Verdict::Holds { executions: 257 }));
+  assert_eq!(checker.check(",[>]", Property::TerminatesWithin(50)), Ok(Verdict::Holds { executions: 257 }));
+  assert!(matches!(checker.check(",[]", Property::TerminatesWithin(50)), Ok(Verdict::Violated { input, .. }) if input != [0]));
+  assert!(checker.check("[", Property::PointerNeverLeftOfStart).is_err());
+}
+
+#[cfg(feature = "symbolic")]
+#[test]
+fn symbolic_input_search() {
+  use brian::symbolic::{Search, SymbolicExecutor};
+  let executor = SymbolicExecutor { max_steps: 1000, max_paths: 100 };
+  assert_eq!(executor.find_input(",+++.", b"d"), Ok(Search::Found(vec![b'a'])));
+  assert_eq!(executor.find_input(",[-]+.", b"d"), Ok(Search::Impossible));
+}
@@ -0,0 +1,296 @@
+//! Backend parity harness: every execution backend must behave identically under the same sandbox
+//!
+//! Runs the bundled programs and generated programs through each backend with the same fuel and input, and compares
+//! fingerprints of the outcome, including runs cut short by the fuel limit. Adding a backend only takes an entry in
+//! `BACKENDS`
+
+use std::{future::Future, hash::{DefaultHasher, Hash, Hasher}, pin::pin, task::{Context, Poll, Waker}};
+use brian::{
+  generator::ProgramGenerator, programs, run_with_fuel, stress::Verification, Brainfuck, BrainfuckState, CompileOptions, EofMode,
+  Opcode, PackedPosition, Profile, Program, SliceEnd, StreamIo,
+};
+
+/// Limits and input shared by all backends for one run
+struct Sandbox<'a> {
+  fuel: usize,
+  input: &'a [u8],
+}
+
+/// Everything observable about a run
+#[derive(Debug, PartialEq, Eq)]
+struct Fingerprint {
+  halted: bool,
+  output: Vec<u8>,
+  pointer: usize,
+  tape_hash: u64,
+}
+impl Fingerprint {
+  fn new(halted: bool, output: Vec<u8>, state: &BrainfuckState) -> Self {
+    let mut hasher = DefaultHasher::new();
+    state.tape().hash(&mut hasher);
+    Self { halted, output, pointer: state.pointer_address(), tape_hash: hasher.finish() }
+  }
+}
+
+type Backend = (&'static str, fn(&Program, &Sandbox) -> Fingerprint);
+
+/// Reference backend, every other one is compared against it
+fn enum_interpreter(program: &Program, sandbox: &Sandbox) -> Fingerprint {
+  let mut state = Box::new(BrainfuckState::new());
+  let mut io = StreamIo::new(sandbox.input, Vec::new());
+  let halted = run_with_fuel(program, &mut state, &mut io, sandbox.fuel);
+  Fingerprint::new(halted, io.output, &state)
+}
+
+fn packed_interpreter(program: &Program, sandbox: &Sandbox) -> Fingerprint {
+  let program = program.pack().expect("test programs fit the packed form");
+  let mut state = Box::new(BrainfuckState::new());
+  let mut io = StreamIo::new(sandbox.input, Vec::new());
+  let halted = program.resume(&mut state, &mut io, sandbox.fuel, &mut PackedPosition::default()) == SliceEnd::Halted;
+  Fingerprint::new(halted, io.output, &state)
+}
+
+const BACKENDS: &[Backend] = &[("packed", packed_interpreter)];
+
+/// Assert that every backend matches the reference on `program`
+fn assert_parity(name: &str, program: &Program, sandbox: &Sandbox) {
+  let expected = enum_interpreter(program, sandbox);
+  for (backend, run) in BACKENDS {
+    assert_eq!(run(program, sandbox), expected, "{backend} diverges on {name} with fuel {}", sandbox.fuel);
+  }
+}
+
+#[test]
+fn bundled_programs() {
+  let hello: String = programs::HELLO_WORLD.source.chars().filter(|x| "+-<>[].,".contains(*x)).collect();
+  let dbfi_input = format!("{hello}!");
+  for bundled in programs::ALL {
+    let input = match bundled.name {
+      "dbfi" => dbfi_input.as_bytes(),
+      _ => b"Hello, World!",
+    };
+    for program in [Program::compile(bundled.source).unwrap(), Program::compile_without_optimizations(bundled.source).unwrap()] {
+      //Small budgets cut runs short in the middle of loops and scans
+      for fuel in [1_000, 100_000, 2_000_000] {
+        assert_parity(bundled.name, &program, &Sandbox { fuel, input });
+      }
+    }
+  }
+}
+
+#[test]
+fn generated_programs() {
+  let generator = ProgramGenerator { io_density: 0.05, ..ProgramGenerator::new(200) };
+  for seed in 0..300 {
+    let code = generator.generate(seed);
+    let sandbox = Sandbox { fuel: 20_000, input: b"\x00\x01\x7f\x80\xff brian" };
+    let program = Program::compile(&code).unwrap();
+    assert_eq!(program.verify(), Ok(()), "seed {seed}");
+    assert_parity(&format!("seed {seed}"), &program, &sandbox);
+  }
+}
+
+#[test]
+fn superinstructions() {
+  let generator = ProgramGenerator::new(200);
+  let bundled = programs::ALL.iter().map(|x| (x.name.to_string(), x.source.to_string()));
+  let generated = (0..100).map(|seed| (format!("seed {seed}"), generator.generate(seed)));
+  let mut fused_any = false;
+  for (name, code) in bundled.chain(generated) {
+    let program = Program::compile(&code).unwrap();
+    //A uniform profile ranks candidates by their number of sites, so plenty of patterns get fused without running anything
+    let profile = Profile { counts: vec![1; program.opcodes().len()] };
+    for fused in [program.fuse_superinstructions(&profile, 8).unwrap(), program.fuse_hot_loop_brackets(&profile).unwrap()] {
+      assert_eq!(fused.verify(), Ok(()), "{name}");
+      fused_any |= fused.opcodes().iter().any(|x| matches!(x, Opcode::Fused(_)));
+      for fuel in [1_000, 100_000, 2_000_000] {
+        let sandbox = Sandbox { fuel, input: b"Hello, World!" };
+        assert_parity(&name, &fused, &sandbox);
+        //Superinstructions take a single step, so the fused program finishes whenever the original one does
+        let expected = enum_interpreter(&program, &sandbox);
+        if expected.halted {
+          assert_eq!(enum_interpreter(&fused, &sandbox), expected, "fusing changes the outcome of {name}");
+        }
+      }
+    }
+  }
+  assert!(fused_any);
+  assert_eq!(Program::compile("+").unwrap().fuse_superinstructions(&Profile::default(), 8), None);
+  assert_eq!(Program::compile("+").unwrap().fuse_hot_loop_brackets(&Profile::default()), None);
+}
+
+#[test]
+fn recompile_with_profile() {
+  for code in [programs::HELLO_WORLD.source, "++++[>+++++<-]>[>+>+<<-]>>>+>+>+<<<<<[>]<[<]>[-]", "++++++++[>++++[>++>+++<<-]>-]>>."] {
+    let program = Program::compile(code).unwrap();
+    let mut bf = Brainfuck::new();
+    bf.load_program(program.clone());
+    let mut expected = Vec::new();
+    let profile = bf.run_profiled(&mut expected).unwrap();
+    for superinstructions in [0, 8] {
+      let recompiled = program.recompile_with_profile(&profile, superinstructions).unwrap();
+      let mut state = Box::new(BrainfuckState::new());
+      let mut io = StreamIo::new(&b""[..], Vec::new());
+      recompiled.run(&mut state, &mut io);
+      assert_eq!(io.output, expected, "recompiling {code} with {superinstructions} superinstructions");
+      assert_eq!((state.pointer_address(), state.tape()), (bf.state().pointer_address(), bf.state().tape()));
+    }
+  }
+  //A profile of another program is rejected instead of indexing past its counts
+  let program = Program::compile("+[>+<-]").unwrap();
+  assert_eq!(Profile::default().hot_loops(&program), Vec::new());
+  assert!(program.recompile_with_profile(&Profile::default(), 8).is_none());
+}
+
+#[test]
+fn sliced_runs() {
+  //Another EOF mode keeps `into_future` off the packed form, so the enum interpreter is paused and resumed
+  for code in [programs::HELLO_WORLD.source, "++++[>+++++<-]>[>+>+<<-]>>>+>+>+<<<<<[>]<[<]>[-]"] {
+    let mut bf = Brainfuck::new();
+    bf.compile(code).unwrap();
+    bf.set_eof_mode(EofMode::Zero);
+    let mut future = pin!(bf.clone().into_future(StreamIo::new(&b""[..], Vec::new()), 3));
+    let mut cx = Context::from_waker(Waker::noop());
+    let (sliced, io, result) = loop {
+      if let Poll::Ready(output) = future.as_mut().poll(&mut cx) { break output }
+    };
+    assert_eq!(result, Ok(()));
+    let mut output = Vec::new();
+    bf.run_with_streams(&b""[..], &mut output).unwrap();
+    assert_eq!((io.output, sliced.state().pointer_address()), (output, bf.state().pointer_address()));
+    assert_eq!(sliced.state().tape(), bf.state().tape());
+  }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn blocking_future() {
+  use std::{io::Read, sync::mpsc, thread, time::Duration};
+  //Blocks until a byte is sent, EOF once the sender is gone
+  struct ChannelInput(mpsc::Receiver<u8>);
+  impl Read for ChannelInput {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+      match (buffer.first_mut(), self.0.recv()) {
+        (Some(first), Ok(byte)) => {
+          *first = byte;
+          Ok(1)
+        },
+        _ => Ok(0),
+      }
+    }
+  }
+  let mut bf = Brainfuck::new();
+  bf.compile(",+.,+.").unwrap();
+  let (sender, receiver) = mpsc::channel();
+  let mut future = pin!(bf.into_blocking_future(StreamIo::new(ChannelInput(receiver), Vec::new()), 3));
+  let mut cx = Context::from_waker(Waker::noop());
+  //The program waits for input on its own thread, not in `poll`
+  assert!(future.as_mut().poll(&mut cx).is_pending());
+  sender.send(b'a').unwrap();
+  sender.send(b'y').unwrap();
+  let (_, io, result) = loop {
+    if let Poll::Ready(output) = future.as_mut().poll(&mut cx) { break output }
+    thread::sleep(Duration::from_millis(1));
+  };
+  assert_eq!((io.output, result), (b"bz".to_vec(), Ok(())));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_prompt() {
+  use std::{cell::{Cell, RefCell}, io, pin::Pin};
+  use brian::{AsyncRead, AsyncWrite, BoundsPolicy};
+  //Has nothing to read until `bytes` is filled in, counting the reads
+  struct PendingInput<'a> { bytes: &'a Cell<&'static [u8]>, reads: &'a Cell<usize> }
+  impl AsyncRead for PendingInput<'_> {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<io::Result<usize>> {
+      self.reads.set(self.reads.get() + 1);
+      match self.bytes.take() {
+        [] => Poll::Pending,
+        bytes => Poll::Ready(io::Read::read(&mut &bytes[..], buffer)),
+      }
+    }
+  }
+  struct SharedOutput<'a>(&'a RefCell<Vec<u8>>);
+  impl AsyncWrite for SharedOutput<'_> {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buffer: &[u8]) -> Poll<io::Result<usize>> {
+      self.0.borrow_mut().extend_from_slice(buffer);
+      Poll::Ready(Ok(buffer.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+      Poll::Ready(Ok(()))
+    }
+  }
+  let mut cx = Context::from_waker(Waker::noop());
+  for unpacked in [false, true] {
+    let (bytes, reads, output) = (Cell::new(&b""[..]), Cell::new(0), RefCell::new(Vec::new()));
+    let mut bf = Brainfuck::new();
+    bf.compile("++++++++[>++++++++<-]>+.,+.").unwrap();
+    //Trapping bounds run on the enum interpreter
+    bf.set_bounds_policy(if unpacked { BoundsPolicy::Trap } else { BoundsPolicy::Wrap });
+    let mut future = pin!(bf.run_async(PendingInput { bytes: &bytes, reads: &reads }, SharedOutput(&output), 3));
+    //The prompt is written before the program waits for input
+    while output.borrow().is_empty() {
+      assert!(future.as_mut().poll(&mut cx).is_pending());
+    }
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert_eq!((output.borrow().as_slice(), reads.get() > 0), (&b"A"[..], true));
+    bytes.set(b"y");
+    let result = loop {
+      if let Poll::Ready(result) = future.as_mut().poll(&mut cx) { break result }
+    };
+    assert!(result.is_ok());
+    assert_eq!(output.borrow().as_slice(), b"Az");
+  }
+  //Programs not reading never wait for input
+  let (bytes, reads, output) = (Cell::new(&b""[..]), Cell::new(0), RefCell::new(Vec::new()));
+  let mut bf = Brainfuck::new();
+  bf.compile("++++++++[>++++++++<-]>+.").unwrap();
+  let mut future = pin!(bf.run_async(PendingInput { bytes: &bytes, reads: &reads }, SharedOutput(&output), 3));
+  while future.as_mut().poll(&mut cx).is_pending() {}
+  assert_eq!((output.borrow().as_slice(), reads.get()), (&b"A"[..], 0));
+}
+
+#[test]
+fn optimizer_equivalence() {
+  let mut bf = Brainfuck::new();
+  bf.set_step_limit(Some(10_000_000));
+  for bundled in [programs::HELLO_WORLD, programs::ROT13] {
+    assert_eq!(bf.check_equivalence(bundled.source, b"Hello, World!"), Ok(Verification::Equal), "{}", bundled.name);
+  }
+  assert_eq!(bf.check_equivalence("+[]", b""), Ok(Verification::Inconclusive));
+  assert!(bf.check_equivalence("+]", b"").is_err());
+  bf.set_eof_mode(EofMode::Max);
+  assert_eq!(bf.check_equivalence(",[-]>,.", b"x"), Ok(Verification::Equal));
+}
+
+#[test]
+fn clear_loops() {
+  let zeroed = CompileOptions { assume_zeroed_tape: true, ..Default::default() };
+  let compile = |code: &str, options: &CompileOptions| Program::compile_with_options(code, options).unwrap();
+  let loops = |program: &Program| program.opcodes().iter().filter(|x| matches!(x, Opcode::LoopStart(_))).count();
+  //Even steps are cleared once the cell is known to be a multiple of their power of two, and kept otherwise
+  assert_eq!(loops(&compile(",>[-]<[->++<]>[--].", &CompileOptions::default())), 0);
+  assert_eq!(loops(&compile(",[->++<]>[--].", &zeroed)), 0);
+  assert_eq!(loops(&compile(",[->++<]>[----].", &zeroed)), 1);
+  assert_eq!(loops(&compile(",[--].", &zeroed)), 1);
+  //Pointer-moving bodies setting other cells clear the condition cell in a single iteration
+  assert_eq!(compile(",[>[-]>+++<<-]>>.", &CompileOptions::default()).opcodes()[1..5], [
+    Opcode::LoopStart(4), Opcode::Set(1, 0), Opcode::Move([(2, 3)].into()), Opcode::LoopEnd(1),
+  ]);
+  //Not a clear, the condition cell never changes
+  assert_eq!(loops(&compile(",[<->].", &zeroed)), 1);
+  for code in [",>[-]<[->++<]>[--].", ",[->++<]>[--].", ",[->++<]>[----].", ",[>[-]<-]>.", ",[>[-]>+++<<-]>>.", "+>,[<[-]>-<+>]<."] {
+    for input in [b"\x00", b"\x01", b"\x02", b"\x7f", b"\xff"] {
+      let sandbox = Sandbox { fuel: 100_000, input };
+      let expected = enum_interpreter(&Program::compile_without_optimizations(code).unwrap(), &sandbox);
+      let optimized = enum_interpreter(&compile(code, &zeroed), &sandbox);
+      //Loops that never terminate must keep doing so, where they stop depends on the fuel spent per iteration
+      match expected.halted {
+        true => assert_eq!(optimized, expected, "{code} on {input:?}"),
+        false => assert!(!optimized.halted, "{code} on {input:?} terminates"),
+      }
+    }
+  }
+}
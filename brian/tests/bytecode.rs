@@ -0,0 +1,113 @@
+//! Bytecode round trips, and rejection of malformed bytecode from untrusted sources
+
+use brian::{generator::ProgramGenerator, BytecodeError, CompileOptions, Opcode, Profile, Program, VerifyError};
+
+/// Header of a current version bytecode file without metadata
+const HEADER: &[u8] = b"BFBC\x05\x00";
+
+fn load(ops: &[u8]) -> Result<Program, BytecodeError> {
+  Program::from_bytecode(&[HEADER, ops].concat())
+}
+
+#[test]
+fn round_trip() {
+  let generator = ProgramGenerator::new(200);
+  let flush = CompileOptions { flush: Some(';'), ..Default::default() };
+  let mut programs: Vec<Program> = (0..50).map(|seed| Program::compile(&generator.generate(seed)).unwrap()).collect();
+  programs.push(Program::compile_with_options("++++[>+++++<-]>[>+>+<<-]>>>+>+>+<<<<<[>]<[<]>[-];.", &flush).unwrap());
+  //Superinstructions, and pointer movements fused into loop brackets
+  for program in programs.clone() {
+    let profile = Profile { counts: vec![1; program.opcodes().len()] };
+    programs.push(program.fuse_superinstructions(&profile, 8).unwrap());
+    programs.push(program.fuse_hot_loop_brackets(&profile).unwrap());
+  }
+  assert!(programs.iter().any(|x| x.opcodes().iter().any(|x| matches!(x, Opcode::Fused(_)))));
+  let mut tagged = Program::compile("+.").unwrap();
+  tagged.metadata_mut().insert("source".into(), "hello.b".into());
+  tagged.metadata_mut().insert("ключ".into(), String::new());
+  programs.push(tagged);
+  for program in programs {
+    let bytecode = program.to_bytecode();
+    assert!(Program::is_bytecode(&bytecode));
+    assert_eq!(Program::from_bytecode(&bytecode), Ok(program));
+  }
+  //Older versions without metadata are still accepted
+  assert_eq!(Program::from_bytecode(b"BFBC\x02\x02\x02\x0a").map(|x| x.opcodes().to_vec()), Ok(vec![Opcode::MovePointer(1), Opcode::Eof]));
+}
+
+#[test]
+fn rejects_bad_header() {
+  assert_eq!(Program::from_bytecode(b""), Err(BytecodeError::BadMagic));
+  assert_eq!(Program::from_bytecode(b"BFXC\x05\x00\x0a"), Err(BytecodeError::BadMagic));
+  assert_eq!(Program::from_bytecode(b"BFBC"), Err(BytecodeError::Truncated));
+  assert_eq!(Program::from_bytecode(b"BFBC\x00\x00\x0a"), Err(BytecodeError::UnsupportedVersion(0)));
+  assert_eq!(Program::from_bytecode(b"BFBC\x06\x00\x0a"), Err(BytecodeError::UnsupportedVersion(6)));
+}
+
+#[test]
+fn rejects_truncated() {
+  let bytecode = Program::compile(",[->+<]>.").unwrap().to_bytecode();
+  for length in 0..bytecode.len() {
+    assert!(Program::from_bytecode(&bytecode[..length]).is_err(), "{length} bytes");
+  }
+  //Increment without its value
+  assert_eq!(load(b"\x00\x00"), Err(BytecodeError::Truncated));
+  //Unterminated varint
+  assert_eq!(load(b"\x02\x80"), Err(BytecodeError::Truncated));
+  //Move with fewer targets than announced
+  assert_eq!(load(b"\x0b\x02\x02\x01"), Err(BytecodeError::Truncated));
+}
+
+#[test]
+fn rejects_malformed_opcodes() {
+  assert_eq!(load(b"\x0e\x0a"), Err(BytecodeError::UnknownOpcode(6)));
+  assert_eq!(load(b"\x03\x0a"), Err(BytecodeError::UnbalancedLoops));
+  assert_eq!(load(b"\x04\x0a"), Err(BytecodeError::UnbalancedLoops));
+  assert_eq!(load(b"\x08\x02\x03\x04\x0a"), Err(BytecodeError::UnbalancedLoops));
+  assert_eq!(load(b"\x02\x02"), Err(BytecodeError::MissingEof));
+  assert_eq!(load(b"\x0a\x0a"), Err(BytecodeError::MissingEof));
+  assert_eq!(load(b""), Err(BytecodeError::MissingEof));
+  assert_eq!(load(b"\x07\x00\x0a"), Err(BytecodeError::InvalidProgram(VerifyError::EmptyScan(0))));
+  //Varints past 64 bits
+  assert_eq!(load(b"\x02\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f\x0a"), Err(BytecodeError::Overflow));
+}
+
+#[test]
+fn rejects_bad_fusion() {
+  //Fused opcodes can't nest
+  assert_eq!(load(b"\x0d\x02\x0d\x00\x02\x02\x0a"), Err(BytecodeError::UnknownOpcode(8)));
+  //Only straight-line opcodes are fused, at least two of them
+  assert_eq!(load(b"\x0d\x02\x05\x00\x05\x00\x0a"), Err(BytecodeError::InvalidProgram(VerifyError::BadFusion(0))));
+  assert_eq!(load(b"\x0d\x01\x02\x02\x0a"), Err(BytecodeError::InvalidProgram(VerifyError::BadFusion(0))));
+  //Loop brackets aren't either
+  assert!(load(b"\x0d\x02\x02\x02\x03\x04\x0a").is_err());
+  assert!(load(b"\x0d\x02\x02\x02\x00\x00\x02\x0a").is_ok());
+}
+
+#[test]
+fn rejects_increments_out_of_range() {
+  //255 and -128, the extremes of `u8` and `i8`
+  assert_eq!(load(b"\x00\x00\xfe\x03\x00\x00\xff\x01\x0a").map(|x| x.opcodes().to_vec()), Ok(vec![
+    Opcode::Increment(0, 255), Opcode::Increment(0, -128), Opcode::Eof,
+  ]));
+  //256 and -129
+  assert_eq!(load(b"\x00\x00\x80\x04\x0a"), Err(BytecodeError::Overflow));
+  assert_eq!(load(b"\x00\x00\x81\x02\x0a"), Err(BytecodeError::Overflow));
+  //Close to `isize::MAX`, also inside superinstructions
+  assert_eq!(load(b"\x00\x00\xfe\xff\xff\xff\xff\xff\xff\xff\xff\x01\x0a"), Err(BytecodeError::Overflow));
+  assert_eq!(load(b"\x0d\x02\x02\x02\x00\x00\xfe\xff\xff\xff\xff\xff\xff\xff\xff\x01\x0a"), Err(BytecodeError::Overflow));
+}
+
+#[test]
+fn rejects_bad_metadata() {
+  let mut program = Program::compile("+").unwrap();
+  program.metadata_mut().insert("key".into(), "value".into());
+  let bytecode = program.to_bytecode();
+  assert_eq!(Program::from_bytecode(&bytecode[..bytecode.len() - 5]), Err(BytecodeError::Truncated));
+  //Key that isn't UTF-8
+  assert_eq!(Program::from_bytecode(b"BFBC\x05\x01\x01\xff\x00\x0a"), Err(BytecodeError::InvalidMetadata));
+  //Value longer than the data
+  assert_eq!(Program::from_bytecode(b"BFBC\x05\x01\x01k\x05ab"), Err(BytecodeError::Truncated));
+  //Huge length
+  assert_eq!(Program::from_bytecode(b"BFBC\x05\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01"), Err(BytecodeError::Truncated));
+}
@@ -0,0 +1,155 @@
+//! Corpus of programs probing behavior that differs between interpreters, checked against the outputs
+//! of the reference each [`Compatibility`] preset matches
+//!
+//! Every case is run both with the preset's compiler options and without optimizations
+
+use brian::{
+  programs, BoundsPolicy, BracketPolicy, Brainfuck, BrainfuckState, Compatibility, CompileOptions, InterpreterConfig, Opcode,
+  OverflowPolicy, ParseBoundsPolicyError, PreviewEnd, Profile, Program, RuntimeError, StreamIo, Trap, TrapKind,
+};
+
+/// Program, its input, and the reference output, or the trap ending the run
+struct Case {
+  name: &'static str,
+  code: String,
+  input: &'static [u8],
+  expected: Result<&'static [u8], TrapKind>,
+}
+
+fn case(name: &'static str, code: impl Into<String>, input: &'static [u8], expected: Result<&'static [u8], TrapKind>) -> Case {
+  Case { name, code: code.into(), input, expected }
+}
+
+fn canonical_corpus() -> Vec<Case> {
+  vec![
+    case("eof leaves the cell unchanged", "+++,.", b"", Ok(b"\x03")),
+    case("eof after input", ",,.", b"x", Ok(b"x")),
+    case("cells wrap below zero", "-.", b"", Ok(b"\xff")),
+    case("cells wrap above 255", format!("{}.", "+".repeat(257)), b"", Ok(b"\x01")),
+    case("wrapped value moved", "-[->+<]>.", b"", Ok(b"\xff")),
+    case("loop skipped on zero", "[-.]+.", b"", Ok(b"\x01")),
+    case("hash is a comment", "+#+#.", b"", Ok(b"\x02")),
+    case("bang is a comment", "+!+.", b"y", Ok(b"\x02")),
+    case("last cell of the tape", format!("{}+.", ">".repeat(29999)), b"", Ok(b"\x01")),
+    case("left of the first cell", "<+.", b"", Err(TrapKind::PointerOutOfBounds)),
+    case("right of the last cell", format!("{}+.", ">".repeat(30000)), b"", Err(TrapKind::PointerOutOfBounds)),
+    case("hello world", programs::HELLO_WORLD.source, b"", Ok(programs::HELLO_WORLD.expected_output.unwrap())),
+  ]
+}
+
+fn run(compat: Compatibility, program: &Program, input: &[u8]) -> Result<Vec<u8>, Trap> {
+  let mut state = Box::new(BrainfuckState::new());
+  let mut io = StreamIo::new(input, Vec::new());
+  compat.interpreter_config().run(program, &mut state, &mut io, None)?;
+  Ok(io.output)
+}
+
+#[test]
+fn canonical() {
+  let compat = Compatibility::Canonical;
+  for case in canonical_corpus() {
+    let builds = [
+      ("optimized", Program::compile_with_options(&case.code, &compat.compile_options()).unwrap()),
+      ("unoptimized", Program::compile_without_optimizations(&case.code).unwrap()),
+    ];
+    for (build, program) in builds {
+      let result = run(compat, &program, case.input);
+      match case.expected {
+        Ok(expected) => assert_eq!(result.as_deref(), Ok(expected), "{} ({build})", case.name),
+        Err(kind) => assert_eq!(result.map_err(|x| x.kind), Err(kind), "{} ({build})", case.name),
+      }
+    }
+  }
+}
+
+#[test]
+fn parse_presets() {
+  for compat in [Compatibility::Brian, Compatibility::Canonical] {
+    assert_eq!(compat.to_string().parse(), Ok(compat));
+  }
+  assert!("bff".parse::<Compatibility>().is_err());
+  assert_eq!(Compatibility::Canonical.tape_length(), 30000);
+}
+
+#[test]
+fn lenient_brackets() {
+  let options = CompileOptions { brackets: BracketPolicy::Lenient, ..Default::default() };
+  //Stray `]` are skipped, and the loop left open runs until the cell is zero
+  for (code, expected) in [("++]+[.-", &b"\x03\x02\x01"[..]), ("]].+[[.-]", b"\x00\x01"), ("+[.-]]", b"\x01")] {
+    assert!(Program::compile_with_report(code, &CompileOptions::default()).is_err(), "{code} is unbalanced");
+    assert!(Program::compile(code).is_err() && Program::compile_without_optimizations(code).is_err(), "{code}");
+    let (program, report) = Program::compile_with_report(code, &options).unwrap();
+    assert_eq!(run(Compatibility::Brian, &program, b"").as_deref(), Ok(expected), "{code}");
+    assert_eq!(report.source_map.spans().len(), program.opcodes().len());
+  }
+}
+
+#[test]
+fn bounds_policy() {
+  let mut bf = Brainfuck::new();
+  bf.compile_without_optimizations("+<+>").unwrap();
+  bf.set_bounds_policy(BoundsPolicy::Trap);
+  let error = bf.run_with_input([]).unwrap_err();
+  assert!(matches!(error, RuntimeError::Trap(Trap { kind: TrapKind::PointerOutOfBounds, instruction: 1 })), "{error}");
+  assert!(bf.config().to_string().contains(" bounds=trap "));
+  //Every way of running the program reports the trap instead of halting
+  let trap = Trap { kind: TrapKind::PointerOutOfBounds, instruction: 1 };
+  bf.reset_state();
+  assert_eq!(bf.preview(100, 100).end, PreviewEnd::Trapped(trap));
+  bf.reset_state();
+  assert_eq!(bf.run_to_vec(), Err(trap));
+  bf.reset_state();
+  assert_eq!(bf.run_with_fuel(Vec::new(), 100), Err(trap));
+  bf.reset_state();
+  assert_eq!(bf.run_with_streams(&b""[..], Vec::new()), Err(trap));
+  //The default policy wraps around to the last cell
+  bf.reset_state();
+  bf.set_bounds_policy(BoundsPolicy::Wrap);
+  assert!(bf.run_with_input([]).is_ok());
+  assert_eq!((bf.state().tape()[0], bf.state().tape()[bf.state().tape().len() - 1]), (1, 1));
+  assert_eq!("trap".parse(), Ok(BoundsPolicy::Trap));
+  assert_eq!("trap-canonical".parse(), Ok(BoundsPolicy::TrapCanonical));
+  assert_eq!("grow".parse::<BoundsPolicy>(), Err(ParseBoundsPolicyError::Grow));
+}
+
+#[test]
+fn overflow_policy() {
+  let config = InterpreterConfig { overflow: OverflowPolicy::Trap, ..Default::default() };
+  let run = |program: &Program| config.run(program, &mut Box::new(BrainfuckState::new()), &mut StreamIo::new(&b""[..], Vec::new()), None);
+  let overflow = |instruction| Err(Trap { kind: TrapKind::CellOverflow, instruction });
+  assert_eq!(run(&Program::compile_without_optimizations("-").unwrap()), overflow(0));
+  assert_eq!(run(&Program::compile_without_optimizations(&"+".repeat(256)).unwrap()), overflow(255));
+  assert_eq!(run(&Program::compile_without_optimizations(&"+".repeat(255)).unwrap()), Ok(true));
+  //Increments that overflow an `isize` when added to a nonzero cell trap too, on their own and inside superinstructions
+  let mut program = Program::compile_without_optimizations("+++").unwrap();
+  program.patch(2, Opcode::Increment(0, isize::MAX)).unwrap();
+  assert_eq!(run(&program), overflow(2));
+  let fused = program.fuse_superinstructions(&Profile { counts: vec![1; program.opcodes().len()] }, 8).unwrap();
+  assert!(fused.opcodes().iter().any(|x| matches!(x, Opcode::Fused(_))));
+  assert_eq!(run(&fused).map_err(|x| x.kind), Err(TrapKind::CellOverflow));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn bounds_policy_in_slices() {
+  use std::{future::Future, pin::pin, task::{Context, Poll, Waker}};
+  //Poll on the current thread, counting the polls
+  fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    (1..).find_map(|polls| match future.as_mut().poll(&mut cx) {
+      Poll::Ready(output) => Some((output, polls)),
+      Poll::Pending => None,
+    }).unwrap()
+  }
+  let mut bf = Brainfuck::new();
+  bf.compile_without_optimizations("+[>+]").unwrap();
+  bf.set_bounds_policy(BoundsPolicy::Trap);
+  let ((_, _, result), polls) = block_on(bf.clone().into_future(StreamIo::new(&b""[..], Vec::new()), 1000));
+  assert_eq!(result.map_err(|trap| trap.kind), Err(TrapKind::PointerOutOfBounds));
+  assert!(polls > 1, "the run is sliced");
+  let (result, polls) = block_on(bf.run_async(&b""[..], Vec::new(), 1000));
+  let trap = result.unwrap_err().into_inner().unwrap().downcast::<Trap>().unwrap();
+  assert_eq!(trap.kind, TrapKind::PointerOutOfBounds);
+  assert!(polls > 1, "the run is sliced");
+}
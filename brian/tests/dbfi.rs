@@ -0,0 +1,61 @@
+//! Conformance test running programs through the dbfi self-interpreter
+//!
+//! Self-interpretation exercises input handling, EOF behavior and deeply nested loops all at once
+
+use brian::{programs, run_with_fuel, BrainfuckState, Program, StreamIo};
+
+/// Instructions a single run may take, generous enough for every case below
+const FUEL: usize = 1 << 32;
+
+/// Run `code` (comments stripped, as dbfi stops reading it at the first `!`) with `input` through dbfi
+fn run_dbfi(dbfi: &Program, code: &str, input: &[u8]) -> Vec<u8> {
+  let code: String = code.chars().filter(|x| "+-<>[].,".contains(*x)).collect();
+  let mut dbfi_input = format!("{code}!").into_bytes();
+  dbfi_input.extend_from_slice(input);
+  let mut state = Box::new(BrainfuckState::new());
+  let mut io = StreamIo::new(&dbfi_input[..], Vec::new());
+  assert!(run_with_fuel(dbfi, &mut state, &mut io, FUEL), "dbfi ran out of fuel");
+  io.output
+}
+
+fn dbfi_builds() -> [(&'static str, Program); 2] {
+  [
+    ("optimized", Program::compile(programs::DBFI.source).unwrap()),
+    ("unoptimized", Program::compile_without_optimizations(programs::DBFI.source).unwrap()),
+  ]
+}
+
+#[test]
+fn hello_world() {
+  for (build, dbfi) in dbfi_builds() {
+    let output = run_dbfi(&dbfi, programs::HELLO_WORLD.source, b"");
+    assert_eq!(output, programs::HELLO_WORLD.expected_output.unwrap(), "{build}");
+  }
+}
+
+#[test]
+fn input_after_program() {
+  for (build, dbfi) in dbfi_builds() {
+    let output = run_dbfi(&dbfi, programs::ROT13.source, b"Hello, World!");
+    assert_eq!(output, b"Uryyb, Jbeyq!", "{build}");
+  }
+}
+
+#[test]
+fn cat_until_eof() {
+  //dbfi leaves the cell unchanged at EOF, so the interpreted program sees the 0 it stored beforehand
+  for (build, dbfi) in dbfi_builds() {
+    let output = run_dbfi(&dbfi, ",[.[-],]", b"brian");
+    assert_eq!(output, b"brian", "{build}");
+  }
+}
+
+#[test]
+fn nested_loops() {
+  //Prints the 26 letters of the alphabet from nested loops
+  let code = "++++++++[>++++++++<-]>+<++[>>+++++++++++++[<.+>-]<<-]";
+  for (build, dbfi) in dbfi_builds() {
+    let output = run_dbfi(&dbfi, code, b"");
+    assert_eq!(output, b"ABCDEFGHIJKLMNOPQRSTUVWXYZ", "{build}");
+  }
+}
@@ -0,0 +1,105 @@
+//! Static tape footprints checked against the cells programs actually access
+
+use std::ops::ControlFlow;
+use brian::{
+  generator::ProgramGenerator, run_with_hooks, BoundsPolicy, BrainfuckState, Hooks, InterpreterConfig, Opcode, Program, StateView,
+  StreamIo, TapeFootprint, Trap, TrapKind,
+};
+
+/// Instructions a run may execute before it's stopped
+const STEPS: usize = 100_000;
+
+/// Lowest and highest offset accessed by a run, relative to the starting cell
+struct Accesses {
+  lowest: isize,
+  highest: isize,
+  steps: usize,
+}
+impl Accesses {
+  fn touch(&mut self, state: &StateView, offset: isize) {
+    //Pointers left of the tape are stored wrapped around, and read back as negative offsets
+    let offset = (state.pointer as isize).wrapping_add(offset);
+    self.lowest = self.lowest.min(offset);
+    self.highest = self.highest.max(offset);
+  }
+}
+impl Hooks for Accesses {
+  fn before(&mut self, _program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    match op {
+      Opcode::Increment(offset, _) | Opcode::Set(offset, _) | Opcode::Output(offset) | Opcode::Input(offset) => self.touch(&state, *offset),
+      Opcode::Move(targets) => {
+        self.touch(&state, 0);
+        targets.iter().for_each(|&(offset, _)| self.touch(&state, offset));
+      },
+      _ => (),
+    }
+    self.steps += 1;
+    if self.steps > STEPS { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+  }
+
+  fn after(&mut self, _program_counter: usize, op: &Opcode, state: StateView) -> ControlFlow<()> {
+    //Conditions of loops and the cells scans stop at
+    if matches!(op, Opcode::LoopStart(_) | Opcode::LoopEnd(_) | Opcode::MoveLoopStart(..) | Opcode::MoveLoopEnd(..) | Opcode::ScanZero(_)) {
+      self.touch(&state, 0);
+    }
+    ControlFlow::Continue(())
+  }
+}
+
+fn footprint(code: &str) -> TapeFootprint {
+  Program::compile_without_optimizations(code).unwrap().tape_footprint()
+}
+
+#[test]
+fn estimates() {
+  assert_eq!(footprint("+>>-<."), TapeFootprint { lowest: Some(0), highest: Some(2) });
+  assert_eq!(footprint("<+"), TapeFootprint { lowest: Some(-1), highest: Some(-1) });
+  assert_eq!(footprint(">>[-<<+>>]"), TapeFootprint { lowest: Some(0), highest: Some(2) });
+  assert_eq!(footprint("+[>+]"), TapeFootprint { lowest: Some(0), highest: None });
+  assert_eq!(footprint("+[<]"), TapeFootprint { lowest: None, highest: Some(0) });
+  assert_eq!(footprint("+[>+]").to_string(), "0..");
+  assert_eq!(footprint("+>>-<.").to_string(), "0..=2");
+  assert!(footprint("+>>-<.").fits(3));
+  assert!(!footprint("+>>-<.").fits(2));
+  assert!(!footprint("<+").fits(10));
+  assert!(!footprint("+[>+]").fits(10));
+}
+
+/// Every access of a run is within the footprint of the program, optimized or not
+#[test]
+fn soundness() {
+  let mut checked = 0;
+  for (length, max_depth) in [(30, 2), (120, 3), (300, 3)] {
+    let generator = ProgramGenerator { max_depth, loop_probability: 0.3, io_density: 0.05, terminating: true, ..ProgramGenerator::new(length) };
+    for seed in 0..1500 {
+      let code = generator.generate(seed);
+      for program in [Program::compile_without_optimizations(&code).unwrap(), Program::compile(&code).unwrap()] {
+        let footprint = program.tape_footprint();
+        let mut accesses = Accesses { lowest: isize::MAX, highest: isize::MIN, steps: 0 };
+        let mut state = Box::new(BrainfuckState::new());
+        run_with_hooks(&program, &mut state, &mut StreamIo::new(&b"\x03\x00\x05abc"[..], Vec::new()), &mut accesses);
+        assert!(footprint.lowest.is_none_or(|x| x <= accesses.lowest), "{code}: {footprint}, accessed {}", accesses.lowest);
+        assert!(footprint.highest.is_none_or(|x| x >= accesses.highest), "{code}: {footprint}, accessed {}", accesses.highest);
+        checked += 1;
+      }
+    }
+  }
+  assert_eq!(checked, 9000);
+}
+
+/// Programs provably staying on the tape skip the bounds checks, others still trap
+#[test]
+fn bounds_checks() {
+  let config = InterpreterConfig { bounds: BoundsPolicy::TrapCanonical, ..Default::default() };
+  let run = |code: &str, state: &mut BrainfuckState| {
+    let mut io = StreamIo::new(&b""[..], Vec::new());
+    config.run(&Program::compile(code).unwrap(), state, &mut io, None).map(|_| io.output)
+  };
+  let mut state = Box::new(BrainfuckState::new());
+  assert_eq!(run("+++[->++<]>.", &mut state), Ok(vec![6]));
+  assert_eq!(run("<+", &mut BrainfuckState::new()).map_err(|x| x.kind), Err(TrapKind::PointerOutOfBounds));
+  assert_eq!(run("+[>+]", &mut BrainfuckState::new()).map_err(|x| x.kind), Err(TrapKind::PointerOutOfBounds));
+  //The footprint is relative to the starting cell, which isn't the first one here
+  state.pointer = 29_999;
+  assert_eq!(run(">+", &mut state), Err(Trap { kind: TrapKind::PointerOutOfBounds, instruction: 0 }));
+}
@@ -0,0 +1,145 @@
+//! Optimizer passes checked against the unoptimized compiler
+//!
+//! Every pass gets a look at the opcodes it's expected to produce, and a differential run of the optimized program
+//! against [`Program::compile_without_optimizations`] on a range of inputs
+
+use brian::{
+  generator::ProgramGenerator, run_with_fuel, stress::{compare, Comparison, StressTest}, BrainfuckState, CompileOptions, Opcode,
+  Program, StreamIo,
+};
+
+/// Instructions the reference run executes before the comparison is given up as inconclusive
+const FUEL: usize = 100_000;
+
+/// Inputs covering zero, odd and even bytes, and both ends of the cell range
+const INPUTS: &[&[u8]] = &[b"", b"\x00", b"\x01\x02", b"\x02\x07", b"\x05\x80", b"\x80\x05", b"\xfe\xff", b"\xff\xfe\x03"];
+
+/// Output, pointer and tape of a run on a fresh tape, `None` if it ran out of fuel
+fn run(program: &Program, input: &[u8]) -> Option<(Vec<u8>, usize, Vec<u8>)> {
+  let mut state = Box::new(BrainfuckState::new());
+  let mut io = StreamIo::new(input, Vec::new());
+  run_with_fuel(program, &mut state, &mut io, FUEL).then(|| (io.output, state.pointer_address(), state.tape().to_vec()))
+}
+
+/// Assert that `code` compiled with each of the [`option_sets`] behaves like the unoptimized program on all [`INPUTS`]
+fn assert_equivalent(code: &str) {
+  let reference = Program::compile_without_optimizations(code).unwrap();
+  let optimized = option_sets().map(|options| (Program::compile_with_options(code, &options).unwrap(), options));
+  for input in INPUTS {
+    //Optimized programs execute fewer instructions, so they finish whenever the reference does
+    let Some(expected) = run(&reference, input) else { continue };
+    for (program, options) in &optimized {
+      assert_eq!(run(program, input).as_ref(), Some(&expected), "{code} on {input:?} with {options:?}");
+    }
+  }
+}
+
+fn opcodes(code: &str, options: &CompileOptions) -> Vec<Opcode> {
+  Program::compile_with_options(code, options).unwrap().opcodes().to_vec()
+}
+
+/// Option sets exercising every pass, none of which changes the final tape of a run on a fresh tape
+fn option_sets() -> [CompileOptions; 4] {
+  [
+    CompileOptions::default(),
+    CompileOptions { assume_zeroed_tape: true, ..Default::default() },
+    CompileOptions { dataflow: true, ..Default::default() },
+    CompileOptions { assume_zeroed_tape: true, dataflow: true, ..Default::default() },
+  ]
+}
+
+#[test]
+fn copy_and_multiply_loops() {
+  let options = CompileOptions::default();
+  assert_eq!(opcodes("[->+<]", &options), [Opcode::Move([(1, 1)].into()), Opcode::Eof]);
+  assert_eq!(opcodes("[>+<-]", &options), [Opcode::Move([(1, 1)].into()), Opcode::Eof]);
+  assert_eq!(opcodes("[->+>++<<]", &options), [Opcode::Move([(1, 1), (2, 2)].into()), Opcode::Eof]);
+  assert_eq!(opcodes("[->>+++<<]", &options), [Opcode::Move([(2, 3)].into()), Opcode::Eof]);
+  assert_eq!(
+    opcodes(",[->+>+<<]>>[-<<+>>]", &options),
+    [Opcode::Input(0), Opcode::Move([(1, 1), (2, 1)].into()), Opcode::MovePointer(2), Opcode::Move([(-2, 1)].into()), Opcode::Eof],
+  );
+  for code in [
+    ",[->+<]>.", ",[>+<-]>.", ",[->+>++<<]>.>.", ",[->>+++<<]>>.", ",[-<+>]<.", ",[->-<]>.",
+    ",[->+>+<<]>>[-<<+>>]<<.>.", ",>,<[->+<]>[-<+++>]<.", ",[->++<]>[->+++<]>.",
+  ] {
+    assert_equivalent(code);
+  }
+}
+
+#[test]
+fn non_unit_steps() {
+  let options = CompileOptions::default();
+  //An odd step reaches zero from any value, an even one only from even values
+  assert_eq!(opcodes("[---]", &options), [Opcode::Set(0, 0), Opcode::Eof]);
+  assert_eq!(opcodes("[--]", &options), [Opcode::LoopStart(2), Opcode::Increment(0, -2), Opcode::LoopEnd(0), Opcode::Eof]);
+  //Known even values are cleared, and counted loops over them unrolled
+  assert_eq!(opcodes(",[-]++++[--]", &options), [Opcode::Input(0), Opcode::Set(0, 0), Opcode::Eof]);
+  assert_eq!(opcodes(",[-]++++[-->+<]", &options), [Opcode::Input(0), Opcode::Set(0, 0), Opcode::Increment(1, 2), Opcode::Eof]);
+  assert!(opcodes(",[-]+++[--]", &options).contains(&Opcode::Increment(0, -2)), "odd values never reach zero");
+  for code in [
+    ",[--]+.", ",[---]+.", ",[-->+<]>.", ",[--->++<]>.", ",[++>-<]>.", ",[-]+++++[-->+++<]>.", ",[-]++++++[-->+<]>.",
+    ",[->++<]>[--]+.", ",[->++<]>[-->+<]>.", ",>,<[->++<]>[---<+>]<.",
+  ] {
+    assert_equivalent(code);
+  }
+}
+
+#[test]
+fn endless_loop_trimming() {
+  let options = CompileOptions::default();
+  //A loop on a known nonzero cell that doesn't change it never ends, so nothing after it is compiled
+  let endless = [Opcode::Input(0), Opcode::Set(0, 1), Opcode::LoopStart(3), Opcode::LoopEnd(2), Opcode::Eof];
+  assert_eq!(opcodes(",[-]+[]+.", &options), endless);
+  assert_eq!(opcodes(",[-]+[]>,.", &options), endless);
+  //Code after an empty loop is reached whenever the cell is zero
+  assert_eq!(opcodes("[]+++", &options), [Opcode::LoopStart(1), Opcode::LoopEnd(0), Opcode::Increment(0, 3), Opcode::Eof]);
+  //Scans end, so the code after them stays
+  assert_eq!(opcodes(",[-]+[>]+.", &options).last(), Some(&Opcode::Eof));
+  assert!(opcodes(",[-]+[>]+.", &options).contains(&Opcode::Output(0)));
+  for code in [",[]+.", ",[-]+[]+.", ",[>+<-]+>[]<.", "+[>]+.", ",[[]+.]"] {
+    assert_equivalent(code);
+  }
+}
+
+#[test]
+fn dataflow_regions() {
+  let options = CompileOptions { dataflow: true, ..Default::default() };
+  //A cell moved away and back is moved once
+  assert_eq!(
+    opcodes(">,[<+>-]<[>+<-]>.", &options),
+    [Opcode::Input(1), Opcode::Move([(1, 1)].into()), Opcode::Output(1), Opcode::MovePointer(1), Opcode::Eof],
+  );
+  //The target of the first loop is the source of the second
+  assert_eq!(
+    opcodes(",[->+<]>[-<+>]<.", &options),
+    [Opcode::Input(0), Opcode::MovePointer(1), Opcode::Move([(-1, 1)].into()), Opcode::Output(-1), Opcode::MovePointer(-1), Opcode::Eof],
+  );
+  for code in [
+    ">,[<+>-]<[>+<-]>.<.", ",[->+<]>[-<+>]<.>.", ",>,<[->+<]>[-<+>>+<]<.>.>.", ",[->+>+<<]>[-<+>]<.>.>.", ",[->++<]>[-]<.>.",
+    ",>,<[->+<]>[-<+>]<.>.", ",[->++<]>[->+<<+>]<.>.>.", ",[->+<]<[->+<]>.<.", ",>,[-<+>]<[->+>+<<]>.>.",
+  ] {
+    assert_equivalent(code);
+  }
+}
+
+#[test]
+fn generated_programs() {
+  let generator = ProgramGenerator::new(100);
+  for seed in 0..100 {
+    let code = generator.generate(seed);
+    assert_equivalent(&code);
+  }
+}
+
+#[test]
+fn stress_test() {
+  assert_eq!(compare(",[->+<]>.", FUEL), Comparison::Equal);
+  assert_eq!(compare("+[]", FUEL), Comparison::Inconclusive);
+  assert_eq!(compare("+[", FUEL), Comparison::Unbalanced);
+  assert_eq!(compare("]", FUEL), Comparison::Unbalanced);
+  //Unbalanced programs are generated, but never reported as diverging
+  let generator = ProgramGenerator { balanced: false, ..ProgramGenerator::new(50) };
+  assert!((0..100).any(|seed| compare(&generator.generate(seed), FUEL) == Comparison::Unbalanced));
+  assert!(StressTest { iterations: 100, generator, fuel: FUEL, ..Default::default() }.run().is_none());
+}
@@ -0,0 +1,80 @@
+//! Sandbox limits of pooled runs, and the corpus runner built on them
+
+use std::{fs, path::PathBuf, sync::Arc};
+use brian::{
+  corpus::{run_corpus, CorpusOptions, Status},
+  pool::{Job, Outcome, Pool, Sandbox},
+  Program,
+};
+
+fn job(code: &str, input: &[u8], sandbox: Sandbox) -> Job {
+  Job { program: Arc::new(Program::compile(code).unwrap()), input: input.to_vec(), sandbox }
+}
+
+#[test]
+fn sandbox_limits() {
+  let pool = Pool::new(2, 4);
+  let sandbox = Sandbox { fuel: 1000, max_output: 4 };
+  let handles = [
+    pool.submit(job(",+.,+.", b"ab", sandbox.clone())),
+    pool.submit(job("+[]", b"", sandbox.clone())),
+    pool.submit(job("++++++[>++++++++<-]>[.]", b"", sandbox.clone())),
+    pool.submit(job("++++++++[.-]", b"", sandbox.clone())),
+    //Output limit is only reported for programs that halt
+    pool.submit(job("+[.]", b"", sandbox)),
+  ];
+  let results: Vec<_> = handles.into_iter().map(|x| x.wait()).collect();
+  assert_eq!((results[0].outcome, &results[0].output[..]), (Outcome::Halted, &b"bc"[..]));
+  assert_eq!((results[1].outcome, &results[1].output[..]), (Outcome::OutOfFuel, &b""[..]));
+  assert_eq!((results[2].outcome, &results[2].output[..]), (Outcome::OutOfFuel, &b"0000"[..]));
+  assert_eq!((results[3].outcome, &results[3].output[..]), (Outcome::OutputLimitExceeded, &b"\x08\x07\x06\x05"[..]));
+  assert_eq!((results[4].outcome, results[4].output.len()), (Outcome::OutOfFuel, 4));
+}
+
+/// Empty directory for a test, removed beforehand if a previous run left it behind
+fn scratch_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("brian-{name}-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+#[test]
+fn corpus() {
+  let dir = scratch_dir("corpus");
+  fs::create_dir(dir.join("nested")).unwrap();
+  let files: &[(&str, &[u8])] = &[
+    ("echo.b", b",+."),
+    ("echo.in", b"a"),
+    ("echo.out", b"b"),
+    ("halts.bf", b"+++"),
+    ("wrong.b", b",."),
+    ("wrong.in", b"a"),
+    ("wrong.out", b"b"),
+    ("nested/endless.b", b"+[]"),
+    ("nested/unbalanced.b", b"+["),
+    ("notes.txt", b"+[]"),
+  ];
+  for (name, contents) in files {
+    fs::write(dir.join(name), contents).unwrap();
+  }
+  let options = CorpusOptions { threads: 2, sandbox: Sandbox { fuel: 10_000, max_output: 16 }, ..Default::default() };
+  let summary = run_corpus(&dir, &options).unwrap();
+  let statuses: Vec<_> = summary.results.iter().map(|x| (x.path.strip_prefix(&dir).unwrap().to_path_buf(), x.status.clone())).collect();
+  assert!(matches!(
+    &statuses[..],
+    [
+      (echo, Status::Passed),
+      (halts, Status::Passed),
+      (endless, Status::TimedOut),
+      (unbalanced, Status::Error(_)),
+      (wrong, Status::Failed),
+    ] if echo.ends_with("echo.b") && halts.ends_with("halts.bf") && endless.ends_with("nested/endless.b")
+      && unbalanced.ends_with("nested/unbalanced.b") && wrong.ends_with("wrong.b")
+  ), "{statuses:?}");
+  assert_eq!((summary.passed, summary.failed, summary.timed_out, summary.errors), (2, 1, 1, 1));
+  assert!(!summary.success());
+  assert!(summary.to_json().starts_with("{\"passed\":2,\"failed\":1,\"timed_out\":1,\"errors\":1,"));
+  assert!(run_corpus(&dir.join("missing"), &options).is_err());
+  fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,47 @@
+//! Source locations of compiled opcodes, checked on the bundled programs with every optimization enabled
+
+use brian::{programs, CompileOptions, Opcode, Program};
+
+/// Command every loop bracket and I/O opcode has to be located at
+fn command(op: &Opcode) -> Option<&'static str> {
+  Some(match op {
+    Opcode::LoopStart(_) | Opcode::MoveLoopStart(..) => "[",
+    Opcode::LoopEnd(_) | Opcode::MoveLoopEnd(..) => "]",
+    Opcode::Output(_) => ".",
+    Opcode::Input(_) => ",",
+    _ => return None,
+  })
+}
+
+#[test]
+fn bundled_programs() {
+  for bundled in programs::ALL {
+    for dataflow in [false, true] {
+      let options = CompileOptions { assume_zeroed_tape: true, drop_dead_stores: true, dataflow, ..Default::default() };
+      let (program, report) = Program::compile_with_report(bundled.source, &options).unwrap();
+      let spans = report.source_map.spans();
+      assert_eq!(spans.len(), program.opcodes().len(), "{}", bundled.name);
+      let mut previous = 0;
+      for (index, (op, span)) in program.opcodes().iter().zip(spans).enumerate() {
+        //Opcodes stay in source order
+        assert!(span.range.start >= previous, "{} opcode {index} located before the previous one", bundled.name);
+        previous = span.range.start;
+        if let Some(command) = command(op) {
+          assert_eq!(&bundled.source[span.range.clone()], command, "{} opcode {index} ({op:?})", bundled.name);
+        }
+      }
+    }
+  }
+}
+
+#[test]
+fn lines_and_columns() {
+  let code = "+++\n  [->+<]\n>.";
+  let (program, report) = Program::compile_with_report(code, &CompileOptions::default()).unwrap();
+  let output = program.opcodes().iter().position(|x| matches!(x, Opcode::Output(_))).unwrap();
+  let span = report.source_map.span(output).unwrap();
+  assert_eq!((span.line, span.column, span.range.clone()), (3, 2, 14..15));
+  //The move loop is merged with the code around it
+  let first = report.source_map.span(0).unwrap();
+  assert_eq!((first.line, first.column), (1, 1));
+}
@@ -0,0 +1,19 @@
+#![no_main]
+
+use brian::{Brainfuck, Program};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  let code = String::from_utf8_lossy(data);
+
+  //Every input must either compile to a well-formed program or be rejected, never panic
+  if let Ok(program) = Program::compile(&code) {
+    assert_eq!(program.verify(), Ok(()));
+  }
+  if let Ok(program) = Program::compile_without_optimizations(&code) {
+    assert_eq!(program.verify(), Ok(()));
+  }
+  let balanced = Program::compile(&code).is_ok();
+  assert_eq!(Brainfuck::new().compile(&code).is_ok(), balanced);
+  assert_eq!(Brainfuck::new().compile_without_optimizations(&code).is_ok(), balanced);
+});
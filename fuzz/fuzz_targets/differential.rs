@@ -0,0 +1,38 @@
+#![no_main]
+
+use brian::stress::{compare, Comparison};
+use libfuzzer_sys::fuzz_target;
+
+const FUEL: usize = 100_000;
+
+/// Map arbitrary bytes to a valid (bracket-balanced) brainfuck program
+fn generate_program(data: &[u8]) -> String {
+  let mut code = String::with_capacity(data.len());
+  let mut depth = 0usize;
+  for byte in data {
+    match byte % 8 {
+      0 => code.push('+'),
+      1 => code.push('-'),
+      2 => code.push('<'),
+      3 => code.push('>'),
+      4 => code.push('.'),
+      5 => code.push(','),
+      6 => {
+        code.push('[');
+        depth += 1;
+      },
+      _ => if depth > 0 {
+        code.push(']');
+        depth -= 1;
+      },
+    }
+  }
+  code.push_str(&"]".repeat(depth));
+  code
+}
+
+fuzz_target!(|data: &[u8]| {
+  let code = generate_program(data);
+  //Optimized programs execute fewer instructions, so runs that exhaust fuel are inconclusive
+  assert_ne!(compare(&code, FUEL), Comparison::Diverged, "optimizer divergence for {code:?}");
+});